@@ -16,6 +16,10 @@ pub const GAME_SERVER_PORT: u16 = 3002;
 pub const GUI_LISTENER_PORT: u16 = 20010;
 /// The default port where `server_pb` should expect to find the simulation controls
 pub const SIMULATION_LISTENER_PORT: u16 = 20014;
+/// The port where `server_pb` listens for [`crate::messages::discovery::RobotBeacon`]s
+pub const DISCOVERY_PORT: u16 = 20016;
+/// How often, in milliseconds, a robot that isn't connected to a server broadcasts a beacon
+pub const BEACON_INTERVAL: u64 = 1000;
 /// The default timeout period in seconds between socket messages after which a socket attempts to reconnect
 pub const SOCKET_TIMEOUT: u64 = 5;
 
@@ -2,8 +2,8 @@ use crate::constants::ROBOT_LOGS_BUFFER;
 use crate::driving::peripherals::RobotPeripheralsBehavior;
 use crate::driving::RobotBehavior;
 use crate::messages::{
-    ExtraImuData, ExtraOptsAtomicTypes, ExtraOptsTypes, FrequentServerToRobot, MotorControlStatus,
-    NetworkStatus, SensorData,
+    ExtraImuData, ExtraOptsAtomicTypes, ExtraOptsTypes, FrequentServerToRobot, LocalizationDebug,
+    MotorControlStatus, NetworkStatus, SensorData,
 };
 use crate::names::RobotName;
 use crate::robot_definition::RobotDefinition;
@@ -52,6 +52,9 @@ pub struct SharedRobotData<R: RobotBehavior + ?Sized> {
     pub motor_control: Watch<CriticalSectionRawMutex, MotorControlStatus, 2>,
     /// Utilization percentage for the three tasks
     pub utilization: [AtomicF32; 3],
+    /// Localization internals, posted by the peripherals task when
+    /// [`FrequentServerToRobot::enable_localization_debug`] is set
+    pub sig_localization_debug: Signal<CriticalSectionRawMutex, LocalizationDebug>,
 
     //
     // ------------------- ROBOT -> CORE DATA -------------------
@@ -166,6 +169,7 @@ impl<R: RobotBehavior> SharedRobotData<R> {
             config: Watch::new_with(config),
             motor_control: Watch::new(),
             utilization: array_init(|_| AtomicF32::new(0.0)),
+            sig_localization_debug: Default::default(),
 
             sig_motor_speeds: Default::default(),
             sig_angle: Default::default(),
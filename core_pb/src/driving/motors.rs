@@ -191,10 +191,12 @@ impl<M: RobotMotorsBehavior> MotorsData<3, M> {
         if self.config.follow_target_path {
             if let Some(sensors) = sensors {
                 let mut target_velocity = (Vector2::new(0.0, 0.0), 0.0);
-                // maintain heading 0
+                // maintain the heading of the next waypoint, or heading 0
+                let heading =
+                    self.config.target_path.first().and_then(|w| w.heading).unwrap_or(0.0);
                 if let Ok(angle) = sensors.angle {
                     target_velocity.1 =
-                        adjust_ang_vel(angle, 0.0, angle_p, angle_tol, angle_snapping_offset);
+                        adjust_ang_vel(angle, heading, angle_p, angle_tol, angle_snapping_offset);
                     // let angle = Rotation2::new(angle).angle();
                     // if angle.abs() < 20.0_f32.to_radians() {
                     // now that we've made sure we're facing the right way, try to follow the path
@@ -1,10 +1,14 @@
-use crate::constants::DEFAULT_NETWORK;
+use crate::constants::{BEACON_INTERVAL, DEFAULT_NETWORK, DISCOVERY_PORT};
 use crate::driving::data::SharedRobotData;
 use crate::driving::RobotBehavior;
+use crate::messages::discovery::{write_beacon, RobotBeacon};
+use crate::messages::path_stream::PathFollower;
 use crate::messages::robot_tcp::{write_tcp, BytesOrT, StatefulTcpReader, TcpError, TcpMessage};
+use crate::messages::robot_udp::{write_udp, UdpReader, MAX_DATAGRAM_LEN};
+use crate::messages::version::ProtocolVersion;
 use crate::messages::{
-    ExtraImuData, ExtraOptsTypes, FrequentServerToRobot, MotorControlStatus, NetworkStatus,
-    RobotToServerMessage, SensorData, ServerToRobotMessage, Task,
+    ExtraImuData, ExtraOptsTypes, FrequentServerToRobot, LocalizationDebug, MotorControlStatus,
+    NetworkStatus, RobotToServerMessage, SensorData, ServerToRobotMessage, Task,
 };
 use crate::names::RobotName;
 use crate::util::utilization::UtilizationMonitor;
@@ -18,6 +22,7 @@ use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::signal::Signal;
 use embassy_sync::watch::{Receiver, Sender};
 use embedded_io_async::{Read, Write};
+use futures::future::pending;
 use futures::{select_biased, FutureExt};
 use heapless::Vec;
 
@@ -27,12 +32,34 @@ pub struct NetworkScanInfo {
     pub is_5g: bool,
 }
 
+/// An IPv4 address and port
+pub type Endpoint = ([u8; 4], u16);
+
+/// A bound UDP socket, see [`RobotNetworkBehavior::udp_bind`]
+pub trait RobotUdpSocket {
+    type Error: Debug;
+
+    /// Send one datagram
+    async fn send_to(&mut self, buf: &[u8], to: Endpoint) -> Result<(), Self::Error>;
+
+    /// Wait for one datagram, returning its length and where it came from
+    async fn recv_from(&mut self, buf: &mut [u8]) -> Result<(usize, Endpoint), Self::Error>;
+}
+
 /// Functionality that robots with networking must support
 pub trait RobotNetworkBehavior {
     type Error: Debug;
     type Socket<'a>: Read + Write
     where
         Self: 'a;
+    type UdpSocket<'a>: RobotUdpSocket
+    where
+        Self: 'a;
+    /// Memory that a [`RobotNetworkBehavior::UdpSocket`] needs for as long as it is open
+    type UdpBuffers: Default;
+
+    /// Where [`RobotBeacon`]s are sent while waiting for the server to connect
+    const BEACON_ADDRESS: [u8; 4] = [255, 255, 255, 255];
 
     /// Get the device's mac address
     async fn mac_address(&mut self) -> [u8; 6];
@@ -69,6 +96,16 @@ pub trait RobotNetworkBehavior {
     /// Dispose of the current socket
     async fn tcp_close<'a>(&mut self, socket: &mut Self::Socket<'a>);
 
+    /// Open a UDP socket on the given port, for messages where low latency matters more than
+    /// reliability; see [`crate::messages::robot_udp`]
+    async fn udp_bind<'a>(
+        &mut self,
+        port: u16,
+        buffers: &'a mut Self::UdpBuffers,
+    ) -> Result<Self::UdpSocket<'a>, Self::Error>
+    where
+        Self: 'a;
+
     async fn prepare_firmware_update(&mut self);
 
     /// See https://docs.embassy.dev/embassy-boot/git/default/struct.FirmwareUpdater.html#method.write_firmware
@@ -95,6 +132,26 @@ struct ExpectedFirmwarePart {
     len: usize,
 }
 
+/// What the robot knows about the current connection to the server, forgotten when the server
+/// reconnects
+#[derive(Default)]
+struct ConnectionState {
+    /// Where the server sends datagrams from; latency critical messages go there once known
+    server_udp: Option<Endpoint>,
+    /// The last path progress sent to the server
+    reported_path_progress: Option<(u32, u32)>,
+    expected_firmware_part: Option<ExpectedFirmwarePart>,
+    /// While a firmware update is in progress, telemetry and debug messages are not sent
+    updating_firmware: bool,
+}
+
+impl ConnectionState {
+    /// Whether the message may be sent to the server right now
+    fn allows(&self, message: &RobotToServerMessage) -> bool {
+        !self.updating_firmware || message.category().allowed_during_update()
+    }
+}
+
 struct NetworkData<'a, R: RobotBehavior + 'a> {
     name: RobotName,
     network: R::Network,
@@ -102,17 +159,24 @@ struct NetworkData<'a, R: RobotBehavior + 'a> {
 
     data: &'a SharedRobotData<R>,
     config: FrequentServerToRobot,
+    /// Keeps `config.target_path` up to date with the windows sent by the server
+    path_follower: PathFollower,
     config_sender: Sender<'a, CriticalSectionRawMutex, FrequentServerToRobot, 2>,
     network_status_sender: Sender<'a, CriticalSectionRawMutex, (NetworkStatus, Option<[u8; 4]>), 2>,
     sensors_receiver: Receiver<'a, CriticalSectionRawMutex, SensorData, 2>,
     motors_receiver: Receiver<'a, CriticalSectionRawMutex, MotorControlStatus, 2>,
 
-    expected_firmware_part: Option<ExpectedFirmwarePart>,
+    connection: ConnectionState,
 
     utilization_monitor: UtilizationMonitor<50, R::Instant>,
 
     socket_failed: bool,
     serialization_buf: [u8; 1024],
+
+    udp: Option<<R::Network as RobotNetworkBehavior>::UdpSocket<'a>>,
+    udp_seq: u32,
+    udp_reader: UdpReader,
+    udp_buf: [u8; MAX_DATAGRAM_LEN],
 }
 
 impl<R: RobotBehavior> NetworkData<'_, R> {
@@ -139,6 +203,24 @@ impl<R: RobotBehavior> NetworkData<'_, R> {
         socket: &mut <R::Network as RobotNetworkBehavior>::Socket<'_>,
         message: RobotToServerMessage,
     ) {
+        if !self.connection.allows(&message) {
+            return;
+        }
+        if message.is_latency_critical() {
+            if let (Some(udp), Some(server)) = (&mut self.udp, self.connection.server_udp) {
+                if let Ok(len) = write_udp(&mut self.udp_seq, &message, &mut self.serialization_buf)
+                {
+                    if udp
+                        .send_to(&self.serialization_buf[..len], server)
+                        .await
+                        .is_ok()
+                    {
+                        return;
+                    }
+                }
+                // fall back to tcp
+            }
+        }
         self.write_tcp(socket, BytesOrT::T(message)).await;
     }
 
@@ -181,19 +263,23 @@ impl<R: RobotBehavior> NetworkData<'_, R> {
         let msg = match &msg.msg {
             BytesOrT::T(t) => t.clone(),
             BytesOrT::Bytes(b) => {
-                if let Some(ExpectedFirmwarePart { offset, len }) = self.expected_firmware_part {
+                if let Some(ExpectedFirmwarePart { offset, len }) =
+                    self.connection.expected_firmware_part
+                {
                     if b.len() == len && self.network.write_firmware(offset, b).await.is_ok() {
                         self.send(s, RobotToServerMessage::ConfirmFirmwarePart { offset, len })
                             .await;
-                        self.expected_firmware_part = None;
+                        self.connection.expected_firmware_part = None;
                     }
                 }
                 return;
             }
         };
         match msg {
-            ServerToRobotMessage::Ping => {
-                self.send(s, RobotToServerMessage::Pong).await;
+            ServerToRobotMessage::Ping(seq) => {
+                let robot_time = self.data.created_at.elapsed();
+                self.send(s, RobotToServerMessage::Pong { seq, robot_time })
+                    .await;
                 self.data.utilization[Task::Wifi as usize]
                     .store(self.utilization_monitor.utilization(), Ordering::Relaxed);
                 let util = [
@@ -203,7 +289,10 @@ impl<R: RobotBehavior> NetworkData<'_, R> {
                 ];
                 self.send(s, RobotToServerMessage::Utilization(util)).await;
             }
-            ServerToRobotMessage::FrequentRobotItems(msg) => {
+            ServerToRobotMessage::RobotConfig(config) => {
+                let hash = config.config_hash();
+                self.config.apply_config(config);
+                let msg = &self.config;
                 self.data
                     .enable_imu
                     .store(msg.enable_imu, Ordering::Relaxed);
@@ -225,11 +314,19 @@ impl<R: RobotBehavior> NetworkData<'_, R> {
                 self.data
                     .display_loop_interval
                     .store(msg.display_loop_interval, Ordering::Relaxed);
-                self.config = msg.clone();
-                self.config_sender.send(msg);
+                self.config_sender.send(self.config.clone());
+                self.send(s, RobotToServerMessage::ConfigApplied(hash))
+                    .await;
+            }
+            ServerToRobotMessage::RobotControl(control) => {
+                let location = self.data.sensors.try_get().and_then(|s| s.location);
+                self.path_follower
+                    .receive(control.target_path.clone(), location);
+                self.config.apply_control(control);
+                self.update_target_path(s).await;
             }
             ServerToRobotMessage::FirmwareWritePart { offset, len } => {
-                self.expected_firmware_part = Some(ExpectedFirmwarePart { offset, len });
+                self.connection.expected_firmware_part = Some(ExpectedFirmwarePart { offset, len });
             }
             ServerToRobotMessage::CalculateFirmwareHash(len) => {
                 let mut buf = Default::default();
@@ -247,11 +344,13 @@ impl<R: RobotBehavior> NetworkData<'_, R> {
                     .await;
             }
             ServerToRobotMessage::MarkFirmwareBooted => {
+                self.connection.updating_firmware = false;
                 self.network.mark_firmware_booted().await;
                 self.send(s, RobotToServerMessage::MarkedFirmwareBooted)
                     .await;
             }
             ServerToRobotMessage::ReadyToStartUpdate => {
+                self.connection.updating_firmware = true;
                 self.network.prepare_firmware_update().await;
                 info!("{} is ready for an update", self.name);
                 self.send(s, RobotToServerMessage::ReadyToStartUpdate).await;
@@ -262,7 +361,9 @@ impl<R: RobotBehavior> NetworkData<'_, R> {
                 self.network.reboot().await;
                 unreachable!("o7")
             }
-            ServerToRobotMessage::CancelFirmwareUpdate => {}
+            ServerToRobotMessage::CancelFirmwareUpdate => {
+                self.connection.updating_firmware = false;
+            }
             #[allow(deprecated)]
             ServerToRobotMessage::ExtraOpts(opts) => {
                 opts.store_into(&self.data.extra_opts);
@@ -275,16 +376,40 @@ impl<R: RobotBehavior> NetworkData<'_, R> {
         }
     }
 
+    /// Give the motors task the points that remain ahead of the robot, and tell the server if
+    /// the robot has made progress
+    async fn update_target_path(
+        &mut self,
+        s: &mut <R::Network as RobotNetworkBehavior>::Socket<'_>,
+    ) {
+        self.config.target_path = self.path_follower.points().clone();
+        self.config_sender.send(self.config.clone());
+        let progress = self.path_follower.progress();
+        if let Some((path_id, index)) = progress {
+            if progress != self.connection.reported_path_progress {
+                self.connection.reported_path_progress = progress;
+                self.send(s, RobotToServerMessage::PathProgress { path_id, index })
+                    .await;
+            }
+        }
+    }
+
     async fn handle_until_broken(
         &mut self,
         s: &mut <R::Network as RobotNetworkBehavior>::Socket<'_>,
     ) {
         let mut logs_buffer = [0; 512];
         let mut stateful_tcp_reader = StatefulTcpReader::new();
+        let mut reported_frame_stats = stateful_tcp_reader.stats();
         let mut socket_ok_time = R::Instant::default();
 
         info!("{} client connected", self.name);
 
+        // the server will send a datagram first if it wants to use udp, may have missed path
+        // progress made while disconnected, and restarts any firmware update it didn't finish
+        self.connection = ConnectionState::default();
+        self.udp_reader.clear();
+
         self.send(s, RobotToServerMessage::Name(self.name)).await;
         if self.socket_failed {
             error!("{} failed to send name", self.name);
@@ -293,6 +418,9 @@ impl<R: RobotBehavior> NetworkData<'_, R> {
 
         info!("{} sent name", self.name);
 
+        self.send(s, RobotToServerMessage::Version(ProtocolVersion::CURRENT))
+            .await;
+
         loop {
             if self.socket_failed && socket_ok_time.elapsed().as_millis() >= 1_000 {
                 error!("{} dropping socket due to extended downtime", self.name);
@@ -302,12 +430,12 @@ impl<R: RobotBehavior> NetworkData<'_, R> {
                 socket_ok_time = R::Instant::default();
             }
 
-            // emit logs if we can find any
-            while let Ok(count) = self.data.defmt_logs.try_read(&mut logs_buffer) {
-                if count == 0 {
-                    break;
+            // emit logs if we can find any; they are debug output, so they wait during updates
+            while !self.connection.updating_firmware {
+                match self.data.defmt_logs.try_read(&mut logs_buffer) {
+                    Ok(count) if count > 0 => self.send_bytes(s, &logs_buffer[..count]).await,
+                    _ => break,
                 }
-                self.send_bytes(s, &logs_buffer[..count]).await;
             }
 
             self.utilization_monitor.stop();
@@ -315,8 +443,12 @@ impl<R: RobotBehavior> NetworkData<'_, R> {
                 &mut self.sensors_receiver,
                 &mut self.motors_receiver,
                 &self.data.sig_extra_imu_data,
+                &self.data.sig_localization_debug,
                 s,
                 &mut stateful_tcp_reader,
+                self.udp.as_mut(),
+                &mut self.udp_buf,
+                &mut self.udp_reader,
             )
             .await;
             self.utilization_monitor.start();
@@ -328,7 +460,22 @@ impl<R: RobotBehavior> NetworkData<'_, R> {
                     // error!("Socket failed with error: {:?}", e);
                     break;
                 }
+                NetworkEvent::ServerToRobotUdp(seq, msg, from) => {
+                    self.connection.server_udp = Some(from);
+                    let msg = TcpMessage {
+                        seq,
+                        msg: BytesOrT::T(msg),
+                    };
+                    self.handle_server_message(s, &msg).await
+                }
                 NetworkEvent::SensorData(data) => {
+                    if let Some(location) = data.location {
+                        let progress = self.path_follower.progress();
+                        self.path_follower.reached(location);
+                        if self.path_follower.progress() != progress {
+                            self.update_target_path(s).await;
+                        }
+                    }
                     self.send(s, RobotToServerMessage::Sensors(data)).await
                 }
                 NetworkEvent::MotorData(data) => {
@@ -344,6 +491,17 @@ impl<R: RobotBehavior> NetworkData<'_, R> {
                 NetworkEvent::ExtraImuData(data) => {
                     self.send(s, RobotToServerMessage::ExtraImuData(data)).await
                 }
+                NetworkEvent::LocalizationDebug(data) => {
+                    self.send(s, RobotToServerMessage::LocalizationDebug(data))
+                        .await
+                }
+            }
+
+            let frame_stats = stateful_tcp_reader.stats();
+            if frame_stats.has_new_errors(&reported_frame_stats) {
+                reported_frame_stats = frame_stats;
+                self.send(s, RobotToServerMessage::FrameStats(frame_stats))
+                    .await;
             }
         }
     }
@@ -356,11 +514,21 @@ pub async fn network_task<R: RobotBehavior>(data: &SharedRobotData<R>, mut netwo
         .expect("Unrecognized mac address");
     info!("{} initialized", name);
 
+    let mut udp_buffers = Default::default();
+    let udp = match network.udp_bind(name.port(), &mut udp_buffers).await {
+        Ok(udp) => Some(udp),
+        Err(_) => {
+            error!("{} failed to bind udp socket, only tcp will be used", name);
+            None
+        }
+    };
+
     let mut net = NetworkData {
         name,
         network,
         data,
         config: FrequentServerToRobot::new(name),
+        path_follower: PathFollower::default(),
         seq: 0,
 
         config_sender: data.config.sender(),
@@ -368,12 +536,17 @@ pub async fn network_task<R: RobotBehavior>(data: &SharedRobotData<R>, mut netwo
 
         sensors_receiver: data.sensors.receiver().unwrap(),
         motors_receiver: data.motor_control.receiver().unwrap(),
-        expected_firmware_part: None,
+        connection: ConnectionState::default(),
 
         utilization_monitor: UtilizationMonitor::new(0.0, 0.0),
 
         socket_failed: false,
         serialization_buf: [0; 1024],
+
+        udp,
+        udp_seq: 0,
+        udp_reader: UdpReader::new(),
+        udp_buf: [0; MAX_DATAGRAM_LEN],
     };
 
     net.utilization_monitor.start();
@@ -381,14 +554,35 @@ pub async fn network_task<R: RobotBehavior>(data: &SharedRobotData<R>, mut netwo
     let mut tx_buffer = [0; 5192];
     let mut rx_buffer = [0; 5192];
 
+    let mac = net.network.mac_address().await;
+
     loop {
         net.connect_wifi().await;
 
-        match net
-            .network
-            .tcp_accept(name.port(), &mut rx_buffer, &mut tx_buffer)
-            .await
-        {
+        let beacon = RobotBeacon {
+            name,
+            mac,
+            version: ProtocolVersion::CURRENT,
+            ip: net.network.wifi_is_connected().await.unwrap_or([0; 4]),
+            port: name.port(),
+        };
+        // the futures are dropped before the connection is handled
+        let accepted = {
+            let accept = pin!(net
+                .network
+                .tcp_accept(name.port(), &mut rx_buffer, &mut tx_buffer));
+            let announce = pin!(announce_forever::<R::Network, R::Instant>(
+                &beacon,
+                net.udp.as_mut(),
+                &mut net.serialization_buf,
+            ));
+            select_biased! {
+                accepted = accept.fuse() => accepted,
+                _ = announce.fuse() => unreachable!("announcing never stops"),
+            }
+        };
+
+        match accepted {
             Ok(mut socket) => net.handle_until_broken(&mut socket).await,
             Err(_) => {
                 info!("{} failed to accept socket", name);
@@ -397,30 +591,100 @@ pub async fn network_task<R: RobotBehavior>(data: &SharedRobotData<R>, mut netwo
     }
 }
 
+/// Broadcast `beacon` every [`BEACON_INTERVAL`] so that the server can find this robot
+async fn announce_forever<N: RobotNetworkBehavior, I: CrossPlatformInstant>(
+    beacon: &RobotBeacon,
+    udp: Option<&mut N::UdpSocket<'_>>,
+    buf: &mut [u8],
+) {
+    let Some(udp) = udp else {
+        return pending().await;
+    };
+    let Ok(len) = write_beacon(beacon, buf) else {
+        error!("{} failed to encode beacon", beacon.name);
+        return pending().await;
+    };
+    loop {
+        // the server may not be listening yet
+        let _ = udp
+            .send_to(&buf[..len], (N::BEACON_ADDRESS, DISCOVERY_PORT))
+            .await;
+        I::sleep(Duration::from_millis(BEACON_INTERVAL)).await;
+    }
+}
+
 enum NetworkEvent<'reader> {
     TimedOut,
     ServerToRobot(Result<TcpMessage<'reader, ServerToRobotMessage>, TcpError>),
+    ServerToRobotUdp(u32, ServerToRobotMessage, Endpoint),
     SensorData(SensorData),
     MotorData(MotorControlStatus),
     ExtraImuData(ExtraImuData),
+    LocalizationDebug(LocalizationDebug),
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn next_event<'reader, R: RobotNetworkBehavior, I: CrossPlatformInstant>(
     sensors: &mut Receiver<'_, CriticalSectionRawMutex, SensorData, 2>,
     motors: &mut Receiver<'_, CriticalSectionRawMutex, MotorControlStatus, 2>,
     imu: &Signal<CriticalSectionRawMutex, ExtraImuData>,
+    localization: &Signal<CriticalSectionRawMutex, LocalizationDebug>,
     socket: &mut R::Socket<'_>,
     stateful_tcp_reader: &'reader mut StatefulTcpReader,
+    udp: Option<&mut R::UdpSocket<'_>>,
+    udp_buf: &mut [u8; MAX_DATAGRAM_LEN],
+    udp_reader: &mut UdpReader,
 ) -> NetworkEvent<'reader> {
     let f1 = pin!(stateful_tcp_reader.read_socket(socket));
     let f2 = pin!(sensors.changed());
     let f3 = pin!(motors.changed());
     let f4 = pin!(imu.wait());
+    let f5 = pin!(async {
+        let Some(udp) = udp else {
+            return pending().await;
+        };
+        loop {
+            // stale and corrupted datagrams are dropped
+            if let Ok((len, from)) = udp.recv_from(udp_buf).await {
+                if let Ok((seq, msg)) = udp_reader.read(&udp_buf[..len]) {
+                    return (seq, msg, from);
+                }
+            }
+        }
+    });
+    let f6 = pin!(localization.wait());
     select_biased! {
+        (seq, msg, from) = f5.fuse() => NetworkEvent::ServerToRobotUdp(seq, msg, from),
         msg = f1.fuse() => NetworkEvent::ServerToRobot(msg),
         data = f2.fuse() => NetworkEvent::SensorData(data),
         data = f3.fuse() => NetworkEvent::MotorData(data),
         data = f4.fuse() => NetworkEvent::ExtraImuData(data),
+        data = f6.fuse() => NetworkEvent::LocalizationDebug(data),
         _ = I::sleep(Duration::from_millis(1000 / 30)).fuse() => NetworkEvent::TimedOut,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reconnect_after_aborted_update() {
+        let telemetry = RobotToServerMessage::Utilization([0.0; 3]);
+        let ota = RobotToServerMessage::ReadyToStartUpdate;
+
+        let mut connection = ConnectionState::default();
+        assert!(connection.allows(&telemetry));
+
+        // the server starts an update, then disconnects before finishing or cancelling it
+        connection.updating_firmware = true;
+        connection.expected_firmware_part = Some(ExpectedFirmwarePart { offset: 0, len: 10 });
+        assert!(!connection.allows(&telemetry));
+        assert!(connection.allows(&ota));
+
+        // handle_until_broken starts every connection like this
+        connection = ConnectionState::default();
+        assert!(connection.allows(&telemetry));
+        assert!(connection.expected_firmware_part.is_none());
+    }
+}
@@ -1,11 +1,14 @@
-use crate::constants::INCHES_PER_GU;
+use crate::constants::{INCHES_PER_GU, MAX_ROBOT_PATH_LENGTH};
 use crate::driving::data::SharedRobotData;
 use crate::driving::RobotBehavior;
+use crate::grid::standard_grid::StandardGrid;
 use crate::localization::corridor_calculated_position::CorridorCalculatedPosition;
 use crate::localization::cv_adjust;
 use crate::localization::region_localization;
+use crate::localization::{get_localization_debug, LocalizationGrid};
 use crate::messages::common::LocalizationAlgorithmSource;
-use crate::messages::{RobotButton, SensorData, Task, MAX_SENSOR_ERR_LEN};
+use crate::messages::{FrequentServerToRobot, RobotButton, SensorData, Task, MAX_SENSOR_ERR_LEN};
+use crate::robot_definition::RobotDefinition;
 use crate::robot_display::DisplayManager;
 use crate::util::utilization::UtilizationMonitor;
 use crate::util::CrossPlatformInstant;
@@ -44,7 +47,9 @@ pub async fn peripherals_task<R: RobotBehavior>(
         angle: Err("unknown".try_into().unwrap()),
         distances: array_init(|_| Err("unknown".try_into().unwrap())),
         location: None,
+        shadow_locations: None,
         battery: Err("unknown".try_into().unwrap()),
+        timestamp: Duration::ZERO,
     };
 
     let sensors_sender = data.sensors.sender();
@@ -61,6 +66,10 @@ pub async fn peripherals_task<R: RobotBehavior>(
     let mut dead_reckoning_time = R::Instant::default();
 
     let mut ccp = None;
+    // corridor calculated position's state while it runs in shadow mode
+    let mut shadow_ccp = None;
+    // only recomputed when the server sends a different grid
+    let mut grid = LocalizationGrid::from(StandardGrid::Pacman);
 
     loop {
         // used to control the sleep between loop iterations
@@ -147,70 +156,79 @@ pub async fn peripherals_task<R: RobotBehavior>(
 
         if something_changed {
             let config = config.get().await;
-            sensors.location = match config.localization_algorithm {
-                LocalizationAlgorithmSource::RegionLocalization => {
-                    ccp = None;
-                    region_localization::estimate_location_2(
-                        config.grid,
-                        config.cv_location,
-                        &sensors.distances,
-                        &data.robot_definition,
-                        config.follow_target_path,
-                    )
-                }
-                LocalizationAlgorithmSource::CVAdjust => {
-                    ccp = None;
-                    cv_adjust::estimate_location(
-                        config.grid,
-                        config.cv_location,
-                        &sensors.distances,
-                        &data.robot_definition,
-                        config.cv_error,
-                    )
-                }
-                LocalizationAlgorithmSource::CorridorCalculatedPosition => {
-                    let mut rccp = match ccp {
-                        Some(ccp) => ccp,
-                        None => {
-                            let initial_loc = config
-                                .cv_location
-                                .map(|loc| loc.cast())
-                                .or_else(|| sensors.location)
-                                .unwrap_or_else(|| {
-                                    // println!("!! No previous location; CCP defaulting to (20, 15)");
-                                    Point2::new(20.0, 15.0)
-                                });
-                            CorridorCalculatedPosition::new(initial_loc, &config.grid)
-                        }
+            let config_grid = config.get_grid();
+            if *grid.grid() != config_grid {
+                grid = LocalizationGrid::new(config_grid);
+                ccp = None;
+                shadow_ccp = None;
+            }
+            if config.localization_algorithm
+                != LocalizationAlgorithmSource::CorridorCalculatedPosition
+            {
+                ccp = None;
+            }
+            let location = run_localization(
+                config.localization_algorithm,
+                &mut ccp,
+                &grid,
+                &config,
+                sensors.location,
+                &sensors.distances,
+                &data.robot_definition,
+            )
+            .or_else(|| match config.localization_algorithm {
+                // corridor calculated position lost track of the robot
+                LocalizationAlgorithmSource::CorridorCalculatedPosition => run_localization(
+                    LocalizationAlgorithmSource::RegionLocalization,
+                    &mut ccp,
+                    &grid,
+                    &config,
+                    sensors.location,
+                    &sensors.distances,
+                    &data.robot_definition,
+                ),
+                _ => None,
+            });
+            // in shadow mode, the other algorithms run too, but only report their estimates
+            if config.localization_algorithm
+                == LocalizationAlgorithmSource::CorridorCalculatedPosition
+            {
+                shadow_ccp = None;
+            }
+            sensors.shadow_locations = if config.enable_localization_shadow {
+                let mut estimates = sensors.shadow_locations.unwrap_or_default();
+                for algorithm in LocalizationAlgorithmSource::get_all() {
+                    estimates[algorithm as usize] = if algorithm == config.localization_algorithm {
+                        location
+                    } else {
+                        run_localization(
+                            algorithm,
+                            &mut shadow_ccp,
+                            &grid,
+                            &config,
+                            estimates[algorithm as usize].or(sensors.location),
+                            &sensors.distances,
+                            &data.robot_definition,
+                        )
                     };
-
-                    // if let Some(next_point) = config.target_path.get(0).copied() {
-                    rccp.set_next_point(&config.target_path);
-
-                    match rccp.estimate_location(
-                        config.grid,
-                        config.cv_location,
-                        &sensors.distances,
-                        &data.robot_definition,
-                    ) {
-                        Some(loc) => {
-                            ccp = Some(rccp);
-                            Some(loc)
-                        }
-                        None => {
-                            ccp = None;
-                            // eprintln!("CCP state corrupted - efaulting to region localization!");
-                            region_localization::estimate_location_2(
-                                config.grid,
-                                config.cv_location,
-                                &sensors.distances,
-                                &data.robot_definition,
-                                config.follow_target_path,
-                            )
-                        }
-                    }
                 }
+                Some(estimates)
+            } else {
+                shadow_ccp = None;
+                None
             };
+            sensors.location = location;
+            if config.enable_localization_debug {
+                data.sig_localization_debug.signal(get_localization_debug(
+                    &grid,
+                    config.cv_location,
+                    sensors.location,
+                    &sensors.distances,
+                    &data.robot_definition,
+                    ccp.as_ref(),
+                ));
+            }
+            sensors.timestamp = data.created_at.elapsed();
             sensors_sender.send(sensors.clone());
         }
 
@@ -243,6 +261,62 @@ pub async fn peripherals_task<R: RobotBehavior>(
     }
 }
 
+/// Run one localization algorithm
+///
+/// [`CorridorCalculatedPosition`] keeps its state in `ccp`, starting from `previous_location` or
+/// the cv location, and gives up (returning `None`) when it loses track of the robot
+fn run_localization(
+    algorithm: LocalizationAlgorithmSource,
+    ccp: &mut Option<CorridorCalculatedPosition>,
+    grid: &LocalizationGrid,
+    config: &FrequentServerToRobot,
+    previous_location: Option<Point2<f32>>,
+    distances: &[Result<Option<f32>, heapless::String<MAX_SENSOR_ERR_LEN>>; 4],
+    robot: &RobotDefinition<3>,
+) -> Option<Point2<f32>> {
+    match algorithm {
+        LocalizationAlgorithmSource::RegionLocalization => {
+            region_localization::estimate_location_2(
+                grid,
+                config.cv_location,
+                distances,
+                robot,
+                config.follow_target_path,
+            )
+        }
+        LocalizationAlgorithmSource::CVAdjust => cv_adjust::estimate_location(
+            grid,
+            config.cv_location,
+            distances,
+            robot,
+            config.cv_error,
+        ),
+        LocalizationAlgorithmSource::CorridorCalculatedPosition => {
+            let mut rccp = match ccp.take() {
+                Some(ccp) => ccp,
+                None => {
+                    let initial_loc = config
+                        .cv_location
+                        .map(|loc| loc.cast())
+                        .or(previous_location)
+                        .unwrap_or(Point2::new(20.0, 15.0));
+                    CorridorCalculatedPosition::new(initial_loc, grid)
+                }
+            };
+
+            let cells: heapless::Vec<_, MAX_ROBOT_PATH_LENGTH> =
+                config.target_path.iter().map(|w| w.cell()).collect();
+            rccp.set_next_point(&cells);
+
+            let location = rccp.estimate_location(grid, config.cv_location, distances, robot);
+            if location.is_some() {
+                *ccp = Some(rccp);
+            }
+            location
+        }
+    }
+}
+
 /// Converts Results from sensors into heapless::String Results to be sent to the GUI
 fn handle_err<T, E: Debug>(r: Result<T, E>) -> Result<T, heapless::String<MAX_SENSOR_ERR_LEN>> {
     let mut fmt_buf = [0; 100];
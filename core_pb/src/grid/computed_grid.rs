@@ -4,6 +4,7 @@ use nalgebra::Point2;
 use ordered_float::OrderedFloat;
 use pacbot_rs::location::Direction;
 use pacbot_rs::location::Direction::*;
+use pacbot_rs::variables::PACMAN_SPAWN_LOC;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
 
@@ -299,6 +300,47 @@ impl TryFrom<Grid> for ComputedGrid {
 }
 
 impl ComputedGrid {
+    /// Compute a [`Grid`] that was drawn by hand, ex. in the gui's grid editor
+    ///
+    /// On top of the usual validation, Pac-Man's spawn cell must be walkable, and every walkable
+    /// cell must be reachable from it
+    pub fn try_from_custom(grid: Grid) -> Result<Self, String> {
+        let s = Self::try_from(grid)?;
+        let spawn = Point2::new(PACMAN_SPAWN_LOC.row, PACMAN_SPAWN_LOC.col);
+        if s.wall_at(&spawn) {
+            return Err(format!(
+                "Pac-Man spawn cell ({}, {}) is a wall",
+                spawn.x, spawn.y
+            ));
+        }
+        if let Some(p) = s
+            .walkable_nodes
+            .iter()
+            .find(|p| s.dist(&spawn, p).is_none())
+        {
+            return Err(format!(
+                "({}, {}) can't be reached from Pac-Man spawn cell",
+                p.x, p.y
+            ));
+        }
+        Ok(s)
+    }
+
+    /// Get the part of the grid that should actually show on the gui; all of it for custom grids
+    pub fn get_soft_boundaries(&self) -> (Point2<f32>, Point2<f32>) {
+        match self.standard_grid {
+            Some(standard_grid) => standard_grid.get_soft_boundaries(),
+            None => (Point2::new(-1.0, -1.0), Point2::new(32.0, 32.0)),
+        }
+    }
+
+    /// Get the rectangles (in grid coordinates) that should be repainted with the background color
+    pub fn get_outside_soft_boundaries(&self) -> Vec<(Point2<f32>, Point2<f32>)> {
+        self.standard_grid
+            .map(|standard_grid| standard_grid.get_outside_soft_boundaries())
+            .unwrap_or_default()
+    }
+
     /// Returns the underlying [`Grid`].
     ///
     /// # Examples
@@ -501,6 +543,16 @@ impl ComputedGrid {
         None
     }
 
+    /// Returns the shortest path, if one exists, that visits each of the waypoints in order
+    /// The path includes the first and last waypoints
+    pub fn bfs_path_through(&self, waypoints: &[Point2<i8>]) -> Option<Vec<Point2<i8>>> {
+        let mut path = vec![*waypoints.first()?];
+        for pair in waypoints.windows(2) {
+            path.extend(self.bfs_path(pair[0], pair[1])?.into_iter().skip(1));
+        }
+        Some(path)
+    }
+
     fn compute_open_grid() -> Self {
         let mut walkable_nodes = vec![];
         let mut valid_actions = vec![];
@@ -762,6 +814,47 @@ mod tests {
         assert_eq!(computed_grid.dist(&points[2], &points[2]), Some(0));
     }
 
+    #[test]
+    fn bfs_path_through() {
+        let grid = StandardGrid::Pacman.compute_grid();
+        let waypoints = [Point2::new(1, 1), Point2::new(1, 4), Point2::new(3, 1)];
+        let path = grid.bfs_path_through(&waypoints).unwrap();
+        assert_eq!(path.first(), Some(&waypoints[0]));
+        assert_eq!(path.last(), Some(&waypoints[2]));
+        assert!(path.contains(&waypoints[1]));
+        assert!(path
+            .windows(2)
+            .all(|p| (p[0].x - p[1].x).abs() + (p[0].y - p[1].y).abs() == 1));
+        assert_eq!(grid.bfs_path_through(&[]), None);
+        assert_eq!(
+            grid.bfs_path_through(&[Point2::new(1, 1), Point2::new(0, 0)]),
+            None
+        );
+    }
+
+    #[test]
+    fn custom_grid() {
+        assert!(ComputedGrid::try_from_custom(GRID_PACMAN).is_ok());
+
+        // a corridor through pacman's spawn cell, and one that can't be reached from it
+        let mut grid = [[true; GRID_SIZE]; GRID_SIZE];
+        for col in 1..=20 {
+            grid[PACMAN_SPAWN_LOC.row as usize][col] = false;
+        }
+        for col in 1..=5 {
+            grid[1][col] = false;
+        }
+        assert_eq!(
+            ComputedGrid::try_from_custom(grid).unwrap_err(),
+            "(1, 1) can't be reached from Pac-Man spawn cell"
+        );
+
+        grid[PACMAN_SPAWN_LOC.row as usize] = [true; GRID_SIZE];
+        assert!(ComputedGrid::try_from_custom(grid)
+            .unwrap_err()
+            .starts_with("Pac-Man spawn cell"));
+    }
+
     #[test]
     fn grid_at() {
         let grid = StandardGrid::Blank.compute_grid();
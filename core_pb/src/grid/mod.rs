@@ -9,3 +9,49 @@ pub const GRID_SIZE: usize = 32;
 ///
 /// The grid is indexed by `grid[row][col]`
 pub type Grid = [[bool; GRID_SIZE]; GRID_SIZE];
+
+/// Write a [`Grid`] in the format of the files in `src/grid/grids`: one line per row, with `W`
+/// for walls and `-` for walkable cells
+#[cfg(feature = "std")]
+pub fn grid_to_text(grid: &Grid) -> String {
+    grid.iter()
+        .map(|row| {
+            row.iter()
+                .map(|wall| if *wall { 'W' } else { '-' })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Read a [`Grid`] written in the format of the files in `src/grid/grids`
+#[cfg(feature = "std")]
+pub fn grid_from_text(text: &str) -> Result<Grid, String> {
+    let rows: Vec<&str> = text.trim_ascii().lines().map(|x| x.trim_ascii()).collect();
+    if rows.len() != GRID_SIZE {
+        return Err(format!("Expected {GRID_SIZE} rows, found {}", rows.len()));
+    }
+    let mut grid = [[true; GRID_SIZE]; GRID_SIZE];
+    for (row, line) in rows.into_iter().enumerate() {
+        if line.chars().count() != GRID_SIZE {
+            return Err(format!("Row {row} is not {GRID_SIZE} cells wide"));
+        }
+        for (col, c) in line.chars().enumerate() {
+            grid[row][col] = c == 'W';
+        }
+    }
+    Ok(grid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::standard_grid::GRID_PACMAN;
+
+    #[test]
+    fn grid_text_round_trip() {
+        let text = grid_to_text(&GRID_PACMAN);
+        assert_eq!(grid_from_text(&text), Ok(GRID_PACMAN));
+        assert!(grid_from_text(&text[GRID_SIZE + 1..]).is_err());
+    }
+}
@@ -4,8 +4,7 @@ use nalgebra::{Point2, Vector2};
 use micromath::F32Ext;
 
 use crate::{
-    grid::standard_grid::StandardGrid, messages::MAX_SENSOR_ERR_LEN,
-    robot_definition::RobotDefinition,
+    localization::LocalizationGrid, messages::MAX_SENSOR_ERR_LEN, robot_definition::RobotDefinition,
 };
 
 /// current_estimate must lie in or between previous_target and next_target.
@@ -31,7 +30,7 @@ struct RegionInfo {
 impl CorridorCalculatedPosition {
     fn compute_region_info(
         &self,
-        grid: &StandardGrid,
+        grid: &LocalizationGrid,
         partial: PartialRegion,
         rays: &(Vector2<i8>, Vector2<i8>, Vector2<i8>, Vector2<i8>),
     ) -> RegionInfo {
@@ -196,7 +195,7 @@ impl CorridorCalculatedPosition {
         rays: &(Vector2<i8>, Vector2<i8>, Vector2<i8>, Vector2<i8>),
         lateral_sensors: (Option<f32>, Option<f32>),
         transverse_sensors: (Option<f32>, Option<f32>),
-        grid: &StandardGrid,
+        grid: &LocalizationGrid,
         robot_definition: &RobotDefinition<3>,
         cv_location: Option<Point2<i8>>,
     ) -> Point2<f32> {
@@ -365,7 +364,7 @@ impl CorridorCalculatedPosition {
 
     pub fn estimate_location(
         &mut self,
-        grid: StandardGrid,
+        grid: &LocalizationGrid,
         cv_location: Option<Point2<i8>>,
         distance_sensors: &[Result<Option<f32>, heapless::String<MAX_SENSOR_ERR_LEN>>; 4],
         robot_definition: &RobotDefinition<3>,
@@ -446,7 +445,7 @@ impl CorridorCalculatedPosition {
             }
         };
 
-        let info = self.compute_region_info(&grid, partial, &rays);
+        let info = self.compute_region_info(grid, partial, &rays);
 
         // info!("info: {:?}", info);
         // info!("current_estimate: {}", self.current_estimate);
@@ -461,7 +460,7 @@ impl CorridorCalculatedPosition {
             &rays,
             sensor_values.0,
             sensor_values.1,
-            &grid,
+            grid,
             robot_definition,
             cv_location,
         );
@@ -505,10 +504,18 @@ impl CorridorCalculatedPosition {
         }
     }
 
+    /// The cells the robot is moving between, previous first
+    pub fn targets(&self) -> (Point2<i8>, Point2<i8>) {
+        (self.previous_target, self.next_target)
+    }
+
     /// An assumption needs to be made here about the initial starting position of the robot.
     /// For now, the assumption will be that it is at (20, 15) and going up
     /// TODO: make better assumptions about start
-    pub fn new(initial_estimate: Point2<f32>, grid: &StandardGrid) -> CorridorCalculatedPosition {
+    pub fn new(
+        initial_estimate: Point2<f32>,
+        grid: &LocalizationGrid,
+    ) -> CorridorCalculatedPosition {
         let rounded_estimate = Point2::new(
             initial_estimate.x.round() as i8,
             initial_estimate.y.round() as i8,
@@ -535,10 +542,11 @@ impl CorridorCalculatedPosition {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::grid::standard_grid::StandardGrid;
 
     #[test]
     pub fn test_initial_region_info1() {
-        let grid = StandardGrid::Pacman;
+        let grid = LocalizationGrid::from(StandardGrid::Pacman);
         let ccp = CorridorCalculatedPosition::new(Point2::new(20.0, 15.0), &grid);
 
         let rays = (
@@ -561,7 +569,7 @@ mod test {
 
     #[test]
     pub fn test_region_info2() {
-        let grid = StandardGrid::Pacman;
+        let grid = LocalizationGrid::from(StandardGrid::Pacman);
         let ccp = CorridorCalculatedPosition::new(Point2::new(1.0, 1.0), &grid);
 
         let rays = (
@@ -571,7 +579,7 @@ mod test {
             Vector2::new(0, -1), // down
         );
 
-        let info = ccp.compute_region_info(&StandardGrid::Pacman, PartialRegion::Closer, &rays);
+        let info = ccp.compute_region_info(&grid, PartialRegion::Closer, &rays);
 
         assert_eq!(
             info,
@@ -584,13 +592,13 @@ mod test {
 
     #[test]
     pub fn test_location_estimate_1_1_no_sensors() {
-        let grid = StandardGrid::Pacman;
+        let grid = LocalizationGrid::from(StandardGrid::Pacman);
         let mut ccp = CorridorCalculatedPosition::new(Point2::new(1.0, 1.0), &grid);
         let robot = RobotDefinition::new(crate::names::RobotName::Stella);
         let cv_location = Some(Point2::new(1, 1));
 
         let estimated_location = ccp.estimate_location(
-            grid,
+            &grid,
             cv_location,
             &[Ok(None), Ok(None), Ok(None), Ok(None)],
             &robot,
@@ -605,7 +613,7 @@ mod test {
 
     #[test]
     pub fn test_invariant_violation_repro() {
-        let grid = StandardGrid::Open;
+        let grid = LocalizationGrid::from(StandardGrid::Open);
         // At (20, 15), moving up to (20, 16)
         let mut ccp = CorridorCalculatedPosition {
             previous_target: Point2::new(20, 15),
@@ -627,7 +635,9 @@ mod test {
             Ok(Some(sensor_right)),
         ];
 
-        let result = ccp.estimate_location(grid, None, &sensors, &robot).unwrap();
+        let result = ccp
+            .estimate_location(&grid, None, &sensors, &robot)
+            .unwrap();
 
         // info!("Moving UP result: {:?}", result);
         assert!(
@@ -644,7 +654,7 @@ mod test {
 
     #[test]
     pub fn test_moving_down_repro() {
-        let grid = StandardGrid::Open;
+        let grid = LocalizationGrid::from(StandardGrid::Open);
         // At (20, 15), moving down to (20, 14)
         let mut ccp = CorridorCalculatedPosition {
             previous_target: Point2::new(20, 15),
@@ -660,7 +670,9 @@ mod test {
             Ok(Some(10.0)),
         ];
 
-        let result = ccp.estimate_location(grid, None, &sensors, &robot).unwrap();
+        let result = ccp
+            .estimate_location(&grid, None, &sensors, &robot)
+            .unwrap();
         // info!("Moving DOWN result: {:?}", result);
         assert!(
             result.x > 19.0 && result.x < 21.0,
@@ -1,5 +1,5 @@
-use crate::grid::standard_grid::StandardGrid;
 use crate::grid::{Grid, GRID_SIZE};
+use crate::localization::LocalizationGrid;
 use crate::messages::MAX_SENSOR_ERR_LEN;
 use crate::robot_definition::RobotDefinition;
 #[cfg(feature = "micromath")]
@@ -18,7 +18,7 @@ const VECTORS: [Vector2<f32>; 4] = [
 
 /// CVAdjust
 pub fn estimate_location(
-    grid: StandardGrid,
+    grid: &LocalizationGrid,
     cv_location: Option<Point2<i8>>,
     distance_sensors: &[Result<Option<f32>, heapless::String<MAX_SENSOR_ERR_LEN>>; 4],
     robot_definition: &RobotDefinition<3>,
@@ -28,8 +28,8 @@ pub fn estimate_location(
     let cv_location_int = cv_location?;
     let cv_location_f32 = cv_location_int.map(|x| x as f32);
 
-    let grid = grid.get_grid();
-    let mut poses = get_estimated_poses(&grid, cv_location_int, distance_sensors, radius);
+    let grid = grid.grid();
+    let mut poses = get_estimated_poses(grid, cv_location_int, distance_sensors, radius);
 
     if [poses[0], poses[2]].iter().all(|x| {
         x.map(|pos| get_dist(pos, cv_location_f32) > cv_error)
@@ -42,7 +42,7 @@ pub fn estimate_location(
             } else {
                 new_location.y -= 1;
             }
-            poses = get_estimated_poses(&grid, new_location, distance_sensors, radius);
+            poses = get_estimated_poses(grid, new_location, distance_sensors, radius);
         }
     }
 
@@ -57,7 +57,7 @@ pub fn estimate_location(
             } else {
                 new_location.x -= 1;
             }
-            poses = get_estimated_poses(&grid, new_location, distance_sensors, radius);
+            poses = get_estimated_poses(grid, new_location, distance_sensors, radius);
         }
     }
 
@@ -1,3 +1,115 @@
+use crate::constants::{GU_PER_M, MAX_SENSOR_DISTANCE};
+use crate::grid::standard_grid::StandardGrid;
+use crate::grid::{Grid, GRID_SIZE};
+use crate::localization::corridor_calculated_position::CorridorCalculatedPosition;
+use crate::localization::cv_adjust::get_sim_ray_cast;
+use crate::localization::region_localization::{
+    get_grid_regions, get_region_candidates, sensor_dists, Region,
+};
+use crate::messages::{LocalizationDebug, MAX_SENSOR_ERR_LEN};
+use crate::robot_definition::RobotDefinition;
+#[cfg(feature = "micromath")]
+use micromath::F32Ext;
+use nalgebra::{Point2, Vector2};
+
 pub mod corridor_calculated_position;
 pub mod cv_adjust;
 pub mod region_localization;
+
+/// The most [`Region`]s a [`LocalizationGrid`] can hold; the official grid has 143
+pub const MAX_GRID_REGIONS: usize = 512;
+
+/// A [`Grid`] that localization algorithms can use, along with its [`Region`]s
+///
+/// Finding the regions takes a while, so robots should keep one of these around until the grid
+/// changes
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocalizationGrid {
+    grid: Grid,
+    regions: heapless::Vec<Region, MAX_GRID_REGIONS>,
+}
+
+impl From<StandardGrid> for LocalizationGrid {
+    fn from(value: StandardGrid) -> Self {
+        Self::new(value.get_grid())
+    }
+}
+
+impl LocalizationGrid {
+    pub fn new(grid: Grid) -> Self {
+        Self {
+            grid,
+            regions: get_grid_regions(&grid),
+        }
+    }
+
+    /// The underlying [`Grid`]
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    /// The [`Region`]s of the grid, see [`get_grid_regions`]
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Whether the given cell is a wall; cells outside the grid are walls
+    pub fn wall_at(&self, p: &Point2<i8>) -> bool {
+        if p.x >= GRID_SIZE as i8 || p.y >= GRID_SIZE as i8 || p.x < 0 || p.y < 0 {
+            true
+        } else {
+            self.grid[p.x as usize][p.y as usize]
+        }
+    }
+
+    pub fn ray_cast_distance(&self, dir: &Vector2<i8>, loc: Point2<i8>) -> i8 {
+        let mut dist: i8 = 0;
+        let mut p = loc;
+
+        while !self.wall_at(&p) {
+            p += *dir;
+            dist += 1;
+        }
+
+        dist
+    }
+
+    pub fn ray_cast(&self, dir: &Vector2<i8>, loc: Point2<i8>) -> bool {
+        (self.ray_cast_distance(dir, loc) as f32) <= MAX_SENSOR_DISTANCE
+    }
+}
+
+/// Collect what localization was thinking, for [`LocalizationDebug`]
+///
+/// The expected distance sensor readings are taken from the cell of the estimated location, or
+/// from the cv location if there is no estimate or it is inside a wall
+pub fn get_localization_debug(
+    grid: &LocalizationGrid,
+    cv_location: Option<Point2<i8>>,
+    estimated_location: Option<Point2<f32>>,
+    distance_sensors: &[Result<Option<f32>, heapless::String<MAX_SENSOR_ERR_LEN>>; 4],
+    robot: &RobotDefinition<3>,
+    ccp: Option<&CorridorCalculatedPosition>,
+) -> LocalizationDebug {
+    let dists = sensor_dists(distance_sensors);
+    let ray_origin = estimated_location
+        .map(|p| p.map(|x| x.round() as i8))
+        .filter(|p| !grid.wall_at(p))
+        .or(cv_location);
+
+    LocalizationDebug {
+        candidates: get_region_candidates(
+            grid,
+            cv_location,
+            dists,
+            robot.radius,
+            robot.sensor_distance * GU_PER_M,
+        ),
+        corridor_targets: ccp.map(|ccp| ccp.targets()),
+        ray_origin,
+        expected_rays: ray_origin
+            .map(|p| get_sim_ray_cast(p, grid.grid(), robot.radius))
+            .unwrap_or_default(),
+        measured_rays: dists.map(|d| d.ok().flatten()),
+    }
+}
@@ -1,8 +1,8 @@
 use crate::constants::{GU_PER_M, MAX_SENSOR_DISTANCE};
-use crate::grid::standard_grid::{get_grid_regions, StandardGrid};
-use crate::grid::Grid;
+use crate::grid::{Grid, GRID_SIZE};
 use crate::localization::cv_adjust::get_sim_ray_cast;
-use crate::messages::MAX_SENSOR_ERR_LEN;
+use crate::localization::{LocalizationGrid, MAX_GRID_REGIONS};
+use crate::messages::{LocalizationCandidate, MAX_LOCALIZATION_CANDIDATES, MAX_SENSOR_ERR_LEN};
 use crate::robot_definition::RobotDefinition;
 #[cfg(feature = "micromath")]
 use micromath::F32Ext;
@@ -27,7 +27,7 @@ pub struct Region {
 }
 
 fn get_region_score(
-    grid: StandardGrid,
+    grid: &LocalizationGrid,
     dists: [Result<Option<f32>, ()>; 4],
     robot_radius: f32,
     max_toi: f32,
@@ -120,7 +120,7 @@ fn get_region_score(
         (rounded_loc.0 + 1, rounded_loc.1 + 1),
     ] {
         // strongly discourage estimating our location inside a wall
-        if get_at(grid.get_grid(), Vector2::new(grid_loc.0, grid_loc.1))
+        if get_at(grid.grid(), Vector2::new(grid_loc.0, grid_loc.1))
             && (grid_loc.0 as f32 - p.x).powi(2) + (grid_loc.1 as f32 - p.y).powi(2)
                 < robot_radius.powi(2) * 0.9
         {
@@ -131,7 +131,7 @@ fn get_region_score(
     Some((-score, Point2::new(p.x, p.y)))
 }
 
-fn get_at(grid: Grid, at: Vector2<i8>) -> bool {
+fn get_at(grid: &Grid, at: Vector2<i8>) -> bool {
     if at.x < 0 || at.y < 0 || at.x as usize >= grid.len() || at.y as usize >= grid[0].len() {
         true
     } else {
@@ -141,24 +141,16 @@ fn get_at(grid: Grid, at: Vector2<i8>) -> bool {
 
 /// RegionLocalization
 pub fn estimate_location_2(
-    grid: StandardGrid,
+    grid: &LocalizationGrid,
     cv_location: Option<Point2<i8>>,
     distance_sensors: &[Result<Option<f32>, heapless::String<MAX_SENSOR_ERR_LEN>>; 4],
     robot: &RobotDefinition<3>,
     do_cv_adjust: bool,
 ) -> Option<Point2<f32>> {
-    let mut dists = [Err(()); 4];
-    for (i, d) in distance_sensors.iter().enumerate() {
-        dists[i] = match d {
-            Err(_) => Err(()),
-            Ok(None) => Ok(None),
-            Ok(Some(d)) => Ok(Some(*d)),
-        };
-    }
     estimate_location(
         grid,
         cv_location,
-        dists,
+        sensor_dists(distance_sensors),
         robot.radius,
         robot.sensor_distance * GU_PER_M,
         do_cv_adjust,
@@ -166,6 +158,21 @@ pub fn estimate_location_2(
     .or(cv_location.map(|p| p.map(|a| a as f32)))
 }
 
+/// Drop the error messages from distance sensor readings
+pub fn sensor_dists(
+    distance_sensors: &[Result<Option<f32>, heapless::String<MAX_SENSOR_ERR_LEN>>; 4],
+) -> [Result<Option<f32>, ()>; 4] {
+    let mut dists = [Err(()); 4];
+    for (i, d) in distance_sensors.iter().enumerate() {
+        dists[i] = match d {
+            Err(_) => Err(()),
+            Ok(None) => Ok(None),
+            Ok(Some(d)) => Ok(Some(*d)),
+        };
+    }
+    dists
+}
+
 pub fn is_close_to_box(
     low_xy: Point2<i8>,
     high_xy: Point2<i8>,
@@ -182,9 +189,35 @@ pub fn is_close_to_box(
         && p.y <= high_xy.y + tolerance
 }
 
+/// Score every region near the cv location, if there is one, with the location it suggests;
+/// locations further from the cv location score lower
+fn scored_regions<'a>(
+    grid: &'a LocalizationGrid,
+    cv_location: Option<Point2<i8>>,
+    distance_sensors: [Result<Option<f32>, ()>; 4],
+    robot_radius: f32,
+    max_toi: f32,
+) -> impl Iterator<Item = (&'a Region, f32, Point2<f32>)> + 'a {
+    grid.regions()
+        .iter()
+        .filter(move |region| {
+            cv_location.is_none_or(|cv| is_close_to_box(region.low_xy, region.high_xy, cv, 1))
+        })
+        .filter_map(move |region| {
+            let (mut score, location) =
+                get_region_score(grid, distance_sensors, robot_radius, max_toi, region)?;
+            if let Some(cv_location) = cv_location {
+                score = score
+                    - (location.x - cv_location.x as f32).abs()
+                    - (location.y - cv_location.y as f32).abs();
+            }
+            Some((region, score, location))
+        })
+}
+
 #[allow(unused)]
 pub fn estimate_location(
-    grid: StandardGrid,
+    grid: &LocalizationGrid,
     mut cv_location: Option<Point2<i8>>,
     distance_sensors: [Result<Option<f32>, ()>; 4],
     robot_radius: f32,
@@ -194,86 +227,58 @@ pub fn estimate_location(
     let mut best_p: Option<Point2<f32>> = None;
     let mut best_score = f32::MIN;
 
-    if cv_location
-        .map(|cv| {
-            cv.x < 0
-                || cv.x >= 32
-                || cv.y < 0
-                || cv.y >= 32
-                || grid.get_grid()[cv.x.max(0) as usize][cv.y.max(0) as usize]
-        })
-        .unwrap_or(false)
-    {
-        cv_location = None;
-    }
+    cv_location = cv_location.filter(|cv| !grid.wall_at(cv));
     // cv_location = Some(cv_location.unwrap_or(Point2::new(1, 1)));
 
-    for region in get_grid_regions(grid) {
-        if let Some(cv_location) = cv_location {
-            if !is_close_to_box(region.low_xy, region.high_xy, cv_location, 1) {
-                continue;
-            }
-        }
-
-        if let Some((mut score, mut pos)) =
-            get_region_score(grid, distance_sensors, robot_radius, max_toi, region)
-        {
-            if let Some(cv_location) = cv_location {
-                score = score
-                    - (pos.x - cv_location.x as f32).abs()
-                    - (pos.y - cv_location.y as f32).abs();
-            }
-            if let (Some(sm_x), Some(cv_loc), true) = (
-                [0, 2]
-                    .into_iter()
-                    .map(|i| (i, distance_sensors[i]))
-                    .flat_map(|(i, x)| x.ok().map(|x| (i, x.unwrap_or(max_toi))))
-                    .min_by_key(|(_, d)| NotNan::new(*d).unwrap()),
-                cv_location,
-                do_cv_adjust,
-            ) {
-                if sm_x.1 > MAX_SENSOR_DISTANCE {
-                    pos.x = cv_loc.x as f32;
-                    // info!(
-                    //     "{pos:?} {} {}",
-                    //     pos.x.max(0.0).round(),
-                    //     pos.y.max(0.0).round()
-                    // );
-                    if grid.get_grid()[pos.x.max(0.0).round() as usize]
-                        [pos.y.max(0.0).round() as usize]
-                    {
-                        pos = cv_loc.map(|x| x as f32);
-                    }
+    for (_, score, mut pos) in
+        scored_regions(grid, cv_location, distance_sensors, robot_radius, max_toi)
+    {
+        if let (Some(sm_x), Some(cv_loc), true) = (
+            [0, 2]
+                .into_iter()
+                .map(|i| (i, distance_sensors[i]))
+                .flat_map(|(i, x)| x.ok().map(|x| (i, x.unwrap_or(max_toi))))
+                .min_by_key(|(_, d)| NotNan::new(*d).unwrap()),
+            cv_location,
+            do_cv_adjust,
+        ) {
+            if sm_x.1 > MAX_SENSOR_DISTANCE {
+                pos.x = cv_loc.x as f32;
+                // info!(
+                //     "{pos:?} {} {}",
+                //     pos.x.max(0.0).round(),
+                //     pos.y.max(0.0).round()
+                // );
+                if grid.grid()[pos.x.max(0.0).round() as usize][pos.y.max(0.0).round() as usize] {
+                    pos = cv_loc.map(|x| x as f32);
                 }
             }
-            if let (Some(sm_y), Some(cv_loc), true) = (
-                [1, 3]
-                    .into_iter()
-                    .map(|i| (i, distance_sensors[i]))
-                    .flat_map(|(i, x)| x.ok().map(|x| (i, x.unwrap_or(max_toi))))
-                    .min_by_key(|(_, d)| NotNan::new(*d).unwrap()),
-                cv_location,
-                do_cv_adjust,
-            ) {
-                if sm_y.1 > MAX_SENSOR_DISTANCE {
-                    pos.y = cv_loc.y as f32;
-                    // info!(
-                    //     "{pos:?} {} {}",
-                    //     pos.x.max(0.0).round(),
-                    //     pos.y.max(0.0).round()
-                    // );
-                    if grid.get_grid()[pos.x.max(0.0).round() as usize]
-                        [pos.y.max(0.0).round() as usize]
-                    {
-                        pos = cv_loc.map(|x| x as f32);
-                    }
+        }
+        if let (Some(sm_y), Some(cv_loc), true) = (
+            [1, 3]
+                .into_iter()
+                .map(|i| (i, distance_sensors[i]))
+                .flat_map(|(i, x)| x.ok().map(|x| (i, x.unwrap_or(max_toi))))
+                .min_by_key(|(_, d)| NotNan::new(*d).unwrap()),
+            cv_location,
+            do_cv_adjust,
+        ) {
+            if sm_y.1 > MAX_SENSOR_DISTANCE {
+                pos.y = cv_loc.y as f32;
+                // info!(
+                //     "{pos:?} {} {}",
+                //     pos.x.max(0.0).round(),
+                //     pos.y.max(0.0).round()
+                // );
+                if grid.grid()[pos.x.max(0.0).round() as usize][pos.y.max(0.0).round() as usize] {
+                    pos = cv_loc.map(|x| x as f32);
                 }
             }
-            // info!("{region:?} {pos:?}");
-            if score > best_score {
-                best_score = score;
-                best_p = Some(pos);
-            }
+        }
+        // info!("{region:?} {pos:?}");
+        if score > best_score {
+            best_score = score;
+            best_p = Some(pos);
         }
     }
     if let Some(best_p) = best_p {
@@ -288,8 +293,7 @@ pub fn estimate_location(
             .min_by_key(|x| NotNan::try_from(x.1).unwrap());
         if let Some((i, val)) = min {
             if val < 1.0 {
-                let raycasts =
-                    get_sim_ray_cast(cv_location.unwrap_or_default(), &grid.get_grid(), 0.0);
+                let raycasts = get_sim_ray_cast(cv_location.unwrap_or_default(), grid.grid(), 0.0);
                 match i {
                     0 => loc.x += raycasts[0] - robot_radius - val,
                     1 => loc.y += raycasts[1] - robot_radius - val,
@@ -304,16 +308,57 @@ pub fn estimate_location(
     // Some(best_p.unwrap_or(cv_location.unwrap_or_default().map(|x| x as f32)))
 }
 
+/// The regions that [`estimate_location`] would choose between, with the location each suggests
+/// and its score, best first
+pub fn get_region_candidates(
+    grid: &LocalizationGrid,
+    cv_location: Option<Point2<i8>>,
+    distance_sensors: [Result<Option<f32>, ()>; 4],
+    robot_radius: f32,
+    max_toi: f32,
+) -> heapless::Vec<LocalizationCandidate, MAX_LOCALIZATION_CANDIDATES> {
+    let mut candidates: heapless::Vec<LocalizationCandidate, MAX_LOCALIZATION_CANDIDATES> =
+        heapless::Vec::new();
+    // like estimate_location, a cv location inside a wall is ignored
+    let cv_location = cv_location.filter(|cv| !grid.wall_at(cv));
+
+    for (region, score, location) in
+        scored_regions(grid, cv_location, distance_sensors, robot_radius, max_toi)
+    {
+        // keep only the best candidates, in order
+        let i = candidates
+            .iter()
+            .position(|c| c.score < score)
+            .unwrap_or(candidates.len());
+        if i < MAX_LOCALIZATION_CANDIDATES {
+            if candidates.is_full() {
+                candidates.pop();
+            }
+            let _ = candidates.insert(
+                i,
+                LocalizationCandidate {
+                    low_xy: region.low_xy,
+                    high_xy: region.high_xy,
+                    location,
+                    score,
+                },
+            );
+        }
+    }
+
+    candidates
+}
+
 #[cfg(feature = "std")]
 pub fn get_possible_regions(
-    grid: StandardGrid,
+    grid: &LocalizationGrid,
     distance_sensors: [Result<Option<f32>, ()>; 4],
     max_toi: f32,
     robot_radius: f32,
 ) -> Vec<(Region, Point2<f32>)> {
     let mut regions = vec![];
 
-    for region in get_grid_regions(grid) {
+    for region in grid.regions() {
         if let Some((score, pos)) =
             get_region_score(grid, distance_sensors, robot_radius, max_toi, region)
         {
@@ -325,3 +370,225 @@ pub fn get_possible_regions(
 
     regions
 }
+
+/// Like [`get_at`], except that cells outside the grid are not walls
+fn wall_in_grid(grid: &Grid, at: Vector2<i8>) -> bool {
+    if at.x < 0 || at.y < 0 || at.x as usize >= grid.len() || at.y as usize >= grid[0].len() {
+        false
+    } else {
+        grid[at.x as usize][at.y as usize]
+    }
+}
+
+fn v_to_p(v: Vector2<i8>) -> Point2<i8> {
+    Point2::new(v.x, v.y)
+}
+
+#[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
+enum PointType {
+    Wall,
+    VerticalBoundary(bool),
+    HorizontalBoundary(bool),
+}
+
+fn is_special(boundary: Option<PointType>) -> bool {
+    match boundary {
+        Some(PointType::Wall) => true,
+        None => false,
+        Some(PointType::HorizontalBoundary(b)) | Some(PointType::VerticalBoundary(b)) => !b,
+    }
+}
+
+const ONE_X: Vector2<i8> = Vector2::new(1, 0);
+const ONE_Y: Vector2<i8> = Vector2::new(0, 1);
+
+fn get_boundary(grid: &Grid, p: Vector2<i8>) -> Option<PointType> {
+    // If the point is a wall, returns None
+    if wall_in_grid(grid, p) {
+        Some(PointType::Wall)
+    }
+    // If the point lies on a vertical region boundary
+    else if (wall_in_grid(grid, p - ONE_Y) && !wall_in_grid(grid, p - ONE_Y - ONE_X))
+        || (wall_in_grid(grid, p + ONE_Y) && !wall_in_grid(grid, p + ONE_Y - ONE_X))
+    {
+        Some(PointType::VerticalBoundary(true))
+    } else if (wall_in_grid(grid, p - ONE_Y) && !wall_in_grid(grid, p - ONE_Y + ONE_X))
+        || (wall_in_grid(grid, p + ONE_Y) && !wall_in_grid(grid, p + ONE_Y + ONE_X))
+    {
+        Some(PointType::HorizontalBoundary(false))
+    }
+    // If the point lies on a horizontal region boundary
+    else if (wall_in_grid(grid, p - ONE_X) && !wall_in_grid(grid, p - ONE_X - ONE_Y))
+        || (wall_in_grid(grid, p + ONE_X) && !wall_in_grid(grid, p + ONE_X - ONE_Y))
+    {
+        Some(PointType::HorizontalBoundary(true))
+    } else if (wall_in_grid(grid, p - ONE_X) && !wall_in_grid(grid, p - ONE_X + ONE_Y))
+        || (wall_in_grid(grid, p + ONE_X) && !wall_in_grid(grid, p + ONE_X + ONE_Y))
+    {
+        Some(PointType::HorizontalBoundary(false))
+    } else {
+        None
+    }
+}
+
+fn build_horizontal_region(grid: &Grid, p: Vector2<i8>) -> Region {
+    let mut end = p + ONE_X;
+    while get_boundary(grid, end).is_none() {
+        end += ONE_X;
+    }
+    Region {
+        low_xy: v_to_p(p - ONE_Y),
+        high_xy: v_to_p(end + ONE_Y),
+
+        dist_low_xy_to_wall: [
+            1 + get_empty_for(grid, p + ONE_X, VECTORS[0]),
+            2,
+            get_empty_for(grid, p, VECTORS[2]),
+            0,
+        ],
+    }
+}
+
+fn build_vertical_region(grid: &Grid, p: Vector2<i8>) -> Region {
+    let mut end = p + ONE_Y;
+    while get_boundary(grid, end).is_none() {
+        end += ONE_Y;
+    }
+    Region {
+        low_xy: v_to_p(p - ONE_X),
+        high_xy: v_to_p(end + ONE_X),
+
+        dist_low_xy_to_wall: [
+            2,
+            1 + get_empty_for(grid, p + ONE_Y, VECTORS[1]),
+            0,
+            get_empty_for(grid, p, VECTORS[3]),
+        ],
+    }
+}
+
+fn get_empty_for(grid: &Grid, mut at: Vector2<i8>, dir: Vector2<i8>) -> i8 {
+    let mut count = 0;
+    // cells outside the grid count as walls here, in case a custom grid isn't surrounded by walls
+    while !get_at(grid, at) {
+        at += dir;
+        count += 1;
+    }
+    count
+}
+
+/// Looks at the given point and returns up to 1 region
+///
+/// - If the point is a wall, returns None
+/// - If the point lies entirely at the bottom left (-x,-y) of a region bounded below (-y) and to
+///     the left (-x) by walls, returns the corresponding region
+/// - If the point lies on a vertical region boundary, where the n-wide 2-tall region
+///     lies to the right (+x), returns the corresponding region
+/// - If the point lies on a horizontal region boundary, where the 2-wide n-tall region
+///     lies above (+y), returns the corresponding region
+pub fn get_region_for_unique_p(grid: &Grid, at: Point2<i8>) -> Option<Region> {
+    let p = Vector2::new(at.x, at.y);
+    match get_boundary(grid, p) {
+        Some(PointType::Wall)
+        | Some(PointType::VerticalBoundary(false))
+        | Some(PointType::HorizontalBoundary(false)) => None,
+        None => {
+            if is_special(get_boundary(grid, p - ONE_X))
+                && is_special(get_boundary(grid, p - ONE_Y))
+            {
+                if get_boundary(grid, p + ONE_X).is_none() {
+                    Some(build_horizontal_region(grid, p - ONE_X))
+                } else if get_boundary(grid, p + ONE_Y).is_none() {
+                    Some(build_vertical_region(grid, p - ONE_Y))
+                } else {
+                    // 2x2 region
+                    Some(Region {
+                        low_xy: v_to_p(p - ONE_Y - ONE_X),
+                        high_xy: v_to_p(p + ONE_Y + ONE_X),
+
+                        dist_low_xy_to_wall: [
+                            1 + get_empty_for(grid, p, VECTORS[0]),
+                            1 + get_empty_for(grid, p, VECTORS[1]),
+                            get_empty_for(grid, p - ONE_X, VECTORS[2]),
+                            get_empty_for(grid, p - ONE_Y, VECTORS[3]),
+                        ],
+                    })
+                }
+            } else {
+                None
+            }
+        }
+        Some(PointType::VerticalBoundary(true)) => Some(build_horizontal_region(grid, p)),
+        Some(PointType::HorizontalBoundary(true)) => Some(build_vertical_region(grid, p)),
+    }
+}
+
+/// Find every [`Region`] of the grid, in order of their unique points
+///
+/// If there are more than [`MAX_GRID_REGIONS`], the rest are left out
+pub fn get_grid_regions(grid: &Grid) -> heapless::Vec<Region, MAX_GRID_REGIONS> {
+    (0..GRID_SIZE as i8)
+        .flat_map(|x| (0..GRID_SIZE as i8).map(move |y| Point2::new(x, y)))
+        .flat_map(|p| get_region_for_unique_p(grid, p))
+        .take(MAX_GRID_REGIONS)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::standard_grid::StandardGrid;
+
+    #[test]
+    fn standard_grid_regions() {
+        assert_eq!(
+            get_grid_regions(&StandardGrid::Pacman.get_grid()).len(),
+            143
+        );
+        for grid in StandardGrid::get_all() {
+            let regions = get_grid_regions(&grid.get_grid());
+            assert!(regions.len() < MAX_GRID_REGIONS, "{grid:?}");
+        }
+    }
+
+    #[test]
+    fn standard_grid_regions_match_snapshot() {
+        let snapshot = include_str!("standard_grid_regions.txt");
+        for grid in StandardGrid::get_all() {
+            let name = format!("{grid:?}").to_lowercase();
+            let expected: Vec<Region> = snapshot
+                .lines()
+                .filter(|line| !line.starts_with('#'))
+                .map(|line| line.split_whitespace().collect::<Vec<_>>())
+                .filter(|words| words[0] == name)
+                .map(|words| {
+                    let n: Vec<i8> = words[1..].iter().map(|w| w.parse().unwrap()).collect();
+                    Region {
+                        low_xy: Point2::new(n[0], n[1]),
+                        high_xy: Point2::new(n[2], n[3]),
+                        dist_low_xy_to_wall: [n[4], n[5], n[6], n[7]],
+                    }
+                })
+                .collect();
+            assert!(!expected.is_empty(), "{grid:?}");
+            assert_eq!(
+                get_grid_regions(&grid.get_grid()).as_slice(),
+                expected.as_slice(),
+                "{grid:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn region_candidates() {
+        let grid = LocalizationGrid::from(StandardGrid::Pacman);
+        let cv_location = Point2::new(1, 1);
+        let radius = 0.3;
+        let dists = get_sim_ray_cast(cv_location, grid.grid(), radius).map(|d| Ok(Some(d)));
+
+        let candidates = get_region_candidates(&grid, Some(cv_location), dists, radius, 10.0);
+        assert!(!candidates.is_empty());
+        assert!(candidates.windows(2).all(|w| w[0].score >= w[1].score));
+        assert!((candidates[0].location - Point2::new(1.0, 1.0)).norm() < 1.0);
+    }
+}
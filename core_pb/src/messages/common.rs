@@ -7,3 +7,18 @@ pub enum LocalizationAlgorithmSource {
     CVAdjust,
     CorridorCalculatedPosition,
 }
+
+/// The number of [`LocalizationAlgorithmSource`]s
+pub const NUM_LOCALIZATION_ALGORITHMS: usize = 3;
+
+impl LocalizationAlgorithmSource {
+    /// Get a list of all localization algorithms, in the order used by
+    /// [`SensorData::shadow_locations`](crate::messages::SensorData::shadow_locations)
+    pub fn get_all() -> [Self; NUM_LOCALIZATION_ALGORITHMS] {
+        [
+            Self::RegionLocalization,
+            Self::CVAdjust,
+            Self::CorridorCalculatedPosition,
+        ]
+    }
+}
@@ -0,0 +1,85 @@
+use crate::messages::robot_tcp::crc32;
+use crate::messages::robot_udp::{UdpError, MAX_DATAGRAM_LEN};
+use crate::messages::version::ProtocolVersion;
+use crate::names::RobotName;
+use serde::{Deserialize, Serialize};
+
+/// Marks the start of every beacon, so that other broadcasts on the port are ignored
+pub const BEACON_MAGIC: [u8; 4] = *b"PBb1";
+/// The number of bytes after the payload of each beacon; a CRC-32 of everything before it
+const BEACON_TRAILER_LEN: usize = 4;
+
+/// Broadcast by robots that aren't connected to a server, so that the server can find them
+/// without hardcoded addresses
+///
+/// Beacons are read by servers built from other versions, so fields may only be appended
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RobotBeacon {
+    pub name: RobotName,
+    pub mac: [u8; 6],
+    pub version: ProtocolVersion,
+    /// The address the robot accepts TCP connections and receives UDP on
+    pub ip: [u8; 4],
+    pub port: u16,
+}
+
+/// Encode a beacon into `buf`, returning its length
+pub fn write_beacon(beacon: &RobotBeacon, buf: &mut [u8]) -> Result<usize, UdpError> {
+    let buf_len = buf.len().min(MAX_DATAGRAM_LEN);
+    if buf_len < BEACON_MAGIC.len() + BEACON_TRAILER_LEN {
+        return Err(UdpError::InsufficientSpace);
+    }
+    let msg_size = BEACON_MAGIC.len()
+        + bincode::serde::encode_into_slice(
+            beacon,
+            &mut buf[BEACON_MAGIC.len()..buf_len - BEACON_TRAILER_LEN],
+            bincode::config::standard(),
+        )
+        .map_err(UdpError::EncodeError)?;
+
+    buf[0..4].copy_from_slice(&BEACON_MAGIC);
+    let crc = crc32(&buf[..msg_size]);
+    buf[msg_size..msg_size + BEACON_TRAILER_LEN].copy_from_slice(&crc.to_be_bytes());
+
+    Ok(msg_size + BEACON_TRAILER_LEN)
+}
+
+/// Decode a beacon; fields appended by newer robots are ignored
+pub fn read_beacon(datagram: &[u8]) -> Result<RobotBeacon, UdpError> {
+    if datagram.len() < BEACON_MAGIC.len() + BEACON_TRAILER_LEN || datagram[0..4] != BEACON_MAGIC {
+        return Err(UdpError::Corrupted);
+    }
+    let payload_end = datagram.len() - BEACON_TRAILER_LEN;
+    let crc = u32::from_be_bytes(datagram[payload_end..].try_into().unwrap());
+    if crc != crc32(&datagram[..payload_end]) {
+        return Err(UdpError::Corrupted);
+    }
+    let (beacon, _) = bincode::serde::decode_from_slice(
+        &datagram[BEACON_MAGIC.len()..payload_end],
+        bincode::config::standard(),
+    )
+    .map_err(UdpError::DecodeError)?;
+    Ok(beacon)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_beacon() {
+        let beacon = RobotBeacon {
+            name: RobotName::Stella,
+            mac: RobotName::Stella.mac_address(),
+            version: ProtocolVersion::CURRENT,
+            ip: [192, 168, 1, 12],
+            port: RobotName::Stella.port(),
+        };
+        let mut buf = [0; 64];
+        let len = write_beacon(&beacon, &mut buf).unwrap();
+        assert_eq!(read_beacon(&buf[..len]).unwrap(), beacon);
+
+        buf[BEACON_MAGIC.len()] ^= 0xFF;
+        assert!(matches!(read_beacon(&buf[..len]), Err(UdpError::Corrupted)));
+    }
+}
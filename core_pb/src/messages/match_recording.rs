@@ -0,0 +1,196 @@
+//! The file format `server_pb` uses to record matches
+//!
+//! `[magic: 4][version: ProtocolVersion][header][record]...`, where the header and each record
+//! are `[len: u32][bincode]`. Records are in time order, so they can be searched by time. A
+//! recording that was cut off, ex. because the server was stopped, is read up to its last
+//! complete record.
+
+use crate::messages::server_status::ServerStatus;
+use crate::messages::settings::PacbotSettings;
+use crate::messages::version::ProtocolVersion;
+use crate::messages::GuiToServerMessage;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Marks the start of every match recording
+pub const MATCH_RECORDING_MAGIC: [u8; 4] = *b"PBm1";
+
+/// Written once at the start of a match recording
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MatchRecordingHeader {
+    /// When recording started, in milliseconds since the unix epoch
+    pub started_at: u64,
+    /// The server's settings when recording started
+    pub settings: PacbotSettings,
+}
+
+/// Something that happened during a match
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
+pub enum MatchEvent {
+    /// The status the server sent to gui clients, at most a few times per second
+    Status(Box<ServerStatus>),
+    /// A command from a gui client
+    FromGui(GuiToServerMessage),
+    /// The server's settings changed
+    Settings(Box<PacbotSettings>),
+    /// A raw frame from the game server
+    GameServerFrame(Vec<u8>),
+}
+
+/// A [`MatchEvent`], and how long after the start of the recording it happened
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MatchRecord {
+    pub time: Duration,
+    pub event: MatchEvent,
+}
+
+#[derive(Debug)]
+pub enum MatchRecordingError {
+    /// The data doesn't start with [`MATCH_RECORDING_MAGIC`]
+    NotARecording,
+    /// The recording was made with different message definitions
+    Incompatible(ProtocolVersion),
+    /// The data ends before the header does
+    MissingHeader,
+    DecodeError(bincode::error::DecodeError),
+}
+
+/// A fully loaded match recording
+#[derive(Clone, Debug)]
+pub struct MatchRecording {
+    pub version: ProtocolVersion,
+    pub header: MatchRecordingHeader,
+    pub records: Vec<MatchRecord>,
+}
+
+fn encode_len_prefixed<T: Serialize>(
+    value: &T,
+    bytes: &mut Vec<u8>,
+) -> Result<(), bincode::error::EncodeError> {
+    let encoded = bincode::serde::encode_to_vec(value, bincode::config::standard())?;
+    bytes.extend_from_slice(&(encoded.len() as u32).to_be_bytes());
+    bytes.extend(encoded);
+    Ok(())
+}
+
+/// Decode one length prefixed value, returning it and the remaining bytes, or None if the data
+/// ends before the value does
+fn decode_len_prefixed<T: DeserializeOwned>(
+    bytes: &[u8],
+) -> Option<Result<(T, &[u8]), bincode::error::DecodeError>> {
+    let len = u32::from_be_bytes(bytes.get(..4)?.try_into().unwrap()) as usize;
+    let data = bytes.get(4..4 + len)?;
+    Some(
+        bincode::serde::decode_from_slice(data, bincode::config::standard())
+            .map(|(value, _)| (value, &bytes[4 + len..])),
+    )
+}
+
+impl MatchRecording {
+    /// The bytes that start a recording
+    pub fn encode_start(
+        header: &MatchRecordingHeader,
+    ) -> Result<Vec<u8>, bincode::error::EncodeError> {
+        let mut bytes = MATCH_RECORDING_MAGIC.to_vec();
+        bytes.extend(bincode::serde::encode_to_vec(
+            ProtocolVersion::CURRENT,
+            bincode::config::standard(),
+        )?);
+        encode_len_prefixed(header, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// The bytes of one record, to be appended to the recording
+    pub fn encode_record(record: &MatchRecord) -> Result<Vec<u8>, bincode::error::EncodeError> {
+        let mut bytes = vec![];
+        encode_len_prefixed(record, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Whether the data looks like a match recording
+    pub fn is_recording(bytes: &[u8]) -> bool {
+        bytes.starts_with(&MATCH_RECORDING_MAGIC)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MatchRecordingError> {
+        if !Self::is_recording(bytes) {
+            return Err(MatchRecordingError::NotARecording);
+        }
+        let bytes = &bytes[MATCH_RECORDING_MAGIC.len()..];
+        let (version, len): (ProtocolVersion, _) =
+            bincode::serde::decode_from_slice(bytes, bincode::config::standard())
+                .map_err(MatchRecordingError::DecodeError)?;
+        if !version.is_compatible() {
+            return Err(MatchRecordingError::Incompatible(version));
+        }
+        let (header, mut bytes) = decode_len_prefixed(&bytes[len..])
+            .ok_or(MatchRecordingError::MissingHeader)?
+            .map_err(MatchRecordingError::DecodeError)?;
+        let mut records = vec![];
+        while let Some(record) = decode_len_prefixed(bytes) {
+            let (record, rest) = record.map_err(MatchRecordingError::DecodeError)?;
+            records.push(record);
+            bytes = rest;
+        }
+        Ok(Self {
+            version,
+            header,
+            records,
+        })
+    }
+
+    /// The index of the first record at or after `time`
+    pub fn seek(&self, time: Duration) -> usize {
+        self.records.partition_point(|r| r.time < time)
+    }
+
+    /// How long after the start of the recording the last record happened
+    pub fn duration(&self) -> Duration {
+        self.records.last().map(|r| r.time).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_match_recording() {
+        let header = MatchRecordingHeader {
+            started_at: 1234,
+            settings: PacbotSettings::default(),
+        };
+        let mut bytes = MatchRecording::encode_start(&header).unwrap();
+        for i in 0..3 {
+            bytes.extend(
+                MatchRecording::encode_record(&MatchRecord {
+                    time: Duration::from_secs(i),
+                    event: MatchEvent::GameServerFrame(vec![i as u8]),
+                })
+                .unwrap(),
+            );
+        }
+        // the server stopped partway through a record
+        let full_len = bytes.len();
+        bytes.extend(
+            MatchRecording::encode_record(&MatchRecord {
+                time: Duration::from_secs(3),
+                event: MatchEvent::Status(Box::default()),
+            })
+            .unwrap(),
+        );
+        bytes.truncate(full_len + 10);
+
+        let recording = MatchRecording::from_bytes(&bytes).unwrap();
+        assert_eq!(recording.header.started_at, 1234);
+        assert_eq!(recording.records.len(), 3);
+        assert_eq!(recording.duration(), Duration::from_secs(2));
+        assert_eq!(recording.seek(Duration::from_millis(1500)), 2);
+        assert!(matches!(
+            MatchRecording::from_bytes(&bytes[1..]),
+            Err(MatchRecordingError::NotARecording)
+        ));
+    }
+}
@@ -4,11 +4,17 @@ use crate::constants::{
     MAX_ROBOT_PATH_LENGTH,
 };
 use crate::grid::standard_grid::StandardGrid;
+use crate::grid::{Grid, GRID_SIZE};
+use crate::messages::common::{LocalizationAlgorithmSource, NUM_LOCALIZATION_ALGORITHMS};
+use crate::messages::path_stream::{PathWindow, Waypoint};
+use crate::messages::robot_tcp::{crc32, FrameStats};
+#[cfg(feature = "std")]
+use crate::messages::scenario::Scenario;
 #[cfg(feature = "std")]
 use crate::messages::server_status::ServerStatus;
-use crate::messages::common::LocalizationAlgorithmSource;
 #[cfg(feature = "std")]
-use crate::messages::settings::PacbotSettings;
+use crate::messages::settings::{NetworkImpairments, PacbotSettings, SimulationGhostSettings};
+use crate::messages::version::ProtocolVersion;
 use crate::names::RobotName;
 #[cfg(feature = "std")]
 use crate::names::NUM_ROBOT_NAMES;
@@ -26,19 +32,29 @@ use portable_atomic::{AtomicBool, AtomicF32, AtomicI32, AtomicI8};
 use serde::{Deserialize, Serialize};
 
 pub mod common;
-pub mod robot_tcp;
+pub mod discovery;
+#[cfg(feature = "std")]
+pub mod match_recording;
 #[cfg(feature = "std")]
 pub mod ota;
+pub mod path_stream;
+pub mod robot_tcp;
+pub mod robot_udp;
+#[cfg(feature = "std")]
+pub mod scenario;
 #[cfg(feature = "std")]
 pub mod server_status;
 #[cfg(feature = "std")]
 pub mod settings;
+pub mod version;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[cfg(feature = "std")]
 #[allow(clippy::large_enum_variant)]
 /// Messages sent from `gui_pb` to `server_pb`
 pub enum GuiToServerMessage {
+    /// The gui's version, sent in reply to [`ServerToGuiMessage::Version`]; MUST remain first
+    Version(ProtocolVersion),
     /// Update server settings
     Settings(PacbotSettings),
     /// Send a message to the game server
@@ -57,8 +73,11 @@ pub enum GuiToServerMessage {
     ConfirmFirmwareUpdate(RobotName),
     /// Clear Over the Air Programming update history for a robot
     ClearFirmwareUpdateHistory(RobotName),
-    /// Set a robot's target location
-    TargetLocation(Point2<i8>),
+    /// Set the pacman robot's target location; it need not be the center of a cell
+    TargetLocation(Point2<f32>),
+    /// Set a robot's target path through the given waypoints, each connected to the next by the
+    /// shortest path; the robot must be the pacman robot, and target paths are turned on
+    TargetPath(RobotName, Vec<Point2<i8>>),
     /// Restart simulation (including rebuild)
     RestartSimulation,
 }
@@ -79,6 +98,8 @@ pub enum VelocityControl {
 #[allow(clippy::large_enum_variant)]
 /// Messages sent from `server_pb` to `gui_pb`
 pub enum ServerToGuiMessage {
+    /// The server's version, sent when a gui connects; MUST remain first
+    Version(ProtocolVersion),
     /// Very frequent; includes all information about the status of the server and robots
     Status(ServerStatus),
     /// Less frequent; includes updated server settings
@@ -89,6 +110,8 @@ pub enum ServerToGuiMessage {
 #[cfg(feature = "std")]
 /// Messages sent from `sim_pb` to `server_pb`
 pub enum SimulationToServerMessage {
+    /// The simulation's version, sent when the server connects; MUST remain first
+    Version(ProtocolVersion),
     /// The positions of the simulated robots, to be shown in the gui
     RobotPositions([Option<(Point2<f32>, Rotation2<f32>)>; NUM_ROBOT_NAMES]),
     /// The display of a simulated robot
@@ -107,25 +130,38 @@ pub enum RobotButton {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[cfg(feature = "std")]
+#[allow(clippy::large_enum_variant)]
 pub enum ServerToSimulationMessage {
     Spawn(RobotName),
     Teleport(RobotName, Point2<i8>),
     Delete(RobotName),
     SetPacman(RobotName),
     SetStandardGrid(StandardGrid),
+    /// Use a grid drawn in the gui instead of a [`StandardGrid`]
+    SetCustomGrid(Grid),
     /// A button press (true) or release (false) for a simulated robot
     RobotButton(RobotName, (RobotButton, bool)),
     RobotJoystick(RobotName, (f32, f32)),
+    /// Latency, loss, bandwidth and disconnects applied to all simulated robot links
+    SetNetworkImpairments(NetworkImpairments),
+    /// Whether ghosts have bodies, and which robots drive as ghosts
+    SetGhosts(SimulationGhostSettings),
+    /// Start or stop writing each robot's true and estimated trajectory to a file
+    RecordTrajectories(bool),
+    /// Replace the grid, robots, game state and simulation settings all at once
+    LoadScenario(Scenario),
 }
 
-/// This is sent regularly and frequently to robots via [`ServerToRobotMessage::FrequentRobotItems`]
+/// Everything the server tells a robot; the robot's tasks receive it as a whole
 ///
-/// Holds information that may change often, or where low latency is critical. Its contents should be passed
-/// along as quickly as possible.
+/// On the network it is split into a [`RobotConfig`], which is only sent when it changes, and
+/// a [`RobotControl`], which is sent regularly and frequently
 #[derive(Clone, Debug, PartialOrd, PartialEq, Serialize, Deserialize)]
 pub struct FrequentServerToRobot {
     /// Which grid is currently in use
     pub grid: StandardGrid,
+    /// A grid drawn in the gui, used instead of `grid` if present
+    pub custom_grid: Option<Grid>,
     /// Overall requested velocity of the robot, ex. using WASD or controller manual input
     pub target_velocity: VelocityControl,
     /// Requested velocity for each individual motor, forwards (+) or backwards (-), for testing
@@ -154,9 +190,17 @@ pub struct FrequentServerToRobot {
     pub cv_location: Option<Point2<i8>>,
     /// What localization strategy the robot should use
     pub localization_algorithm: LocalizationAlgorithmSource,
+    /// Whether the robot should send [`RobotToServerMessage::LocalizationDebug`]
+    pub enable_localization_debug: bool,
+    /// Whether the robot should run every localization algorithm and report their estimates in
+    /// [`SensorData::shadow_locations`]; only `localization_algorithm` is used for driving
+    pub enable_localization_shadow: bool,
     /// The points the robot should try to go to
-    pub target_path: heapless::Vec<Point2<i8>, MAX_ROBOT_PATH_LENGTH>,
-    /// Whether the robot should try to follow the target path (including maintaining heading 0)
+    ///
+    /// Not sent directly; the robot fills it from the [`PathWindow`] in [`RobotControl`]
+    pub target_path: heapless::Vec<Waypoint, MAX_ROBOT_PATH_LENGTH>,
+    /// Whether the robot should try to follow the target path, facing the [`Waypoint::heading`]
+    /// of the next waypoint, or angle 0 when it has none
     pub follow_target_path: bool,
     /// This angle should be considered angle 0
     pub angle_offset: f32,
@@ -181,6 +225,7 @@ impl FrequentServerToRobot {
         let definition = RobotDefinition::new(robot);
         Self {
             grid: StandardGrid::Pacman,
+            custom_grid: None,
             target_velocity: VelocityControl::None,
             motors_override: [None; 3],
             pwm_override: [[None; 2]; 3],
@@ -190,6 +235,8 @@ impl FrequentServerToRobot {
             pid: definition.default_pid,
             cv_location: Some(Point2::new(1, 1)),
             localization_algorithm: LocalizationAlgorithmSource::RegionLocalization,
+            enable_localization_debug: false,
+            enable_localization_shadow: false,
             target_path: heapless::Vec::new(),
             follow_target_path: false,
             angle_offset: 0.0,
@@ -208,9 +255,205 @@ impl FrequentServerToRobot {
             display_loop_interval: 500,
         }
     }
+
+    /// The walls of the grid currently in use
+    pub fn get_grid(&self) -> Grid {
+        self.custom_grid.unwrap_or_else(|| self.grid.get_grid())
+    }
+
+    /// The parts that rarely change; see [`RobotConfig`]
+    pub fn robot_config(&self) -> RobotConfig {
+        RobotConfig {
+            grid: self.grid,
+            custom_grid: self.custom_grid,
+            motor_config: self.motor_config,
+            encoder_config: self.encoder_config,
+            dist_sensor_config: self.dist_sensor_config,
+            pid: self.pid,
+            localization_algorithm: self.localization_algorithm,
+            enable_localization_debug: self.enable_localization_debug,
+            enable_localization_shadow: self.enable_localization_shadow,
+            angle_offset: self.angle_offset,
+            lookahead_dist: self.lookahead_dist,
+            robot_speed: self.robot_speed,
+            turn_multiplier: self.turn_multiplier,
+            snapping_dist: self.snapping_dist,
+            cv_error: self.cv_error,
+            enable_imu: self.enable_imu,
+            enable_extra_imu_data: self.enable_extra_imu_data,
+            enable_dists: self.enable_dists,
+            enable_battery_monitor: self.enable_battery_monitor,
+            enable_display: self.enable_display,
+            enable_gamepad: self.enable_gamepad,
+            display_loop_interval: self.display_loop_interval,
+        }
+    }
+
+    /// The parts that change often; see [`RobotControl`]
+    pub fn robot_control(&self, target_path: PathWindow) -> RobotControl {
+        RobotControl {
+            target_velocity: self.target_velocity,
+            motors_override: self.motors_override,
+            pwm_override: self.pwm_override,
+            cv_location: self.cv_location,
+            target_path,
+            follow_target_path: self.follow_target_path,
+        }
+    }
+
+    pub fn apply_config(&mut self, config: RobotConfig) {
+        self.grid = config.grid;
+        self.custom_grid = config.custom_grid;
+        self.motor_config = config.motor_config;
+        self.encoder_config = config.encoder_config;
+        self.dist_sensor_config = config.dist_sensor_config;
+        self.pid = config.pid;
+        self.localization_algorithm = config.localization_algorithm;
+        self.enable_localization_debug = config.enable_localization_debug;
+        self.enable_localization_shadow = config.enable_localization_shadow;
+        self.angle_offset = config.angle_offset;
+        self.lookahead_dist = config.lookahead_dist;
+        self.robot_speed = config.robot_speed;
+        self.turn_multiplier = config.turn_multiplier;
+        self.snapping_dist = config.snapping_dist;
+        self.cv_error = config.cv_error;
+        self.enable_imu = config.enable_imu;
+        self.enable_extra_imu_data = config.enable_extra_imu_data;
+        self.enable_dists = config.enable_dists;
+        self.enable_battery_monitor = config.enable_battery_monitor;
+        self.enable_display = config.enable_display;
+        self.enable_gamepad = config.enable_gamepad;
+        self.display_loop_interval = config.display_loop_interval;
+    }
+
+    /// Apply everything except the target path, which should be passed through a
+    /// [`path_stream::PathFollower`]
+    pub fn apply_control(&mut self, control: RobotControl) {
+        self.target_velocity = control.target_velocity;
+        self.motors_override = control.motors_override;
+        self.pwm_override = control.pwm_override;
+        self.cv_location = control.cv_location;
+        self.follow_target_path = control.follow_target_path;
+    }
+}
+
+/// The parts of [`FrequentServerToRobot`] that rarely change, sent via
+/// [`ServerToRobotMessage::RobotConfig`] until the robot acknowledges them with
+/// [`RobotToServerMessage::ConfigApplied`]
+#[derive(Clone, Debug, PartialOrd, PartialEq, Serialize, Deserialize)]
+pub struct RobotConfig {
+    pub grid: StandardGrid,
+    pub custom_grid: Option<Grid>,
+    pub motor_config: [[usize; 2]; 3],
+    pub encoder_config: [(usize, bool); 3],
+    pub dist_sensor_config: [usize; 4],
+    pub pid: [f32; 3],
+    pub localization_algorithm: LocalizationAlgorithmSource,
+    pub enable_localization_debug: bool,
+    pub enable_localization_shadow: bool,
+    pub angle_offset: f32,
+    pub lookahead_dist: f32,
+    pub robot_speed: f32,
+    pub turn_multiplier: f32,
+    pub snapping_dist: f32,
+    pub cv_error: f32,
+
+    pub enable_imu: bool,
+    pub enable_extra_imu_data: bool,
+    pub enable_dists: bool,
+    pub enable_battery_monitor: bool,
+    pub enable_display: bool,
+    pub enable_gamepad: bool,
+    pub display_loop_interval: u64,
+}
+
+impl RobotConfig {
+    /// Identifies this configuration, so the server can tell whether a robot has applied it
+    pub fn config_hash(&self) -> u32 {
+        let mut buf = [0; 256 + GRID_SIZE * GRID_SIZE];
+        match bincode::serde::encode_into_slice(self, &mut buf, bincode::config::standard()) {
+            Ok(len) => crc32(&buf[..len]),
+            Err(_) => 0,
+        }
+    }
 }
 
-/// Firmware related items MUST remain first, or OTA programming will break
+/// The parts of [`FrequentServerToRobot`] that change often or where low latency is critical,
+/// sent regularly via [`ServerToRobotMessage::RobotControl`]; its contents should be passed
+/// along as quickly as possible
+#[derive(Clone, Debug, PartialOrd, PartialEq, Serialize, Deserialize)]
+pub struct RobotControl {
+    pub target_velocity: VelocityControl,
+    pub motors_override: [Option<f32>; 3],
+    pub pwm_override: [[Option<u16>; 2]; 3],
+    pub cv_location: Option<Point2<i8>>,
+    pub target_path: PathWindow,
+    pub follow_target_path: bool,
+}
+
+/// How important a message between the server and a robot is, used to decide which messages
+/// are sent when the connection is restricted
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum MessageCategory {
+    /// Needed to identify and reprogram a robot; never filtered
+    OtaCritical,
+    /// Changes how the robot behaves
+    Control,
+    /// Regular reports on the state of the robot or the connection
+    Telemetry,
+    /// Only useful when tuning or investigating problems
+    Debug,
+}
+
+impl MessageCategory {
+    /// Whether messages in this category are sent and received in safe mode
+    pub fn allowed_in_safe_mode(self) -> bool {
+        self == MessageCategory::OtaCritical
+    }
+
+    /// Whether messages in this category are sent while a firmware update is in progress
+    pub fn allowed_during_update(self) -> bool {
+        matches!(
+            self,
+            MessageCategory::OtaCritical | MessageCategory::Control
+        )
+    }
+}
+
+/// The most candidate regions in a [`LocalizationDebug`]
+pub const MAX_LOCALIZATION_CANDIDATES: usize = 16;
+
+/// A region that region localization considered; see
+/// [`crate::localization::region_localization::Region`]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct LocalizationCandidate {
+    pub low_xy: Point2<i8>,
+    pub high_xy: Point2<i8>,
+    /// Where the robot would be, if it were in this region
+    pub location: Point2<f32>,
+    /// The candidate with the highest score is chosen
+    pub score: f32,
+}
+
+/// Why the robot's localization chose its estimated location; only sent when
+/// [`FrequentServerToRobot::enable_localization_debug`] is set
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LocalizationDebug {
+    /// The best regions considered by region localization, best first
+    pub candidates: heapless::Vec<LocalizationCandidate, MAX_LOCALIZATION_CANDIDATES>,
+    /// The cells corridor calculated position is moving between, when it is in use
+    pub corridor_targets: Option<(Point2<i8>, Point2<i8>)>,
+    /// The cell that the expected distance sensor readings are measured from
+    pub ray_origin: Option<Point2<i8>>,
+    /// Distance sensor readings that would be expected at `ray_origin`, in order of angle 0, 90,
+    /// 180, 270
+    pub expected_rays: [f32; 4],
+    /// The distance sensor readings that were used, in the same order
+    pub measured_rays: [Option<f32>; 4],
+}
+
+/// Firmware related items should remain first, so that robots with older firmware can still
+/// be reprogrammed; see [`ServerToRobotMessage::category`]
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[repr(usize)]
 pub enum ServerToRobotMessage {
@@ -225,12 +468,47 @@ pub enum ServerToRobotMessage {
     Reboot = 5,
     MarkFirmwareBooted = 6,
     CancelFirmwareUpdate = 7,
-    /// See [`FrequentServerToRobot`]
-    FrequentRobotItems(FrequentServerToRobot) = 8,
-    Ping = 9,
+    /// See [`RobotConfig`]; the robot replies with [`RobotToServerMessage::ConfigApplied`]
+    RobotConfig(RobotConfig) = 8,
+    /// See [`RobotControl`]
+    RobotControl(RobotControl) = 9,
+    /// The robot replies with [`RobotToServerMessage::Pong`] carrying the same number
+    Ping(u32) = 10,
     ExtraOpts(ExtraOptsTypes) = 11,
 }
 
+impl ServerToRobotMessage {
+    pub fn category(&self) -> MessageCategory {
+        match self {
+            ServerToRobotMessage::ReadyToStartUpdate
+            | ServerToRobotMessage::FirmwareWritePart { .. }
+            | ServerToRobotMessage::CalculateFirmwareHash(_)
+            | ServerToRobotMessage::MarkFirmwareUpdated
+            | ServerToRobotMessage::IsFirmwareSwapped
+            | ServerToRobotMessage::Reboot
+            | ServerToRobotMessage::MarkFirmwareBooted
+            | ServerToRobotMessage::CancelFirmwareUpdate => MessageCategory::OtaCritical,
+            ServerToRobotMessage::RobotConfig(_) | ServerToRobotMessage::RobotControl(_) => {
+                MessageCategory::Control
+            }
+            ServerToRobotMessage::Ping(_) => MessageCategory::Telemetry,
+            ServerToRobotMessage::ExtraOpts(_) => MessageCategory::Debug,
+        }
+    }
+
+    /// Whether this message should be sent over UDP, if it is available; see
+    /// [`robot_udp`]
+    pub fn is_latency_critical(&self) -> bool {
+        matches!(self, ServerToRobotMessage::RobotControl(_))
+    }
+
+    /// Whether this message may be sent to a robot whose firmware was built with a different
+    /// [`ProtocolVersion`], so that it can be reprogrammed
+    pub fn works_across_versions(&self) -> bool {
+        self.category() == MessageCategory::OtaCritical
+    }
+}
+
 #[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialOrd, PartialEq)]
 pub struct ExtraOptsTypes {
     pub opts_bool: [bool; 8],
@@ -261,25 +539,88 @@ pub struct ExtraImuData {
     pub rotation_vector: ([f32; 4], f32, u8),
 }
 
-/// Firmware related items MUST remain first, or OTA programming will break
+/// Firmware related items should remain first, so that robots with older firmware can still
+/// be reprogrammed; see [`RobotToServerMessage::category`]
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[repr(usize)]
 pub enum RobotToServerMessage {
     ReadyToStartUpdate = 0,
-    ConfirmFirmwarePart { offset: usize, len: usize } = 1,
+    ConfirmFirmwarePart {
+        offset: usize,
+        len: usize,
+    } = 1,
     MarkedFirmwareUpdated = 2,
     FirmwareHash([u8; 32]) = 3,
     Rebooting = 4,
     FirmwareIsSwapped(bool) = 5,
     MarkedFirmwareBooted = 6,
     Name(RobotName) = 7,
-    MotorControlStatus((Duration, MotorControlStatus)) = 8,
-    Utilization([f32; 3]) = 9,
-    Sensors(SensorData) = 10,
-    Pong = 11,
-    ReceivedExtraOpts(ExtraOptsTypes) = 12,
-    ExtraIndicators(ExtraOptsTypes) = 13,
-    ExtraImuData(ExtraImuData) = 14,
+    /// Sent right after [`RobotToServerMessage::Name`]; MUST remain in this position
+    Version(ProtocolVersion) = 8,
+    MotorControlStatus((Duration, MotorControlStatus)) = 9,
+    Utilization([f32; 3]) = 10,
+    Sensors(SensorData) = 11,
+    /// Answers [`ServerToRobotMessage::Ping`]; `robot_time` is the time since the robot started
+    Pong {
+        seq: u32,
+        robot_time: Duration,
+    } = 12,
+    ReceivedExtraOpts(ExtraOptsTypes) = 13,
+    ExtraIndicators(ExtraOptsTypes) = 14,
+    ExtraImuData(ExtraImuData) = 15,
+    /// Problems the robot has seen in frames from the server; sent when they change
+    FrameStats(FrameStats) = 16,
+    /// The [`RobotConfig::config_hash`] of the configuration the robot is now using
+    ConfigApplied(u32) = 17,
+    /// The robot's position along the streamed target path, sent when it changes; see
+    /// [`path_stream::PathFollower::progress`]
+    PathProgress {
+        path_id: u32,
+        index: u32,
+    } = 18,
+    LocalizationDebug(LocalizationDebug) = 19,
+}
+
+impl RobotToServerMessage {
+    pub fn category(&self) -> MessageCategory {
+        match self {
+            RobotToServerMessage::ReadyToStartUpdate
+            | RobotToServerMessage::ConfirmFirmwarePart { .. }
+            | RobotToServerMessage::MarkedFirmwareUpdated
+            | RobotToServerMessage::FirmwareHash(_)
+            | RobotToServerMessage::Rebooting
+            | RobotToServerMessage::FirmwareIsSwapped(_)
+            | RobotToServerMessage::MarkedFirmwareBooted
+            | RobotToServerMessage::Name(_)
+            | RobotToServerMessage::Version(_) => MessageCategory::OtaCritical,
+            RobotToServerMessage::MotorControlStatus(_)
+            | RobotToServerMessage::Utilization(_)
+            | RobotToServerMessage::Sensors(_)
+            | RobotToServerMessage::Pong { .. }
+            | RobotToServerMessage::ConfigApplied(_)
+            | RobotToServerMessage::PathProgress { .. } => MessageCategory::Telemetry,
+            RobotToServerMessage::ReceivedExtraOpts(_)
+            | RobotToServerMessage::ExtraIndicators(_)
+            | RobotToServerMessage::ExtraImuData(_)
+            | RobotToServerMessage::FrameStats(_)
+            | RobotToServerMessage::LocalizationDebug(_) => MessageCategory::Debug,
+        }
+    }
+
+    /// Whether this message should be sent over UDP, if it is available; see
+    /// [`robot_udp`]
+    pub fn is_latency_critical(&self) -> bool {
+        matches!(
+            self,
+            RobotToServerMessage::Sensors(_) | RobotToServerMessage::MotorControlStatus(_)
+        )
+    }
+
+    /// Whether this message must be understood even when the robot's firmware was built with a
+    /// different [`ProtocolVersion`], so that it can be reprogrammed
+    pub fn works_across_versions(&self) -> bool {
+        self.category() == MessageCategory::OtaCritical
+    }
 }
 
 /// The different async tasks that run on the robot
@@ -312,8 +653,13 @@ pub struct SensorData {
     pub distances: [Result<Option<f32>, heapless::String<MAX_SENSOR_ERR_LEN>>; 4],
     /// The best guess location of the robot
     pub location: Option<Point2<f32>>,
+    /// The estimate of every localization algorithm, indexed by [`LocalizationAlgorithmSource`];
+    /// only sent when [`FrequentServerToRobot::enable_localization_shadow`] is set
+    pub shadow_locations: Option<[Option<Point2<f32>>; NUM_LOCALIZATION_ALGORITHMS]>,
     /// The battery level of the robot
     pub battery: Result<f32, heapless::String<MAX_SENSOR_ERR_LEN>>,
+    /// The time since the robot started when these readings were taken
+    pub timestamp: Duration,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -330,9 +676,20 @@ pub enum NetworkStatus {
     Connecting,
     /// After a message is received
     Connected,
+    /// The peer was built with different message definitions, so only Over the Air Programming
+    /// messages are exchanged
+    ///
+    /// Only set by whichever side receives the peer's [`ProtocolVersion`] and can act on it: the
+    /// server for robots and the simulation, and the gui for the server
+    Incompatible(ProtocolVersion),
 }
 
 impl NetworkStatus {
+    /// Whether the peer was found to be built with different message definitions
+    pub fn is_incompatible(&self) -> bool {
+        matches!(self, NetworkStatus::Incompatible(_))
+    }
+
     #[cfg(feature = "std")]
     pub fn status(&self) -> ColoredStatus {
         match self {
@@ -344,6 +701,10 @@ impl NetworkStatus {
             }
             NetworkStatus::Connecting => ColoredStatus::Warn(Some("Connecting".to_string())),
             NetworkStatus::Connected => ColoredStatus::Ok(Some("Connected".to_string())),
+            NetworkStatus::Incompatible(version) => ColoredStatus::Error(Some(format!(
+                "Incompatible: peer has {version}, this is {}",
+                ProtocolVersion::CURRENT
+            ))),
         }
     }
 }
@@ -0,0 +1,302 @@
+use crate::constants::MAX_ROBOT_PATH_LENGTH;
+#[cfg(feature = "micromath")]
+use micromath::F32Ext;
+use nalgebra::Point2;
+use serde::{Deserialize, Serialize};
+
+/// A point on a robot's target path
+#[derive(Copy, Clone, Debug, PartialOrd, PartialEq, Serialize, Deserialize)]
+pub struct Waypoint {
+    /// A floating point (row, col) coordinate; need not be the center of a cell
+    pub location: Point2<f32>,
+    /// The speed, in gu/s, to drive toward this point; when not given, the robot picks a speed
+    /// based on how straight the path ahead is
+    pub speed: Option<f32>,
+    /// The angle, in radians, the robot should face while driving toward this point; when not
+    /// given, 0
+    pub heading: Option<f32>,
+}
+
+impl Waypoint {
+    /// The grid cell this waypoint is in
+    pub fn cell(&self) -> Point2<i8> {
+        self.location.map(|x| x.round() as i8)
+    }
+}
+
+impl From<Point2<i8>> for Waypoint {
+    /// The center of a cell
+    fn from(cell: Point2<i8>) -> Self {
+        cell.map(|x| x as f32).into()
+    }
+}
+
+impl From<Point2<f32>> for Waypoint {
+    /// An exact location, with no speed or heading
+    fn from(location: Point2<f32>) -> Self {
+        Self {
+            location,
+            speed: None,
+            heading: None,
+        }
+    }
+}
+
+/// How many of the points ahead of the robot [`PathFollower::reached`] looks through for the
+/// robot's location
+pub const REACHED_LOOKAHEAD: usize = 4;
+
+/// Part of the server's target path, sent to a robot in [`crate::messages::RobotControl`]
+///
+/// The full plan may be much longer than [`MAX_ROBOT_PATH_LENGTH`]; the window slides forward
+/// as the robot reports its progress with [`crate::messages::RobotToServerMessage::PathProgress`]
+#[derive(Clone, Debug, Default, PartialOrd, PartialEq, Serialize, Deserialize)]
+pub struct PathWindow {
+    /// Identifies the plan; it changes whenever the server plans a path that doesn't continue
+    /// the previous one
+    pub path_id: u32,
+    /// The index in the full plan of the first point in `points`
+    pub start: u32,
+    pub points: heapless::Vec<Waypoint, MAX_ROBOT_PATH_LENGTH>,
+}
+
+/// Keeps the server's full target path, so that it can be streamed to the robot in windows
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Default)]
+pub struct PathStream {
+    path_id: u32,
+    /// Every point of the current plan, including those already passed
+    plan: Vec<Waypoint>,
+    /// Points before this index are no longer part of the server's target path
+    passed: usize,
+    /// Points before this index have been passed, according to the robot
+    acknowledged: usize,
+}
+
+#[cfg(feature = "std")]
+impl PathStream {
+    /// Create a stream with no plan; plans are numbered from the id after `path_id`
+    ///
+    /// The server starts from a random id, so that after it restarts it is unlikely to reuse the
+    /// id of a plan the robot already followed
+    pub fn new(path_id: u32) -> Self {
+        Self {
+            path_id,
+            ..Default::default()
+        }
+    }
+
+    pub fn path_id(&self) -> u32 {
+        self.path_id
+    }
+
+    /// Update the plan with the points that remain ahead of the robot
+    ///
+    /// If they continue the current plan, its id and indices are kept; otherwise a new plan
+    /// is started
+    pub fn update(&mut self, remaining: &[Waypoint]) {
+        if remaining.is_empty() {
+            self.passed = self.plan.len();
+            return;
+        }
+        let continues_from = (self.passed..self.plan.len()).find(|&k| {
+            let overlap = (self.plan.len() - k).min(remaining.len());
+            self.plan[k..k + overlap] == remaining[..overlap]
+        });
+        match continues_from {
+            Some(k) => {
+                self.plan.truncate(k);
+                self.plan.extend_from_slice(remaining);
+                self.passed = k;
+            }
+            None => {
+                self.path_id = self.path_id.wrapping_add(1);
+                self.plan = remaining.to_vec();
+                self.passed = 0;
+                self.acknowledged = 0;
+            }
+        }
+    }
+
+    /// Record the progress reported by the robot; reports about other plans are ignored
+    pub fn acknowledge(&mut self, path_id: u32, index: u32) {
+        if path_id == self.path_id {
+            self.acknowledged = self.acknowledged.max(index as usize);
+        }
+    }
+
+    /// The points the robot should follow next
+    pub fn window(&self) -> PathWindow {
+        let start = self.passed.max(self.acknowledged).min(self.plan.len());
+        PathWindow {
+            path_id: self.path_id,
+            start: start as u32,
+            points: self.plan[start..]
+                .iter()
+                .copied()
+                .take(MAX_ROBOT_PATH_LENGTH)
+                .collect(),
+        }
+    }
+}
+
+/// Tracks the robot's progress along the plan streamed by a [`PathStream`]
+///
+/// Progress is kept across reconnects, so a window that was sent before the robot moved on
+/// doesn't send it back to points it already passed
+#[derive(Clone, Debug, Default)]
+pub struct PathFollower {
+    path_id: Option<u32>,
+    /// The index in the full plan of the first point in `points`
+    progress: u32,
+    points: heapless::Vec<Waypoint, MAX_ROBOT_PATH_LENGTH>,
+}
+
+impl PathFollower {
+    /// Accept a window from the server, then skip the points up to the robot's estimated
+    /// `location`
+    pub fn receive(&mut self, window: PathWindow, location: Option<Point2<f32>>) {
+        if self.path_id != Some(window.path_id) {
+            self.path_id = Some(window.path_id);
+            self.progress = window.start;
+        }
+        // windows never move the robot backwards
+        let skip = self.progress.saturating_sub(window.start) as usize;
+        self.progress = self.progress.max(window.start);
+        self.points = window.points.iter().copied().skip(skip).collect();
+        if let Some(location) = location {
+            self.reached(location);
+        }
+    }
+
+    /// Mark the points up to the robot's estimated `location` as passed
+    ///
+    /// Only the next [`REACHED_LOOKAHEAD`] points are searched for the robot's cell, so that on a
+    /// path that comes back through the same cell, the points before it returns aren't skipped
+    pub fn reached(&mut self, location: Point2<f32>) {
+        let cell = location.map(|x| x.round() as i8);
+        let Some(first) = self
+            .points
+            .iter()
+            .take(REACHED_LOOKAHEAD)
+            .position(|p| p.cell() == cell)
+        else {
+            return;
+        };
+        // points next to each other in the same cell are all reached together
+        let passed = first
+            + self.points[first..]
+                .iter()
+                .take_while(|p| p.cell() == cell)
+                .count();
+        self.progress += passed as u32;
+        self.points = self.points.iter().copied().skip(passed).collect();
+    }
+
+    /// The points that remain ahead of the robot
+    pub fn points(&self) -> &heapless::Vec<Waypoint, MAX_ROBOT_PATH_LENGTH> {
+        &self.points
+    }
+
+    /// The id of the plan being followed and the index of the next point in it
+    pub fn progress(&self) -> Option<(u32, u32)> {
+        self.path_id.map(|id| (id, self.progress))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(xs: &[i8]) -> Vec<Waypoint> {
+        xs.iter().map(|x| Point2::new(*x, 1).into()).collect()
+    }
+
+    #[test]
+    pub fn test_path_stream() {
+        let plan = points(&(1..=25).collect::<Vec<_>>());
+        let mut stream = PathStream::new(7);
+        stream.update(&plan);
+        let id = stream.path_id();
+        let first = stream.window();
+        assert_eq!(first.start, 0);
+        assert_eq!(first.points.len(), MAX_ROBOT_PATH_LENGTH);
+
+        let mut follower = PathFollower::default();
+        follower.receive(first.clone(), Some(Point2::new(3.2, 0.9)));
+        assert_eq!(follower.progress(), Some((id, 3)));
+        assert_eq!(follower.points()[0].cell(), Point2::new(4, 1));
+
+        // the robot reports its progress, and the window slides past the original limit
+        let (path_id, index) = follower.progress().unwrap();
+        stream.acknowledge(path_id, index);
+        let window = stream.window();
+        assert_eq!(window.start, 3);
+        assert_eq!(window.points.last().unwrap().cell(), Point2::new(13, 1));
+
+        // an old window, ex. after a reconnect, doesn't send the robot back
+        follower.receive(first, None);
+        assert_eq!(follower.progress(), Some((id, 3)));
+        assert_eq!(follower.points()[0].cell(), Point2::new(4, 1));
+
+        // the server trims and extends its path without starting a new plan
+        let mut remaining = plan[5..].to_vec();
+        remaining.push(Point2::new(25.3, 1.6).into());
+        stream.update(&remaining);
+        assert_eq!(stream.path_id(), id);
+        assert_eq!(stream.window().start, 5);
+
+        // an unrelated path is a new plan
+        stream.update(&points(&[-1, -2]));
+        assert_ne!(stream.path_id(), id);
+        follower.receive(stream.window(), None);
+        assert_eq!(follower.progress(), Some((stream.path_id(), 0)));
+        assert_eq!(follower.points().len(), 2);
+    }
+
+    #[test]
+    pub fn test_revisited_cells_are_not_skipped() {
+        // into a dead end and back out again
+        let mut stream = PathStream::new(0);
+        stream.update(&points(&[2, 3, 4, 3, 2, 1]));
+        let mut follower = PathFollower::default();
+        follower.receive(stream.window(), Some(Point2::new(2.0, 1.0)));
+        assert_eq!(follower.progress().unwrap().1, 1);
+        follower.reached(Point2::new(3.1, 1.0));
+        assert_eq!(follower.progress().unwrap().1, 2);
+        assert_eq!(follower.points()[0].cell(), Point2::new(4, 1));
+        follower.reached(Point2::new(4.0, 1.0));
+        follower.reached(Point2::new(3.0, 1.0));
+        assert_eq!(follower.progress().unwrap().1, 4);
+        assert_eq!(follower.points()[0].cell(), Point2::new(2, 1));
+
+        // a location too far ahead isn't trusted
+        follower.reached(Point2::new(20.0, 1.0));
+        assert_eq!(follower.progress().unwrap().1, 4);
+    }
+
+    #[test]
+    pub fn test_off_center_waypoints() {
+        // a corner cut toward the inside of a turn, approached at a set speed and heading
+        let corner = Waypoint {
+            location: Point2::new(3.2, 1.2),
+            speed: Some(1.5),
+            heading: Some(0.5),
+        };
+        let mut plan = points(&[1, 2]);
+        plan.push(corner);
+        plan.push(Point2::new(3.0, 2.0).into());
+        let mut stream = PathStream::new(0);
+        stream.update(&plan);
+        let window = stream.window();
+        assert_eq!(window.points[2], corner);
+
+        let mut follower = PathFollower::default();
+        follower.receive(window, Some(Point2::new(2.1, 1.0)));
+        assert_eq!(follower.points()[0], corner);
+        // the corner is passed anywhere in its cell
+        follower.reached(Point2::new(2.7, 1.4));
+        assert_eq!(follower.progress().unwrap().1, 3);
+        assert_eq!(follower.points()[0].location, Point2::new(3.0, 2.0));
+    }
+}
@@ -1,7 +1,7 @@
 use defmt_or_log::error;
 use embedded_io_async::{Read, ReadReady};
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 pub struct TcpMessage<'a, T> {
     pub seq: u32,
@@ -32,30 +32,98 @@ pub enum TcpError {
     Eof,
 }
 
+/// Marks the start of every frame, so that a reader can find its place again after corruption
+pub const FRAME_MAGIC: [u8; 4] = *b"PBv2";
+/// The number of bytes before the payload of each frame
+///
+/// `[magic: 4][size: u32][seq: u32][kind: u8]`
+pub const FRAME_HEADER_LEN: usize = 4 + 4 + 4 + 1;
+/// The number of bytes after the payload of each frame; a CRC-32 of everything before it
+pub const FRAME_TRAILER_LEN: usize = 4;
+/// The largest frame a [`StatefulTcpReader`] can hold
+pub const MAX_FRAME_LEN: usize = 5192;
+
+/// Counts of problems [`StatefulTcpReader`] has recovered from
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameStats {
+    /// Frames received intact
+    pub received: u32,
+    /// Frames with an impossible size or a bad checksum
+    pub corrupted: u32,
+    /// Times the reader skipped ahead to the next frame after losing its place
+    pub resynced: u32,
+    /// Bytes skipped while looking for the next frame
+    pub skipped_bytes: u32,
+    /// Frames whose sequence number wasn't the one after the previous frame
+    pub out_of_order: u32,
+}
+
+impl FrameStats {
+    pub fn is_clean(&self) -> bool {
+        self.corrupted == 0 && self.resynced == 0 && self.out_of_order == 0
+    }
+
+    /// Whether any frames were corrupted, resynced or out of order since `earlier`
+    ///
+    /// Frames received intact don't count, so that stats aren't reported again after every frame
+    pub fn has_new_errors(&self, earlier: &FrameStats) -> bool {
+        (self.corrupted, self.resynced, self.out_of_order)
+            != (earlier.corrupted, earlier.resynced, earlier.out_of_order)
+    }
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// The standard (IEEE) CRC-32 of the given bytes
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, b| {
+        CRC32_TABLE[((crc ^ *b as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
 pub fn write_tcp<'a, T: Serialize>(
     seq: &mut u32,
     msg: impl Into<BytesOrT<'a, T>>,
     buf: &mut [u8],
 ) -> Result<usize, TcpError> {
-    let mut msg_size = 4 + 4 + 1;
-    if buf.len() < msg_size {
+    let mut msg_size = FRAME_HEADER_LEN;
+    if buf.len() < msg_size + FRAME_TRAILER_LEN {
         return Err(TcpError::InsufficientSpace);
     }
 
     let msg = msg.into();
 
+    let payload_space = buf.len() - FRAME_TRAILER_LEN;
     let is_bytes = match msg {
         BytesOrT::T(t) => {
             msg_size += bincode::serde::encode_into_slice(
                 t,
-                &mut buf[msg_size..],
+                &mut buf[msg_size..payload_space],
                 bincode::config::standard(),
             )
             .map_err(TcpError::EncodeError)?;
             false
         }
         BytesOrT::Bytes(bytes) => {
-            if msg_size + bytes.len() > buf.len() {
+            if msg_size + bytes.len() > payload_space {
                 return Err(TcpError::InsufficientSpace);
             }
             buf[msg_size..msg_size + bytes.len()].copy_from_slice(bytes);
@@ -63,22 +131,47 @@ pub fn write_tcp<'a, T: Serialize>(
             true
         }
     };
+    let frame_size = msg_size + FRAME_TRAILER_LEN;
+    if frame_size > MAX_FRAME_LEN {
+        return Err(TcpError::InvalidMessageSize);
+    }
 
-    // message size
-    buf[0..4].copy_from_slice(&(msg_size as u32).to_be_bytes());
+    buf[0..4].copy_from_slice(&FRAME_MAGIC);
+    // frame size
+    buf[4..8].copy_from_slice(&(frame_size as u32).to_be_bytes());
     // seq number
-    buf[4..8].copy_from_slice(&seq.to_be_bytes());
-    *seq += 1;
+    buf[8..12].copy_from_slice(&seq.to_be_bytes());
+    *seq = seq.wrapping_add(1);
     // bytes vs t info
-    buf[8] = if is_bytes { 1 } else { 0 };
+    buf[12] = if is_bytes { 1 } else { 0 };
+    let crc = crc32(&buf[..msg_size]);
+    buf[msg_size..frame_size].copy_from_slice(&crc.to_be_bytes());
 
-    Ok(msg_size)
+    Ok(frame_size)
+}
+
+/// If `buf` begins with a frame header, the total length of that frame (header and trailer
+/// included)
+///
+/// A length shorter than [`FRAME_HEADER_LEN`] means the stream is corrupt
+pub fn frame_len(buf: &[u8]) -> Option<usize> {
+    if buf.len() < 8 {
+        return None;
+    }
+    if buf[0..4] != FRAME_MAGIC {
+        return Some(0);
+    }
+    Some(u32::from_be_bytes(buf[4..8].try_into().unwrap()) as usize)
 }
 
 pub struct StatefulTcpReader {
-    buf: [u8; 5192],
+    buf: [u8; MAX_FRAME_LEN],
     buf_len: usize,
     used_len: usize,
+    last_seq: Option<u32>,
+    /// Whether bytes have been skipped since the last good frame
+    lost_place: bool,
+    stats: FrameStats,
 }
 
 impl Default for StatefulTcpReader {
@@ -90,15 +183,25 @@ impl Default for StatefulTcpReader {
 impl StatefulTcpReader {
     pub fn new() -> Self {
         Self {
-            buf: [0; 5192],
+            buf: [0; MAX_FRAME_LEN],
             buf_len: 0,
             used_len: 0,
+            last_seq: None,
+            lost_place: false,
+            stats: FrameStats::default(),
         }
     }
 
+    /// Prepare for a new connection; [`FrameStats`] are kept
     pub fn clear(&mut self) {
         self.buf_len = 0;
         self.used_len = 0;
+        self.last_seq = None;
+        self.lost_place = false;
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        self.stats
     }
 
     pub async fn try_read_socket<'a, T: DeserializeOwned>(
@@ -106,6 +209,7 @@ impl StatefulTcpReader {
         socket: &mut (impl Read + ReadReady),
     ) -> Result<TcpMessage<'a, T>, TcpError> {
         if socket.read_ready().map_err(|_| TcpError::SocketError)? {
+            self.clear_used();
             self.buf_len += socket
                 .read(&mut self.buf[self.buf_len..])
                 .await
@@ -142,6 +246,7 @@ impl StatefulTcpReader {
         &'a mut self,
         buf: &mut &[u8],
     ) -> Result<TcpMessage<'a, T>, TcpError> {
+        self.clear_used();
         self.buf_len += std::io::Read::read(buf, &mut self.buf[self.buf_len..])
             .map_err(|_| TcpError::SocketError)?;
         self.fetch_message()?.ok_or(TcpError::WouldBlock)
@@ -155,55 +260,162 @@ impl StatefulTcpReader {
         }
     }
 
-    fn has_message(&mut self) -> bool {
+    /// Throw away the first `len` bytes, because they can't be the start of a valid frame
+    fn skip(&mut self, len: usize) {
+        self.buf.copy_within(len..self.buf_len, 0);
+        self.buf_len -= len;
+        self.stats.skipped_bytes = self.stats.skipped_bytes.saturating_add(len as u32);
+        self.lost_place = true;
+    }
+
+    /// Discard bytes until the buffer starts with a frame that is valid and complete, returning
+    /// its length, or until there is not enough data left to tell
+    fn find_frame(&mut self) -> Option<usize> {
         self.clear_used();
-        // do we have a message?
-        if self.buf_len >= 9 {
-            // do we have the whole message?
-            let msg_size = u32::from_be_bytes(self.buf[0..4].try_into().unwrap()) as usize;
-            if self.buf_len >= msg_size {
-                // we do have the whole message
-                return true;
+        loop {
+            // line up with the next magic word, keeping a possible partial one at the end
+            let magic_at = self.buf[..self.buf_len]
+                .windows(FRAME_MAGIC.len())
+                .position(|w| w == FRAME_MAGIC);
+            match magic_at {
+                Some(0) => {}
+                Some(i) => self.skip(i),
+                None => {
+                    let keep = usize::min(self.buf_len, FRAME_MAGIC.len() - 1);
+                    if self.buf_len > keep {
+                        self.skip(self.buf_len - keep);
+                    }
+                    return None;
+                }
+            }
+            if self.buf_len < FRAME_HEADER_LEN {
+                return None;
             }
+            let frame_size = frame_len(&self.buf[..self.buf_len]).unwrap_or(0);
+            if !(FRAME_HEADER_LEN + FRAME_TRAILER_LEN..=MAX_FRAME_LEN).contains(&frame_size) {
+                error!("Received invalid frame size {}", frame_size);
+                self.stats.corrupted += 1;
+                self.skip(1);
+                continue;
+            }
+            if self.buf_len < frame_size {
+                return None;
+            }
+            let crc_at = frame_size - FRAME_TRAILER_LEN;
+            let crc = u32::from_be_bytes(self.buf[crc_at..frame_size].try_into().unwrap());
+            if crc != crc32(&self.buf[..crc_at]) {
+                error!("Received frame with bad checksum");
+                self.stats.corrupted += 1;
+                self.skip(1);
+                continue;
+            }
+            return Some(frame_size);
         }
-        false
+    }
+
+    fn has_message(&mut self) -> bool {
+        self.find_frame().is_some()
     }
 
     fn fetch_message<T: DeserializeOwned>(&mut self) -> Result<Option<TcpMessage<T>>, TcpError> {
-        self.clear_used();
-        // do we have a message?
-        if self.buf_len >= 9 {
-            // do we have the whole message?
-            let msg_size = u32::from_be_bytes(self.buf[0..4].try_into().unwrap()) as usize;
-            if msg_size < 9 {
-                error!("Received invalid message size {}", msg_size);
-                // this is impossible... reset internal buffers in hopes of recovery
-                self.used_len = 0;
-                self.buf_len = 0;
-                return Err(TcpError::InvalidMessageSize);
-            }
-            if self.buf_len >= msg_size {
-                // we do have the whole message
-                // we can't remove the bytes from the buffer because we might have to return
-                // a reference to them. Instead, mark these bytes as used
-                self.used_len = msg_size;
-                let seq = u32::from_be_bytes(self.buf[4..8].try_into().unwrap());
-                return Ok(Some(TcpMessage {
-                    seq,
-                    msg: match self.buf[8] {
-                        0 => BytesOrT::T(
-                            bincode::serde::decode_from_slice(
-                                &self.buf[9..msg_size],
-                                bincode::config::standard(),
-                            )
-                            .map_err(TcpError::DecodeError)?
-                            .0,
-                        ),
-                        _ => BytesOrT::Bytes(&self.buf[9..msg_size]),
-                    },
-                }));
+        let Some(frame_size) = self.find_frame() else {
+            return Ok(None);
+        };
+        // we can't remove the bytes from the buffer because we might have to return
+        // a reference to them. Instead, mark these bytes as used
+        self.used_len = frame_size;
+        self.stats.received += 1;
+        if self.lost_place {
+            self.stats.resynced += 1;
+            self.lost_place = false;
+        }
+        let seq = u32::from_be_bytes(self.buf[8..12].try_into().unwrap());
+        if self
+            .last_seq
+            .is_some_and(|last| seq != last.wrapping_add(1))
+        {
+            self.stats.out_of_order += 1;
+        }
+        self.last_seq = Some(seq);
+        let payload = &self.buf[FRAME_HEADER_LEN..frame_size - FRAME_TRAILER_LEN];
+        Ok(Some(TcpMessage {
+            seq,
+            msg: match self.buf[12] {
+                0 => BytesOrT::T(
+                    bincode::serde::decode_from_slice(payload, bincode::config::standard())
+                        .map_err(TcpError::DecodeError)?
+                        .0,
+                ),
+                _ => BytesOrT::Bytes(payload),
+            },
+        }))
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    fn frame(seq: &mut u32, value: u32) -> Vec<u8> {
+        let mut buf = [0; 64];
+        let len = write_tcp(seq, value, &mut buf).unwrap();
+        buf[..len].to_vec()
+    }
+
+    fn read_all(reader: &mut StatefulTcpReader, mut bytes: &[u8]) -> Vec<u32> {
+        let mut values = vec![];
+        loop {
+            match reader.read_u8_ref::<u32>(&mut bytes) {
+                Ok(TcpMessage {
+                    msg: BytesOrT::T(t),
+                    ..
+                }) => values.push(t),
+                Ok(_) => panic!("expected a value"),
+                Err(_) if bytes.is_empty() => break,
+                Err(_) => {}
             }
         }
-        Ok(None)
+        values
+    }
+
+    #[test]
+    pub fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    pub fn test_resync_after_corruption() {
+        let mut seq = 0;
+        let mut stream = frame(&mut seq, 1);
+        let mut corrupt = frame(&mut seq, 2);
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0xFF;
+        stream.extend(corrupt);
+        stream.extend([0xAA; 7]);
+        stream.extend(frame(&mut seq, 3));
+
+        let mut reader = StatefulTcpReader::new();
+        assert_eq!(read_all(&mut reader, &stream), vec![1, 3]);
+        let stats = reader.stats();
+        assert_eq!(stats.received, 2);
+        assert_eq!(stats.corrupted, 1);
+        assert_eq!(stats.resynced, 1);
+        assert_eq!(stats.out_of_order, 1);
+    }
+
+    #[test]
+    pub fn test_intact_frames_are_not_new_errors() {
+        let mut seq = 0;
+        let mut reader = StatefulTcpReader::new();
+        read_all(&mut reader, &frame(&mut seq, 1));
+        let reported = reader.stats();
+        read_all(&mut reader, &frame(&mut seq, 2));
+        assert!(!reader.stats().has_new_errors(&reported));
+
+        let mut corrupt = frame(&mut seq, 3);
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0xFF;
+        read_all(&mut reader, &corrupt);
+        assert!(reader.stats().has_new_errors(&reported));
     }
 }
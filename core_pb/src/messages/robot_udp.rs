@@ -0,0 +1,160 @@
+use crate::messages::robot_tcp::crc32;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Marks the start of every datagram, so that stray packets are ignored
+pub const DATAGRAM_MAGIC: [u8; 4] = *b"PBu1";
+/// The number of bytes before the payload of each datagram
+///
+/// `[magic: 4][seq: u32]`
+pub const DATAGRAM_HEADER_LEN: usize = 4 + 4;
+/// The number of bytes after the payload of each datagram; a CRC-32 of everything before it
+pub const DATAGRAM_TRAILER_LEN: usize = 4;
+/// The largest datagram that will be sent or received
+pub const MAX_DATAGRAM_LEN: usize = 1024;
+/// A datagram this far behind the newest one is assumed to come from a peer that restarted,
+/// rather than one that was delayed
+const RESTART_WINDOW: u32 = 64;
+
+#[derive(Debug)]
+pub enum UdpError {
+    EncodeError(bincode::error::EncodeError),
+    DecodeError(bincode::error::DecodeError),
+    InsufficientSpace,
+    Corrupted,
+    /// A newer datagram was already received
+    Stale,
+}
+
+/// Counts of datagrams seen by a [`UdpReader`]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DatagramStats {
+    /// Datagrams received intact and in order
+    pub received: u32,
+    /// Datagrams with a bad header or checksum
+    pub corrupted: u32,
+    /// Datagrams dropped because a newer one had already arrived
+    pub stale: u32,
+}
+
+/// Encode one datagram into `buf`, returning its length
+///
+/// Unlike [`crate::messages::robot_tcp::write_tcp`], delivery isn't guaranteed, so only
+/// messages that are sent again regularly should use this
+pub fn write_udp<T: Serialize>(seq: &mut u32, msg: &T, buf: &mut [u8]) -> Result<usize, UdpError> {
+    let buf_len = buf.len().min(MAX_DATAGRAM_LEN);
+    if buf_len < DATAGRAM_HEADER_LEN + DATAGRAM_TRAILER_LEN {
+        return Err(UdpError::InsufficientSpace);
+    }
+    let msg_size = DATAGRAM_HEADER_LEN
+        + bincode::serde::encode_into_slice(
+            msg,
+            &mut buf[DATAGRAM_HEADER_LEN..buf_len - DATAGRAM_TRAILER_LEN],
+            bincode::config::standard(),
+        )
+        .map_err(UdpError::EncodeError)?;
+
+    buf[0..4].copy_from_slice(&DATAGRAM_MAGIC);
+    buf[4..8].copy_from_slice(&seq.to_be_bytes());
+    *seq = seq.wrapping_add(1);
+    let crc = crc32(&buf[..msg_size]);
+    buf[msg_size..msg_size + DATAGRAM_TRAILER_LEN].copy_from_slice(&crc.to_be_bytes());
+
+    Ok(msg_size + DATAGRAM_TRAILER_LEN)
+}
+
+/// Decodes datagrams from one peer, dropping any that arrive after a newer one
+#[derive(Default)]
+pub struct UdpReader {
+    last_seq: Option<u32>,
+    stats: DatagramStats,
+}
+
+impl UdpReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Prepare for a new peer; [`DatagramStats`] are kept
+    pub fn clear(&mut self) {
+        self.last_seq = None;
+    }
+
+    pub fn stats(&self) -> DatagramStats {
+        self.stats
+    }
+
+    /// Decode a datagram, returning its sequence number and contents
+    pub fn read<T: DeserializeOwned>(&mut self, datagram: &[u8]) -> Result<(u32, T), UdpError> {
+        if datagram.len() < DATAGRAM_HEADER_LEN + DATAGRAM_TRAILER_LEN
+            || datagram[0..4] != DATAGRAM_MAGIC
+        {
+            self.stats.corrupted = self.stats.corrupted.saturating_add(1);
+            return Err(UdpError::Corrupted);
+        }
+        let payload_end = datagram.len() - DATAGRAM_TRAILER_LEN;
+        let crc = u32::from_be_bytes(datagram[payload_end..].try_into().unwrap());
+        if crc != crc32(&datagram[..payload_end]) {
+            self.stats.corrupted = self.stats.corrupted.saturating_add(1);
+            return Err(UdpError::Corrupted);
+        }
+
+        let seq = u32::from_be_bytes(datagram[4..8].try_into().unwrap());
+        if let Some(last_seq) = self.last_seq {
+            let behind = last_seq.wrapping_sub(seq);
+            if behind < RESTART_WINDOW {
+                self.stats.stale = self.stats.stale.saturating_add(1);
+                return Err(UdpError::Stale);
+            }
+        }
+
+        let (msg, _) = bincode::serde::decode_from_slice(
+            &datagram[DATAGRAM_HEADER_LEN..payload_end],
+            bincode::config::standard(),
+        )
+        .map_err(UdpError::DecodeError)?;
+        self.last_seq = Some(seq);
+        self.stats.received = self.stats.received.saturating_add(1);
+        Ok((seq, msg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datagram(seq: u32, value: u32) -> Vec<u8> {
+        let mut buf = [0; 64];
+        let len = write_udp(&mut { seq }, &value, &mut buf).unwrap();
+        buf[..len].to_vec()
+    }
+
+    #[test]
+    pub fn test_udp_drops_stale() {
+        let mut reader = UdpReader::new();
+        assert_eq!(reader.read::<u32>(&datagram(5, 1)).unwrap(), (5, 1));
+        assert!(matches!(
+            reader.read::<u32>(&datagram(4, 2)),
+            Err(UdpError::Stale)
+        ));
+        assert!(matches!(
+            reader.read::<u32>(&datagram(5, 3)),
+            Err(UdpError::Stale)
+        ));
+        assert_eq!(reader.read::<u32>(&datagram(500, 4)).unwrap(), (500, 4));
+        // the peer restarted
+        assert_eq!(reader.read::<u32>(&datagram(0, 5)).unwrap(), (0, 5));
+
+        let mut corrupt = datagram(1, 6);
+        corrupt[DATAGRAM_HEADER_LEN] ^= 0xFF;
+        assert!(matches!(
+            reader.read::<u32>(&corrupt),
+            Err(UdpError::Corrupted)
+        ));
+
+        let stats = reader.stats();
+        assert_eq!(stats.received, 3);
+        assert_eq!(stats.stale, 2);
+        assert_eq!(stats.corrupted, 1);
+    }
+}
@@ -0,0 +1,80 @@
+use crate::grid::standard_grid::StandardGrid;
+use crate::messages::settings::{NetworkImpairments, SimulationGhostSettings};
+use crate::names::RobotName;
+use nalgebra::Point2;
+use pacbot_rs::game_state::GameState;
+use serde::{Deserialize, Serialize};
+
+/// A reproducible starting point for the simulator
+///
+/// Scenarios are usually stored as RON files and loaded with `cargo run --bin sim_pb -- <file>`,
+/// or sent at runtime with [`ServerToSimulationMessage::LoadScenario`] using the gui's
+/// "Load scenario" button. Any field left out of the file takes its default value.
+///
+/// [`ServerToSimulationMessage::LoadScenario`]: crate::messages::ServerToSimulationMessage::LoadScenario
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scenario {
+    /// The grid the robots drive on
+    pub grid: StandardGrid,
+    /// Robots to spawn; any other simulated robots are removed
+    pub robots: Vec<ScenarioRobot>,
+    /// The robot that moves pacman in the game state
+    pub pacman: RobotName,
+    /// The initial game state, including pellets, ghosts and whether the game is paused
+    ///
+    /// When `None`, a new paused game is started
+    pub game_state: Option<GameState>,
+    /// Noise applied to simulated sensors and motors
+    pub noise: SimulationNoise,
+    pub network: NetworkImpairments,
+    pub ghosts: SimulationGhostSettings,
+    /// Seed for the simulator's random number generator, so that noise is repeatable
+    pub seed: u64,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self {
+            grid: StandardGrid::Pacman,
+            robots: vec![],
+            pacman: RobotName::Stella,
+            game_state: None,
+            noise: SimulationNoise::default(),
+            network: NetworkImpairments::default(),
+            ghosts: SimulationGhostSettings::default(),
+            seed: 0,
+        }
+    }
+}
+
+/// A robot to spawn at the start of a [`Scenario`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScenarioRobot {
+    pub name: RobotName,
+    /// In grid units; when `None`, the grid's default spawn location is used
+    #[serde(default)]
+    pub position: Option<Point2<f32>>,
+    /// In radians, counter-clockwise from the positive x axis
+    #[serde(default)]
+    pub angle: f32,
+}
+
+/// Noise applied to simulated sensors and motors
+#[derive(Copy, Clone, Debug, PartialOrd, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimulationNoise {
+    /// Maximum fractional error of each distance sensor reading
+    pub distance_sensors: f32,
+    /// Maximum fractional error of each motor's speed
+    pub motors: f32,
+}
+
+impl Default for SimulationNoise {
+    fn default() -> Self {
+        Self {
+            distance_sensors: 0.01,
+            motors: 0.08,
+        }
+    }
+}
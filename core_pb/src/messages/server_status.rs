@@ -1,10 +1,18 @@
+use crate::messages::common::NUM_LOCALIZATION_ALGORITHMS;
+use crate::messages::discovery::RobotBeacon;
 use crate::messages::ota::{OverTheAirStep, OverTheAirStepCompletion};
-use crate::messages::{ExtraImuData, ExtraOptsTypes, MotorControlStatus, NetworkStatus};
+use crate::messages::robot_tcp::FrameStats;
+use crate::messages::{
+    ExtraImuData, ExtraOptsTypes, LocalizationDebug, MotorControlStatus, NetworkStatus,
+};
 use crate::names::{RobotName, NUM_ROBOT_NAMES};
+use crate::threaded_websocket::LinkMetrics;
+use crate::util::clock_sync::ClockEstimate;
 use crate::util::ColoredStatus;
 use nalgebra::{Point2, Rotation2};
 use pacbot_rs::game_state::GameState;
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::time::Duration;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -13,16 +21,27 @@ pub struct ServerStatus {
     pub inference_time: ColoredStatus,
 
     pub simulation_connection: NetworkStatus,
+    pub simulation_link: LinkMetrics,
 
     pub game_state: GameState,
     pub game_server_connection: NetworkStatus,
+    pub game_server_link: LinkMetrics,
     pub advanced_game_server: bool,
+    /// The file the current match is being recorded to, if any
+    pub match_recording: Option<String>,
 
     pub cv_location: Option<Point2<i8>>,
     pub target_path: Vec<Point2<i8>>,
+    /// Why the last target path sent by a gui was rejected, until the target path is next changed
+    pub target_path_error: Option<String>,
 
     pub gui_clients: usize,
+    /// Combined over all gui clients; `connected_for` is since any client was connected
+    pub gui_link: LinkMetrics,
     pub robots: [RobotStatus; NUM_ROBOT_NAMES],
+
+    /// Time since the server started; robot timestamps are converted to this time base
+    pub server_time: Duration,
 }
 
 impl Default for ServerStatus {
@@ -32,16 +51,62 @@ impl Default for ServerStatus {
             inference_time: ColoredStatus::NotApplicable(Some("N/A".to_string())),
 
             simulation_connection: NetworkStatus::default(),
+            simulation_link: LinkMetrics::default(),
 
             game_state: GameState::default(),
             game_server_connection: NetworkStatus::default(),
+            game_server_link: LinkMetrics::default(),
             advanced_game_server: false,
+            match_recording: None,
 
             cv_location: None,
             target_path: vec![],
+            target_path_error: None,
 
             gui_clients: 0,
+            gui_link: LinkMetrics::default(),
             robots: RobotName::get_all().map(RobotStatus::new),
+
+            server_time: Duration::ZERO,
+        }
+    }
+}
+
+/// Round trip times and losses of the pings sent to a robot
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PingStats {
+    /// The most recent round trip times, oldest first
+    pub history: VecDeque<Duration>,
+    /// Smoothed difference between consecutive round trip times, as in RFC 3550
+    pub jitter: Duration,
+    pub sent: u32,
+    /// Pings that weren't answered within a timeout, or whose answer arrived after the answer to
+    /// a later ping
+    pub lost: u32,
+}
+
+impl PingStats {
+    /// How many round trip times are kept in [`PingStats::history`]
+    pub const HISTORY_LEN: usize = 50;
+
+    pub fn record(&mut self, rtt: Duration) {
+        if let Some(last) = self.history.back() {
+            let diff = rtt.abs_diff(*last).as_secs_f32();
+            let jitter = self.jitter.as_secs_f32();
+            self.jitter = Duration::from_secs_f32(jitter + (diff - jitter) / 16.0);
+        }
+        if self.history.len() == Self::HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(rtt);
+    }
+
+    /// The fraction of pings that were lost
+    pub fn loss(&self) -> f32 {
+        if self.sent == 0 {
+            0.0
+        } else {
+            self.lost as f32 / self.sent as f32
         }
     }
 }
@@ -50,12 +115,32 @@ impl Default for ServerStatus {
 pub struct RobotStatus {
     pub name: RobotName,
     pub connection: NetworkStatus,
+    /// The latest beacon broadcast by this robot while it was waiting for a connection
+    pub beacon: Option<RobotBeacon>,
+    /// The server's TCP connection to this robot
+    pub link: LinkMetrics,
     pub ping: Option<Duration>,
+    pub ping_stats: PingStats,
+    /// How the robot's clock relates to [`ServerStatus::server_time`], once it has answered pings
+    pub clock: Option<ClockEstimate>,
+    /// Problems seen by the server in frames from this robot, updated when there is a new one
+    pub server_frame_stats: FrameStats,
+    /// Problems seen by this robot in frames from the server, updated when there is a new one
+    pub robot_frame_stats: FrameStats,
+    /// The [`crate::messages::RobotConfig::config_hash`] of the latest configuration sent to
+    /// this robot
+    pub config_hash: Option<u32>,
+    /// The [`crate::messages::RobotConfig::config_hash`] of the configuration this robot says it
+    /// is using
+    pub applied_config_hash: Option<u32>,
 
     pub ota_current: OverTheAirStep,
     pub ota_completed: Vec<OverTheAirStepCompletion>,
 
+    /// The robot's latest motor status, and when it was measured according to the robot's clock
     pub last_motor_status: (Duration, MotorControlStatus),
+    /// When [`RobotStatus::last_motor_status`] was measured, in [`ServerStatus::server_time`]
+    pub motor_status_time: Option<Duration>,
     pub utilization: [f32; 3],
 
     pub sim_position: Option<(Point2<f32>, Rotation2<f32>)>,
@@ -63,26 +148,46 @@ pub struct RobotStatus {
     pub imu_angle: Result<f32, String>,
     pub distance_sensors: [Result<Option<f32>, String>; 4],
     pub estimated_location: Option<Point2<f32>>,
+    /// The estimate of every localization algorithm, if the robot is running them in shadow mode
+    pub shadow_locations: Option<[Option<Point2<f32>>; NUM_LOCALIZATION_ALGORITHMS]>,
     pub battery: Result<f32, ()>,
+    /// When the latest sensor readings were taken, in [`ServerStatus::server_time`]
+    pub sensors_time: Option<Duration>,
 
     pub display: Option<Vec<u128>>,
 
     pub received_extra_opts: Option<ExtraOptsTypes>,
     pub extra_indicators: Option<ExtraOptsTypes>,
     pub extra_imu_data: Option<ExtraImuData>,
+    /// The latest localization internals, if the robot has been asked for them
+    pub localization_debug: Option<LocalizationDebug>,
 }
 
 impl RobotStatus {
+    /// Whether the robot is using the latest configuration sent to it
+    pub fn config_applied(&self) -> bool {
+        self.config_hash.is_some() && self.config_hash == self.applied_config_hash
+    }
+
     pub fn new(name: RobotName) -> Self {
         Self {
             name,
             connection: NetworkStatus::default(),
+            beacon: None,
+            link: LinkMetrics::default(),
             ping: None,
+            ping_stats: PingStats::default(),
+            clock: None,
+            server_frame_stats: FrameStats::default(),
+            robot_frame_stats: FrameStats::default(),
+            config_hash: None,
+            applied_config_hash: None,
 
             ota_current: OverTheAirStep::GuiRequest,
             ota_completed: vec![],
 
             last_motor_status: Default::default(),
+            motor_status_time: None,
             utilization: [0.0; 3],
 
             sim_position: None,
@@ -90,13 +195,16 @@ impl RobotStatus {
             imu_angle: Err(String::new()),
             distance_sensors: [const { Err(String::new()) }; 4],
             estimated_location: None,
+            shadow_locations: None,
             battery: Err(()),
+            sensors_time: None,
 
             display: None,
 
             received_extra_opts: None,
             extra_indicators: None,
             extra_imu_data: None,
+            localization_debug: None,
         }
     }
 }
@@ -1,7 +1,10 @@
 use crate::constants::{GAME_SERVER_PORT, SIMULATION_LISTENER_PORT};
+use crate::grid::computed_grid::ComputedGrid;
 use crate::grid::standard_grid::StandardGrid;
+use crate::grid::Grid;
 use crate::messages::{ExtraOptsTypes, FrequentServerToRobot};
 use crate::names::{RobotName, NUM_ROBOT_NAMES};
+use crate::threaded_websocket::Backoff;
 use nalgebra::Point2;
 use serde::{Deserialize, Serialize};
 
@@ -10,10 +13,13 @@ use serde::{Deserialize, Serialize};
 pub struct PacbotSettings {
     /// Host a web server for browser clients
     pub host_http: bool,
-    /// In safe mode, only messages related to over the air programming will be sent and received
+    /// In safe mode, only messages related to over the air programming will be sent and received;
+    /// see [`crate::messages::MessageCategory`]
     pub safe_mode: bool,
     /// Which grid is current in use
     pub standard_grid: StandardGrid,
+    /// A grid drawn in the gui's grid editor; when set, it is used instead of `standard_grid`
+    pub custom_grid: Option<Grid>,
     /// Which robot's position should be used as the pacman location
     pub pacman: RobotName,
     /// Whether the robot should try to drive the target path
@@ -30,6 +36,8 @@ pub struct PacbotSettings {
     pub robots: [RobotSettings; NUM_ROBOT_NAMES],
     /// Options for pathing, speed
     pub driving: DriveSettings,
+    /// How long the server and guis wait between failed attempts to connect to something
+    pub reconnect_backoff: Backoff,
 }
 
 impl Default for PacbotSettings {
@@ -43,13 +51,24 @@ impl Default for PacbotSettings {
             target_speed: 3.0,
             simulation: Default::default(),
             standard_grid: Default::default(),
+            custom_grid: None,
             robots: RobotName::get_all().map(RobotSettings::new),
             game_server: Default::default(),
             driving: Default::default(),
+            reconnect_backoff: Default::default(),
         }
     }
 }
 
+impl PacbotSettings {
+    /// The grid in use; the custom grid if there is a valid one, otherwise the standard grid
+    pub fn compute_grid(&self) -> ComputedGrid {
+        self.custom_grid
+            .and_then(|grid| ComputedGrid::try_from_custom(grid).ok())
+            .unwrap_or_else(|| self.standard_grid.compute_grid())
+    }
+}
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, PartialOrd)]
 pub enum ShouldDoTargetPath {
     Yes,
@@ -96,6 +115,12 @@ pub struct SimulationSettings {
     pub connection: ConnectionSettings,
     /// Which robots should be spawned in
     pub robots: [bool; NUM_ROBOT_NAMES],
+    /// Simulated degradation of the links between simulated robots and the server
+    pub network: NetworkImpairments,
+    /// Physical ghosts in the simulation
+    pub ghosts: SimulationGhostSettings,
+    /// Record each simulated robot's true pose alongside its estimates, sensors and commands
+    pub record_trajectories: bool,
 }
 
 impl Default for SimulationSettings {
@@ -108,15 +133,50 @@ impl Default for SimulationSettings {
                 port: SIMULATION_LISTENER_PORT,
             },
             robots: RobotName::get_all().map(|name| name == RobotName::Stella),
+            network: NetworkImpairments::default(),
+            ghosts: SimulationGhostSettings::default(),
+            record_trajectories: false,
         }
     }
 }
 
+/// Options for giving the game state's ghosts a physical presence in the simulation
+#[derive(Clone, Debug, Default, PartialOrd, PartialEq, Serialize, Deserialize)]
+pub struct SimulationGhostSettings {
+    /// Spawn a collider for each ghost that follows its position in the game state
+    pub bodies: bool,
+    /// Which simulated robots should drive after a ghost instead of being controlled normally
+    ///
+    /// Ghosts are assigned to robots in order; extra robots are left alone
+    pub robots: [bool; NUM_ROBOT_NAMES],
+}
+
+/// Conditions applied to the TCP links of simulated robots, to imitate competition Wi-Fi
+///
+/// The default is a perfect link
+#[derive(Copy, Clone, Debug, Default, PartialOrd, PartialEq, Serialize, Deserialize)]
+pub struct NetworkImpairments {
+    /// Latency added to every frame, in milliseconds
+    pub latency_ms: u64,
+    /// Maximum random latency added on top of `latency_ms`, in milliseconds
+    pub jitter_ms: u64,
+    /// Probability (0 to 1) that any given frame is dropped
+    pub frame_loss: f32,
+    /// Maximum throughput in each direction, in bytes per second; 0 for unlimited
+    pub bandwidth_cap: u32,
+    /// Time between the start of each disconnect, in seconds; 0 to never disconnect
+    pub disconnect_interval: f32,
+    /// How long each disconnect lasts, in seconds
+    pub disconnect_duration: f32,
+}
+
 /// Game server network options
 #[derive(Clone, Debug, PartialOrd, PartialEq, Serialize, Deserialize)]
 pub struct GameServerSettings {
     /// Network details
     pub connection: ConnectionSettings,
+    /// Whether the server records each match, from unpause to reset, to a file
+    pub record_matches: bool,
 }
 
 impl Default for GameServerSettings {
@@ -127,6 +187,7 @@ impl Default for GameServerSettings {
                 ipv4: [127, 0, 0, 1],
                 port: GAME_SERVER_PORT,
             },
+            record_matches: true,
         }
     }
 }
@@ -139,6 +200,11 @@ pub struct RobotSettings {
     pub connection: ConnectionSettings,
     /// Configuration; see [`FrequentServerToRobot`]
     pub config: FrequentServerToRobot,
+    /// Send latency critical messages over UDP; see [`crate::messages::robot_udp`]
+    pub udp: bool,
+    /// Only accept beacons for this robot that come from its known mac address; see
+    /// [`RobotName::mac_address`]
+    pub pin_mac: bool,
 
     /// Whether to send extra_opts to robot
     pub extra_opts_enabled: bool,
@@ -156,6 +222,8 @@ impl RobotSettings {
                 port: name.port(),
             },
             config: FrequentServerToRobot::new(name),
+            udp: false,
+            pin_mac: false,
 
             extra_opts_enabled: false,
             extra_opts: ExtraOptsTypes::default(),
@@ -174,6 +242,9 @@ pub struct DriveSettings {
     pub speed_multiplier: f32,
     /// The maximum speed, in gu/s, when pathing autonomously
     pub speed_cap: f32,
+    /// How far, in gu, waypoints at turns are moved toward the inside of the turn, so that the
+    /// robot cuts corners instead of driving through the center of every cell; 0 to disable
+    pub corner_cut: f32,
 
     /// The translational speed, in gu/s, when driving with manual controls
     pub manual_speed: f32,
@@ -188,6 +259,7 @@ impl Default for DriveSettings {
             speed_base: 3.0,
             speed_multiplier: 2.0,
             speed_cap: 8.0,
+            corner_cut: 0.0,
             manual_speed: 8.0,
             manual_rotation_speed: 2.0,
         }
@@ -0,0 +1,41 @@
+use core::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+
+include!(concat!(env!("OUT_DIR"), "/generated_build_id.rs"));
+include!(concat!(env!("OUT_DIR"), "/generated_protocol.rs"));
+
+/// Identifies the message definitions a component was built with
+///
+/// This is the first thing sent on every link. Peers with a different
+/// [`protocol`](Self::protocol) can't understand each other, except for the Over the Air
+/// Programming messages, which must never change.
+#[derive(Copy, Clone, Debug, PartialOrd, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolVersion {
+    /// [`PROTOCOL`] at the time this component was built
+    pub protocol: u32,
+    /// The commit this component was built from, or 0 if unknown
+    pub build_id: u32,
+}
+
+impl ProtocolVersion {
+    /// The version of this build
+    pub const CURRENT: Self = Self {
+        protocol: PROTOCOL,
+        build_id: BUILD_ID,
+    };
+
+    /// Whether a peer with this version can exchange all messages with this build
+    pub fn is_compatible(&self) -> bool {
+        self.protocol == PROTOCOL
+    }
+}
+
+impl Display for ProtocolVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "protocol {:08x}, build {:08x}",
+            self.protocol, self.build_id
+        )
+    }
+}
@@ -1,3 +1,4 @@
+use crate::messages::path_stream::Waypoint;
 use crate::messages::SensorData;
 use crate::{constants::MAX_ROBOT_PATH_LENGTH, localization};
 use localization::cv_adjust::get_dist;
@@ -11,7 +12,7 @@ const LOCAL_MAX_PATH_LENGTH: usize = MAX_ROBOT_PATH_LENGTH + 1;
 
 pub fn pure_pursuit(
     sensors: &SensorData,
-    path: &heapless::Vec<Point2<i8>, MAX_ROBOT_PATH_LENGTH>,
+    path: &heapless::Vec<Waypoint, MAX_ROBOT_PATH_LENGTH>,
     lookahead: f32,
     speed: f32,
     turn_multiplier: f32,
@@ -31,7 +32,7 @@ pub fn pure_pursuit(
     }
 
     let mut path_f32: heapless::Vec<Point2<f32>, LOCAL_MAX_PATH_LENGTH> =
-        path.into_iter().map(|x| x.map(|y| y as f32)).collect();
+        path.iter().map(|x| x.location).collect();
 
     // path_f32
     //     .insert(0, round_point(loc))
@@ -53,17 +54,25 @@ pub fn pure_pursuit(
         path.iter()
             .enumerate()
             .skip(1)
-            .take_while(|(i, x)| (path[1] - path[0]) == (**x - path[*i - 1]))
+            .take_while(|(i, x)| {
+                (path[1].location - path[0].location) == (x.location - path[*i - 1].location)
+            })
             .count()
     };
 
-    let base_speed = speed
-        + match num_straight_points {
-            0 | 1 => -0.4,
-            2 => -0.2,
-            3 | 4 => 0.0,
-            _ => 0.2,
-        };
+    // a speed given by the server takes precedence over speeding up on straightaways
+    let base_speed = match path[0].speed {
+        Some(speed) => speed,
+        None => {
+            speed
+                + match num_straight_points {
+                    0 | 1 => -0.4,
+                    2 => -0.2,
+                    3 | 4 => 0.0,
+                    _ => 0.2,
+                }
+        }
+    };
 
     let calc_speed = calculate_speed(&path_f32, base_speed, &loc, turn_multiplier);
 
@@ -148,7 +148,8 @@ impl<'r, R: RobotBehavior> DisplayManager<'r, R> {
             None => "UNKNOWN",
             Some((NetworkStatus::NotConnected, _)) => "NO CONNECTION",
             Some((NetworkStatus::ConnectionFailed, _)) => "WIFI FAILED",
-            Some((NetworkStatus::Connecting, _)) => "CONNECTING",
+            // only the server checks versions, so the robot never sees itself as incompatible
+            Some((NetworkStatus::Connecting | NetworkStatus::Incompatible(_), _)) => "CONNECTING",
             Some((NetworkStatus::Connected, ip)) => {
                 if let Some([a, b, c, d]) = ip {
                     format_no_std::show(&mut buf, format_args!("{a}.{b}.{c}.{d}"))
@@ -17,12 +17,15 @@ use futures::select;
 #[allow(unused)]
 use log::{error, info};
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 #[allow(unused)]
 use std::any::TypeId;
+use std::collections::hash_map::RandomState;
 use std::fmt::Debug;
 use std::future;
+use std::hash::{BuildHasher, Hasher};
 use std::pin::pin;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 #[cfg(not(target_arch = "wasm32"))]
 use {
@@ -47,6 +50,104 @@ use {
 /// ipv4 address with port number
 pub type Address = ([u8; 4], u16);
 
+/// How long [`ThreadedSocket`] waits between failed connection attempts
+///
+/// The delay grows exponentially from `initial` up to `max`, and is randomly varied by up to
+/// `jitter` (a fraction of the delay) so that many clients don't retry in lockstep
+#[derive(Copy, Clone, Debug, PartialOrd, PartialEq, Serialize, Deserialize)]
+pub struct Backoff {
+    /// The delay after the first failed attempt
+    pub initial: Duration,
+    /// The longest delay between attempts
+    pub max: Duration,
+    /// How much the delay grows after each failed attempt
+    pub multiplier: f32,
+    /// The largest random change to the delay, as a fraction of it
+    pub jitter: f32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(8),
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl Backoff {
+    /// The delay after the given number of consecutive failed attempts (at least 1)
+    pub fn delay(&self, failed_attempts: u32) -> Duration {
+        // RandomState is seeded differently for each instance
+        let random = RandomState::new().build_hasher().finish() % 10_000;
+        self.delay_with_random(failed_attempts, random as f32 / 10_000.0)
+    }
+
+    /// See [`Backoff::delay`]; `random` is between 0 and 1
+    pub fn delay_with_random(&self, failed_attempts: u32, random: f32) -> Duration {
+        let exponent = failed_attempts.saturating_sub(1).min(32) as i32;
+        let delay = (self.initial.as_secs_f32() * self.multiplier.powi(exponent))
+            .min(self.max.as_secs_f32());
+        let jitter = 1.0 + self.jitter * (random * 2.0 - 1.0);
+        Duration::from_secs_f32((delay * jitter).max(0.0))
+    }
+}
+
+/// Counters describing a [`ThreadedSocket`]'s connection, see [`ThreadedSocket::metrics`]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LinkMetrics {
+    /// Messages sent since the socket was created
+    pub messages_sent: u64,
+    /// Messages received since the socket was created
+    pub messages_received: u64,
+    /// Bytes sent since the socket was created
+    pub bytes_sent: u64,
+    /// Bytes received since the socket was created
+    pub bytes_received: u64,
+    /// How many times the connection was established again after the first time
+    pub reconnects: u32,
+    /// Connection attempts that failed since the last successful one
+    pub failed_attempts: u32,
+    /// Why the last connection attempt failed or the last connection was dropped
+    pub last_error: Option<String>,
+    /// How long the current connection has been open
+    pub connected_for: Option<Duration>,
+}
+
+impl LinkMetrics {
+    /// A one line description, ex. for a tooltip
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{} msgs ({} bytes) in, {} msgs ({} bytes) out, {} reconnects",
+            self.messages_received,
+            self.bytes_received,
+            self.messages_sent,
+            self.bytes_sent,
+            self.reconnects
+        );
+        if let Some(connected_for) = self.connected_for {
+            summary += &format!(", connected for {}s", connected_for.as_secs());
+        } else if self.failed_attempts > 0 {
+            summary += &format!(", {} failed attempts", self.failed_attempts);
+        }
+        if let Some(error) = &self.last_error {
+            summary += &format!("\nLast error: {error}");
+        }
+        summary
+    }
+}
+
+/// Shared between a [`ThreadedSocket`] and the thread that manages its connection
+#[derive(Default)]
+struct LinkState {
+    backoff: Backoff,
+    metrics: LinkMetrics,
+    connected_at: Option<WebTimeInstant>,
+    ever_connected: bool,
+}
+
 /// Simple poll-based wrapper around a socket (websocket or TCP) connection that runs in a separate thread
 ///
 /// Associated methods return immediately even when (for some) the operation might not be completed.
@@ -95,6 +196,7 @@ pub struct ThreadedSocket<SendType: Debug, ReceiveType: Debug> {
     sender: Sender<TextOrT<SendType>>,
     status_receiver: Receiver<NetworkStatus>,
     receiver: Receiver<TextOrT<ReceiveType>>,
+    link: Arc<Mutex<LinkState>>,
 }
 
 /// Represents data that is either the given type, or text
@@ -133,6 +235,20 @@ impl<
         self.status
     }
 
+    /// Counters describing the connection, including why it last failed
+    pub fn metrics(&self) -> LinkMetrics {
+        let link = self.link.lock().unwrap();
+        LinkMetrics {
+            connected_for: link.connected_at.map(|t| t.elapsed()),
+            ..link.metrics.clone()
+        }
+    }
+
+    /// Change how long to wait between failed connection attempts; see [`Backoff`]
+    pub fn set_backoff(&self, backoff: Backoff) {
+        self.link.lock().unwrap().backoff = backoff;
+    }
+
     /// Queue something to be sent to the socket
     ///
     /// If the connection is not available, the data will be discarded
@@ -217,11 +333,14 @@ impl<
 
         block_on(addr_sender.send(addr)).unwrap();
 
+        let link = Arc::new(Mutex::new(LinkState::default()));
+        let link2 = link.clone();
         let name2 = name.clone();
         #[cfg(target_arch = "wasm32")]
         spawn_local(async {
             run_socket_forever::<_, _, SocketType, _, _, _, _, WebTimeInstant>(
                 name2,
+                link2,
                 addr_rx,
                 sender_rx,
                 status_tx,
@@ -245,6 +364,7 @@ impl<
                 WebTimeInstant,
             >(
                 name2,
+                link2,
                 addr_rx,
                 sender_rx,
                 status_tx,
@@ -261,6 +381,7 @@ impl<
             sender,
             status_receiver,
             receiver,
+            link,
         }
     }
 }
@@ -293,8 +414,8 @@ impl<
 pub trait ThreadableSocket<SendType, ReceiveType>: Sized {
     /// Try to connect to the address
     ///
-    /// Do not do any retries, fail as soon as possible
-    async fn my_connect(addr: Address) -> Result<Self, ()>;
+    /// Do not do any retries, fail as soon as possible; the error describes what went wrong
+    async fn my_connect(addr: Address) -> Result<Self, String>;
 
     /// Send the data to the socket
     ///
@@ -303,8 +424,8 @@ pub trait ThreadableSocket<SendType, ReceiveType>: Sized {
 
     /// Try to read from the socket
     ///
-    /// If the connection is no longer available, return Err(())
-    async fn my_read(&mut self) -> Result<TextOrT<Vec<u8>>, ()>;
+    /// If the connection is no longer available, return an error describing why
+    async fn my_read(&mut self) -> Result<TextOrT<Vec<u8>>, String>;
 
     /// Close the socket
     async fn my_close(self);
@@ -313,7 +434,7 @@ pub trait ThreadableSocket<SendType, ReceiveType>: Sized {
 /// A future that yields the next message from the socket, or never if the socket is None
 async fn socket_read_fut<T: ThreadableSocket<S, R>, S, R>(
     socket: &mut Option<T>,
-) -> Result<TextOrT<Vec<u8>>, ()> {
+) -> Result<TextOrT<Vec<u8>>, String> {
     if let Some(socket) = socket {
         socket.my_read().await
     } else {
@@ -321,7 +442,15 @@ async fn socket_read_fut<T: ThreadableSocket<S, R>, S, R>(
     }
 }
 
+/// Record that the connection is no longer open, and why
+fn link_dropped(link: &Mutex<LinkState>, error: String) {
+    let mut link = link.lock().unwrap();
+    link.connected_at = None;
+    link.metrics.last_error = Some(error);
+}
+
 /// Runs on a separate thread to babysit the socket
+#[allow(clippy::too_many_arguments)]
 async fn run_socket_forever<
     OutgoingType: Serialize + Debug,
     IncomingType: DeserializeOwned + Debug,
@@ -333,6 +462,7 @@ async fn run_socket_forever<
     Instant: CrossPlatformInstant + Default,
 >(
     name: String,
+    link: Arc<Mutex<LinkState>>,
     addresses: Receiver<Option<Address>>,
     data_outgoing: Receiver<TextOrT<OutgoingType>>,
     statuses: Sender<NetworkStatus>,
@@ -345,8 +475,11 @@ async fn run_socket_forever<
     let mut sent_first_message = false;
     let mut received_first_message = false;
     let mut disconnect_time: Option<Instant> = None;
+    // after a failed attempt, when it happened and how long to wait before the next one
+    let mut retry: Option<(Instant, Duration)> = None;
     loop {
-        if socket.is_none() {
+        let retry_due = retry.is_none_or(|(time, delay)| time.elapsed() >= delay);
+        if socket.is_none() && retry_due {
             if let Some(address) = addr {
                 info!("[{name}] Connecting to {addr:?}...");
                 statuses
@@ -358,6 +491,8 @@ async fn run_socket_forever<
                         info!("[{name}] Address changed from {addr:?} to {new_addr:?}");
                         statuses.send(NetworkStatus::NotConnected).await.map_err(|_| ())?;
                         addr = new_addr.unwrap();
+                        retry = None;
+                        link.lock().unwrap().metrics.failed_attempts = 0;
                     }
                     conn = SocketType::my_connect(address).fuse() => {
                         match conn {
@@ -365,11 +500,26 @@ async fn run_socket_forever<
                                 info!("[{name}] Connected to {addr:?}");
                                 statuses.send(NetworkStatus::Connected).await.map_err(|_| ())?;
                                 socket = Some(s);
+                                retry = None;
+                                let mut link = link.lock().unwrap();
+                                if link.ever_connected {
+                                    link.metrics.reconnects += 1;
+                                }
+                                link.ever_connected = true;
+                                link.connected_at = Some(WebTimeInstant::default());
+                                link.metrics.failed_attempts = 0;
                             }
-                            Err(()) => {
+                            Err(e) => {
+                                let delay = {
+                                    let mut link = link.lock().unwrap();
+                                    link.metrics.failed_attempts += 1;
+                                    link.metrics.last_error = Some(e);
+                                    link.backoff.delay(link.metrics.failed_attempts)
+                                };
                                 info!(
-                                    "[{name}] Connection failed to {addr:?}, retrying soon"
+                                    "[{name}] Connection failed to {addr:?}, retrying in {delay:?}"
                                 );
+                                retry = Some((Instant::default(), delay));
                                 statuses
                                     .send(NetworkStatus::ConnectionFailed)
                                     .await
@@ -380,8 +530,13 @@ async fn run_socket_forever<
                 }
             }
         }
+        // wake up in time for the next connection attempt
+        let tick = retry
+            .map(|(time, delay)| delay.saturating_sub(time.elapsed()))
+            .unwrap_or(Duration::MAX)
+            .min(Duration::from_secs(1));
         select! {
-            _ = sleep(Duration::from_secs(1)).fuse() => {
+            _ = sleep(tick).fuse() => {
                 if let Some(time) = disconnect_time{
                     let time_elapsed = time.elapsed().as_secs();
                     if time_elapsed >= SOCKET_TIMEOUT{
@@ -391,6 +546,10 @@ async fn run_socket_forever<
                         received_first_message = false;
                         if let Some(socket) = socket.take() {
                             info!("[{name}] Closing socket to {addr:?}");
+                            link_dropped(
+                                &link,
+                                format!("Nothing received for {SOCKET_TIMEOUT} seconds"),
+                            );
                             disconnect_time = Some(Instant::default());
                             socket.my_close().await
                         }
@@ -403,8 +562,11 @@ async fn run_socket_forever<
                     statuses.send(NetworkStatus::NotConnected).await.map_err(|_| ())?;
                     sent_first_message = false;
                     received_first_message = false;
+                    retry = None;
+                    link.lock().unwrap().metrics.failed_attempts = 0;
                     if let Some(socket) = socket.take() {
                         info!("[{name}] Closing socket to {addr:?}");
+                        link.lock().unwrap().connected_at = None;
                         socket.my_close().await
                     }
                     addr = new_addr.unwrap();
@@ -412,35 +574,50 @@ async fn run_socket_forever<
             }
             incoming_data = socket_read_fut(&mut socket).fuse() => {
                 disconnect_time = Some(Instant::default());
-                if let Ok(incoming_data) = incoming_data {
-                    //info!("[{name}] Received data from {addr:?}");
-                    let incoming_data = match incoming_data {
-                        TextOrT::T(data) => {
-                            match deserializer(received_first_message, &data) {
-                                Ok(data) => for d in data {
-                                    received_first_message = true;
-                                    data_incoming.send(d).await.map_err(|_| ())?;
-                                },
-                                Err(e) => {
-                                    info!("[{name}] Error deserializing data: {e:?}");
+                match incoming_data {
+                    Ok(incoming_data) => {
+                        //info!("[{name}] Received data from {addr:?}");
+                        let incoming_data = match incoming_data {
+                            TextOrT::T(data) => {
+                                link.lock().unwrap().metrics.bytes_received += data.len() as u64;
+                                match deserializer(received_first_message, &data) {
+                                    Ok(data) => for d in data {
+                                        received_first_message = true;
+                                        link.lock().unwrap().metrics.messages_received += 1;
+                                        data_incoming.send(d).await.map_err(|_| ())?;
+                                    },
+                                    Err(e) => {
+                                        info!("[{name}] Error deserializing data: {e:?}");
+                                    }
                                 }
+                                None
+                            },
+                            TextOrT::Text(text) => Some(TextOrT::Text(text)),
+                            TextOrT::Bytes(data) => Some(TextOrT::Bytes(data))
+                        };
+                        if let Some(data) = incoming_data {
+                            {
+                                let mut link = link.lock().unwrap();
+                                link.metrics.messages_received += 1;
+                                link.metrics.bytes_received += match &data {
+                                    TextOrT::Text(text) => text.len(),
+                                    TextOrT::T(_) => 0,
+                                    TextOrT::Bytes(data) => data.len(),
+                                } as u64;
                             }
-                            None
-                        },
-                        TextOrT::Text(text) => Some(TextOrT::Text(text)),
-                        TextOrT::Bytes(data) => Some(TextOrT::Bytes(data))
-                    };
-                    if let Some(data) = incoming_data {
-                        data_incoming.send(data).await.map_err(|_| ())?;
+                            data_incoming.send(data).await.map_err(|_| ())?;
+                        }
                     }
-                } else {
-                    info!("[{name}] Connection closed to {addr:?} due to error reading");
-                    sent_first_message = false;
-                    received_first_message = false;
-                    statuses.send(NetworkStatus::ConnectionFailed).await.map_err(|_| ())?;
-                    if let Some(socket) = socket.take() {
-                        info!("[{name}] Closing socket to {addr:?}");
-                        socket.my_close().await
+                    Err(e) => {
+                        info!("[{name}] Connection closed to {addr:?} due to error reading: {e}");
+                        sent_first_message = false;
+                        received_first_message = false;
+                        statuses.send(NetworkStatus::ConnectionFailed).await.map_err(|_| ())?;
+                        if let Some(socket) = socket.take() {
+                            info!("[{name}] Closing socket to {addr:?}");
+                            link_dropped(&link, e);
+                            socket.my_close().await
+                        }
                     }
                 }
             }
@@ -452,6 +629,14 @@ async fn run_socket_forever<
                         t => TextOrT::Bytes(serializer(sent_first_message, t).expect("failed to serialize data")),
                     };
                     sent_first_message = true;
+                    {
+                        let mut link = link.lock().unwrap();
+                        link.metrics.messages_sent += 1;
+                        link.metrics.bytes_sent += match &outgoing_data {
+                            TextOrT::Text(text) => text.len(),
+                            TextOrT::T(data) | TextOrT::Bytes(data) => data.len(),
+                        } as u64;
+                    }
                     socket.my_send(outgoing_data).await
                 }
             }
@@ -463,7 +648,7 @@ async fn run_socket_forever<
 impl<SendType: Serialize, ReceiveType: DeserializeOwned> ThreadableSocket<SendType, ReceiveType>
     for WebSocketStream<ConnectStream>
 {
-    async fn my_connect(addr: Address) -> Result<Self, ()> {
+    async fn my_connect(addr: Address) -> Result<Self, String> {
         let ([a, b, c, d], port) = addr;
         let addr = if addr.0 == [127, 0, 0, 1] {
             format!("ws://localhost:{port}")
@@ -472,7 +657,10 @@ impl<SendType: Serialize, ReceiveType: DeserializeOwned> ThreadableSocket<SendTy
         };
         Ok(async_tungstenite::async_std::connect_async(addr)
             .await
-            .map_err(|e| error!("[WebSocketStream] Error connecting: {:?}", e))?
+            .map_err(|e| {
+                error!("[WebSocketStream] Error connecting: {:?}", e);
+                e.to_string()
+            })?
             .0)
     }
 
@@ -485,23 +673,23 @@ impl<SendType: Serialize, ReceiveType: DeserializeOwned> ThreadableSocket<SendTy
         }
     }
 
-    async fn my_read(&mut self) -> Result<TextOrT<Vec<u8>>, ()> {
+    async fn my_read(&mut self) -> Result<TextOrT<Vec<u8>>, String> {
         match self.next().await {
             Some(Ok(Message::Binary(bytes))) => Ok(TextOrT::T(bytes)),
             Some(Ok(Message::Text(text))) => Ok(TextOrT::Text(text)),
             Some(Ok(Message::Close(_))) => {
                 error!("[WebSocketStream] Connection closing");
-                Err(())
+                Err("Closed by peer".to_string())
             }
             Some(Ok(msg)) => {
                 error!("[WebSocketStream] Unexpected message type: {:?}", msg);
-                Err(())
+                Err(format!("Unexpected message type: {msg:?}"))
             }
-            Some(err) => {
-                error!("[WebSocketStream] Error reading message: {:?}", err);
-                Err(())
+            Some(Err(e)) => {
+                error!("[WebSocketStream] Error reading message: {:?}", e);
+                Err(e.to_string())
             }
-            _ => Err(()),
+            None => Err("Stream ended".to_string()),
         }
     }
 
@@ -524,7 +712,7 @@ pub struct WasmThreadableWebsocket {
 impl<SendType: Serialize, ReceiveType: DeserializeOwned> ThreadableSocket<SendType, ReceiveType>
     for WasmThreadableWebsocket
 {
-    async fn my_connect(addr: Address) -> Result<Self, ()> {
+    async fn my_connect(addr: Address) -> Result<Self, String> {
         let ([a, b, c, d], port) = addr;
         let addr = format!("{a}.{b}.{c}.{d}:{port}");
 
@@ -532,7 +720,8 @@ impl<SendType: Serialize, ReceiveType: DeserializeOwned> ThreadableSocket<SendTy
         let tx2 = msg_tx.clone();
         let tx3 = msg_tx.clone();
 
-        let ws = WebSocket::new(&("ws://".to_string() + &addr)).map_err(|_| ())?;
+        let ws = WebSocket::new(&("ws://".to_string() + &addr))
+            .map_err(|e| format!("Failed to create websocket: {e:?}"))?;
 
         let onmessage_callback = Closure::<dyn FnMut(_)>::new(move |e: MessageEvent| {
             // Handle difference Text/Binary,...
@@ -604,11 +793,11 @@ impl<SendType: Serialize, ReceiveType: DeserializeOwned> ThreadableSocket<SendTy
                 info!(
                     "[WasmThreadableWebsocket] Websocket had a javascript error, failed to connect"
                 );
-                Err(())
+                Err("Javascript error while connecting".to_string())
             }
             Err(e) => {
                 info!("[WasmThreadableWebsocket] Channel could not receive data: {e:?}");
-                Err(())
+                Err(format!("Channel could not receive data: {e:?}"))
             }
         }
     }
@@ -623,7 +812,7 @@ impl<SendType: Serialize, ReceiveType: DeserializeOwned> ThreadableSocket<SendTy
         }
     }
 
-    async fn my_read(&mut self) -> Result<TextOrT<Vec<u8>>, ()> {
+    async fn my_read(&mut self) -> Result<TextOrT<Vec<u8>>, String> {
         match self.messages.recv().await {
             Ok(Ok(msg)) => match msg {
                 TextOrT::Text(text) => Ok(TextOrT::Text(text)),
@@ -632,7 +821,7 @@ impl<SendType: Serialize, ReceiveType: DeserializeOwned> ThreadableSocket<SendTy
             },
             Ok(Err(_)) => {
                 info!("[WasmThreadableWebsocket] Websocket had a javascript error");
-                Err(())
+                Err("Javascript error".to_string())
             }
             Err(e) => {
                 panic!("[WasmThreadableWebsocket] Channel could not receive data: {e:?}");
@@ -657,13 +846,13 @@ pub struct TcpStreamThreadableSocket {
 impl<SendType: Serialize, ReceiveType: DeserializeOwned> ThreadableSocket<SendType, ReceiveType>
     for TcpStreamThreadableSocket
 {
-    async fn my_connect(addr: Address) -> Result<Self, ()> {
+    async fn my_connect(addr: Address) -> Result<Self, String> {
         let ([a, b, c, d], port) = addr;
         match TcpStream::connect(format!("{a}.{b}.{c}.{d}:{port}")).await {
             Ok(stream) => Ok(Self { stream }),
             Err(e) => {
                 error!("[TcpStreamThreadableSocket] Error connecting: {e:?}");
-                Err(())
+                Err(e.to_string())
             }
         }
     }
@@ -678,10 +867,10 @@ impl<SendType: Serialize, ReceiveType: DeserializeOwned> ThreadableSocket<SendTy
         }
     }
 
-    async fn my_read(&mut self) -> Result<TextOrT<Vec<u8>>, ()> {
+    async fn my_read(&mut self) -> Result<TextOrT<Vec<u8>>, String> {
         let mut buf = [0; 1024];
         match self.stream.read(&mut buf).await {
-            Err(_) => Err(()),
+            Err(e) => Err(e.to_string()),
             Ok(len) => Ok(TextOrT::T(buf[..len].to_vec())),
         }
     }
@@ -692,3 +881,27 @@ impl<SendType: Serialize, ReceiveType: DeserializeOwned> ThreadableSocket<SendTy
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_backoff() {
+        let backoff = Backoff {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: 0.5,
+        };
+        let delays: Vec<_> = (1..=6)
+            .map(|attempt| backoff.delay_with_random(attempt, 0.5).as_secs_f32())
+            .collect();
+        assert_eq!(delays, vec![1.0, 2.0, 4.0, 8.0, 10.0, 10.0]);
+        assert_eq!(backoff.delay_with_random(2, 0.0).as_secs_f32(), 1.0);
+        assert_eq!(backoff.delay_with_random(2, 1.0).as_secs_f32(), 3.0);
+        for attempt in 1..100 {
+            assert!(backoff.delay(attempt) <= Duration::from_secs(15));
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// How many ping exchanges the estimate is based on
+const MAX_SAMPLES: usize = 64;
+/// Exchanges whose round trip took this much longer than the fastest one are ignored, since the
+/// extra delay was probably on only one leg, which skews the offset
+const RTT_TOLERANCE: Duration = Duration::from_millis(5);
+/// Drift isn't estimated until the samples span at least this many seconds
+const MIN_DRIFT_SPAN: f64 = 5.0;
+
+/// The relationship between a robot's clock and the server's clock
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClockEstimate {
+    /// Robot time minus server time, in seconds, when the server's clock read zero
+    pub offset: f64,
+    /// How many seconds the robot's clock gains per second of server time
+    pub drift: f64,
+}
+
+impl ClockEstimate {
+    /// Robot time minus server time, in seconds, at the given server time
+    pub fn offset_at(&self, server_time: Duration) -> f64 {
+        self.offset + self.drift * server_time.as_secs_f64()
+    }
+
+    /// Convert a time read from the robot's clock to the server's clock
+    pub fn robot_to_server(&self, robot_time: Duration) -> Duration {
+        Duration::from_secs_f64(
+            ((robot_time.as_secs_f64() - self.offset) / (1.0 + self.drift)).max(0.0),
+        )
+    }
+}
+
+struct ClockSample {
+    /// Server time halfway through the exchange, in seconds
+    server_time: f64,
+    /// Robot time minus server time, in seconds
+    offset: f64,
+    rtt: Duration,
+}
+
+/// Estimates a [`ClockEstimate`] from ping exchanges, in the style of NTP
+///
+/// Only the fastest exchanges are used, and a line is fit through their offsets to find drift
+#[derive(Default)]
+pub struct ClockSync {
+    samples: VecDeque<ClockSample>,
+}
+
+impl ClockSync {
+    /// Record a ping exchange; `sent` and `received` are read from the server's clock, and
+    /// `robot_time` is when the robot answered, according to its clock
+    pub fn add_sample(&mut self, sent: Duration, robot_time: Duration, received: Duration) {
+        let Some(rtt) = received.checked_sub(sent) else {
+            return;
+        };
+        let server_time = (sent + rtt / 2).as_secs_f64();
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(ClockSample {
+            server_time,
+            offset: robot_time.as_secs_f64() - server_time,
+            rtt,
+        });
+    }
+
+    /// Forget all samples, for example because the robot restarted
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    pub fn estimate(&self) -> Option<ClockEstimate> {
+        let min_rtt = self.samples.iter().map(|s| s.rtt).min()?;
+        let good: Vec<&ClockSample> = self
+            .samples
+            .iter()
+            .filter(|s| s.rtt <= min_rtt + RTT_TOLERANCE)
+            .collect();
+        let n = good.len() as f64;
+        let mean_t = good.iter().map(|s| s.server_time).sum::<f64>() / n;
+        let mean_o = good.iter().map(|s| s.offset).sum::<f64>() / n;
+        let var_t: f64 = good.iter().map(|s| (s.server_time - mean_t).powi(2)).sum();
+        let cov: f64 = good
+            .iter()
+            .map(|s| (s.server_time - mean_t) * (s.offset - mean_o))
+            .sum();
+        let span = good.last()?.server_time - good.first()?.server_time;
+
+        let drift = if span >= MIN_DRIFT_SPAN && var_t > 0.0 {
+            cov / var_t
+        } else {
+            0.0
+        };
+        Some(ClockEstimate {
+            offset: mean_o - drift * mean_t,
+            drift,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_clock_sync() {
+        // the robot started 3 seconds after the server, and its clock runs 100ppm fast
+        let robot_clock = |server: f64| (server - 3.0) * 1.0001;
+        let mut sync = ClockSync::default();
+        for i in 0..40 {
+            let sent = 3.5 + i as f64 * 0.5;
+            // every fourth exchange is delayed on the way back
+            let rtt = if i % 4 == 0 { 0.030 } else { 0.004 };
+            sync.add_sample(
+                Duration::from_secs_f64(sent),
+                Duration::from_secs_f64(robot_clock(sent + 0.002)),
+                Duration::from_secs_f64(sent + rtt),
+            );
+        }
+        let estimate = sync.estimate().unwrap();
+        assert!((estimate.drift - 0.0001).abs() < 0.00001);
+        let server = estimate.robot_to_server(Duration::from_secs_f64(robot_clock(20.0)));
+        assert!((server.as_secs_f64() - 20.0).abs() < 0.001);
+    }
+}
@@ -5,6 +5,8 @@ use ecolor::Color32;
 use serde::{Deserialize, Serialize};
 
 pub mod average_rate;
+#[cfg(feature = "std")]
+pub mod clock_sync;
 pub mod moving_average;
 pub mod stopwatch;
 pub mod utilization;
@@ -0,0 +1,432 @@
+use eframe::egui::{InputState, Key};
+use gilrs::{Axis, Button, EventType, Gamepad, GamepadId, Gilrs};
+use serde::{Deserialize, Serialize};
+
+/// The key [`Bindings`] are stored under in eframe's per user storage
+pub const BINDINGS_STORAGE_KEY: &str = "bindings";
+
+/// The number of [`Action`]s
+pub const NUM_ACTIONS: usize = 37;
+/// The number of [`AxisAction`]s
+pub const NUM_AXIS_ACTIONS: usize = 3;
+
+/// Identifies a gamepad between sessions: its uuid, which identical gamepads share, and its index
+/// among the connected gamepads with that uuid
+///
+/// gilrs doesn't expose a serial number or device path, so identical gamepads can only be told
+/// apart by the order they were connected in; if they are reconnected in a different order,
+/// their keys are swapped
+pub type GamepadKey = ([u8; 16], usize);
+
+/// The [`GamepadKey`] of a connected gamepad
+pub fn gamepad_key(gilrs: &Gilrs, id: GamepadId) -> GamepadKey {
+    let uuid = gilrs.gamepad(id).uuid();
+    let index = gilrs
+        .gamepads()
+        .filter(|(_, gamepad)| gamepad.uuid() == uuid)
+        .position(|(other, _)| other == id)
+        .unwrap_or(0);
+    (uuid, index)
+}
+
+/// Something that can be done with a key or gamepad button
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    DriveUp,
+    DriveLeft,
+    DriveDown,
+    DriveRight,
+    RotateCounterclockwise,
+    RotateClockwise,
+    /// Drive faster while held
+    SpeedUp,
+    /// Drive slower while held
+    SlowDown,
+    /// Pause or unpause the game, or the replay during playback
+    TogglePause,
+    Reset,
+    NextRobot,
+    PreviousRobot,
+    ToggleTargetPathMode,
+    /// Stop every robot, turn off target paths, and pause the game; works even while a text box
+    /// is focused
+    EmergencyStop,
+    AngleFree,
+    AngleLocked,
+    AngleFaceForward,
+    AngleAssistedDriving,
+    /// Go back a frame during playback, or to the beginning while shift is held
+    ReplayBack,
+    /// Go forwards a frame during playback, or to the end while shift is held
+    ReplayForwards,
+    StrategyManual,
+    StrategyAi,
+    StrategyTestUniform,
+    StrategyTestForward,
+    /// Connect to or disconnect from the selected robot
+    ToggleConnection,
+    GridPacman,
+    GridPlayground,
+    GridOpen,
+    /// Use the grid cell under the pointer as the cv location
+    CvPointer,
+    CvGameState,
+    CvLocalization,
+    /// Run a motor of the selected robot at half power while held
+    FirstMotorForwards,
+    FirstMotorBackwards,
+    SecondMotorForwards,
+    SecondMotorBackwards,
+    ThirdMotorForwards,
+    ThirdMotorBackwards,
+}
+
+impl Action {
+    /// All actions, in the order used by [`Bindings::actions`]
+    pub fn get_all() -> [Self; NUM_ACTIONS] {
+        [
+            Self::DriveUp,
+            Self::DriveLeft,
+            Self::DriveDown,
+            Self::DriveRight,
+            Self::RotateCounterclockwise,
+            Self::RotateClockwise,
+            Self::SpeedUp,
+            Self::SlowDown,
+            Self::TogglePause,
+            Self::Reset,
+            Self::NextRobot,
+            Self::PreviousRobot,
+            Self::ToggleTargetPathMode,
+            Self::EmergencyStop,
+            Self::AngleFree,
+            Self::AngleLocked,
+            Self::AngleFaceForward,
+            Self::AngleAssistedDriving,
+            Self::ReplayBack,
+            Self::ReplayForwards,
+            Self::StrategyManual,
+            Self::StrategyAi,
+            Self::StrategyTestUniform,
+            Self::StrategyTestForward,
+            Self::ToggleConnection,
+            Self::GridPacman,
+            Self::GridPlayground,
+            Self::GridOpen,
+            Self::CvPointer,
+            Self::CvGameState,
+            Self::CvLocalization,
+            Self::FirstMotorForwards,
+            Self::FirstMotorBackwards,
+            Self::SecondMotorForwards,
+            Self::SecondMotorBackwards,
+            Self::ThirdMotorForwards,
+            Self::ThirdMotorBackwards,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::DriveUp => "Drive up",
+            Self::DriveLeft => "Drive left",
+            Self::DriveDown => "Drive down",
+            Self::DriveRight => "Drive right",
+            Self::RotateCounterclockwise => "Rotate counterclockwise",
+            Self::RotateClockwise => "Rotate clockwise",
+            Self::SpeedUp => "Speed up (hold)",
+            Self::SlowDown => "Slow down (hold)",
+            Self::TogglePause => "Pause/unpause",
+            Self::Reset => "Reset pacman game",
+            Self::NextRobot => "Next robot",
+            Self::PreviousRobot => "Previous robot",
+            Self::ToggleTargetPathMode => "Toggle target path mode",
+            Self::EmergencyStop => "Emergency stop",
+            Self::AngleFree => "Angle: free",
+            Self::AngleLocked => "Angle: locked",
+            Self::AngleFaceForward => "Angle: face forward",
+            Self::AngleAssistedDriving => "Angle: assisted driving",
+            Self::ReplayBack => "Replay: previous frame (shift: beginning)",
+            Self::ReplayForwards => "Replay: next frame (shift: end)",
+            Self::StrategyManual => "Strategy: manual",
+            Self::StrategyAi => "Strategy: AI",
+            Self::StrategyTestUniform => "Strategy: test uniform",
+            Self::StrategyTestForward => "Strategy: test forwards",
+            Self::ToggleConnection => "Toggle robot connection",
+            Self::GridPacman => "Grid: pacman",
+            Self::GridPlayground => "Grid: playground",
+            Self::GridOpen => "Grid: open",
+            Self::CvPointer => "CV position: mouse pointer",
+            Self::CvGameState => "CV position: game state",
+            Self::CvLocalization => "CV position: particle filter",
+            Self::FirstMotorForwards => "First motor forwards (hold)",
+            Self::FirstMotorBackwards => "First motor backwards (hold)",
+            Self::SecondMotorForwards => "Second motor forwards (hold)",
+            Self::SecondMotorBackwards => "Second motor backwards (hold)",
+            Self::ThirdMotorForwards => "Third motor forwards (hold)",
+            Self::ThirdMotorBackwards => "Third motor backwards (hold)",
+        }
+    }
+}
+
+/// Something that can be done with a gamepad stick
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AxisAction {
+    DriveX,
+    DriveY,
+    /// Positive is counterclockwise
+    Rotate,
+}
+
+impl AxisAction {
+    /// All axis actions, in the order used by [`Bindings::axes`]
+    pub fn get_all() -> [Self; NUM_AXIS_ACTIONS] {
+        [Self::DriveX, Self::DriveY, Self::Rotate]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::DriveX => "Drive x",
+            Self::DriveY => "Drive y",
+            Self::Rotate => "Rotate",
+        }
+    }
+}
+
+/// The key and gamepad button that trigger an [`Action`]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Binding {
+    pub key: Option<Key>,
+    pub button: Option<Button>,
+}
+
+/// The gamepad axis that controls an [`AxisAction`]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AxisBinding {
+    pub axis: Option<Axis>,
+    pub inverted: bool,
+}
+
+/// A binding waiting for the user to press the key or button, or move the stick, it should use
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Capture {
+    Key(Action),
+    Button(Action),
+    Axis(AxisAction),
+}
+
+/// Which keys and gamepad inputs do what, saved for each user
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Bindings {
+    /// Indexed by [`Action`]
+    pub actions: [Binding; NUM_ACTIONS],
+    /// Indexed by [`AxisAction`]
+    pub axes: [AxisBinding; NUM_AXIS_ACTIONS],
+    /// Stick values smaller than this are ignored
+    pub deadzone: f32,
+    /// Stick values past the deadzone are raised to this power; higher values give finer control
+    /// near the center
+    pub response_exponent: f32,
+    /// The gamepads whose inputs are ignored
+    pub disabled_gamepads: Vec<GamepadKey>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let mut actions = [Binding::default(); NUM_ACTIONS];
+        for (action, key, button) in [
+            (Action::DriveUp, Some(Key::W), Some(Button::DPadUp)),
+            (Action::DriveLeft, Some(Key::A), Some(Button::DPadLeft)),
+            (Action::DriveDown, Some(Key::S), Some(Button::DPadDown)),
+            (Action::DriveRight, Some(Key::D), Some(Button::DPadRight)),
+            (Action::RotateCounterclockwise, Some(Key::Q), None),
+            (Action::RotateClockwise, Some(Key::E), None),
+            (Action::SpeedUp, None, Some(Button::RightTrigger2)),
+            (Action::SlowDown, None, Some(Button::LeftTrigger2)),
+            (Action::TogglePause, Some(Key::Space), Some(Button::Start)),
+            (Action::Reset, Some(Key::R), None),
+            (Action::NextRobot, None, Some(Button::RightTrigger)),
+            (Action::PreviousRobot, None, Some(Button::LeftTrigger)),
+            (
+                Action::ToggleTargetPathMode,
+                Some(Key::Y),
+                Some(Button::Select),
+            ),
+            (Action::EmergencyStop, Some(Key::Escape), Some(Button::Mode)),
+            (Action::AngleFree, None, Some(Button::East)),
+            (Action::AngleLocked, None, Some(Button::West)),
+            (Action::AngleFaceForward, None, Some(Button::North)),
+            (Action::AngleAssistedDriving, None, Some(Button::South)),
+            (Action::ReplayBack, Some(Key::ArrowLeft), None),
+            (Action::ReplayForwards, Some(Key::ArrowRight), None),
+            (Action::StrategyManual, Some(Key::Z), None),
+            (Action::StrategyAi, Some(Key::X), None),
+            (Action::StrategyTestUniform, Some(Key::C), None),
+            (Action::StrategyTestForward, Some(Key::V), None),
+            (Action::ToggleConnection, Some(Key::P), None),
+            (Action::GridPacman, Some(Key::B), None),
+            (Action::GridPlayground, Some(Key::N), None),
+            (Action::GridOpen, Some(Key::M), None),
+            (Action::CvPointer, Some(Key::T), None),
+            (Action::CvGameState, Some(Key::G), None),
+            (Action::CvLocalization, Some(Key::H), None),
+            (Action::FirstMotorForwards, Some(Key::U), None),
+            (Action::FirstMotorBackwards, Some(Key::J), None),
+            (Action::SecondMotorForwards, Some(Key::I), None),
+            (Action::SecondMotorBackwards, Some(Key::K), None),
+            (Action::ThirdMotorForwards, Some(Key::O), None),
+            (Action::ThirdMotorBackwards, Some(Key::L), None),
+        ] {
+            actions[action as usize] = Binding { key, button };
+        }
+
+        Self {
+            actions,
+            axes: [
+                AxisBinding {
+                    axis: Some(Axis::LeftStickX),
+                    inverted: false,
+                },
+                AxisBinding {
+                    axis: Some(Axis::LeftStickY),
+                    inverted: false,
+                },
+                AxisBinding {
+                    axis: Some(Axis::RightStickX),
+                    inverted: true,
+                },
+            ],
+            deadzone: 0.1,
+            response_exponent: 1.0,
+            disabled_gamepads: vec![],
+        }
+    }
+}
+
+impl Bindings {
+    pub fn gamepad_enabled(&self, gilrs: &Gilrs, id: GamepadId) -> bool {
+        !self.disabled_gamepads.contains(&gamepad_key(gilrs, id))
+    }
+
+    /// Enable or disable a connected gamepad
+    pub fn set_gamepad_enabled(&mut self, gilrs: &Gilrs, id: GamepadId, enabled: bool) {
+        let key = gamepad_key(gilrs, id);
+        self.disabled_gamepads.retain(|k| *k != key);
+        if !enabled {
+            self.disabled_gamepads.push(key);
+        }
+    }
+
+    /// Connected gamepads that haven't been disabled
+    pub fn gamepads<'a>(&'a self, gilrs: &'a Gilrs) -> impl Iterator<Item = Gamepad<'a>> + 'a {
+        gilrs
+            .gamepads()
+            .filter(|(id, _)| self.gamepad_enabled(gilrs, *id))
+            .map(|(_, gamepad)| gamepad)
+    }
+
+    /// Whether the key or any enabled gamepad's button for the action is held down
+    pub fn held(&self, input: &InputState, gilrs: &Gilrs, action: Action) -> bool {
+        let binding = &self.actions[action as usize];
+        binding.key.is_some_and(|key| input.key_down(key))
+            || binding
+                .button
+                .is_some_and(|button| self.gamepads(gilrs).any(|gp| gp.is_pressed(button)))
+    }
+
+    /// Whether the key or gamepad button for the action was pressed this frame
+    ///
+    /// `gamepad_events` should only contain events from enabled gamepads
+    pub fn pressed(
+        &self,
+        input: &InputState,
+        gamepad_events: &[EventType],
+        action: Action,
+    ) -> bool {
+        let binding = &self.actions[action as usize];
+        binding.key.is_some_and(|key| input.key_pressed(key))
+            || binding.button.is_some_and(|button| {
+                gamepad_events
+                    .iter()
+                    .any(|event| matches!(event, EventType::ButtonPressed(b, _) if *b == button))
+            })
+    }
+
+    /// Whether the key or gamepad button for the action was released this frame
+    ///
+    /// `gamepad_events` should only contain events from enabled gamepads
+    pub fn released(
+        &self,
+        input: &InputState,
+        gamepad_events: &[EventType],
+        action: Action,
+    ) -> bool {
+        let binding = &self.actions[action as usize];
+        binding.key.is_some_and(|key| input.key_released(key))
+            || binding.button.is_some_and(|button| {
+                gamepad_events
+                    .iter()
+                    .any(|event| matches!(event, EventType::ButtonReleased(b, _) if *b == button))
+            })
+    }
+
+    /// The combined value of the action's axis on every enabled gamepad, from -1 to 1, after the
+    /// deadzone and response curve
+    pub fn axis(&self, gilrs: &Gilrs, action: AxisAction) -> f32 {
+        let binding = &self.axes[action as usize];
+        let Some(axis) = binding.axis else {
+            return 0.0;
+        };
+        let value: f32 = self.gamepads(gilrs).map(|gp| gp.value(axis)).sum();
+        let value = self.response(value.clamp(-1.0, 1.0));
+        if binding.inverted {
+            -value
+        } else {
+            value
+        }
+    }
+
+    /// Apply the deadzone and response curve to a stick value from -1 to 1
+    pub fn response(&self, value: f32) -> f32 {
+        let magnitude = ((value.abs() - self.deadzone) / (1.0 - self.deadzone)).clamp(0.0, 1.0);
+        magnitude.powf(self.response_exponent).copysign(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions_are_in_order() {
+        for (i, action) in Action::get_all().into_iter().enumerate() {
+            assert_eq!(i, action as usize);
+        }
+        for (i, action) in AxisAction::get_all().into_iter().enumerate() {
+            assert_eq!(i, action as usize);
+        }
+    }
+
+    #[test]
+    fn default_keys_are_unique() {
+        let bindings = Bindings::default();
+        let keys: Vec<_> = bindings.actions.iter().flat_map(|b| b.key).collect();
+        for (i, key) in keys.iter().enumerate() {
+            assert!(!keys[i + 1..].contains(key), "{key:?}");
+        }
+    }
+
+    #[test]
+    fn response_curve() {
+        let bindings = Bindings {
+            deadzone: 0.2,
+            response_exponent: 2.0,
+            ..Default::default()
+        };
+        assert_eq!(bindings.response(0.1), 0.0);
+        assert_eq!(bindings.response(-0.2), 0.0);
+        assert_eq!(bindings.response(1.0), 1.0);
+        assert_eq!(bindings.response(-1.0), -1.0);
+        assert!((bindings.response(0.6) - 0.25).abs() < 1e-6);
+        assert!((bindings.response(-0.6) + 0.25).abs() < 1e-6);
+    }
+}
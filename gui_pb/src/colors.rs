@@ -15,6 +15,22 @@ pub const PACMAN_DISTANCE_SENSOR_RAY_COLOR: Color32 = Color32::GREEN;
 pub const PACMAN_AI_TARGET_LOCATION_COLOR: Color32 =
     Color32::from_rgba_premultiplied(128, 0, 128, 255);
 
+pub const LOCALIZATION_BEST_CANDIDATE_COLOR: Color32 = Color32::from_rgb(0, 200, 0);
+pub const LOCALIZATION_CANDIDATE_COLOR: Color32 = Color32::from_rgb(255, 140, 0);
+pub const LOCALIZATION_EXPECTED_RAY_COLOR: Color32 = Color32::from_rgb(0, 160, 255);
+pub const LOCALIZATION_MEASURED_RAY_COLOR: Color32 = Color32::from_rgb(255, 0, 255);
+pub const LOCALIZATION_CORRIDOR_COLOR: Color32 = Color32::from_rgb(255, 220, 0);
+/// Shadow mode estimates, indexed by [`core_pb::messages::common::LocalizationAlgorithmSource`]
+pub const LOCALIZATION_SHADOW_COLORS: [Color32; 3] = [
+    Color32::from_rgb(255, 80, 80),
+    Color32::from_rgb(80, 220, 255),
+    Color32::from_rgb(200, 120, 255),
+];
+
+pub const PATH_EDITOR_WAYPOINT_COLOR: Color32 = Color32::LIGHT_BLUE;
+pub const PATH_EDITOR_PATH_COLOR: Color32 = Color32::from_rgb(0, 160, 255);
+pub const PATH_EDITOR_UNREACHABLE_COLOR: Color32 = Color32::RED;
+
 pub const GHOST_RED_COLOR: Color32 = Color32::RED;
 pub const GHOST_PINK_COLOR: Color32 = Color32::from_rgb(255, 192, 203);
 pub const GHOST_ORANGE_COLOR: Color32 = Color32::from_rgb(255, 140, 0);
@@ -5,12 +5,15 @@ use crate::App;
 use core_pb::constants::GU_PER_M;
 use core_pb::grid::standard_grid::StandardGrid;
 use core_pb::localization::region_localization::{get_possible_regions, is_close_to_box};
+use core_pb::messages::common::LocalizationAlgorithmSource;
 use core_pb::names::RobotName;
 use core_pb::pacbot_rs::ghost_state::GhostColor;
 use core_pb::robot_definition::RobotDefinition;
 use core_pb::util::TRANSLUCENT_YELLOW_COLOR;
-use eframe::egui::{Color32, CornerRadius, Painter, Pos2, Rect, Stroke, StrokeKind};
-use nalgebra::{Point2, Rotation2};
+use eframe::egui::{
+    Align2, Color32, CornerRadius, FontId, Painter, Pos2, Rect, Stroke, StrokeKind, Vec2,
+};
+use nalgebra::{Point2, Rotation2, Vector2};
 
 pub fn draw_grid(app: &mut App, painter: &Painter) {
     let wts = app.world_to_screen;
@@ -28,7 +31,7 @@ pub fn draw_grid(app: &mut App, painter: &Painter) {
     }
 
     // make sure the area outside the soft boundary is not drawn on
-    for (p1, p2) in app.settings.standard_grid.get_outside_soft_boundaries() {
+    for (p1, p2) in app.grid.get_outside_soft_boundaries() {
         painter.rect(
             Rect::from_two_pos(wts.map_point2(p1), wts.map_point2(p2)),
             CornerRadius::ZERO,
@@ -191,7 +194,7 @@ pub fn draw_game(app: &mut App, painter: &Painter) {
 
     // draw possible region boundaries
     for (region, point) in get_possible_regions(
-        app.grid.standard_grid().unwrap(),
+        &app.localization_grid,
         app.server_status.robots[app.ui_settings.selected_robot as usize]
             .distance_sensors
             .clone()
@@ -222,7 +225,10 @@ pub fn draw_game(app: &mut App, painter: &Painter) {
         painter.circle_filled(wts.map_point2(point), 2.0, Color32::RED);
     }
 
-    if app.settings.standard_grid != StandardGrid::Pacman {
+    draw_localization_debug(app, painter);
+    draw_localization_shadow(app, painter);
+
+    if *app.grid.standard_grid() != Some(StandardGrid::Pacman) {
         return;
     }
 
@@ -269,3 +275,133 @@ pub fn draw_game(app: &mut App, painter: &Painter) {
         }
     }
 }
+
+/// Show why the selected robot's localization chose its estimated location, if it was asked to
+/// report that
+fn draw_localization_debug(app: &App, painter: &Painter) {
+    let name = app.ui_settings.selected_robot;
+    if !app.settings.robots[name as usize]
+        .config
+        .enable_localization_debug
+    {
+        return;
+    }
+    let Some(debug) = &app.server_status.robots[name as usize].localization_debug else {
+        return;
+    };
+    let wts = app.world_to_screen;
+    let to_screen = |p: Point2<f32>| wts.map_point(Pos2::new(p.x, p.y));
+
+    // candidate regions, from worst to best so that the best is drawn on top
+    for (i, candidate) in debug.candidates.iter().enumerate().rev() {
+        let color = if i == 0 {
+            LOCALIZATION_BEST_CANDIDATE_COLOR
+        } else {
+            LOCALIZATION_CANDIDATE_COLOR
+        };
+        painter.rect_stroke(
+            Rect::from_two_pos(
+                to_screen(candidate.low_xy.map(|x| x as f32)),
+                to_screen(candidate.high_xy.map(|x| x as f32)),
+            ),
+            CornerRadius::ZERO,
+            Stroke::new(if i == 0 { 2.0 } else { 1.0 }, color),
+            StrokeKind::Middle,
+        );
+        painter.circle_filled(to_screen(candidate.location), 3.0, color);
+        painter.text(
+            to_screen(candidate.location),
+            Align2::LEFT_BOTTOM,
+            format!("{:.2}", candidate.score),
+            FontId::monospace(10.0),
+            color,
+        );
+    }
+
+    // the cells corridor calculated position is moving between
+    if let Some((previous, next)) = debug.corridor_targets {
+        let (previous, next) = (previous.map(|x| x as f32), next.map(|x| x as f32));
+        painter.arrow(
+            to_screen(previous),
+            to_screen(next) - to_screen(previous),
+            Stroke::new(2.0, LOCALIZATION_CORRIDOR_COLOR),
+        );
+        painter.circle_stroke(
+            to_screen(previous),
+            wts.map_dist(0.3),
+            Stroke::new(1.0, LOCALIZATION_CORRIDOR_COLOR),
+        );
+    }
+
+    // expected distance sensor rays, with a mark where each sensor actually measured
+    if let Some(origin) = debug.ray_origin {
+        let origin = origin.map(|x| x as f32);
+        let radius = name.robot().radius;
+        for i in 0..4 {
+            let dir = Vector2::new(
+                f32::cos(i as f32 * f32::consts::FRAC_PI_2),
+                f32::sin(i as f32 * f32::consts::FRAC_PI_2),
+            );
+            let normal = Vector2::new(-dir.y, dir.x) * 0.25;
+            let expected = origin + dir * (debug.expected_rays[i] + radius);
+            painter.line_segment(
+                [to_screen(origin + dir * radius), to_screen(expected)],
+                Stroke::new(1.0, LOCALIZATION_EXPECTED_RAY_COLOR),
+            );
+            painter.line_segment(
+                [to_screen(expected - normal), to_screen(expected + normal)],
+                Stroke::new(2.0, LOCALIZATION_EXPECTED_RAY_COLOR),
+            );
+            if let Some(measured) = debug.measured_rays[i] {
+                let measured = origin + dir * (measured + radius);
+                painter.line_segment(
+                    [to_screen(measured - normal), to_screen(measured + normal)],
+                    Stroke::new(2.0, LOCALIZATION_MEASURED_RAY_COLOR),
+                );
+            }
+        }
+    }
+}
+
+/// Show where every localization algorithm thinks the selected robot is, if it is running them in
+/// shadow mode, with their distances from the simulated robot
+fn draw_localization_shadow(app: &App, painter: &Painter) {
+    let name = app.ui_settings.selected_robot;
+    if !app.settings.robots[name as usize]
+        .config
+        .enable_localization_shadow
+    {
+        return;
+    }
+    let status = &app.server_status.robots[name as usize];
+    let Some(estimates) = status.shadow_locations else {
+        return;
+    };
+    let wts = app.world_to_screen;
+
+    let mut legend_pos = painter.clip_rect().left_top() + Vec2::splat(5.0);
+    for algorithm in LocalizationAlgorithmSource::get_all() {
+        let color = LOCALIZATION_SHADOW_COLORS[algorithm as usize];
+        let estimate = estimates[algorithm as usize];
+        if let Some(p) = estimate {
+            painter.circle_stroke(
+                wts.map_point(Pos2::new(p.x, p.y)),
+                wts.map_dist(name.robot().radius),
+                Stroke::new(2.0, color),
+            );
+        }
+        let error = match (estimate, status.sim_position) {
+            (Some(p), Some((actual, _))) => format!("{:.2}", (p - actual).magnitude()),
+            (Some(_), None) => "-".to_string(),
+            (None, _) => "lost".to_string(),
+        };
+        let text = painter.text(
+            legend_pos,
+            Align2::LEFT_TOP,
+            format!("{algorithm:?}: {error}"),
+            FontId::monospace(12.0),
+            color,
+        );
+        legend_pos.y = text.bottom();
+    }
+}
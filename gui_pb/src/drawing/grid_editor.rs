@@ -0,0 +1,217 @@
+use crate::colors::*;
+use crate::drawing::settings::dropdown;
+use crate::App;
+use anyhow::Error;
+use core_pb::grid::computed_grid::ComputedGrid;
+use core_pb::grid::standard_grid::{StandardGrid, GRID_PACMAN};
+use core_pb::grid::{grid_from_text, grid_to_text, Grid, GRID_SIZE};
+use core_pb::pacbot_rs::variables::PACMAN_SPAWN_LOC;
+use eframe::egui;
+use eframe::egui::{Color32, CornerRadius, Pos2, Rect, Sense, Stroke, StrokeKind, Ui, Vec2};
+use log::error;
+#[cfg(not(target_arch = "wasm32"))]
+use native_dialog::FileDialog;
+
+/// Draws custom grids, which can be used by the server and simulation instead of a
+/// [`StandardGrid`]
+pub struct GridEditor {
+    pub grid: Grid,
+    /// Why `grid` can't be used, if it can't
+    validation: Result<(), String>,
+    /// While dragging, whether cells are being made walls or walkable
+    painting: Option<bool>,
+    /// The grid loaded by "Load template"
+    template: StandardGrid,
+}
+
+impl Default for GridEditor {
+    fn default() -> Self {
+        Self {
+            grid: GRID_PACMAN,
+            validation: Ok(()),
+            painting: None,
+            template: StandardGrid::Pacman,
+        }
+    }
+}
+
+impl GridEditor {
+    /// Replace the grid being edited
+    pub fn set_grid(&mut self, grid: Grid) {
+        self.grid = grid;
+        self.validate();
+    }
+
+    fn validate(&mut self) {
+        self.validation = ComputedGrid::try_from_custom(self.grid).map(|_| ());
+    }
+}
+
+/// Save a grid as text to a file chosen by the user
+#[cfg(not(target_arch = "wasm32"))]
+fn export_grid(grid: &Grid) -> Result<(), Error> {
+    let path = FileDialog::new()
+        .add_filter("Grid", &["txt"])
+        .set_filename("grid.txt")
+        .show_save_single_file()?;
+
+    if let Some(path) = path {
+        std::fs::write(path, grid_to_text(grid))?;
+    }
+
+    Ok(())
+}
+
+/// Read a grid from a text file chosen by the user
+#[cfg(not(target_arch = "wasm32"))]
+fn import_grid() -> Result<Option<Grid>, Error> {
+    let path = FileDialog::new()
+        .add_filter("Grid", &["txt"])
+        .show_open_single_file()?;
+
+    match path {
+        Some(path) => {
+            let text = std::fs::read_to_string(path)?;
+            Ok(Some(grid_from_text(&text).map_err(Error::msg)?))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Save a grid as text to a file chosen by the user
+#[cfg(target_arch = "wasm32")]
+fn export_grid(_grid: &Grid) -> Result<(), Error> {
+    Err(anyhow::anyhow!("Grids can't be exported from the web gui"))
+}
+
+/// Read a grid from a text file chosen by the user
+#[cfg(target_arch = "wasm32")]
+fn import_grid() -> Result<Option<Grid>, Error> {
+    Err(anyhow::anyhow!("Grids can't be imported from the web gui"))
+}
+
+pub fn draw_grid_editor(app: &mut App, ui: &mut Ui) {
+    let editor = &mut app.grid_editor;
+
+    ui.horizontal(|ui| {
+        dropdown(
+            ui,
+            "grid_editor_template".to_string(),
+            "",
+            &mut editor.template,
+            &StandardGrid::get_all(),
+        );
+        if ui.button("Load template").clicked() {
+            editor.set_grid(editor.template.get_grid());
+        }
+        if ui.button("Load current grid").clicked() {
+            editor.set_grid(*app.grid.grid());
+        }
+        ui.separator();
+        if ui.button("Import text").clicked() {
+            match import_grid() {
+                Ok(Some(grid)) => editor.set_grid(grid),
+                Ok(None) => {}
+                Err(e) => error!("Failed to import grid: {e:?}"),
+            }
+        }
+        if ui.button("Export text").clicked() {
+            if let Err(e) = export_grid(&editor.grid) {
+                error!("Failed to export grid: {e:?}");
+            }
+        }
+        ui.separator();
+        if ui
+            .add_enabled(
+                editor.validation.is_ok(),
+                egui::Button::new("Push to server"),
+            )
+            .on_hover_text("Use this grid in the server and simulation")
+            .clicked()
+        {
+            app.settings.custom_grid = Some(editor.grid);
+        }
+        if ui
+            .add_enabled(
+                app.settings.custom_grid.is_some(),
+                egui::Button::new("Use standard grid"),
+            )
+            .clicked()
+        {
+            app.settings.custom_grid = None;
+        }
+    });
+    match &editor.validation {
+        Ok(()) => ui.colored_label(Color32::GREEN, "Valid"),
+        Err(e) => ui.colored_label(Color32::RED, e),
+    };
+    ui.separator();
+
+    // the grid is shown the same way as its text: rows top to bottom, columns left to right
+    let size = ui.available_size().min_elem();
+    let (response, painter) = ui.allocate_painter(Vec2::splat(size), Sense::click_and_drag());
+    let rect = response.rect;
+    let cell_size = size / GRID_SIZE as f32;
+    let cell_rect = |row: usize, col: usize| {
+        Rect::from_min_size(
+            rect.min + Vec2::new(col as f32, row as f32) * cell_size,
+            Vec2::splat(cell_size),
+        )
+    };
+
+    let mut changed = false;
+    if let Some(pos) = response.interact_pointer_pos() {
+        let cell = (pos - rect.min) / cell_size;
+        if (0.0..GRID_SIZE as f32).contains(&cell.x) && (0.0..GRID_SIZE as f32).contains(&cell.y) {
+            let (row, col) = (cell.y as usize, cell.x as usize);
+            if response.clicked() {
+                editor.grid[row][col] = !editor.grid[row][col];
+                changed = true;
+            }
+            if response.drag_started() {
+                editor.painting = Some(!editor.grid[row][col]);
+            }
+            if let Some(wall) = editor.painting {
+                changed |= editor.grid[row][col] != wall;
+                editor.grid[row][col] = wall;
+            }
+        }
+    }
+    if response.drag_stopped() {
+        editor.painting = None;
+    }
+    if changed {
+        editor.validate();
+    }
+
+    for row in 0..GRID_SIZE {
+        for col in 0..GRID_SIZE {
+            painter.rect(
+                cell_rect(row, col),
+                CornerRadius::ZERO,
+                if editor.grid[row][col] {
+                    WALL_COLOR
+                } else {
+                    app.background_color
+                },
+                Stroke::new(0.5, Color32::DARK_GRAY),
+                StrokeKind::Inside,
+            );
+        }
+    }
+    painter.circle_filled(
+        cell_rect(PACMAN_SPAWN_LOC.row as usize, PACMAN_SPAWN_LOC.col as usize).center(),
+        cell_size * 0.3,
+        PACMAN_COLOR,
+    );
+    if let Some(pos) = response.hover_pos() {
+        let cell = (pos - rect.min) / cell_size;
+        ui.painter().text(
+            Pos2::new(rect.right(), rect.top()),
+            egui::Align2::RIGHT_TOP,
+            format!("({}, {})", cell.y as usize, cell.x as usize),
+            egui::FontId::monospace(12.0),
+            Color32::WHITE,
+        );
+    }
+}
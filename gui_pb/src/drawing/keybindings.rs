@@ -0,0 +1,125 @@
+use crate::bindings::{gamepad_key, Action, AxisAction, Bindings, Capture};
+use crate::App;
+use eframe::egui;
+use eframe::egui::Ui;
+
+/// A button showing a binding; clicking it waits for a new one and right clicking removes it
+///
+/// Returns whether the binding should be removed
+fn binding_button(
+    ui: &mut Ui,
+    binding_capture: &mut Option<Capture>,
+    capture: Capture,
+    current: Option<String>,
+) -> bool {
+    let text = if *binding_capture == Some(capture) {
+        "Waiting...".to_string()
+    } else {
+        current.unwrap_or_else(|| "-".to_string())
+    };
+    let response = ui
+        .button(text)
+        .on_hover_text("Click to change, right click to remove");
+    if response.clicked() {
+        *binding_capture = Some(capture);
+    }
+    response.secondary_clicked()
+}
+
+pub fn draw_keybindings(app: &mut App, ui: &mut Ui) {
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        let bindings = &mut app.bindings;
+        let capture = &mut app.binding_capture;
+
+        egui::Grid::new("action_bindings").show(ui, |ui| {
+            ui.strong("Action");
+            ui.strong("Key");
+            ui.strong("Gamepad");
+            ui.end_row();
+            for action in Action::get_all() {
+                let binding = &mut bindings.actions[action as usize];
+                ui.label(action.name());
+                let key = binding.key.map(|key| key.name().to_string());
+                if binding_button(ui, capture, Capture::Key(action), key) {
+                    binding.key = None;
+                }
+                let button = binding.button.map(|button| format!("{button:?}"));
+                if binding_button(ui, capture, Capture::Button(action), button) {
+                    binding.button = None;
+                }
+                ui.end_row();
+            }
+        });
+        ui.separator();
+
+        egui::Grid::new("axis_bindings").show(ui, |ui| {
+            ui.strong("Stick");
+            ui.strong("Axis");
+            ui.end_row();
+            for action in AxisAction::get_all() {
+                let binding = &mut bindings.axes[action as usize];
+                ui.label(action.name());
+                let axis = binding.axis.map(|axis| format!("{axis:?}"));
+                if binding_button(ui, capture, Capture::Axis(action), axis) {
+                    binding.axis = None;
+                }
+                ui.checkbox(&mut binding.inverted, "Inverted");
+                ui.end_row();
+            }
+        });
+        ui.add(egui::Slider::new(&mut bindings.deadzone, 0.0..=0.9).text("Deadzone"));
+        ui.add(
+            egui::Slider::new(&mut bindings.response_exponent, 0.5..=3.0).text("Response curve"),
+        )
+        .on_hover_text("Higher values give finer control near the center of the stick");
+        ui.horizontal(|ui| {
+            if capture.is_some() && ui.button("Cancel").clicked() {
+                *capture = None;
+            }
+            if ui.button("Reset to defaults").clicked() {
+                *bindings = Bindings::default();
+            }
+        });
+        ui.separator();
+
+        ui.label("Gamepads");
+        let mut any_gamepads = false;
+        let mut any_identical = false;
+        for (id, gamepad) in app.gilrs.gamepads() {
+            any_gamepads = true;
+            let mut enabled = bindings.gamepad_enabled(&app.gilrs, id);
+            // number identical gamepads, so they can be told apart
+            let label = match gamepad_key(&app.gilrs, id) {
+                (_, 0) => gamepad.name().to_string(),
+                (_, index) => {
+                    any_identical = true;
+                    format!("{} ({})", gamepad.name(), index + 1)
+                }
+            };
+            if ui.checkbox(&mut enabled, label).changed() {
+                bindings.set_gamepad_enabled(&app.gilrs, id, enabled);
+            }
+        }
+        if !any_gamepads {
+            ui.label("None connected");
+        }
+        if any_identical {
+            ui.label(
+                "Identical gamepads are numbered in the order they were connected; if they are \
+                reconnected in a different order, which one is disabled changes",
+            );
+        }
+        ui.separator();
+
+        ui.label("Mouse and modifiers, which can't be changed");
+        ui.label("[Left click] Set simulated robot position");
+        ui.label("[Right click] Set target position");
+        ui.label("[shift] Drive faster");
+        ui.label("[ctrl] Drive slower");
+        ui.separator();
+        ui.label("While editing a path");
+        ui.label("[Left click] Add waypoint");
+        ui.label("[Left drag] Move waypoint");
+        ui.label("[Right click] Remove waypoint");
+    });
+}
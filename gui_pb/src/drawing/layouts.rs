@@ -0,0 +1,263 @@
+use crate::drawing::tab::Tab;
+use crate::App;
+use eframe::egui;
+use eframe::egui::Ui;
+use egui_dock::{DockState, NodeIndex};
+use serde::{Deserialize, Serialize};
+
+/// The key [`Layouts`] are stored under in eframe's per user storage
+pub const LAYOUTS_STORAGE_KEY: &str = "layouts";
+/// The key [`crate::drawing::settings::UiSettings`] are stored under in eframe's per user storage
+pub const UI_SETTINGS_STORAGE_KEY: &str = "ui_settings";
+
+/// The layout used when there is nothing saved
+pub const DEFAULT_LAYOUT: &str = "Default";
+/// The names of the layouts made by [`built_in_layout`]
+pub const BUILT_IN_LAYOUTS: [&str; 4] = [DEFAULT_LAYOUT, "Driving", "Tuning", "OTA"];
+
+/// The layouts that always exist; they can be changed, and reset to how they started
+pub fn built_in_layout(name: &str) -> Option<DockState<Tab>> {
+    match name {
+        DEFAULT_LAYOUT => {
+            let mut dock_state = DockState::new(vec![
+                Tab::Grid,
+                Tab::Motors,
+                Tab::Robot,
+                Tab::Stopwatch,
+                Tab::ExtraOpts,
+                Tab::Imu,
+                Tab::Telemetry,
+                Tab::GridEditor,
+            ]);
+            let surface = dock_state.main_surface_mut();
+            surface.split_right(NodeIndex::root(), 0.75, vec![Tab::Settings]);
+            let [_, left] = surface.split_left(
+                NodeIndex::root(),
+                0.15,
+                vec![
+                    Tab::OverTheAirProgramming,
+                    Tab::Keybindings,
+                    Tab::PathEditor,
+                ],
+            );
+            let [_, below] = surface.split_below(left, 0.6, vec![Tab::RobotDisplay]);
+            surface.split_below(below, 0.6, vec![Tab::RobotButtonPanel]);
+            Some(dock_state)
+        }
+        "Driving" => {
+            let mut dock_state = DockState::new(vec![Tab::Grid, Tab::Robot]);
+            let surface = dock_state.main_surface_mut();
+            surface.split_right(NodeIndex::root(), 0.75, vec![Tab::Settings]);
+            surface.split_left(
+                NodeIndex::root(),
+                0.2,
+                vec![Tab::PathEditor, Tab::Keybindings],
+            );
+            Some(dock_state)
+        }
+        "Tuning" => {
+            let mut dock_state = DockState::new(vec![Tab::Motors, Tab::Imu, Tab::ExtraOpts]);
+            let surface = dock_state.main_surface_mut();
+            let [main, _] = surface.split_right(NodeIndex::root(), 0.75, vec![Tab::Settings]);
+            let [main, _] = surface.split_right(main, 0.5, vec![Tab::Grid]);
+            surface.split_below(main, 0.6, vec![Tab::Telemetry, Tab::Stopwatch]);
+            Some(dock_state)
+        }
+        "OTA" => {
+            let mut dock_state = DockState::new(vec![Tab::OverTheAirProgramming]);
+            let surface = dock_state.main_surface_mut();
+            let [main, _] = surface.split_right(NodeIndex::root(), 0.6, vec![Tab::Settings]);
+            surface.split_below(main, 0.6, vec![Tab::RobotDisplay]);
+            Some(dock_state)
+        }
+        _ => None,
+    }
+}
+
+/// Named arrangements of tabs, saved for each user
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Layouts {
+    /// The name of the layout being shown
+    pub current: String,
+    /// Every layout; the current one is only updated when switching or saving
+    pub layouts: Vec<(String, DockState<Tab>)>,
+    /// The name to give the current layout when it is saved as a new one
+    #[serde(skip)]
+    pub new_name: String,
+}
+
+impl Default for Layouts {
+    fn default() -> Self {
+        Self {
+            current: DEFAULT_LAYOUT.to_string(),
+            layouts: BUILT_IN_LAYOUTS
+                .into_iter()
+                .filter_map(|name| Some((name.to_string(), built_in_layout(name)?)))
+                .collect(),
+            new_name: String::new(),
+        }
+    }
+}
+
+impl Layouts {
+    pub fn get(&self, name: &str) -> Option<&DockState<Tab>> {
+        self.layouts
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, dock_state)| dock_state)
+    }
+
+    /// Save a layout under the given name, replacing any layout with the same name
+    pub fn set(&mut self, name: String, dock_state: DockState<Tab>) {
+        match self.layouts.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, existing)) => *existing = dock_state,
+            None => self.layouts.push((name, dock_state)),
+        }
+    }
+
+    /// Add the built in layouts that are missing, such as ones added since the layouts were saved
+    pub fn add_missing_built_ins(&mut self) {
+        for name in BUILT_IN_LAYOUTS {
+            if self.get(name).is_none() {
+                if let Some(dock_state) = built_in_layout(name) {
+                    self.layouts.push((name.to_string(), dock_state));
+                }
+            }
+        }
+    }
+}
+
+impl App {
+    /// Copy the tabs being shown into [`Layouts`]
+    fn store_layout(&mut self) {
+        if let Some(dock_state) = &self.dock_state {
+            self.layouts
+                .set(self.layouts.current.clone(), dock_state.clone());
+        }
+    }
+
+    pub fn switch_layout(&mut self, name: String) {
+        self.store_layout();
+        if let Some(dock_state) = self.layouts.get(&name) {
+            self.dock_state = Some(dock_state.clone());
+            self.layouts.current = name;
+        }
+    }
+
+    /// Save the layouts and [`crate::drawing::settings::UiSettings`] to eframe's per user
+    /// storage
+    pub fn save_ui_state(&mut self, storage: &mut dyn eframe::Storage) {
+        self.store_layout();
+        eframe::set_value(storage, LAYOUTS_STORAGE_KEY, &self.layouts);
+        eframe::set_value(storage, UI_SETTINGS_STORAGE_KEY, &self.ui_settings);
+    }
+}
+
+/// The layout menu, for switching between and managing layouts
+pub fn draw_layout_menu(app: &mut App, ui: &mut Ui) {
+    let mut switch_to = None;
+    for (name, _) in &app.layouts.layouts {
+        if ui
+            .selectable_label(*name == app.layouts.current, name)
+            .clicked()
+        {
+            switch_to = Some(name.clone());
+        }
+    }
+    if let Some(name) = switch_to {
+        app.switch_layout(name);
+    }
+    ui.separator();
+
+    ui.horizontal(|ui| {
+        ui.text_edit_singleline(&mut app.layouts.new_name);
+        if ui
+            .add_enabled(
+                !app.layouts.new_name.is_empty(),
+                egui::Button::new("Save as"),
+            )
+            .clicked()
+        {
+            app.store_layout();
+            if let Some(dock_state) = app.dock_state.clone() {
+                let name = std::mem::take(&mut app.layouts.new_name);
+                app.layouts.set(name.clone(), dock_state);
+                app.layouts.current = name;
+            }
+        }
+    });
+    let built_in = built_in_layout(&app.layouts.current);
+    if built_in.is_some() {
+        if ui.button("Reset layout").clicked() {
+            app.dock_state = built_in;
+        }
+    } else if ui.button("Delete layout").clicked() {
+        let current = app.layouts.current.clone();
+        app.layouts.layouts.retain(|(name, _)| *name != current);
+        app.layouts.current = DEFAULT_LAYOUT.to_string();
+        app.dock_state = Some(
+            app.layouts
+                .get(DEFAULT_LAYOUT)
+                .cloned()
+                .unwrap_or_else(|| built_in_layout(DEFAULT_LAYOUT).unwrap()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tabs(dock_state: &DockState<Tab>) -> Vec<Tab> {
+        dock_state
+            .iter_all_tabs()
+            .map(|(_, tab)| tab.clone())
+            .collect()
+    }
+
+    #[test]
+    fn layouts_round_trip() {
+        let mut layouts = Layouts::default();
+        layouts.set(
+            "Custom".to_string(),
+            DockState::new(vec![Tab::Grid, Tab::Settings]),
+        );
+        layouts.set(
+            DEFAULT_LAYOUT.to_string(),
+            DockState::new(vec![Tab::Motors]),
+        );
+        layouts.current = "Custom".to_string();
+        layouts.new_name = "Unsaved".to_string();
+
+        let loaded: Layouts = ron::from_str(&ron::to_string(&layouts).unwrap()).unwrap();
+        assert_eq!(loaded.current, "Custom");
+        assert_eq!(loaded.new_name, "");
+        assert_eq!(loaded.layouts.len(), layouts.layouts.len());
+        assert_eq!(
+            tabs(loaded.get("Custom").unwrap()),
+            vec![Tab::Grid, Tab::Settings]
+        );
+        assert_eq!(tabs(loaded.get(DEFAULT_LAYOUT).unwrap()), vec![Tab::Motors]);
+        assert!(loaded.get("Missing").is_none());
+    }
+
+    #[test]
+    fn missing_built_ins_are_added() {
+        let mut layouts = Layouts {
+            current: "Custom".to_string(),
+            layouts: vec![
+                ("Custom".to_string(), DockState::new(vec![Tab::Grid])),
+                ("Driving".to_string(), DockState::new(vec![Tab::Robot])),
+            ],
+            new_name: String::new(),
+        };
+        layouts.add_missing_built_ins();
+        for name in BUILT_IN_LAYOUTS {
+            assert!(layouts.get(name).is_some(), "{name}");
+        }
+        // saved layouts are kept, even when they replace a built in one
+        assert_eq!(tabs(layouts.get("Custom").unwrap()), vec![Tab::Grid]);
+        assert_eq!(tabs(layouts.get("Driving").unwrap()), vec![Tab::Robot]);
+        assert_eq!(layouts.layouts.len(), BUILT_IN_LAYOUTS.len() + 1);
+    }
+}
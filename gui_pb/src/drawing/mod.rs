@@ -1,10 +1,15 @@
 pub mod extra_opts;
 pub mod game;
+pub mod grid_editor;
 pub mod imu;
+pub mod keybindings;
+pub mod layouts;
 pub mod motors;
 pub mod over_the_air;
+pub mod path_editor;
 pub mod replay_manager;
 pub mod settings;
 pub mod tab;
+pub mod telemetry;
 mod timings;
 pub mod widgets;
@@ -233,10 +233,14 @@ pub fn draw_motors(app: &mut App, ui: &mut Ui) {
         });
     });
 
-    if app.ui_settings.record_motor_data {
-        let (dur, status) =
-            &app.server_status.robots[app.ui_settings.selected_robot as usize].last_motor_status;
-        let x = dur.as_secs_f64();
+    let robot = &app.server_status.robots[app.ui_settings.selected_robot as usize];
+    // frames can't be placed on the server's timeline until the robot's clock is known
+    if let Some(time) = robot
+        .motor_status_time
+        .filter(|_| app.ui_settings.record_motor_data)
+    {
+        let status = &robot.last_motor_status.1;
+        let x = time.as_secs_f64();
         if app.motor_status_frames.first_x.is_none() {
             app.motor_status_frames.first_x = Some(x);
         }
@@ -264,7 +268,7 @@ pub fn draw_motors(app: &mut App, ui: &mut Ui) {
     }
 
     Plot::new("motor_plot")
-        .x_axis_label("uptime (s)")
+        .x_axis_label("server time (s)")
         .legend(Legend::default())
         .show(ui, |plot_ui| {
             for m in 0..3 {
@@ -0,0 +1,270 @@
+use crate::colors::*;
+use crate::App;
+use anyhow::Error;
+use core_pb::grid::computed_grid::ComputedGrid;
+use core_pb::messages::GuiToServerMessage;
+use eframe::egui;
+use eframe::egui::{Align2, Color32, FontId, Painter, Pos2, Response, Stroke, Ui};
+use log::error;
+use nalgebra::Point2;
+#[cfg(not(target_arch = "wasm32"))]
+use native_dialog::FileDialog;
+use serde::{Deserialize, Serialize};
+
+/// How close, in grid units, the pointer must be to a waypoint to drag or remove it
+const WAYPOINT_GRAB_DIST: f32 = 0.5;
+
+/// A target path that was given a name, so that it can be sent again later
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedPath {
+    pub name: String,
+    pub waypoints: Vec<Point2<i8>>,
+}
+
+/// Draws custom target paths on the Grid tab
+///
+/// While editing, left clicking the grid adds a waypoint, dragging moves one, and right clicking
+/// removes one; consecutive waypoints are connected by the shortest path
+#[derive(Default)]
+pub struct PathEditor {
+    pub editing: bool,
+    pub waypoints: Vec<Point2<i8>>,
+    /// The name to give the path when it is saved
+    pub name: String,
+    pub saved: Vec<SavedPath>,
+    /// The index of the waypoint being dragged
+    dragging: Option<usize>,
+}
+
+impl PathEditor {
+    /// The index of the waypoint nearest to the given grid position, if it is close enough to grab
+    fn waypoint_near(&self, pos: Pos2) -> Option<usize> {
+        self.waypoints
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (i, (p.x as f32 - pos.x).hypot(p.y as f32 - pos.y)))
+            .filter(|(_, dist)| *dist < WAYPOINT_GRAB_DIST)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    /// The path the robot would follow from `start` through the waypoints, if every waypoint is
+    /// reachable
+    pub fn preview(
+        &self,
+        grid: &ComputedGrid,
+        start: Option<Point2<i8>>,
+    ) -> Option<Vec<Point2<i8>>> {
+        let waypoints: Vec<_> = start.into_iter().chain(self.waypoints.clone()).collect();
+        grid.bfs_path_through(&waypoints)
+    }
+
+    /// Save the current waypoints under the current name, replacing any path with the same name
+    pub fn save_current(&mut self) {
+        let path = SavedPath {
+            name: self.name.clone(),
+            waypoints: self.waypoints.clone(),
+        };
+        match self.saved.iter_mut().find(|p| p.name == self.name) {
+            Some(existing) => *existing = path,
+            None => self.saved.push(path),
+        }
+    }
+}
+
+/// Handle clicks and drags on the Grid tab
+pub fn edit_path(app: &mut App, response: &Response) {
+    let editor = &mut app.path_editor;
+    if !editor.editing {
+        editor.dragging = None;
+        return;
+    }
+    let Some(pos) = response.interact_pointer_pos() else {
+        return;
+    };
+    let pos = app.world_to_screen.inverse().map_point(pos);
+    let node = app.grid.node_nearest(pos.x, pos.y);
+
+    if response.drag_started_by(egui::PointerButton::Primary) {
+        editor.dragging = editor.waypoint_near(pos);
+    }
+    if let (Some(i), Some(node)) = (editor.dragging, node) {
+        editor.waypoints[i] = node;
+    }
+    if response.drag_stopped() {
+        editor.dragging = None;
+    }
+    if response.clicked() && editor.waypoint_near(pos).is_none() {
+        editor.waypoints.extend(node);
+    }
+    if response.secondary_clicked() {
+        if let Some(i) = editor.waypoint_near(pos) {
+            editor.waypoints.remove(i);
+        }
+    }
+}
+
+/// Draw the waypoints being edited, and the path that connects them
+pub fn draw_path_editor(app: &App, painter: &Painter) {
+    let editor = &app.path_editor;
+    if !editor.editing {
+        return;
+    }
+    let wts = app.world_to_screen;
+    let to_screen = |p: &Point2<i8>| wts.map_point(Pos2::new(p.x as f32, p.y as f32));
+
+    match editor.preview(&app.grid, app.server_status.cv_location) {
+        Some(path) => {
+            for pair in path.windows(2) {
+                painter.line_segment(
+                    [to_screen(&pair[0]), to_screen(&pair[1])],
+                    Stroke::new(3.0, PATH_EDITOR_PATH_COLOR),
+                );
+            }
+        }
+        None => {
+            for pair in editor.waypoints.windows(2) {
+                painter.line_segment(
+                    [to_screen(&pair[0]), to_screen(&pair[1])],
+                    Stroke::new(2.0, PATH_EDITOR_UNREACHABLE_COLOR),
+                );
+            }
+        }
+    }
+    for (i, waypoint) in editor.waypoints.iter().enumerate() {
+        painter.circle_filled(
+            to_screen(waypoint),
+            wts.map_dist(0.4),
+            PATH_EDITOR_WAYPOINT_COLOR,
+        );
+        painter.text(
+            to_screen(waypoint),
+            Align2::CENTER_CENTER,
+            (i + 1).to_string(),
+            FontId::monospace(wts.map_dist(0.5)),
+            app.background_color,
+        );
+    }
+}
+
+/// Controls for editing, sending, and saving target paths
+pub fn draw_path_controls(app: &mut App, ui: &mut Ui) {
+    let robot = app.ui_settings.selected_robot;
+    let editor = &mut app.path_editor;
+    ui.checkbox(&mut editor.editing, "Edit path on grid");
+    ui.label(format!("{} waypoints", editor.waypoints.len()));
+    let mut send = None;
+    ui.horizontal(|ui| {
+        if ui
+            .add_enabled(
+                !editor.waypoints.is_empty(),
+                egui::Button::new(format!("Send to {robot}")),
+            )
+            .clicked()
+        {
+            send = Some(editor.waypoints.clone());
+        }
+        if ui.button("Clear").clicked() {
+            editor.waypoints.clear();
+        }
+    });
+    if let Some(e) = &app.server_status.target_path_error {
+        ui.colored_label(Color32::RED, e);
+    }
+    ui.separator();
+
+    ui.horizontal(|ui| {
+        ui.text_edit_singleline(&mut editor.name);
+        if ui
+            .add_enabled(!editor.name.is_empty(), egui::Button::new("Save"))
+            .clicked()
+        {
+            editor.save_current();
+        }
+    });
+    let mut removed = None;
+    egui::Grid::new("saved_paths").show(ui, |ui| {
+        for (i, path) in editor.saved.iter().enumerate() {
+            ui.label(&path.name);
+            if ui.button("Edit").clicked() {
+                editor.name = path.name.clone();
+                editor.waypoints = path.waypoints.clone();
+                editor.editing = true;
+            }
+            if ui.button("Send").clicked() {
+                send = Some(path.waypoints.clone());
+            }
+            if ui.button(egui_phosphor::regular::TRASH).clicked() {
+                removed = Some(i);
+            }
+            ui.end_row();
+        }
+    });
+    if let Some(i) = removed {
+        editor.saved.remove(i);
+    }
+    ui.horizontal(|ui| {
+        if ui.button("Save paths to file").clicked() {
+            if let Err(e) = save_paths(&editor.saved) {
+                error!("Failed to save paths: {e:?}");
+            }
+        }
+        if ui.button("Load paths from file").clicked() {
+            match load_paths() {
+                Ok(Some(paths)) => editor.saved = paths,
+                Ok(None) => {}
+                Err(e) => error!("Failed to load paths: {e:?}"),
+            }
+        }
+    });
+
+    if let Some(waypoints) = send {
+        app.send(GuiToServerMessage::TargetPath(robot, waypoints));
+    }
+}
+
+/// Save named paths to a file chosen by the user
+#[cfg(not(target_arch = "wasm32"))]
+fn save_paths(paths: &[SavedPath]) -> Result<(), Error> {
+    let path = FileDialog::new()
+        .add_filter("Pacbot Paths", &["pbp"])
+        .set_filename("paths.pbp")
+        .show_save_single_file()?;
+
+    if let Some(path) = path {
+        let bytes = bincode::serde::encode_to_vec(paths, bincode::config::standard())?;
+        std::fs::write(path, bytes)?;
+    }
+
+    Ok(())
+}
+
+/// Load named paths from a file chosen by the user
+#[cfg(not(target_arch = "wasm32"))]
+fn load_paths() -> Result<Option<Vec<SavedPath>>, Error> {
+    let path = FileDialog::new()
+        .add_filter("Pacbot Paths", &["pbp"])
+        .show_open_single_file()?;
+
+    match path {
+        Some(path) => {
+            let bytes = std::fs::read(path)?;
+            let (paths, _) =
+                bincode::serde::decode_from_slice(&bytes, bincode::config::standard())?;
+            Ok(Some(paths))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Save named paths to a file chosen by the user
+#[cfg(target_arch = "wasm32")]
+fn save_paths(_paths: &[SavedPath]) -> Result<(), Error> {
+    Err(anyhow::anyhow!("Paths can't be saved from the web gui"))
+}
+
+/// Load named paths from a file chosen by the user
+#[cfg(target_arch = "wasm32")]
+fn load_paths() -> Result<Option<Vec<SavedPath>>, Error> {
+    Err(anyhow::anyhow!("Paths can't be loaded from the web gui"))
+}
@@ -1,187 +1,201 @@
-#![allow(dead_code)]
-
 use crate::replay::Replay;
+use crate::App;
 use core_pb::grid::standard_grid::StandardGrid;
-use core_pb::pacbot_rs::game_state::GameState;
-use nalgebra::Isometry2;
-use std::time::SystemTime;
+use core_pb::grid::Grid;
+use core_pb::messages::server_status::ServerStatus;
+use eframe::egui;
+use eframe::egui::Ui;
+use std::time::Duration;
+use web_time::Instant;
 
 /// The public interface for recording and replaying GUI data
 pub struct ReplayManager {
-    /// The current replay, which may be recording or playing back
-    pub replay: Replay,
+    /// Collects the statuses received from the server
+    pub recording: Replay,
+    /// Whether statuses should be added to `recording`
+    pub record: bool,
+    /// The replay being played back; while it is set, its frames are drawn instead of the
+    /// statuses received from the server
+    pub playback: Option<Replay>,
     /// When current_frame was played; used to determine when to advance the replay
-    pub playback_time: SystemTime,
+    pub playback_time: Instant,
     /// Whether playback is paused
     pub playback_paused: bool,
-    /// Speed of playback - 0 is stopped, 1 is normal forwards
+    /// Speed of playback - 0 is stopped, 1 is normal forwards, negative is backwards
     pub playback_speed: f32,
+    /// The frame of `playback` that was last given to the gui
+    shown_frame: Option<usize>,
 }
 
 impl Default for ReplayManager {
     fn default() -> Self {
         Self {
-            replay: Replay::default(),
-            playback_time: SystemTime::now(),
+            recording: Replay::default(),
+            record: false,
+            playback: None,
+            playback_time: Instant::now(),
             playback_paused: true,
             playback_speed: 1.0,
+            shown_frame: None,
         }
     }
 }
 
-#[allow(dead_code)]
 impl ReplayManager {
-    /// Create a new ReplayManager; assumes that it is starting in recording mode
-    ///
-    /// Note: pacman_state is copied once to initialize the replay
-    pub fn new(
-        filename: String,
-        standard_grid: StandardGrid,
-        pacman_state: GameState,
-        pacbot_location: Isometry2<f32>,
-    ) -> ReplayManager {
-        let replay = Replay::new(
-            filename.to_owned(),
-            standard_grid,
-            pacman_state,
-            pacbot_location,
-        );
-        ReplayManager {
-            replay,
-            playback_time: SystemTime::now(),
-            playback_paused: true,
-            playback_speed: 1.0,
-        }
+    /// Whether a replay is being played back instead of showing the server's statuses
+    pub fn is_playing(&self) -> bool {
+        self.playback.is_some()
     }
 
-    pub fn reset_replay(
+    /// The grid of the frame being played back, if any
+    pub fn standard_grid(&self) -> Option<StandardGrid> {
+        self.playback.as_ref().and_then(|r| r.standard_grid())
+    }
+
+    /// The custom grid of the frame being played back, if any
+    pub fn custom_grid(&self) -> Option<Grid> {
+        self.playback.as_ref().and_then(|r| r.custom_grid())
+    }
+
+    /// Record a status received from the server, if recording is enabled
+    pub fn record_status(
         &mut self,
-        selected_grid: StandardGrid,
-        pacman_state: &GameState,
-        pacbot_pos: Isometry2<f32>,
+        status: &ServerStatus,
+        standard_grid: StandardGrid,
+        custom_grid: Option<Grid>,
     ) {
-        self.replay = Replay::new(
-            "replay".to_string(),
-            selected_grid,
-            pacman_state.to_owned(),
-            pacbot_pos,
-        );
+        if self.record {
+            self.recording
+                .record_status(status, standard_grid, custom_grid);
+        }
+    }
+
+    /// Start playing back a replay from its beginning, paused
+    pub fn start_playback(&mut self, mut replay: Replay) {
+        replay.go_to_beginning();
+        self.playback = Some(replay);
+        self.playback_paused = true;
+        self.shown_frame = None;
     }
 
-    fn update_with_replay(&mut self, _pacman_state: &mut GameState) {
-        // let mut pacman_state_new = self.replay.get_pacman_state();
-        //
-        // pacman_state_new.pause();
-        // *pacman_state = pacman_state_new;
+    /// Go back to showing the server's statuses
+    pub fn stop_playback(&mut self) {
+        self.playback = None;
+        self.shown_frame = None;
+    }
+
+    /// Advance playback according to the time that passed
+    ///
+    /// Returns the status to show, if it changed since the last call
+    pub fn update(&mut self) -> Option<&ServerStatus> {
+        let playback = self.playback.as_mut()?;
+        let now = Instant::now();
+
+        if self.playback_paused || self.playback_speed == 0.0 {
+            // When playback is paused, constantly set this to now so that it starts up correctly
+            self.playback_time = now;
+        } else if self.playback_speed > 0.0 {
+            while !playback.is_at_end() {
+                let step = playback.time_to_next().as_secs_f32() / self.playback_speed;
+                if now.duration_since(self.playback_time).as_secs_f32() < step {
+                    break;
+                }
+                playback.step_forwards();
+                self.playback_time += Duration::from_secs_f32(step);
+            }
+            if playback.is_at_end() {
+                self.playback_paused = true;
+            }
+        } else {
+            while !playback.is_at_beginning() {
+                let step = playback.time_to_previous().as_secs_f32() / -self.playback_speed;
+                if now.duration_since(self.playback_time).as_secs_f32() < step {
+                    break;
+                }
+                playback.step_back();
+                self.playback_time += Duration::from_secs_f32(step);
+            }
+            if playback.is_at_beginning() {
+                self.playback_paused = true;
+            }
+        }
+
+        let frame = Some(playback.current_frame());
+        if frame != self.shown_frame {
+            self.shown_frame = frame;
+            playback.status()
+        } else {
+            None
+        }
+    }
+
+    /// Pause or resume playback; resuming at the end of the replay starts it over
+    pub fn toggle_paused(&mut self) {
+        if let Some(playback) = &mut self.playback {
+            if self.playback_paused {
+                if self.playback_speed >= 0.0 && playback.is_at_end() {
+                    playback.go_to_beginning();
+                } else if self.playback_speed < 0.0 && playback.is_at_beginning() {
+                    playback.go_to_end();
+                }
+            }
+            self.playback_paused = !self.playback_paused;
+        }
     }
 }
 
-// /// Play back or save frames as necessary
-// ///
-// /// When not in Playback mode, update_replay_playback has no effect
-// pub fn update_replay_manager_system(
-//     pacman_state: Res<PacmanGameState>,
-//     phys_render: Res<LightPhysicsInfo>,
-//     replay_manager: ResMut<ReplayManager>,
-//     settings: Res<UserSettings>,
-// ) {
-//     if let Err(e) = update_replay_manager(pacman_state, phys_render, replay_manager, settings) {
-//         eprintln!("Update replay manager failed: {:?}", e);
-//     }
-// }
+/// Controls for scrubbing through, pausing, stepping and changing the speed of playback
+pub fn draw_replay_controls(app: &mut App, ui: &mut Ui) {
+    let manager = &mut app.replay_manager;
+    let Some(playback) = &mut manager.playback else {
+        return;
+    };
+    let (toggle, exit) = ui
+        .horizontal(|ui| {
+            ui.label(&playback.label);
+            if ui.button(egui_phosphor::regular::SKIP_BACK).clicked() {
+                playback.go_to_beginning();
+            }
+            if ui.button(egui_phosphor::regular::CARET_LEFT).clicked() {
+                manager.playback_paused = true;
+                playback.step_back();
+            }
+            let play_pause = match manager.playback_paused {
+                true => egui_phosphor::regular::PLAY,
+                false => egui_phosphor::regular::PAUSE,
+            };
+            let toggle = ui.button(play_pause).clicked();
+            if ui.button(egui_phosphor::regular::CARET_RIGHT).clicked() {
+                manager.playback_paused = true;
+                playback.step_forwards();
+            }
+            if ui.button(egui_phosphor::regular::SKIP_FORWARD).clicked() {
+                playback.go_to_end();
+            }
 
-// fn update_replay_manager(
-//     pacman_state: Res<PacmanGameState>,
-//     phys_render: Res<LightPhysicsInfo>,
-//     mut replay_manager: ResMut<ReplayManager>,
-//     settings: Res<UserSettings>,
-// ) -> Result<(), Error> {
-//     if pacman_state.is_changed() && settings.mode != AppMode::Playback {
-//         // if we aren't recording the physics position, we should record the game position
-//         if !settings.replay_save_location {
-//             replay_manager
-//                 .replay
-//                 .record_pacman_location(Isometry2::from_parts(
-//                     Translation::new(
-//                         pacman_state.0.get_state().pacman_loc.row as f32,
-//                         pacman_state.0.get_state().pacman_loc.col as f32,
-//                     ),
-//                     Rotation::new(match pacman_state.0.get_state().pacman_loc.dir {
-//                         pacbot_rs::location::RIGHT => std::f32::consts::FRAC_PI_2,
-//                         pacbot_rs::location::UP => std::f32::consts::PI,
-//                         pacbot_rs::location::LEFT => std::f32::consts::FRAC_PI_2 * 3.0,
-//                         pacbot_rs::location::DOWN => 0.0,
-//                         _ => 0.0,
-//                     }),
-//                 ))?;
-//         }
-//         replay_manager.replay.record_pacman_state(&pacman_state.0)?;
-//     }
-//
-//     if settings.mode != AppMode::Playback && settings.replay_save_location {
-//         // save physics position
-//         if let Some(position) = phys_render.real_pos {
-//             replay_manager.replay.record_pacman_location(position)?;
-//         }
-//     }
-//
-//     if replay_manager.playback_paused {
-//         // When playback is paused, constantly set this to now so that it starts up correctly
-//         replay_manager.playback_time = SystemTime::now();
-//         return Ok(());
-//     }
-//
-//     if replay_manager.replay.is_at_end() {
-//         replay_manager.playback_paused = true;
-//         // we have reached the end of the replay
-//         return Ok(());
-//     }
-//
-//     let now = SystemTime::now();
-//
-//     if replay_manager.playback_speed >= 0.0 {
-//         loop {
-//             let time_to_next = replay_manager.replay.time_to_next().as_secs_f32();
-//             let should_step_replay = time_to_next / replay_manager.playback_speed
-//                 < now
-//                     .duration_since(replay_manager.playback_time)?
-//                     .as_secs_f32();
-//
-//             if !should_step_replay {
-//                 break;
-//             }
-//
-//             replay_manager.replay.step_forwards();
-//             let speed = replay_manager.playback_speed;
-//             replay_manager.playback_time += Duration::from_secs_f32(time_to_next / speed);
-//         }
-//     } else {
-//         loop {
-//             let time_to_previous = replay_manager.replay.time_to_previous().as_secs_f32();
-//             let should_step_replay = time_to_previous / -replay_manager.playback_speed
-//                 < now
-//                     .duration_since(replay_manager.playback_time)?
-//                     .as_secs_f32();
-//
-//             if !should_step_replay {
-//                 break;
-//             }
-//
-//             replay_manager.replay.step_back();
-//             let speed = replay_manager.playback_speed;
-//             replay_manager.playback_time += Duration::from_secs_f32(time_to_previous / -speed);
-//         }
-//     }
-//
-//     Ok(())
-// }
-//
-// pub fn replay_playback(
-//     mut pacman_state: ResMut<PacmanGameState>,
-//     mut replay_manager: ResMut<ReplayManager>,
-//     settings: Res<UserSettings>,
-// ) {
-//     if settings.mode == AppMode::Playback {
-//         replay_manager.update_with_replay(&mut pacman_state.0);
-//     }
-// }
+            let mut time = playback.current_time().as_secs_f32();
+            let duration = playback.duration().as_secs_f32();
+            ui.label(format!("{time:.1}s / {duration:.1}s"));
+            ui.add(
+                egui::DragValue::new(&mut manager.playback_speed)
+                    .range(-8.0..=8.0)
+                    .speed(0.05)
+                    .suffix("x"),
+            );
+            let width = ui.available_width() - 100.0;
+            ui.spacing_mut().slider_width = width.max(50.0);
+            if ui
+                .add(egui::Slider::new(&mut time, 0.0..=duration).show_value(false))
+                .changed()
+            {
+                playback.seek(Duration::from_secs_f32(time));
+            }
+            (toggle, ui.button("Exit playback").clicked())
+        })
+        .inner;
+    if exit {
+        manager.stop_playback();
+    } else if toggle {
+        manager.toggle_paused();
+    }
+}
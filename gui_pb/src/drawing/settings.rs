@@ -1,23 +1,31 @@
 use crate::App;
+use anyhow::Error;
 use core_pb::constants::GUI_LISTENER_PORT;
 use core_pb::messages::common::LocalizationAlgorithmSource;
+use core_pb::messages::robot_tcp::FrameStats;
+use core_pb::messages::scenario::Scenario;
+use core_pb::messages::server_status::RobotStatus;
 use core_pb::messages::settings::{
-    ConnectionSettings, CvLocationSource, ShouldDoTargetPath, StrategyChoice,
+    ConnectionSettings, CvLocationSource, NetworkImpairments, ShouldDoTargetPath, StrategyChoice,
 };
 use core_pb::messages::{
     FrequentServerToRobot, GameServerCommand, GuiToServerMessage, NetworkStatus,
     ServerToRobotMessage, ServerToSimulationMessage,
 };
 use core_pb::names::{RobotName, NUM_ROBOT_NAMES};
-use core_pb::threaded_websocket::TextOrT;
+use core_pb::threaded_websocket::{Backoff, TextOrT};
 use core_pb::util::ColoredStatus;
 use eframe::egui;
 use eframe::egui::{Align, Color32, Layout, TextEdit, Ui, WidgetText};
+use log::error;
+#[cfg(not(target_arch = "wasm32"))]
+use native_dialog::FileDialog;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::str::FromStr;
+use std::time::Duration;
 
 const COMP_DO_TARGET_PATH: ShouldDoTargetPath = ShouldDoTargetPath::DoWhilePlayed;
 const COMP_TARGET_SPEED: f32 = 4.0;
@@ -27,6 +35,9 @@ const COMP_TURN_SPEED_MULT: f32 = 0.1;
 const COMP_SNAPPING_DIST: f32 = 0.2;
 const COMP_CV_ERROR: f32 = 1.5;
 
+/// Settings that only affect this gui, saved for each user
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct UiSettings {
     pub selected_robot: RobotName,
     pub any_robot_has_been_selected: bool,
@@ -35,6 +46,9 @@ pub struct UiSettings {
 
     pub mdrc_server_collapsed: bool,
     pub simulation_collapsed: bool,
+    pub simulation_network_collapsed: bool,
+    pub reconnect_collapsed: bool,
+    pub simulation_ghosts_collapsed: bool,
     pub game_server_collapsed: bool,
     pub robots_collapsed: [bool; NUM_ROBOT_NAMES],
     pub graph_lines: [[bool; 4]; 3],
@@ -42,6 +56,8 @@ pub struct UiSettings {
 
     pub angle_behavior: VelocityControlAngleBehavior,
 
+    /// Not saved, so that restarting the gui doesn't silently keep recording
+    #[serde(skip)]
     pub record_motor_data: bool,
 }
 
@@ -59,6 +75,9 @@ impl Default for UiSettings {
 
             mdrc_server_collapsed: true,
             simulation_collapsed: true,
+            simulation_network_collapsed: true,
+            reconnect_collapsed: true,
+            simulation_ghosts_collapsed: true,
             game_server_collapsed: true,
             robots_collapsed: [true; NUM_ROBOT_NAMES],
             graph_lines: [[true; 4]; 3],
@@ -193,6 +212,83 @@ pub fn dropdown<T: Debug + PartialEq + Clone>(
         });
 }
 
+/// Read a simulation scenario from a RON file chosen by the user
+#[cfg(not(target_arch = "wasm32"))]
+fn load_scenario() -> Result<Option<Scenario>, Error> {
+    let path = FileDialog::new()
+        .add_filter("Scenario", &["ron"])
+        .show_open_single_file()?;
+
+    match path {
+        Some(path) => {
+            let text = std::fs::read_to_string(path)?;
+            Ok(Some(ron::from_str(&text)?))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Read a simulation scenario from a RON file chosen by the user
+#[cfg(target_arch = "wasm32")]
+fn load_scenario() -> Result<Option<Scenario>, Error> {
+    Err(anyhow::anyhow!(
+        "Scenarios can't be loaded from the web gui"
+    ))
+}
+
+fn frame_stats_summary(stats: &FrameStats) -> String {
+    format!(
+        "{} ok, {} corrupted, {} resynced ({} bytes skipped), {} out of order",
+        stats.received, stats.corrupted, stats.resynced, stats.skipped_bytes, stats.out_of_order
+    )
+}
+
+fn robot_tooltip(status: &RobotStatus) -> Option<String> {
+    let mut lines: Vec<String> = status
+        .ping
+        .iter()
+        .map(|x| {
+            format!(
+                "Ping: {x:?} (jitter {:?}, {:.0}% lost)",
+                status.ping_stats.jitter,
+                status.ping_stats.loss() * 100.0
+            )
+        })
+        .collect();
+    if let Some(clock) = status.clock {
+        lines.push(format!(
+            "Clock offset: {:.1}ms, drift: {:.0}ppm",
+            clock.offset * 1000.0,
+            clock.drift * 1_000_000.0
+        ));
+    }
+    if let Some(beacon) = &status.beacon {
+        let [a, b, c, d] = beacon.ip;
+        lines.push(format!(
+            "Announced at {a}.{b}.{c}.{d}:{} ({})",
+            beacon.port, beacon.version
+        ));
+    }
+    if status.connection == NetworkStatus::Connected {
+        lines.push(match status.config_applied() {
+            true => "Latest config applied".to_string(),
+            false => "Waiting for robot to apply latest config".to_string(),
+        });
+    }
+    if status.link.reconnects > 0 || status.link.last_error.is_some() {
+        lines.push(format!("Link: {}", status.link.summary()));
+    }
+    for (label, stats) in [
+        ("From robot", &status.server_frame_stats),
+        ("To robot", &status.robot_frame_stats),
+    ] {
+        if !stats.is_clean() {
+            lines.push(format!("{label}: {}", frame_stats_summary(stats)));
+        }
+    }
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
 fn collapsable_section(
     ui: &mut Ui,
     collapsed: &mut bool,
@@ -293,6 +389,15 @@ fn draw_settings_inner(app: &mut App, ui: &mut Ui, fields: &mut HashMap<String,
     ui.end_row();
     ui.checkbox(&mut app.settings.safe_mode, "Safe mode");
     ui.end_row();
+    ui.checkbox(
+        &mut app.settings.game_server.record_matches,
+        "Record matches",
+    )
+    .on_hover_text(match &app.server_status.match_recording {
+        Some(path) => format!("Recording to {path}"),
+        None => "The server records each match to matches/, from unpause to reset".to_string(),
+    });
+    ui.end_row();
     if ui.button("Competition mode").clicked() {
         app.settings.do_target_path = COMP_DO_TARGET_PATH;
         app.settings.target_speed = COMP_TARGET_SPEED;
@@ -345,6 +450,17 @@ fn draw_settings_inner(app: &mut App, ui: &mut Ui, fields: &mut HashMap<String,
         },
     );
     ui.end_row();
+    ui.checkbox(
+        &mut app.settings.robots[app.ui_settings.selected_robot as usize].udp,
+        "Use UDP for control",
+    );
+    ui.end_row();
+    ui.checkbox(
+        &mut app.settings.robots[app.ui_settings.selected_robot as usize].pin_mac,
+        "Pin mac address",
+    )
+    .on_hover_text("Only accept beacons for this robot from its known mac address");
+    ui.end_row();
 
     if app.ui_settings.selected_robot.is_simulated() {
         app.ui_settings.devices_collapsed = true;
@@ -440,6 +556,15 @@ fn draw_settings_inner(app: &mut App, ui: &mut Ui, fields: &mut HashMap<String,
         true,
     );
 
+    num(
+        "corner_cut".to_string(),
+        ui,
+        fields,
+        &mut app.settings.driving.corner_cut,
+        "Corner cut",
+        true,
+    );
+
     ui.end_row();
     dropdown(
         ui,
@@ -491,9 +616,67 @@ fn draw_settings_inner(app: &mut App, ui: &mut Ui, fields: &mut HashMap<String,
         fields,
         &mut app.ui_settings.mdrc_server,
         &mut app.ui_settings.mdrc_server_collapsed,
-        &app.network.0.status(),
+        &app.server_connection_status(),
         |_| {},
-        None::<&str>,
+        Some(format!(
+            "This gui: {}\nAll guis, seen by the server: {}",
+            app.network.0.metrics().summary(),
+            app.server_status.gui_link.summary()
+        )),
+    );
+
+    collapsable_section(
+        ui,
+        &mut app.ui_settings.reconnect_collapsed,
+        ColoredStatus::NotApplicable(None).to_color32(),
+        |ui| {
+            ui.label("Reconnecting");
+        },
+        |ui| {
+            let backoff = &mut app.settings.reconnect_backoff;
+            let mut initial_ms = backoff.initial.as_millis() as u64;
+            num(
+                "backoff_initial".to_string(),
+                ui,
+                fields,
+                &mut initial_ms,
+                "First retry after (ms)",
+                true,
+            );
+            backoff.initial = Duration::from_millis(initial_ms);
+            let mut max_ms = backoff.max.as_millis() as u64;
+            num(
+                "backoff_max".to_string(),
+                ui,
+                fields,
+                &mut max_ms,
+                "Longest wait (ms)",
+                true,
+            );
+            backoff.max = Duration::from_millis(max_ms);
+            num(
+                "backoff_multiplier".to_string(),
+                ui,
+                fields,
+                &mut backoff.multiplier,
+                "Growth per attempt",
+                true,
+            );
+            num(
+                "backoff_jitter".to_string(),
+                ui,
+                fields,
+                &mut backoff.jitter,
+                "Jitter (0-1)",
+                true,
+            );
+            backoff.jitter = backoff.jitter.clamp(0.0, 1.0);
+            if ui.button("Reset").clicked() {
+                *backoff = Backoff::default();
+            }
+            ui.end_row();
+        },
+        Some("How long the server and guis wait between failed connection attempts"),
     );
 
     generic_server(
@@ -504,9 +687,123 @@ fn draw_settings_inner(app: &mut App, ui: &mut Ui, fields: &mut HashMap<String,
         &mut app.ui_settings.simulation_collapsed,
         &app.server_status.simulation_connection,
         |_| {},
-        None::<&str>,
+        Some(app.server_status.simulation_link.summary()),
     );
 
+    collapsable_section(
+        ui,
+        &mut app.ui_settings.simulation_network_collapsed,
+        if app.settings.simulation.network == NetworkImpairments::default() {
+            ColoredStatus::NotApplicable(None).to_color32()
+        } else {
+            ColoredStatus::Warn(None).to_color32()
+        },
+        |ui| {
+            ui.label("Simulated network");
+        },
+        |ui| {
+            let network = &mut app.settings.simulation.network;
+            num(
+                "sim_latency".to_string(),
+                ui,
+                fields,
+                &mut network.latency_ms,
+                "Latency (ms)",
+                true,
+            );
+            num(
+                "sim_jitter".to_string(),
+                ui,
+                fields,
+                &mut network.jitter_ms,
+                "Jitter (ms)",
+                true,
+            );
+            num(
+                "sim_frame_loss".to_string(),
+                ui,
+                fields,
+                &mut network.frame_loss,
+                "Frame loss (0-1)",
+                true,
+            );
+            num(
+                "sim_bandwidth_cap".to_string(),
+                ui,
+                fields,
+                &mut network.bandwidth_cap,
+                "Bandwidth (B/s, 0 = unlimited)",
+                true,
+            );
+            num(
+                "sim_disconnect_interval".to_string(),
+                ui,
+                fields,
+                &mut network.disconnect_interval,
+                "Disconnect every (s)",
+                true,
+            );
+            num(
+                "sim_disconnect_duration".to_string(),
+                ui,
+                fields,
+                &mut network.disconnect_duration,
+                "Disconnect for (s)",
+                true,
+            );
+            if ui.button("Perfect link").clicked() {
+                *network = NetworkImpairments::default();
+            }
+            ui.end_row();
+        },
+        Some("Latency, loss and disconnects applied to simulated robots"),
+    );
+
+    collapsable_section(
+        ui,
+        &mut app.ui_settings.simulation_ghosts_collapsed,
+        ColoredStatus::NotApplicable(None).to_color32(),
+        |ui| {
+            ui.label("Simulated ghosts");
+        },
+        |ui| {
+            let ghosts = &mut app.settings.simulation.ghosts;
+            ui.checkbox(&mut ghosts.bodies, "Ghost bodies");
+            ui.end_row();
+            for name in RobotName::get_all() {
+                if name.is_simulated() {
+                    ui.checkbox(
+                        &mut ghosts.robots[name as usize],
+                        format!("{name} drives as a ghost"),
+                    );
+                    ui.end_row();
+                }
+            }
+        },
+        Some("Give ghosts colliders, or let simulated robots chase their positions"),
+    );
+
+    ui.checkbox(
+        &mut app.settings.simulation.record_trajectories,
+        "Record sim trajectories",
+    )
+    .on_hover_text("Write true and estimated poses for each simulated robot to trajectories/");
+    ui.end_row();
+    if ui
+        .button("Load scenario")
+        .on_hover_text("Replace the simulation's grid, robots and game state with a RON scenario")
+        .clicked()
+    {
+        match load_scenario() {
+            Ok(Some(scenario)) => app.send(GuiToServerMessage::SimulationCommand(
+                ServerToSimulationMessage::LoadScenario(scenario),
+            )),
+            Ok(None) => {}
+            Err(e) => error!("Failed to load scenario: {e:?}"),
+        }
+    }
+    ui.end_row();
+
     generic_server(
         ui,
         if app.server_status.advanced_game_server {
@@ -549,7 +846,7 @@ fn draw_settings_inner(app: &mut App, ui: &mut Ui, fields: &mut HashMap<String,
                     )));
             }
         },
-        None::<&str>,
+        Some(app.server_status.game_server_link.summary()),
     );
 
     ui.separator();
@@ -566,6 +863,12 @@ fn draw_settings_inner(app: &mut App, ui: &mut Ui, fields: &mut HashMap<String,
             &mut app.ui_settings.robots_collapsed[name as usize],
             &app.server_status.robots[name as usize].connection,
             |ui| {
+                if conn_status == NetworkStatus::Connected
+                    && !app.server_status.robots[name as usize].config_applied()
+                {
+                    ui.label(egui_phosphor::regular::HOURGLASS)
+                        .on_hover_text("Waiting for robot to apply latest config");
+                }
                 ui.add_enabled_ui(conn_status == NetworkStatus::Connected, |ui| {
                     if ui.button(egui_phosphor::regular::ARROW_CLOCKWISE).clicked() {
                         app.network
@@ -602,9 +905,7 @@ fn draw_settings_inner(app: &mut App, ui: &mut Ui, fields: &mut HashMap<String,
                     );
                 }
             },
-            app.server_status.robots[name as usize]
-                .ping
-                .map(|x| format!("Ping: {x:?}")),
+            robot_tooltip(&app.server_status.robots[name as usize]),
         );
         if app.settings.robots[name as usize].connection.connect {
             any_robot_enabled = Some(name);
@@ -655,4 +956,22 @@ fn draw_settings_inner(app: &mut App, ui: &mut Ui, fields: &mut HashMap<String,
         ],
     );
     ui.end_row();
+    ui.checkbox(
+        &mut app.settings.robots[app.ui_settings.selected_robot as usize]
+            .config
+            .enable_localization_debug,
+        "Localization overlay",
+    )
+    .on_hover_text("Show the regions, corridor and sensor rays the robot's localization used");
+    ui.end_row();
+    ui.checkbox(
+        &mut app.settings.robots[app.ui_settings.selected_robot as usize]
+            .config
+            .enable_localization_shadow,
+        "Localization shadow mode",
+    )
+    .on_hover_text(
+        "Run every localization algorithm on the robot and show where each one thinks it is",
+    );
+    ui.end_row();
 }
@@ -1,16 +1,22 @@
 use crate::drawing::extra_opts::draw_extra_opts;
 use crate::drawing::game::{draw_game, draw_grid};
+use crate::drawing::grid_editor::draw_grid_editor;
 use crate::drawing::imu::draw_imu_data;
+use crate::drawing::keybindings::draw_keybindings;
 use crate::drawing::motors::draw_motors;
 use crate::drawing::over_the_air::draw_over_the_air;
+use crate::drawing::path_editor::{draw_path_controls, draw_path_editor, edit_path};
 use crate::drawing::settings::draw_settings;
+use crate::drawing::telemetry::draw_telemetry;
 use crate::drawing::timings::draw_timings;
 use crate::transform::Transform;
 use crate::App;
 use core_pb::constants::{ROBOT_DISPLAY_HEIGHT, ROBOT_DISPLAY_WIDTH};
-use eframe::egui::{Color32, CornerRadius, Pos2, Rect, Stroke, StrokeKind, Ui, WidgetText};
+use eframe::egui::{Color32, CornerRadius, Pos2, Rect, Sense, Stroke, StrokeKind, Ui, WidgetText};
 use egui_dock::TabViewer;
+use serde::{Deserialize, Serialize};
 
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Tab {
     /// Main game grid
     Grid,
@@ -36,6 +42,12 @@ pub enum Tab {
     ExtraOpts,
     /// Data about individual sensors on the IMU
     Imu,
+    /// Plots of any numeric telemetry from the server or robots
+    Telemetry,
+    /// Custom target paths drawn on the grid
+    PathEditor,
+    /// Custom grids for the server and simulation
+    GridEditor,
 }
 
 impl TabViewer for App {
@@ -54,6 +66,9 @@ impl TabViewer for App {
             Tab::RobotButtonPanel => "Robot Button Panel",
             Tab::ExtraOpts => "Extra Opts",
             Tab::Imu => "Imu",
+            Tab::Telemetry => "Telemetry",
+            Tab::PathEditor => "Path Editor",
+            Tab::GridEditor => "Grid Editor",
             Tab::Unknown => "?",
         }
         .into()
@@ -63,7 +78,7 @@ impl TabViewer for App {
         match tab {
             Tab::Grid => {
                 let rect = ui.max_rect();
-                let (src_p1, src_p2) = self.settings.standard_grid.get_soft_boundaries();
+                let (src_p1, src_p2) = self.grid.get_soft_boundaries();
 
                 self.world_to_screen = if self.rotated_grid {
                     Transform::new_letterboxed(
@@ -86,11 +101,18 @@ impl TabViewer for App {
                 let painter = ui.painter_at(rect);
                 draw_grid(self, &painter);
                 draw_game(self, &painter);
+
+                let response = ui.interact(rect, ui.id().with("grid"), Sense::click_and_drag());
+                edit_path(self, &response);
+                draw_path_editor(self, &painter);
             }
             Tab::Stopwatch => draw_timings(self, ui),
             Tab::Settings => draw_settings(self, ui),
             Tab::OverTheAirProgramming => draw_over_the_air(self, ui),
             Tab::Motors => draw_motors(self, ui),
+            Tab::Telemetry => draw_telemetry(self, ui),
+            Tab::PathEditor => draw_path_controls(self, ui),
+            Tab::GridEditor => draw_grid_editor(self, ui),
             Tab::RobotDisplay => {
                 let rect = ui.max_rect();
 
@@ -182,55 +204,7 @@ impl TabViewer for App {
                     );
                 }
             }
-            Tab::Keybindings => {
-                ui.label("General");
-                ui.label("[Left click] Set simulated robot position");
-                ui.label("[Right click] Set target position");
-                ui.label("[Y] Toggle rotated grid");
-                ui.label("[P] Toggle selected robot connection");
-                ui.separator();
-                ui.label("Movement, relative to estimated location");
-                ui.label("[W] Up");
-                ui.label("[A] Left");
-                ui.label("[S] Down");
-                ui.label("[D] Right");
-                ui.label("[Q] Rotate counterclockwise");
-                ui.label("[E] Rotate clockwise");
-                ui.separator();
-                ui.label("Test raw motor control");
-                ui.label("[U] First motor forwards");
-                ui.label("[J] First motor backwards");
-                ui.label("[I] Second motor forwards");
-                ui.label("[K] Second motor backwards");
-                ui.label("[O] Third motor forwards");
-                ui.label("[L] Third motor backwards");
-                ui.separator();
-                ui.label("Gameplay");
-                ui.label("[space] Pause/unpause");
-                ui.label("[R] Reset pacman game");
-                ui.separator();
-                ui.label("Strategy");
-                ui.label("[Z] Manual");
-                ui.label("[X] AI");
-                ui.label("[C] Test uniform");
-                ui.label("[V] Test forwards");
-                ui.separator();
-                ui.label("Grid");
-                ui.label("[B] Pacman grid");
-                ui.label("[N] Playground grid");
-                ui.separator();
-                ui.label("CV position source");
-                ui.label("[T] Mouse pointer");
-                ui.label("[G] Game state");
-                ui.label("[H] Particle filter");
-                // ui.separator();
-                // ui.label("Replay controls");
-                // ui.label("[shift + left] Go to beginning");
-                // ui.label("[left] Previous frame");
-                // ui.label("[space] Pause/unpause");
-                // ui.label("[right] Next frame");
-                // ui.label("[shift + right] Go to end");
-            }
+            Tab::Keybindings => draw_keybindings(self, ui),
             Tab::ExtraOpts => {
                 draw_extra_opts(self, ui);
             }
@@ -0,0 +1,442 @@
+use crate::App;
+use anyhow::Error;
+use core_pb::messages::server_status::{RobotStatus, ServerStatus};
+use core_pb::messages::ExtraOptsTypes;
+use core_pb::names::RobotName;
+use eframe::egui;
+use eframe::egui::Ui;
+use egui_plot::{Legend, Line, Plot, PlotPoints};
+use log::error;
+#[cfg(not(target_arch = "wasm32"))]
+use native_dialog::FileDialog;
+use std::fmt::Write;
+
+/// How many samples of each channel are kept; at the server's status rate, around 20 minutes
+const MAX_SAMPLES: usize = 30_000;
+
+/// A number reported by the server about itself
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ServerChannel {
+    Score,
+    Ticks,
+    GuiClients,
+    TargetPathLength,
+}
+
+/// A number reported by the server about a robot
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RobotChannel {
+    Battery,
+    /// Milliseconds
+    Ping,
+    /// Milliseconds
+    PingJitter,
+    PingLoss,
+    /// Robot time minus server time, in milliseconds
+    ClockOffset,
+    ImuAngle,
+    DistanceSensor(usize),
+    EstimatedX,
+    EstimatedY,
+    SimX,
+    SimY,
+    SimAngle,
+    Utilization(usize),
+    MotorSpeed(usize),
+    MotorSetPoint(usize),
+    ExtraBool(usize),
+    ExtraF32(usize),
+    ExtraI8(usize),
+    ExtraI32(usize),
+}
+
+/// Anything that can be plotted in the telemetry tab
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TelemetryChannel {
+    Server(ServerChannel),
+    Robot(RobotName, RobotChannel),
+}
+
+impl ServerChannel {
+    pub fn all() -> Vec<Self> {
+        vec![
+            Self::Score,
+            Self::Ticks,
+            Self::GuiClients,
+            Self::TargetPathLength,
+        ]
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Self::Score => "Score",
+            Self::Ticks => "Ticks",
+            Self::GuiClients => "Gui clients",
+            Self::TargetPathLength => "Target path length",
+        }
+        .to_string()
+    }
+
+    pub fn value(&self, status: &ServerStatus) -> Option<f64> {
+        match self {
+            Self::Score => Some(status.game_state.curr_score as f64),
+            Self::Ticks => Some(status.game_state.curr_ticks as f64),
+            Self::GuiClients => Some(status.gui_clients as f64),
+            Self::TargetPathLength => Some(status.target_path.len() as f64),
+        }
+    }
+}
+
+impl RobotChannel {
+    /// Every channel of the robot, with one per entry of each array in its status
+    pub fn all(name: RobotName) -> Vec<Self> {
+        let robot = RobotStatus::new(name);
+        let motors = robot.last_motor_status.1;
+        let extra = ExtraOptsTypes::default();
+        let mut channels = vec![
+            Self::Battery,
+            Self::Ping,
+            Self::PingJitter,
+            Self::PingLoss,
+            Self::ClockOffset,
+            Self::ImuAngle,
+        ];
+        channels.extend((0..robot.distance_sensors.len()).map(Self::DistanceSensor));
+        channels.extend([
+            Self::EstimatedX,
+            Self::EstimatedY,
+            Self::SimX,
+            Self::SimY,
+            Self::SimAngle,
+        ]);
+        channels.extend((0..robot.utilization.len()).map(Self::Utilization));
+        channels.extend((0..motors.measured_speeds.len()).map(Self::MotorSpeed));
+        channels.extend((0..motors.speed_set_points.len()).map(Self::MotorSetPoint));
+        channels.extend((0..extra.opts_bool.len()).map(Self::ExtraBool));
+        channels.extend((0..extra.opts_f32.len()).map(Self::ExtraF32));
+        channels.extend((0..extra.opts_i8.len()).map(Self::ExtraI8));
+        channels.extend((0..extra.opts_i32.len()).map(Self::ExtraI32));
+        channels
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Self::Battery => "battery".to_string(),
+            Self::Ping => "ping (ms)".to_string(),
+            Self::PingJitter => "ping jitter (ms)".to_string(),
+            Self::PingLoss => "ping loss".to_string(),
+            Self::ClockOffset => "clock offset (ms)".to_string(),
+            Self::ImuAngle => "imu angle".to_string(),
+            Self::DistanceSensor(i) => format!("distance sensor {i}"),
+            Self::EstimatedX => "estimated x".to_string(),
+            Self::EstimatedY => "estimated y".to_string(),
+            Self::SimX => "simulated x".to_string(),
+            Self::SimY => "simulated y".to_string(),
+            Self::SimAngle => "simulated angle".to_string(),
+            Self::Utilization(i) => format!("utilization {i}"),
+            Self::MotorSpeed(i) => format!("motor {i} speed"),
+            Self::MotorSetPoint(i) => format!("motor {i} setpoint"),
+            Self::ExtraBool(i) => format!("extra bool {i}"),
+            Self::ExtraF32(i) => format!("extra f32 {i}"),
+            Self::ExtraI8(i) => format!("extra i8 {i}"),
+            Self::ExtraI32(i) => format!("extra i32 {i}"),
+        }
+    }
+
+    pub fn value(&self, name: RobotName, status: &ServerStatus) -> Option<f64> {
+        let robot = &status.robots[name as usize];
+        let extra = robot.extra_indicators.as_ref();
+        match *self {
+            Self::Battery => robot.battery.ok().map(|x| x as f64),
+            Self::Ping => robot.ping.map(|x| x.as_secs_f64() * 1000.0),
+            Self::PingJitter => Some(robot.ping_stats.jitter.as_secs_f64() * 1000.0),
+            Self::PingLoss => Some(robot.ping_stats.loss() as f64),
+            Self::ClockOffset => robot
+                .clock
+                .map(|c| c.offset_at(status.server_time) * 1000.0),
+            Self::ImuAngle => robot.imu_angle.as_ref().ok().map(|x| *x as f64),
+            Self::DistanceSensor(i) => robot.distance_sensors[i]
+                .as_ref()
+                .ok()
+                .copied()
+                .flatten()
+                .map(|x| x as f64),
+            Self::EstimatedX => robot.estimated_location.map(|p| p.x as f64),
+            Self::EstimatedY => robot.estimated_location.map(|p| p.y as f64),
+            Self::SimX => robot.sim_position.map(|(p, _)| p.x as f64),
+            Self::SimY => robot.sim_position.map(|(p, _)| p.y as f64),
+            Self::SimAngle => robot.sim_position.map(|(_, r)| r.angle() as f64),
+            Self::Utilization(i) => Some(robot.utilization[i] as f64),
+            Self::MotorSpeed(i) => Some(robot.last_motor_status.1.measured_speeds[i] as f64),
+            Self::MotorSetPoint(i) => Some(robot.last_motor_status.1.speed_set_points[i] as f64),
+            Self::ExtraBool(i) => extra.map(|e| e.opts_bool[i] as u8 as f64),
+            Self::ExtraF32(i) => extra.map(|e| e.opts_f32[i] as f64),
+            Self::ExtraI8(i) => extra.map(|e| e.opts_i8[i] as f64),
+            Self::ExtraI32(i) => extra.map(|e| e.opts_i32[i] as f64),
+        }
+    }
+}
+
+impl TelemetryChannel {
+    pub fn label(&self) -> String {
+        match self {
+            Self::Server(channel) => channel.label(),
+            Self::Robot(name, channel) => format!("{name} {}", channel.label()),
+        }
+    }
+
+    pub fn value(&self, status: &ServerStatus) -> Option<f64> {
+        match self {
+            Self::Server(channel) => channel.value(status),
+            Self::Robot(name, channel) => channel.value(*name, status),
+        }
+    }
+}
+
+/// The channels shown in the telemetry tab, and their history
+#[derive(Default)]
+pub struct TelemetryPlot {
+    /// The channels being plotted
+    channels: Vec<TelemetryChannel>,
+    /// [`ServerStatus::server_time`] of each sample, in seconds
+    times: Vec<f64>,
+    /// For each channel, its value at each of `times`; channels added later are `None` before
+    /// they were added
+    values: Vec<Vec<Option<f64>>>,
+    /// Whether new statuses are ignored, so that the plot can be inspected
+    pub frozen: bool,
+    /// The robot whose channels are listed when adding a channel, or the server if None
+    source: Option<RobotName>,
+    /// The range of times shown by the plot when it was last drawn
+    visible: Option<(f64, f64)>,
+    /// Set to make the plot fit all of its data again
+    reset_view: bool,
+}
+
+impl TelemetryPlot {
+    /// Start plotting a channel, if it isn't already
+    pub fn add_channel(&mut self, channel: TelemetryChannel) {
+        if !self.channels.contains(&channel) {
+            self.channels.push(channel);
+            self.values.push(vec![None; self.times.len()]);
+        }
+    }
+
+    pub fn remove_channel(&mut self, index: usize) {
+        self.channels.remove(index);
+        self.values.remove(index);
+    }
+
+    /// Forget all samples, but keep the selected channels
+    pub fn clear(&mut self) {
+        self.times.clear();
+        for values in &mut self.values {
+            values.clear();
+        }
+    }
+
+    /// Record the selected channels from a status, unless frozen or the status was already seen
+    ///
+    /// If the server's time goes backwards, ex. because the server restarted or a replay was
+    /// rewound, the history is cleared
+    pub fn sample(&mut self, status: &ServerStatus) {
+        if self.frozen || self.channels.is_empty() {
+            return;
+        }
+        let time = status.server_time.as_secs_f64();
+        match self.times.last() {
+            Some(last) if *last == time => return,
+            Some(last) if *last > time => self.clear(),
+            _ => {}
+        }
+        self.times.push(time);
+        for (channel, values) in self.channels.iter().zip(&mut self.values) {
+            values.push(channel.value(status));
+        }
+        if self.times.len() > MAX_SAMPLES {
+            let excess = self.times.len() - MAX_SAMPLES;
+            self.times.drain(..excess);
+            for values in &mut self.values {
+                values.drain(..excess);
+            }
+        }
+    }
+
+    /// The samples between `start` and `end` seconds, as CSV with a column for each channel
+    pub fn to_csv(&self, start: f64, end: f64) -> String {
+        let mut csv = "time".to_string();
+        for channel in &self.channels {
+            write!(csv, ",{}", channel.label()).unwrap();
+        }
+        csv.push('\n');
+        for (i, time) in self.times.iter().enumerate() {
+            if *time < start || *time > end {
+                continue;
+            }
+            write!(csv, "{time}").unwrap();
+            for values in &self.values {
+                csv.push(',');
+                if let Some(value) = values[i] {
+                    write!(csv, "{value}").unwrap();
+                }
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// The samples currently visible in the plot, as CSV
+    pub fn visible_csv(&self) -> String {
+        let (start, end) = self.visible.unwrap_or((f64::MIN, f64::MAX));
+        self.to_csv(start, end)
+    }
+}
+
+/// Save CSV data to a file chosen by the user
+#[cfg(not(target_arch = "wasm32"))]
+fn save_csv(csv: String) -> Result<(), Error> {
+    let path = FileDialog::new()
+        .add_filter("CSV", &["csv"])
+        .set_filename("telemetry.csv")
+        .show_save_single_file()?;
+
+    if let Some(path) = path {
+        std::fs::write(path, csv)?;
+    }
+
+    Ok(())
+}
+
+/// Save CSV data to a file chosen by the user
+#[cfg(target_arch = "wasm32")]
+fn save_csv(_csv: String) -> Result<(), Error> {
+    Err(anyhow::anyhow!(
+        "Telemetry can't be exported from the web gui"
+    ))
+}
+
+pub fn draw_telemetry(app: &mut App, ui: &mut Ui) {
+    let telemetry = &mut app.telemetry;
+
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_salt("telemetry_source")
+            .selected_text(match telemetry.source {
+                None => "Server".to_string(),
+                Some(name) => name.to_string(),
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut telemetry.source, None, "Server");
+                for name in RobotName::get_all() {
+                    ui.selectable_value(&mut telemetry.source, Some(name), name.to_string());
+                }
+            });
+        let options: Vec<TelemetryChannel> = match telemetry.source {
+            None => ServerChannel::all()
+                .into_iter()
+                .map(TelemetryChannel::Server)
+                .collect(),
+            Some(name) => RobotChannel::all(name)
+                .into_iter()
+                .map(|channel| TelemetryChannel::Robot(name, channel))
+                .collect(),
+        };
+        let mut added = None;
+        egui::ComboBox::from_id_salt("telemetry_add")
+            .selected_text("Add channel")
+            .height(400.0)
+            .show_ui(ui, |ui| {
+                for channel in options {
+                    if ui.selectable_label(false, channel.label()).clicked() {
+                        added = Some(channel);
+                    }
+                }
+            });
+        if let Some(channel) = added {
+            telemetry.add_channel(channel);
+        }
+        ui.separator();
+        ui.checkbox(&mut telemetry.frozen, "Freeze");
+        if ui.button("Reset view").clicked() {
+            telemetry.reset_view = true;
+        }
+        if ui.button("Clear data").clicked() {
+            telemetry.clear();
+        }
+        if ui.button("Export CSV").clicked() {
+            if let Err(e) = save_csv(telemetry.visible_csv()) {
+                error!("Failed to export telemetry: {e:?}");
+            }
+        }
+    });
+    ui.horizontal_wrapped(|ui| {
+        let mut removed = None;
+        for (i, channel) in telemetry.channels.iter().enumerate() {
+            if ui
+                .button(format!("{} {}", egui_phosphor::regular::X, channel.label()))
+                .on_hover_text("Stop plotting this channel")
+                .clicked()
+            {
+                removed = Some(i);
+            }
+        }
+        if let Some(i) = removed {
+            telemetry.remove_channel(i);
+        }
+    });
+    ui.separator();
+
+    let reset_view = std::mem::take(&mut telemetry.reset_view);
+    telemetry.visible = Plot::new("telemetry_plot")
+        .legend(Legend::default())
+        .x_axis_label("Server time (s)")
+        .show(ui, |plot_ui| {
+            if reset_view {
+                plot_ui.set_auto_bounds(true);
+            }
+            for (channel, values) in telemetry.channels.iter().zip(&telemetry.values) {
+                let points: Vec<[f64; 2]> = telemetry
+                    .times
+                    .iter()
+                    .zip(values)
+                    .filter_map(|(time, value)| value.map(|value| [*time, value]))
+                    .collect();
+                plot_ui.line(Line::new(channel.label(), PlotPoints::new(points)));
+            }
+            let bounds = plot_ui.plot_bounds();
+            (bounds.min()[0], bounds.max()[0])
+        })
+        .inner
+        .into();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_telemetry_csv() {
+        let mut telemetry = TelemetryPlot::default();
+        let mut status = ServerStatus::default();
+        telemetry.add_channel(TelemetryChannel::Server(ServerChannel::Score));
+        for i in 0..3 {
+            status.server_time = Duration::from_secs(i);
+            status.game_state.curr_score = (i * 10) as _;
+            telemetry.sample(&status);
+            telemetry.sample(&status);
+        }
+        telemetry.add_channel(TelemetryChannel::Server(ServerChannel::GuiClients));
+        status.server_time = Duration::from_secs(3);
+        telemetry.sample(&status);
+
+        assert_eq!(
+            telemetry.to_csv(1.0, 3.0),
+            "time,Score,Gui clients\n1,10,\n2,20,\n3,20,0\n"
+        );
+
+        // the server restarted
+        status.server_time = Duration::ZERO;
+        telemetry.sample(&status);
+        assert_eq!(telemetry.to_csv(f64::MIN, f64::MAX).lines().count(), 2);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::bindings::{Action, AxisAction, Capture};
 use crate::drawing::settings::VelocityControlAngleBehavior;
 use crate::App;
 use core_pb::grid::standard_grid::StandardGrid;
@@ -6,11 +7,12 @@ use core_pb::messages::{
     GameServerCommand, GuiToServerMessage, NetworkStatus, RobotButton, ServerToSimulationMessage,
     VelocityControl,
 };
+use core_pb::names::RobotName;
 use core_pb::pacbot_rs::location::Direction;
 use core_pb::robot_definition::RobotDefinition;
 use core_pb::threaded_websocket::TextOrT;
 use eframe::egui;
-use eframe::egui::{Event, Key, PointerButton};
+use eframe::egui::{Event, PointerButton};
 use gilrs::{Axis, Button, EventType};
 use log::info;
 use nalgebra::{Point2, Vector2};
@@ -28,202 +30,413 @@ impl App {
         }
     }
 
+    /// Drain gamepad events, returning those from enabled gamepads
+    fn gamepad_events(&mut self) -> Vec<EventType> {
+        let mut events = vec![];
+        while let Some(gilrs::Event { id, event, .. }) = self.gilrs.next_event() {
+            let gamepad = self.gilrs.gamepad(id);
+            match event {
+                EventType::Connected => info!("Gamepad connected: {}", gamepad.name()),
+                EventType::Disconnected => info!("Gamepad disconnected: {}", gamepad.name()),
+                _ if self.bindings.gamepad_enabled(&self.gilrs, id) => events.push(event),
+                _ => {}
+            }
+        }
+        events
+    }
+
+    /// If a binding is being changed, give it the first key, button or stick movement; returns
+    /// whether a binding is being changed
+    fn capture_binding(&mut self, ctx: &egui::Context, gamepad_events: &[EventType]) -> bool {
+        let Some(capture) = self.binding_capture else {
+            return false;
+        };
+        match capture {
+            Capture::Key(action) => {
+                let key = ctx.input(|i| {
+                    i.events.iter().find_map(|event| match event {
+                        Event::Key {
+                            key, pressed: true, ..
+                        } => Some(*key),
+                        _ => None,
+                    })
+                });
+                if let Some(key) = key {
+                    self.bindings.actions[action as usize].key = Some(key);
+                    self.binding_capture = None;
+                }
+            }
+            Capture::Button(action) => {
+                let button = gamepad_events.iter().find_map(|event| match event {
+                    EventType::ButtonPressed(button, _) if *button != Button::Unknown => {
+                        Some(*button)
+                    }
+                    _ => None,
+                });
+                if let Some(button) = button {
+                    self.bindings.actions[action as usize].button = Some(button);
+                    self.binding_capture = None;
+                }
+            }
+            Capture::Axis(action) => {
+                let axis = gamepad_events.iter().find_map(|event| match event {
+                    EventType::AxisChanged(axis, value, _)
+                        if *axis != Axis::Unknown && value.abs() > 0.5 =>
+                    {
+                        Some(*axis)
+                    }
+                    _ => None,
+                });
+                if let Some(axis) = axis {
+                    self.bindings.axes[action as usize].axis = Some(axis);
+                    self.binding_capture = None;
+                }
+            }
+        }
+        if self.binding_capture.is_none() {
+            // otherwise a captured space or enter would also press the binding's button again
+            ctx.memory_mut(|m| {
+                if let Some(id) = m.focused() {
+                    m.surrender_focus(id)
+                }
+            });
+        }
+        true
+    }
+
+    /// Stop every robot, stop following target paths, and pause the game
+    pub fn emergency_stop(&mut self) {
+        for name in RobotName::get_all() {
+            self.send(GuiToServerMessage::RobotVelocity(
+                name,
+                VelocityControl::Stop,
+            ));
+        }
+        self.target_vel = VelocityControl::Stop;
+        self.settings.do_target_path = ShouldDoTargetPath::No;
+        self.send(GuiToServerMessage::GameServerCommand(
+            GameServerCommand::Pause,
+        ));
+    }
+
+    /// Select the next or previous robot that the server should connect to, or any robot if
+    /// there are none
+    fn switch_robot(&mut self, forwards: bool) {
+        let mut choices: Vec<_> = RobotName::get_all()
+            .into_iter()
+            .filter(|name| self.settings.robots[*name as usize].connection.connect)
+            .collect();
+        if choices.is_empty() {
+            choices = RobotName::get_all().to_vec();
+        }
+        let next = match choices
+            .iter()
+            .position(|name| *name == self.ui_settings.selected_robot)
+        {
+            Some(i) if forwards => (i + 1) % choices.len(),
+            Some(i) => (i + choices.len() - 1) % choices.len(),
+            None => 0,
+        };
+        // the robot that is no longer selected shouldn't keep driving
+        self.set_target_vel(VelocityControl::None);
+        self.ui_settings.selected_robot = choices[next];
+        self.ui_settings.any_robot_has_been_selected = true;
+    }
+
+    /// Add a step in the given direction to the target path, starting from the cv location or
+    /// the earliest point in the target path where the step is possible
+    fn step_target_path(&self, dir: Direction) {
+        if let Some(curr_loc) = self.server_status.cv_location {
+            let next_loc = Point2::new(curr_loc.x + dir.vector().0, curr_loc.y + dir.vector().1);
+            if !self.grid.wall_at(&next_loc) && !self.server_status.target_path.contains(&next_loc)
+            {
+                self.send(GuiToServerMessage::TargetLocation(
+                    next_loc.map(|x| x as f32),
+                ));
+            } else if let Some(prev) = self
+                .server_status
+                .target_path
+                .iter()
+                .map(|loc| Point2::new(loc.x + dir.vector().0, loc.y + dir.vector().1))
+                .find(|loc| {
+                    !self.grid.wall_at(loc) && !self.server_status.target_path.contains(loc)
+                })
+            {
+                self.send(GuiToServerMessage::TargetLocation(prev.map(|x| x as f32)));
+            }
+        }
+    }
+
     pub fn read_input(&mut self, ctx: &egui::Context) {
+        // gamepad events must be drained every frame, even when they aren't used
+        let gamepad_events = self.gamepad_events();
+        if self.capture_binding(ctx, &gamepad_events) {
+            self.set_target_vel(VelocityControl::None);
+            return;
+        }
+        if ctx.input(|i| {
+            self.bindings
+                .pressed(i, &gamepad_events, Action::EmergencyStop)
+        }) {
+            self.emergency_stop();
+        }
+
         // don't activate keybindings if some element (text box, button) is focused
         if ctx.memory(|m| m.focused().is_some()) {
             self.set_target_vel(VelocityControl::None);
             return;
         }
 
-        ctx.input(|i| {
+        let switch_robot = ctx.input(|i| {
+            let held = |action| self.bindings.held(i, &self.gilrs, action);
+            let pressed = |action| self.bindings.pressed(i, &gamepad_events, action);
+            let released = |action| self.bindings.released(i, &gamepad_events, action);
+
             let mut target_vel = (Vector2::new(0.0, 0.0), 0.0);
             let mut scale = self.settings.target_speed;
-            if i.modifiers.shift {
+            if i.modifiers.shift || held(Action::SpeedUp) {
                 scale *= 1.5;
             }
-            if i.modifiers.ctrl || i.modifiers.command {
+            if i.modifiers.ctrl || i.modifiers.command || held(Action::SlowDown) {
                 scale /= 3.0;
             }
-            for (key, (lin, ang), dir) in [
-                (Key::W, (Vector2::new(0.0, scale), 0.0), Direction::Right),
-                (Key::A, (Vector2::new(-scale, 0.0), 0.0), Direction::Up),
-                (Key::D, (Vector2::new(scale, 0.0), 0.0), Direction::Down),
-                (Key::S, (Vector2::new(0.0, -scale), 0.0), Direction::Left),
-                (Key::Q, (Vector2::new(0.0, 0.0), scale), Direction::Stay),
-                (Key::E, (Vector2::new(0.0, 0.0), -scale), Direction::Stay),
+            let in_target_path_mode = self.settings.do_target_path == ShouldDoTargetPath::Yes
+                || (self.settings.do_target_path == ShouldDoTargetPath::DoWhilePlayed
+                    && !self.server_status.game_state.paused);
+
+            for (action, (lin, ang), dir) in [
+                (
+                    Action::DriveUp,
+                    (Vector2::new(0.0, scale), 0.0),
+                    Direction::Right,
+                ),
+                (
+                    Action::DriveLeft,
+                    (Vector2::new(-scale, 0.0), 0.0),
+                    Direction::Up,
+                ),
+                (
+                    Action::DriveRight,
+                    (Vector2::new(scale, 0.0), 0.0),
+                    Direction::Down,
+                ),
+                (
+                    Action::DriveDown,
+                    (Vector2::new(0.0, -scale), 0.0),
+                    Direction::Left,
+                ),
+                (
+                    Action::RotateCounterclockwise,
+                    (Vector2::new(0.0, 0.0), scale),
+                    Direction::Stay,
+                ),
+                (
+                    Action::RotateClockwise,
+                    (Vector2::new(0.0, 0.0), -scale),
+                    Direction::Stay,
+                ),
             ] {
-                if self.settings.do_target_path == ShouldDoTargetPath::Yes
-                    || self.settings.do_target_path == ShouldDoTargetPath::DoWhilePlayed
-                        && !self.server_status.game_state.paused
-                {
-                    // instead of doing manual velocity, create a manual target path
-                    if i.key_down(key) && dir != Direction::Stay {
-                        // what is the earliest location in the current target path where we
-                        // could start moving in this direction?
-                        if let Some(curr_loc) = self.server_status.cv_location {
-                            let next_loc = Point2::new(
-                                curr_loc.x + dir.vector().0,
-                                curr_loc.y + dir.vector().1,
-                            );
-                            if !self.grid.wall_at(&next_loc)
-                                && !self.server_status.target_path.contains(&next_loc)
-                            {
-                                self.send(GuiToServerMessage::TargetLocation(next_loc));
-                            } else if let Some(prev) = self
-                                .server_status
-                                .target_path
-                                .iter()
-                                .map(|loc| {
-                                    Point2::new(loc.x + dir.vector().0, loc.y + dir.vector().1)
-                                })
-                                .filter(|loc| {
-                                    !self.grid.wall_at(&loc)
-                                        && !self.server_status.target_path.contains(&loc)
-                                })
-                                .next()
-                            {
-                                self.send(GuiToServerMessage::TargetLocation(prev));
-                            }
+                if held(action) {
+                    if in_target_path_mode {
+                        // instead of doing manual velocity, create a manual target path
+                        if dir != Direction::Stay {
+                            self.step_target_path(dir);
                         }
-                    }
-                } else {
-                    if i.key_down(key) {
+                    } else {
                         target_vel.0 += lin;
                         target_vel.1 += ang;
                     }
                 }
             }
-            while let Some(gilrs::Event { event, .. }) = self.gilrs.next_event() {
-                match event {
-                    EventType::ButtonPressed(b, _) => match b {
-                        Button::East => {
-                            self.ui_settings.angle_behavior = VelocityControlAngleBehavior::Free
-                        }
-                        Button::West => {
-                            self.ui_settings.angle_behavior = VelocityControlAngleBehavior::Locked(
-                                self.server_status.robots[self.ui_settings.selected_robot as usize]
-                                    .clone()
-                                    .imu_angle
-                                    .unwrap_or(0.0),
-                            )
-                        }
-                        Button::North => {
-                            self.ui_settings.angle_behavior =
-                                VelocityControlAngleBehavior::FaceForward
-                        }
 
-                        Button::South => {
-                            self.ui_settings.angle_behavior =
-                                VelocityControlAngleBehavior::AssistedDriving
-                        }
-                        _ => {}
-                    },
-                    EventType::Connected => {
-                        info!("Gamepad connected")
+            let drive_x = self.bindings.axis(&self.gilrs, AxisAction::DriveX);
+            let drive_y = self.bindings.axis(&self.gilrs, AxisAction::DriveY);
+            if in_target_path_mode {
+                // the stick chooses a direction for the target path
+                let threshold = 0.5;
+                let dir = if drive_x.abs() > drive_y.abs() {
+                    if drive_x > threshold {
+                        Direction::Right
+                    } else if drive_x < -threshold {
+                        Direction::Left
+                    } else {
+                        Direction::Stay
                     }
-                    EventType::Disconnected => {
-                        info!("Gamepad disconnected")
+                } else if drive_y > threshold {
+                    Direction::Up
+                } else if drive_y < -threshold {
+                    Direction::Down
+                } else {
+                    Direction::Stay
+                };
+                if dir != Direction::Stay {
+                    self.step_target_path(dir);
+                }
+            } else {
+                target_vel.0 += Vector2::new(drive_x, drive_y) * scale;
+                target_vel.1 += self.bindings.axis(&self.gilrs, AxisAction::Rotate) * scale;
+            }
+
+            for (action, angle_behavior) in [
+                (Action::AngleFree, VelocityControlAngleBehavior::Free),
+                (
+                    Action::AngleLocked,
+                    VelocityControlAngleBehavior::Locked(
+                        self.server_status.robots[self.ui_settings.selected_robot as usize]
+                            .imu_angle
+                            .clone()
+                            .unwrap_or(0.0),
+                    ),
+                ),
+                (
+                    Action::AngleFaceForward,
+                    VelocityControlAngleBehavior::FaceForward,
+                ),
+                (
+                    Action::AngleAssistedDriving,
+                    VelocityControlAngleBehavior::AssistedDriving,
+                ),
+            ] {
+                if pressed(action) {
+                    self.ui_settings.angle_behavior = angle_behavior;
+                }
+            }
+
+            if pressed(Action::TogglePause) {
+                if self.replay_manager.is_playing() {
+                    self.replay_manager.toggle_paused()
+                } else if self.server_status.game_state.paused {
+                    self.send(GuiToServerMessage::GameServerCommand(
+                        GameServerCommand::Unpause,
+                    ))
+                } else {
+                    self.send(GuiToServerMessage::GameServerCommand(
+                        GameServerCommand::Pause,
+                    ))
+                }
+            }
+            if pressed(Action::Reset) {
+                self.send(GuiToServerMessage::GameServerCommand(
+                    GameServerCommand::Reset,
+                ))
+            }
+            if pressed(Action::ToggleTargetPathMode) {
+                self.settings.do_target_path =
+                    if self.settings.do_target_path == ShouldDoTargetPath::Yes {
+                        ShouldDoTargetPath::DoWhilePlayed
+                    } else {
+                        ShouldDoTargetPath::Yes
+                    }
+            }
+            let switch_robot = if pressed(Action::NextRobot) {
+                Some(true)
+            } else if pressed(Action::PreviousRobot) {
+                Some(false)
+            } else {
+                None
+            };
+
+            // if the currently selected robot isn't connected, but the game server is, then
+            // interpret WASD presses as an attempt to manually play Pacman
+            if self.server_status.robots[self.ui_settings.selected_robot as usize].connection
+                == NetworkStatus::NotConnected
+                && self.server_status.game_server_connection == NetworkStatus::Connected
+            {
+                for (action, dir1, dir2) in [
+                    (Action::DriveUp, Direction::Right, Direction::Up),
+                    (Action::DriveLeft, Direction::Up, Direction::Left),
+                    (Action::DriveDown, Direction::Left, Direction::Down),
+                    (Action::DriveRight, Direction::Down, Direction::Right),
+                ] {
+                    if pressed(action) {
+                        self.send(GuiToServerMessage::GameServerCommand(
+                            GameServerCommand::Direction(if self.rotated_grid {
+                                dir1
+                            } else {
+                                dir2
+                            }),
+                        ));
                     }
-                    _ => {}
                 }
             }
-            if let Some((_, gp)) = self.gilrs.gamepads().next() {
-                // Adjust scale with triggers
-                if let Some(t) = gp.button_data(Button::LeftTrigger2) {
-                    if t.is_pressed() {
-                        scale /= 3.0;
+
+            if let Some(playback) = &mut self.replay_manager.playback {
+                if pressed(Action::ReplayBack) {
+                    if i.modifiers.shift {
+                        playback.go_to_beginning()
+                    } else {
+                        playback.step_back()
                     }
+                    self.replay_manager.playback_paused = true;
                 }
-                if let Some(t) = gp.button_data(Button::RightTrigger2) {
-                    if t.is_pressed() {
-                        scale *= 1.5;
+                if pressed(Action::ReplayForwards) {
+                    if i.modifiers.shift {
+                        playback.go_to_end()
+                    } else {
+                        playback.step_forwards()
                     }
+                    self.replay_manager.playback_paused = true;
                 }
+            }
 
-                let in_target_path_mode = self.settings.do_target_path == ShouldDoTargetPath::Yes
-                    || (self.settings.do_target_path == ShouldDoTargetPath::DoWhilePlayed
-                        && !self.server_status.game_state.paused);
+            for (action, strategy) in [
+                (Action::StrategyManual, StrategyChoice::Manual),
+                (Action::StrategyAi, StrategyChoice::ReinforcementLearning),
+                (Action::StrategyTestUniform, StrategyChoice::TestUniform),
+                (Action::StrategyTestForward, StrategyChoice::TestForward),
+            ] {
+                if pressed(action) {
+                    self.settings.driving.strategy = strategy;
+                }
+            }
 
-                if in_target_path_mode {
-                    // Handle left stick for target path directions
-                    let deadzone = 0.5;
-                    let left_x = gp
-                        .axis_data(Axis::LeftStickX)
-                        .map(|a| a.value())
-                        .unwrap_or(0.0);
-                    let left_y = gp
-                        .axis_data(Axis::LeftStickY)
-                        .map(|a| a.value())
-                        .unwrap_or(0.0);
+            let selected = self.ui_settings.selected_robot;
+            if pressed(Action::ToggleConnection) {
+                let connection = &mut self.settings.robots[selected as usize].connection;
+                connection.connect = !connection.connect;
+            }
+            for (action, motor, direction) in [
+                (Action::FirstMotorForwards, 0, 0),
+                (Action::FirstMotorBackwards, 0, 1),
+                (Action::SecondMotorForwards, 1, 0),
+                (Action::SecondMotorBackwards, 1, 1),
+                (Action::ThirdMotorForwards, 2, 0),
+                (Action::ThirdMotorBackwards, 2, 1),
+            ] {
+                let pwm_override = &mut self.settings.robots[selected as usize].config.pwm_override
+                    [motor][direction];
+                if pressed(action) {
+                    *pwm_override = Some(RobotDefinition::new(selected).pwm_top / 2);
+                } else if released(action) {
+                    *pwm_override = None;
+                }
+            }
 
-                    let dir = if left_x.abs() > deadzone || left_y.abs() > deadzone {
-                        if left_x.abs() > left_y.abs() {
-                            if left_x > deadzone {
-                                Direction::Right
-                            } else if left_x < -deadzone {
-                                Direction::Left
-                            } else {
-                                Direction::Stay
-                            }
-                        } else {
-                            if left_y > deadzone {
-                                Direction::Up
-                            } else if left_y < -deadzone {
-                                Direction::Down
-                            } else {
-                                Direction::Stay
-                            }
-                        }
-                    } else {
-                        Direction::Stay
-                    };
+            if pressed(Action::CvGameState) {
+                self.settings.cv_location_source = CvLocationSource::GameState
+            }
+            if pressed(Action::CvLocalization) {
+                self.settings.cv_location_source = CvLocationSource::Localization
+            }
+            if pressed(Action::CvPointer) {
+                if let Some(pos) = self.pointer_pos {
+                    let pos = self.world_to_screen.inverse().map_point(pos);
+                    let p = Point2::new(pos.x.round() as i8, pos.y.round() as i8);
+                    self.settings.cv_location_source = CvLocationSource::Constant(Some(p))
+                }
+            }
 
-                    if dir != Direction::Stay {
-                        if let Some(curr_loc) = self.server_status.cv_location {
-                            let next_loc = Point2::new(
-                                curr_loc.x + dir.vector().0,
-                                curr_loc.y + dir.vector().1,
-                            );
-                            if !self.grid.wall_at(&next_loc)
-                                && !self.server_status.target_path.contains(&next_loc)
-                            {
-                                self.send(GuiToServerMessage::TargetLocation(next_loc));
-                            } else if let Some(prev) = self
-                                .server_status
-                                .target_path
-                                .iter()
-                                .map(|loc| {
-                                    Point2::new(loc.x + dir.vector().0, loc.y + dir.vector().1)
-                                })
-                                .filter(|loc| {
-                                    !self.grid.wall_at(loc)
-                                        && !self.server_status.target_path.contains(loc)
-                                })
-                                .next()
-                            {
-                                self.send(GuiToServerMessage::TargetLocation(prev));
-                            }
-                        }
-                    }
-                } else {
-                    // Existing velocity handling for gamepad
-                    if let Some(left_x) = gp.axis_data(Axis::LeftStickX) {
-                        if left_x.value() != 0.0 {
-                            target_vel.0 += Vector2::new(left_x.value() * scale, 0.0);
-                        }
-                    }
-                    if let Some(left_y) = gp.axis_data(Axis::LeftStickY) {
-                        if left_y.value() != 0.0 {
-                            target_vel.0 += Vector2::new(0.0, left_y.value() * scale);
-                        }
-                    }
-                    if let Some(right_x) = gp.axis_data(Axis::RightStickX) {
-                        if right_x.value() != 0.0 {
-                            target_vel.1 += -right_x.value() * scale;
-                        }
-                    }
+            for (action, grid) in [
+                (Action::GridPacman, StandardGrid::Pacman),
+                (Action::GridPlayground, StandardGrid::Playground),
+                (Action::GridOpen, StandardGrid::Open),
+            ] {
+                if pressed(action) {
+                    self.settings.standard_grid = grid;
+                    self.settings.custom_grid = None;
                 }
             }
+
             let (lin, ang) = target_vel;
             let v = match self.ui_settings.angle_behavior {
                 VelocityControlAngleBehavior::Free => VelocityControl::LinVelAngVel(lin, ang),
@@ -249,186 +462,8 @@ impl App {
             };
             self.set_target_vel(v);
 
-            // if the currently selected robot isn't connected, but the game server is, then
-            // interpret WASD presses as an attempt to manually play Pacman
-            if self.server_status.robots[self.ui_settings.selected_robot as usize].connection
-                == NetworkStatus::NotConnected
-                && self.server_status.game_server_connection == NetworkStatus::Connected
-            {
-                for (key, dir1, dir2) in [
-                    (Key::W, Direction::Right, Direction::Up),
-                    (Key::A, Direction::Up, Direction::Left),
-                    (Key::S, Direction::Left, Direction::Down),
-                    (Key::D, Direction::Down, Direction::Right),
-                ] {
-                    if i.key_pressed(key) {
-                        self.send(GuiToServerMessage::GameServerCommand(
-                            GameServerCommand::Direction(if self.rotated_grid {
-                                dir1
-                            } else {
-                                dir2
-                            }),
-                        ));
-                    }
-                }
-                if i.key_pressed(Key::W) {}
-            }
-
             for event in &i.events {
                 match event {
-                    Event::Key {
-                        key, pressed: true, ..
-                    } => {
-                        match key {
-                            Key::Y => {
-                                self.settings.do_target_path =
-                                    if self.settings.do_target_path == ShouldDoTargetPath::Yes {
-                                        ShouldDoTargetPath::DoWhilePlayed
-                                    } else {
-                                        ShouldDoTargetPath::Yes
-                                    }
-                            }
-                            // Game state
-                            Key::R => self.send(GuiToServerMessage::GameServerCommand(
-                                GameServerCommand::Reset,
-                            )),
-                            Key::Space => {
-                                if self.server_status.game_state.paused {
-                                    self.send(GuiToServerMessage::GameServerCommand(
-                                        GameServerCommand::Unpause,
-                                    ))
-                                } else {
-                                    self.send(GuiToServerMessage::GameServerCommand(
-                                        GameServerCommand::Pause,
-                                    ))
-                                }
-                            }
-                            // Strategy
-                            Key::Z => self.settings.driving.strategy = StrategyChoice::Manual,
-                            Key::X => {
-                                self.settings.driving.strategy =
-                                    StrategyChoice::ReinforcementLearning
-                            }
-                            Key::C => self.settings.driving.strategy = StrategyChoice::TestUniform,
-                            Key::V => self.settings.driving.strategy = StrategyChoice::TestForward,
-                            // Driving
-                            Key::P => {
-                                self.settings.robots[self.ui_settings.selected_robot as usize]
-                                    .connection
-                                    .connect = !self.settings.robots
-                                    [self.ui_settings.selected_robot as usize]
-                                    .connection
-                                    .connect
-                            }
-                            Key::U => {
-                                self.settings.robots[self.ui_settings.selected_robot as usize]
-                                    .config
-                                    .pwm_override[0][0] = Some(
-                                    RobotDefinition::new(self.ui_settings.selected_robot).pwm_top
-                                        / 2,
-                                )
-                            }
-                            Key::J => {
-                                self.settings.robots[self.ui_settings.selected_robot as usize]
-                                    .config
-                                    .pwm_override[0][1] = Some(
-                                    RobotDefinition::new(self.ui_settings.selected_robot).pwm_top
-                                        / 2,
-                                )
-                            }
-                            Key::I => {
-                                self.settings.robots[self.ui_settings.selected_robot as usize]
-                                    .config
-                                    .pwm_override[1][0] = Some(
-                                    RobotDefinition::new(self.ui_settings.selected_robot).pwm_top
-                                        / 2,
-                                )
-                            }
-                            Key::K => {
-                                self.settings.robots[self.ui_settings.selected_robot as usize]
-                                    .config
-                                    .pwm_override[1][1] = Some(
-                                    RobotDefinition::new(self.ui_settings.selected_robot).pwm_top
-                                        / 2,
-                                )
-                            }
-                            Key::O => {
-                                self.settings.robots[self.ui_settings.selected_robot as usize]
-                                    .config
-                                    .pwm_override[2][0] = Some(
-                                    RobotDefinition::new(self.ui_settings.selected_robot).pwm_top
-                                        / 2,
-                                )
-                            }
-                            Key::L => {
-                                self.settings.robots[self.ui_settings.selected_robot as usize]
-                                    .config
-                                    .pwm_override[2][1] = Some(
-                                    RobotDefinition::new(self.ui_settings.selected_robot).pwm_top
-                                        / 2,
-                                )
-                            }
-                            // CV source
-                            Key::G => {
-                                self.settings.cv_location_source = CvLocationSource::GameState
-                            }
-                            Key::H => {
-                                self.settings.cv_location_source = CvLocationSource::Localization
-                            }
-                            Key::T => {
-                                if let Some(pos) = self.pointer_pos {
-                                    let pos = self.world_to_screen.inverse().map_point(pos);
-                                    let p = Point2::new(pos.x.round() as i8, pos.y.round() as i8);
-                                    // if !self.grid.wall_at(&p) {
-                                    self.settings.cv_location_source =
-                                        CvLocationSource::Constant(Some(p))
-                                    // }
-                                }
-                            }
-                            // Grid
-                            Key::B => self.settings.standard_grid = StandardGrid::Pacman,
-                            Key::N => self.settings.standard_grid = StandardGrid::Playground,
-                            Key::M => self.settings.standard_grid = StandardGrid::Open,
-                            _ => {}
-                        }
-                    }
-                    Event::Key {
-                        key,
-                        pressed: false,
-                        ..
-                    } => match key {
-                        Key::U => {
-                            self.settings.robots[self.ui_settings.selected_robot as usize]
-                                .config
-                                .pwm_override[0][0] = None
-                        }
-                        Key::J => {
-                            self.settings.robots[self.ui_settings.selected_robot as usize]
-                                .config
-                                .pwm_override[0][1] = None
-                        }
-                        Key::I => {
-                            self.settings.robots[self.ui_settings.selected_robot as usize]
-                                .config
-                                .pwm_override[1][0] = None
-                        }
-                        Key::K => {
-                            self.settings.robots[self.ui_settings.selected_robot as usize]
-                                .config
-                                .pwm_override[1][1] = None
-                        }
-                        Key::O => {
-                            self.settings.robots[self.ui_settings.selected_robot as usize]
-                                .config
-                                .pwm_override[2][0] = None
-                        }
-                        Key::L => {
-                            self.settings.robots[self.ui_settings.selected_robot as usize]
-                                .config
-                                .pwm_override[2][1] = None
-                        }
-                        _ => {}
-                    },
                     // Mouse buttons
                     Event::PointerButton {
                         button: PointerButton::Primary,
@@ -437,7 +472,8 @@ impl App {
                         ..
                     } => {
                         let pos2 = self.world_to_screen.inverse().map_point(*pos);
-                        if *pressed {
+                        // while editing a path, clicks on the grid belong to the path editor
+                        if *pressed && !self.path_editor.editing {
                             self.send(GuiToServerMessage::SimulationCommand(
                                 ServerToSimulationMessage::Teleport(
                                     self.ui_settings.selected_robot,
@@ -480,13 +516,23 @@ impl App {
                         ..
                     } => {
                         let pos = self.world_to_screen.inverse().map_point(*pos);
-                        if let Some(loc) = self.grid.node_nearest(pos.x, pos.y) {
-                            self.send(GuiToServerMessage::TargetLocation(loc))
+                        if !self.path_editor.editing {
+                            // the robot is sent to the exact point clicked, unless it is in a wall
+                            if self.grid.node_nearest(pos.x, pos.y).is_some() {
+                                self.send(GuiToServerMessage::TargetLocation(Point2::new(
+                                    pos.x, pos.y,
+                                )))
+                            }
                         }
                     }
                     _ => {}
                 }
             }
-        })
+
+            switch_robot
+        });
+        if let Some(forwards) = switch_robot {
+            self.switch_robot(forwards);
+        }
     }
 }
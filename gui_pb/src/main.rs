@@ -1,3 +1,4 @@
+mod bindings;
 mod colors;
 
 mod input;
@@ -6,16 +7,31 @@ mod replay;
 mod drawing;
 mod transform;
 
+use crate::bindings::{Bindings, Capture, BINDINGS_STORAGE_KEY};
+use crate::drawing::grid_editor::GridEditor;
+use crate::drawing::layouts::{
+    built_in_layout, draw_layout_menu, Layouts, DEFAULT_LAYOUT, LAYOUTS_STORAGE_KEY,
+    UI_SETTINGS_STORAGE_KEY,
+};
 use crate::drawing::motors::MotorStatusGraphFrames;
+use crate::drawing::path_editor::PathEditor;
+use crate::drawing::replay_manager::{draw_replay_controls, ReplayManager};
 use crate::drawing::settings::UiSettings;
 use crate::drawing::tab::Tab;
+use crate::drawing::telemetry::TelemetryPlot;
 use crate::drawing::widgets::draw_widgets;
+use crate::replay::Replay;
 use crate::transform::Transform;
 use anyhow::Error;
 use core_pb::grid::computed_grid::ComputedGrid;
 use core_pb::grid::standard_grid::StandardGrid;
+use core_pb::grid::Grid;
+use core_pb::localization::LocalizationGrid;
+#[cfg(not(target_arch = "wasm32"))]
+use core_pb::messages::match_recording::MatchRecording;
 use core_pb::messages::server_status::ServerStatus;
 use core_pb::messages::settings::PacbotSettings;
+use core_pb::messages::version::ProtocolVersion;
 use core_pb::messages::{
     GameServerCommand, GuiToServerMessage, NetworkStatus, ServerToGuiMessage, VelocityControl,
 };
@@ -25,10 +41,14 @@ use core_pb::util::stopwatch::Stopwatch;
 use core_pb::util::WebTimeInstant;
 use eframe::egui;
 use eframe::egui::{Align, Color32, Pos2, Visuals};
-use egui_dock::{DockArea, DockState, NodeIndex, Style};
+use egui_dock::{DockArea, DockState, Style};
 use gilrs::Gilrs;
-use log::info;
+use log::{error, info};
+#[cfg(not(target_arch = "wasm32"))]
+use native_dialog::FileDialog;
 use std::collections::HashMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs;
 use std::time::Duration;
 
 // When compiling natively:
@@ -100,13 +120,16 @@ fn main() {
 /// Stores all the data needed for the application
 pub struct App {
     dock_state: Option<DockState<Tab>>,
+    layouts: Layouts,
 
     grid: ComputedGrid,
+    /// [`App::grid`], prepared for showing possible localization regions
+    localization_grid: LocalizationGrid,
     pointer_pos: Option<Pos2>,
     background_color: Color32,
     world_to_screen: Transform,
     robot_buttons_wts: Transform,
-    // replay_manager: ReplayManager,
+    replay_manager: ReplayManager,
     server_status: ServerStatus,
     saved_game_state: Option<GameState>,
     network: (
@@ -118,11 +141,20 @@ pub struct App {
     ui_settings: UiSettings,
     target_vel: VelocityControl,
     motor_status_frames: MotorStatusGraphFrames<3>,
+    telemetry: TelemetryPlot,
+    path_editor: PathEditor,
+    grid_editor: GridEditor,
     gui_stopwatch: Stopwatch<5, 30, WebTimeInstant>,
     rotated_grid: bool,
     settings_fields: Option<HashMap<String, (String, String)>>,
     pacbot_server_connection_status: NetworkStatus,
+    /// Set when the server turns out to be built with different message definitions; the
+    /// connection is dropped until the address or connect setting changes
+    incompatible_server: Option<ProtocolVersion>,
     gilrs: Gilrs,
+    bindings: Bindings,
+    /// The binding waiting for a key, button or stick movement, if one is being changed
+    binding_capture: Option<Capture>,
 }
 
 impl eframe::App for App {
@@ -131,13 +163,15 @@ impl eframe::App for App {
 
         self.pointer_pos = ctx.pointer_latest_pos();
         self.background_color = ctx.style().visuals.panel_fill;
-        if *self.grid.standard_grid() != Some(self.settings.standard_grid) {
-            self.grid = self.settings.standard_grid.compute_grid();
-        }
+        self.update_grid();
         self.gui_stopwatch.mark_completed("Initialization").unwrap();
         self.read_input(ctx);
         self.gui_stopwatch.mark_completed("Read input").unwrap();
         self.manage_network();
+        if let Some(status) = self.replay_manager.update() {
+            self.server_status = status.clone();
+        }
+        self.telemetry.sample(&self.server_status);
         self.gui_stopwatch.mark_completed("Manage network").unwrap();
 
         self.draw_layout(ctx);
@@ -148,6 +182,11 @@ impl eframe::App for App {
             .mark_completed("Request repaint")
             .unwrap();
     }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, BINDINGS_STORAGE_KEY, &self.bindings);
+        self.save_ui_state(storage);
+    }
 }
 
 impl App {
@@ -160,30 +199,32 @@ impl App {
 
         cc.egui_ctx.set_fonts(fonts);
 
-        let mut dock_state = DockState::new(vec![
-            Tab::Grid,
-            Tab::Motors,
-            Tab::Robot,
-            Tab::Stopwatch,
-            Tab::ExtraOpts,
-            Tab::Imu,
-        ]);
-        let surface = dock_state.main_surface_mut();
-        surface.split_right(NodeIndex::root(), 0.75, vec![Tab::Settings]);
-        let [_, left] = surface.split_left(
-            NodeIndex::root(),
-            0.15,
-            vec![Tab::OverTheAirProgramming, Tab::Keybindings],
-        );
-        let [_, below] = surface.split_below(left, 0.6, vec![Tab::RobotDisplay]);
-        surface.split_below(below, 0.6, vec![Tab::RobotButtonPanel]);
-
-        let ui_settings: UiSettings = Default::default();
+        let mut layouts: Layouts = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, LAYOUTS_STORAGE_KEY))
+            .unwrap_or_default();
+        layouts.add_missing_built_ins();
+        let dock_state = layouts
+            .get(&layouts.current)
+            .cloned()
+            .or_else(|| built_in_layout(DEFAULT_LAYOUT))
+            .unwrap();
+
+        let ui_settings: UiSettings = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, UI_SETTINGS_STORAGE_KEY))
+            .unwrap_or_default();
+        let bindings = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, BINDINGS_STORAGE_KEY))
+            .unwrap_or_default();
 
         Self {
             dock_state: Some(dock_state),
+            layouts,
 
             grid: Default::default(),
+            localization_grid: StandardGrid::Pacman.into(),
             pointer_pos: None,
             background_color: Color32::BLACK,
             world_to_screen: Transform::new_letterboxed(
@@ -200,7 +241,7 @@ impl App {
                 Pos2::new(0.0, 1.0),
                 false,
             ),
-            // todo replay_manager: Default::default(),
+            replay_manager: Default::default(),
             server_status: Default::default(),
             saved_game_state: Option::None,
             network: (
@@ -210,6 +251,9 @@ impl App {
             old_settings: Default::default(),
             settings: Default::default(),
             motor_status_frames: MotorStatusGraphFrames::new(ui_settings.selected_robot),
+            telemetry: Default::default(),
+            path_editor: Default::default(),
+            grid_editor: Default::default(),
             ui_settings,
             target_vel: VelocityControl::None,
             gui_stopwatch: Stopwatch::new(
@@ -223,7 +267,40 @@ impl App {
             rotated_grid: true,
             settings_fields: Some(HashMap::new()),
             pacbot_server_connection_status: NetworkStatus::NotConnected,
+            incompatible_server: None,
             gilrs: Gilrs::new().unwrap(),
+            bindings,
+            binding_capture: None,
+        }
+    }
+
+    /// The grid being shown, which is the recorded one during playback
+    pub fn standard_grid(&self) -> StandardGrid {
+        self.replay_manager
+            .standard_grid()
+            .unwrap_or(self.settings.standard_grid)
+    }
+
+    /// The custom grid being shown, if any, which is the recorded one during playback
+    pub fn custom_grid(&self) -> Option<Grid> {
+        match self.replay_manager.is_playing() {
+            true => self.replay_manager.custom_grid(),
+            false => self.settings.custom_grid,
+        }
+    }
+
+    /// Recompute the grid if the one being shown changed
+    fn update_grid(&mut self) {
+        let changed = match self.custom_grid() {
+            Some(grid) => self.grid.standard_grid().is_some() || *self.grid.grid() != grid,
+            None => *self.grid.standard_grid() != Some(self.standard_grid()),
+        };
+        if changed {
+            self.grid = self
+                .custom_grid()
+                .and_then(|grid| ComputedGrid::try_from_custom(grid).ok())
+                .unwrap_or_else(|| self.standard_grid().compute_grid());
+            self.localization_grid = LocalizationGrid::new(*self.grid.grid());
         }
     }
 
@@ -231,6 +308,14 @@ impl App {
         self.network.0.send(TextOrT::T(message))
     }
 
+    /// The status of the connection to the server, including whether it was refused
+    pub fn server_connection_status(&self) -> NetworkStatus {
+        match self.incompatible_server {
+            Some(version) => NetworkStatus::Incompatible(version),
+            None => self.pacbot_server_connection_status,
+        }
+    }
+
     pub fn manage_network(&mut self) {
         let new_addr = if self.ui_settings.mdrc_server.connect {
             Some((
@@ -242,14 +327,31 @@ impl App {
         };
         if self.network.1 != new_addr {
             self.network.1 = new_addr;
+            self.incompatible_server = None;
             self.network.0.connect(new_addr)
         }
         // we must check for changed settings before updating them from the server
         if self.old_settings != self.settings {
+            if self.old_settings.reconnect_backoff != self.settings.reconnect_backoff {
+                self.network.0.set_backoff(self.settings.reconnect_backoff);
+            }
             self.send(GuiToServerMessage::Settings(self.settings.clone()));
         }
         while let Some(TextOrT::T(msg)) = self.network.0.read() {
             match msg {
+                ServerToGuiMessage::Version(version) => {
+                    if version.is_compatible() {
+                        self.send(GuiToServerMessage::Version(ProtocolVersion::CURRENT));
+                    } else {
+                        error!(
+                            "Server has {version}, but the gui has {}; disconnecting",
+                            ProtocolVersion::CURRENT
+                        );
+                        self.incompatible_server = Some(version);
+                        self.network.0.connect(None);
+                        break;
+                    }
+                }
                 ServerToGuiMessage::Settings(settings) => {
                     if self.pacbot_server_connection_status != NetworkStatus::Connected
                         && self.network.0.status() == NetworkStatus::Connected
@@ -258,14 +360,22 @@ impl App {
                         // send our settings to hopefully replace the server's
                         self.send(GuiToServerMessage::Settings(self.settings.clone()));
                     }
-                    self.settings = settings.clone();
-                    if &Some(self.settings.standard_grid) != self.grid.standard_grid() {
-                        self.grid = self.settings.standard_grid.compute_grid();
+                    if settings.reconnect_backoff != self.old_settings.reconnect_backoff {
+                        self.network.0.set_backoff(settings.reconnect_backoff);
                     }
+                    self.settings = settings.clone();
+                    self.update_grid();
                     self.old_settings = settings
                 }
                 ServerToGuiMessage::Status(status) => {
-                    self.server_status = status;
+                    self.replay_manager.record_status(
+                        &status,
+                        self.settings.standard_grid,
+                        self.settings.custom_grid,
+                    );
+                    if !self.replay_manager.is_playing() {
+                        self.server_status = status;
+                    }
                 }
             }
         }
@@ -279,14 +389,21 @@ impl App {
                 ui.with_layout(egui::Layout::left_to_right(Align::Center), |ui| {
                     // grid selector
                     egui::ComboBox::from_label("")
-                        .selected_text(format!("{:?}", self.settings.standard_grid))
+                        .selected_text(match self.settings.custom_grid {
+                            Some(_) => "Custom".to_string(),
+                            None => format!("{:?}", self.settings.standard_grid),
+                        })
                         .show_ui(ui, |ui| {
                             StandardGrid::get_all().iter().for_each(|grid| {
-                                ui.selectable_value(
-                                    &mut self.settings.standard_grid,
-                                    *grid,
-                                    format!("{:?}", grid),
-                                );
+                                let selected = self.settings.custom_grid.is_none()
+                                    && self.settings.standard_grid == *grid;
+                                if ui
+                                    .selectable_label(selected, format!("{:?}", grid))
+                                    .clicked()
+                                {
+                                    self.settings.standard_grid = *grid;
+                                    self.settings.custom_grid = None;
+                                }
                             });
                         });
                     // top left buttons
@@ -305,13 +422,32 @@ impl App {
                         }
 
                         ui.menu_button("Replay", |ui| {
+                            ui.checkbox(&mut self.replay_manager.record, "Record");
+                            ui.label(format!(
+                                "{} frames, {:.1}s recorded",
+                                self.replay_manager.recording.frame_count(),
+                                self.replay_manager.recording.duration().as_secs_f32()
+                            ));
+                            if ui.button("Play recording").clicked() {
+                                let recording = self.replay_manager.recording.clone();
+                                self.replay_manager.start_playback(recording);
+                            }
+                            if ui.button("Clear recording").clicked() {
+                                self.replay_manager.recording = Replay::default();
+                            }
+                            ui.separator();
                             if ui.button("Save").clicked() {
-                                self.save_replay().expect("Failed to save replay!");
+                                if let Err(e) = self.save_replay() {
+                                    error!("Failed to save replay: {e:?}");
+                                }
                             }
                             if ui.button("Load").clicked() {
-                                self.load_replay().expect("Failed to load replay!");
+                                if let Err(e) = self.load_replay() {
+                                    error!("Failed to load replay: {e:?}");
+                                }
                             }
                         });
+                        ui.menu_button("Layout", |ui| draw_layout_menu(self, ui));
                         draw_widgets(self, ui)
                     });
                 });
@@ -329,6 +465,10 @@ impl App {
             });
         });
 
+        if self.replay_manager.is_playing() {
+            egui::TopBottomPanel::bottom("replay").show(ctx, |ui| draw_replay_controls(self, ui));
+        }
+
         // take out dock_state to pass it to DockArea::new and allow tabs to use data from App
         let mut dock_state = self.dock_state.take().unwrap();
         DockArea::new(&mut dock_state)
@@ -337,47 +477,59 @@ impl App {
         self.dock_state = Some(dock_state);
     }
 
-    /// Save the current replay to file
+    /// Save the current recording to file
+    #[cfg(not(target_arch = "wasm32"))]
     pub fn save_replay(&self) -> Result<(), Error> {
-        todo!()
-        // let path = FileDialog::new()
-        //     .add_filter("Pacbot Replay", &["pb"])
-        //     .set_filename("replay.pb")
-        //     .show_save_single_file()?;
-        //
-        // if let Some(path) = path {
-        //     let bytes = self.data.replay_manager.replay.to_bytes()?;
-        //     let mut file = fs::OpenOptions::new()
-        //         .write(true)
-        //         .create(true)
-        //         .truncate(true)
-        //         .open(path)?;
-        //     file.write_all(&bytes)?;
-        // }
-        //
-        // Ok(())
+        let path = FileDialog::new()
+            .add_filter("Pacbot Replay", &["pb"])
+            .set_filename("replay.pb")
+            .show_save_single_file()?;
+
+        if let Some(path) = path {
+            let bytes = self.replay_manager.recording.to_bytes()?;
+            fs::write(path, bytes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Save the current recording to file
+    #[cfg(target_arch = "wasm32")]
+    pub fn save_replay(&self) -> Result<(), Error> {
+        Err(anyhow::anyhow!("Replays can't be saved from the web gui"))
+    }
+
+    /// Load a replay, or a match recorded by the server, from file, and start playing it back
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_replay(&mut self) -> Result<(), Error> {
+        let path = FileDialog::new()
+            .add_filter("Pacbot Replay", &["pb", "pbm"])
+            .show_open_single_file()?;
+
+        if let Some(path) = path {
+            let label = path
+                .file_stem()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let bytes = fs::read(&path)?;
+            let replay = if MatchRecording::is_recording(&bytes) {
+                let recording = MatchRecording::from_bytes(&bytes)
+                    .map_err(|e| anyhow::anyhow!("Invalid match recording: {e:?}"))?;
+                Replay::from_match_recording(label, &recording)
+            } else {
+                let mut replay = Replay::from_bytes(&bytes)?;
+                replay.label = label;
+                replay
+            };
+            self.replay_manager.start_playback(replay);
+        }
+
+        Ok(())
     }
 
-    /// Load a replay from file
+    /// Load a replay from file, and start playing it back
+    #[cfg(target_arch = "wasm32")]
     pub fn load_replay(&mut self) -> Result<(), Error> {
-        todo!()
-        // let path = FileDialog::new()
-        //     .add_filter("Pacbot Replay", &["pb"])
-        //     .show_open_single_file()?;
-        //
-        // if let Some(path) = path {
-        //     let mut file = File::open(&path)?;
-        //     let metadata = fs::metadata(&path).expect("unable to read metadata");
-        //     let mut buffer = vec![0; metadata.len() as usize];
-        //     file.read_exact(&mut buffer)?;
-        //
-        //     let replay = Replay::from_bytes(&buffer)?;
-        //
-        //     // self.settings.mode = AppMode::Playback;
-        //     self.data.replay_manager.replay = replay.0;
-        //     self.data.replay_manager.playback_paused = true;
-        // }
-        //
-        // Ok(())
+        Err(anyhow::anyhow!("Replays can't be loaded from the web gui"))
     }
 }
@@ -2,517 +2,230 @@
 
 use anyhow::{anyhow, Error};
 use core_pb::grid::standard_grid::StandardGrid;
-use core_pb::pacbot_rs::game_state::GameState;
-use nalgebra::Isometry2;
+use core_pb::grid::Grid;
+use core_pb::messages::match_recording::{MatchEvent, MatchRecording};
+use core_pb::messages::server_status::ServerStatus;
+use core_pb::messages::version::ProtocolVersion;
 use serde::{Deserialize, Serialize};
-use std::time::{Duration, SystemTime};
+use std::collections::VecDeque;
+use std::time::Duration;
+use web_time::Instant;
 
-/// The types of data that might be stored in a [`ReplayFrame`]
-#[derive(Clone, Debug, Serialize, Deserialize)]
-enum ReplayFrameData {
-    /// Pacbot's real physical location, as determined by the [`PacbotSimulation`], as well as the
-    /// particle filter's best guess
-    PacbotLocation(Isometry2<f32>, Isometry2<f32>),
-    // /// Information that changes frequently in Pacman, like ghost locations and pellets
-    // PacmanGameState(Box<GameEngine>),
-    // /// Pacbot's sensors
-    // PacbotSensors(PacbotSensors),
-    // /// The AI's desired path
-    // TargetPath(Vec<IntLocation>),
-    // /// The robot target velocity
-    // TargetVelocity(Vector2<f32>, f32),
-}
+/// The most frames a recording keeps, about 20 minutes of the server's statuses; when more are
+/// recorded, the oldest are dropped
+pub const MAX_RECORDED_FRAMES: usize = 30_000;
 
-/// The metadata included in one frame of a [`Replay`]
+/// One status received from the server, and when it was received
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct ReplayFrame {
-    /// The data in the frame
-    pub data: ReplayFrameData,
-    /// When the data was created
-    pub timestamp: SystemTime,
+    /// How long after the start of the recording the status was received
+    time: Duration,
+    /// The grid the server was using
+    standard_grid: StandardGrid,
+    /// The custom grid the server was using instead of `standard_grid`, if any
+    custom_grid: Option<Grid>,
+    /// Everything the server reported, including the game state, target path, and every robot's
+    /// pose, sensors and motor status
+    status: Box<ServerStatus>,
 }
 
 /// A collection of frames representing a full replay, along with associated metadata
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Replay {
-    /// The time when recording started
-    start_time: SystemTime,
-    /// The StandardGrid the recording uses
-    standard_grid: StandardGrid,
     /// The name/label given to this replay (usually matches the file name)
     pub label: String,
     /// The data of the replay
-    frames: Vec<ReplayFrame>,
+    frames: VecDeque<ReplayFrame>,
     /// Index of the most recently recorded or played frame
     current_frame: usize,
-    /// Index of the most recently recorded or played pacman state frame
-    pacman_state_frame: usize,
-    /// Index of the most recently recorded or played pacman location frame
-    location_frame: usize,
+    /// When the recording would have started, if it had been recorded without breaks
+    #[serde(skip)]
+    started: Option<Instant>,
 }
 
 impl Default for Replay {
     fn default() -> Self {
-        Self::new(
-            "replay".to_string(),
-            StandardGrid::Pacman,
-            GameState::default(),
-            StandardGrid::Pacman.get_default_pacbot_isometry(),
-        )
+        Self::new("replay".to_string())
     }
 }
 
-#[allow(dead_code)]
 impl Replay {
-    /// Start a new Replay
-    ///
-    /// Note: pacman_state is copied once
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use pacbot_rs::game_engine::GameEngine;
-    /// use rapier2d::math::Isometry;
-    /// use rapier2d::na::Vector2;
-    /// use mdrc_pacbot_util::replay::Replay;
-    /// use mdrc_pacbot_util::grid::standard_grids::StandardGrid;
-    ///
-    /// let mut replay = Replay::new(
-    ///     "My First Replay".to_string(),
-    ///     StandardGrid::Pacman,
-    ///     GameEngine::default(),
-    ///     StandardGrid::Pacman.get_default_pacbot_isometry()
-    /// );
-    ///
-    /// assert_eq!(replay.frame_count(), 2);
-    /// assert_eq!(replay.current_frame(), 1);
-    /// ```
-    pub fn new(
-        label: String,
-        standard_grid: StandardGrid,
-        _pacman_state: GameState,
-        pacbot_location: Isometry2<f32>,
-    ) -> Self {
-        let start_time = SystemTime::now();
-        let frames = vec![
-            // ReplayFrame {
-            //     data: ReplayFrameData::PacmanGameState(Box::new(pacman_state)),
-            //     timestamp: start_time,
-            // },
-            ReplayFrame {
-                data: ReplayFrameData::PacbotLocation(pacbot_location, pacbot_location),
-                timestamp: start_time,
-            },
-        ];
+    /// Start a new, empty Replay
+    pub fn new(label: String) -> Self {
         Self {
-            start_time,
-            standard_grid,
             label,
-            frames,
-            current_frame: 1,
-            pacman_state_frame: 0,
-            location_frame: 1,
+            frames: VecDeque::new(),
+            current_frame: 0,
+            started: None,
         }
     }
 
-    /// Create a new Replay starting at the current frame in the given Replay
-    ///
-    /// All frames are updated so that the most recent one matches the current time
-    ///
-    /// May return Err if the given replay frame is in the future
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use pacbot_rs::game_engine::GameEngine;
-    /// use rand::rngs::ThreadRng;
-    /// use rapier2d::math::Isometry;
-    /// use rapier2d::na::Vector2;
-    /// use mdrc_pacbot_util::replay::Replay;
-    /// use mdrc_pacbot_util::grid::standard_grids::StandardGrid;
-    ///
-    /// let mut pacman_state = GameEngine::default();
-    /// let mut rng = ThreadRng::default();
-    ///
-    /// let mut replay = Replay::new(
-    ///     "My First Replay".to_string(),
-    ///     StandardGrid::Pacman,
-    ///     pacman_state.to_owned(),
-    ///     StandardGrid::Pacman.get_default_pacbot_isometry()
-    /// );
-    ///
-    /// assert_eq!(replay.frame_count(), 2);
-    /// assert_eq!(replay.current_frame(), 1);
-    ///
-    /// for i in 0..3 {
-    ///     pacman_state.step();
-    ///     replay.record_pacman_state(pacman_state.to_owned()).unwrap();
-    /// }
-    ///
-    /// replay.step_back();
-    /// replay.step_back();
-    ///
-    /// assert!(!replay.is_at_end());
-    /// assert_eq!(replay.frame_count(), 5);
-    /// assert_eq!(replay.current_frame(), 2);
-    ///
-    /// let replay = Replay::starting_at(&replay);
+    /// Create a new Replay using bytes from a file
     ///
-    /// assert!(replay.is_at_end());
-    /// assert_eq!(replay.frame_count(), 3);
-    /// assert_eq!(replay.current_frame(), 2);
-    /// ```
-    pub fn starting_at(other: &Replay) -> Self {
-        let mut frames = Vec::new();
-        let now = SystemTime::now();
-        let offset = now
-            .duration_since(other.frames[other.current_frame].timestamp)
-            .expect("Other replay ended in the future!");
-
-        let pacman_state_frame = 0;
-        let mut location_frame = 0;
-
-        for frame in 0..=other.current_frame {
-            match other.frames[frame].data {
-                ReplayFrameData::PacbotLocation(_, _) => location_frame = frame,
-                // ReplayFrameData::PacmanGameState(_) => pacman_state_frame = frame,
-                // _ => {}
-            }
-            frames.push(ReplayFrame {
-                data: other.frames[frame].data.to_owned(),
-                timestamp: other.frames[frame].timestamp + offset,
-            })
+    /// Replays store whole [`ServerStatus`]es, so those recorded with different message
+    /// definitions can't be read
+    pub fn from_bytes(bytes: &[u8]) -> Result<Replay, Error> {
+        let (version, len): (ProtocolVersion, _) =
+            bincode::serde::decode_from_slice(bytes, bincode::config::standard())?;
+        if !version.is_compatible() {
+            return Err(anyhow!(
+                "Replay was recorded with {version}, but the gui has {}",
+                ProtocolVersion::CURRENT
+            ));
         }
+        let (replay, _) =
+            bincode::serde::decode_from_slice(&bytes[len..], bincode::config::standard())?;
+        Ok(replay)
+    }
 
+    /// Create a new Replay from the statuses in a recording made by the server
+    pub fn from_match_recording(label: String, recording: &MatchRecording) -> Self {
+        let mut standard_grid = recording.header.settings.standard_grid;
+        let mut custom_grid = recording.header.settings.custom_grid;
+        let mut frames = VecDeque::new();
+        for record in &recording.records {
+            match &record.event {
+                MatchEvent::Status(status) => frames.push_back(ReplayFrame {
+                    time: record.time,
+                    standard_grid,
+                    custom_grid,
+                    status: status.clone(),
+                }),
+                MatchEvent::Settings(settings) => {
+                    standard_grid = settings.standard_grid;
+                    custom_grid = settings.custom_grid;
+                }
+                MatchEvent::FromGui(_) | MatchEvent::GameServerFrame(_) => {}
+            }
+        }
         Self {
-            start_time: frames[0].timestamp,
-            standard_grid: other.standard_grid,
-            label: other.label.to_owned(),
+            label,
             frames,
-            current_frame: other.current_frame,
-            pacman_state_frame,
-            location_frame,
+            current_frame: 0,
+            started: None,
         }
     }
 
-    /// Create a new Replay using bytes from a file
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use mdrc_pacbot_util::replay::Replay;
-    ///
-    /// let replay = Replay::default();
-    /// let replay_bytes = replay.to_bytes().expect("Failed to serialize replay!");
-    /// let replay2 = Replay::from_bytes(&replay_bytes).expect("Failed to deserialize replay!");
-    /// ```
-    pub fn from_bytes(bytes: &[u8]) -> Result<(Replay, usize), bincode::error::DecodeError> {
-        bincode::serde::decode_from_slice(bytes, bincode::config::standard())
+    /// Get the bytes associated with the Replay
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::error::EncodeError> {
+        let mut bytes =
+            bincode::serde::encode_to_vec(ProtocolVersion::CURRENT, bincode::config::standard())?;
+        bytes.extend(bincode::serde::encode_to_vec(
+            self,
+            bincode::config::standard(),
+        )?);
+        Ok(bytes)
     }
 
-    /// Get the bytes associated with the Replay
-    ///
-    /// # Examples
+    /// Add a status to the end of the replay, and move to it
     ///
-    /// ```
-    /// use mdrc_pacbot_util::replay::Replay;
-    ///
-    /// let replay = Replay::default();
-    /// let replay_bytes = replay.to_bytes().expect("Failed to serialize replay!");
-    /// let replay2 = Replay::from_bytes(&replay_bytes).expect("Failed to deserialize replay!");
-    /// ```
-    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::error::EncodeError> {
-        bincode::serde::encode_to_vec(self, bincode::config::standard())
+    /// If the replay was loaded or cleared, recording continues right after the last frame. Only
+    /// the last [`MAX_RECORDED_FRAMES`] are kept.
+    pub fn record_status(
+        &mut self,
+        status: &ServerStatus,
+        standard_grid: StandardGrid,
+        custom_grid: Option<Grid>,
+    ) {
+        let last_time = self.duration();
+        let started = *self.started.get_or_insert_with(|| {
+            let now = Instant::now();
+            now.checked_sub(last_time).unwrap_or(now)
+        });
+        self.frames.push_back(ReplayFrame {
+            time: started.elapsed().max(last_time),
+            standard_grid,
+            custom_grid,
+            status: Box::new(status.clone()),
+        });
+        while self.frames.len() > MAX_RECORDED_FRAMES {
+            self.frames.pop_front();
+        }
+        self.current_frame = self.frames.len() - 1;
+    }
+
+    /// The status at the current frame, if there are any frames
+    pub fn status(&self) -> Option<&ServerStatus> {
+        self.frames
+            .get(self.current_frame)
+            .map(|f| f.status.as_ref())
+    }
+
+    /// The grid at the current frame, if there are any frames
+    pub fn standard_grid(&self) -> Option<StandardGrid> {
+        self.frames.get(self.current_frame).map(|f| f.standard_grid)
+    }
+
+    /// The custom grid at the current frame, if there are any frames and it had one
+    pub fn custom_grid(&self) -> Option<Grid> {
+        self.frames
+            .get(self.current_frame)
+            .and_then(|f| f.custom_grid)
     }
 
     /// Returns whether the replay has played its last frame
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use pacbot_rs::game_engine::GameEngine;
-    /// use mdrc_pacbot_util::replay::Replay;
-    ///
-    /// let mut replay = Replay::default();
-    /// assert!(replay.is_at_end());
-    /// replay.record_pacman_state(GameEngine::default()).unwrap();
-    /// assert!(replay.is_at_end());
-    /// replay.step_back();
-    /// assert!(!replay.is_at_end());
-    /// replay.step_forwards();
-    /// assert!(replay.is_at_end());
-    /// ```
     pub fn is_at_end(&self) -> bool {
-        self.current_frame == self.frames.len() - 1
+        self.current_frame + 1 >= self.frames.len()
     }
 
     /// Returns whether the replay is at the beginning
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use pacbot_rs::game_engine::GameEngine;
-    /// use mdrc_pacbot_util::replay::Replay;
-    ///
-    /// let mut replay = Replay::default();
-    /// assert!(replay.is_at_beginning());
-    /// replay.record_pacman_state(GameEngine::default()).unwrap();
-    /// assert!(!replay.is_at_beginning());
-    /// replay.step_back();
-    /// assert!(replay.is_at_beginning());
-    /// replay.step_forwards();
-    /// assert!(!replay.is_at_beginning());
-    /// ```
     pub fn is_at_beginning(&self) -> bool {
-        self.current_frame <= 1
-    }
-    //
-    // /// Returns the most recent PacmanState
-    // ///
-    // /// # Examples
-    // ///
-    // /// ```
-    // /// use pacbot_rs::game_engine::GameEngine;
-    // /// use rand::rngs::ThreadRng;
-    // /// use mdrc_pacbot_util::replay::Replay;
-    // ///
-    // /// let mut replay = Replay::default();
-    // /// let mut pacman_state = GameEngine::default();
-    // /// let mut rng = ThreadRng::default();
-    // ///
-    // /// pacman_state.step();
-    // /// replay.record_pacman_state(pacman_state.to_owned()).unwrap();
-    // /// let pacman_state_1 = pacman_state.to_owned();
-    // /// pacman_state.step();
-    // /// replay.record_pacman_state(pacman_state.to_owned()).unwrap();
-    // /// let pacman_state_2 = pacman_state.to_owned();
-    // ///
-    // /// let _ = replay.get_pacman_state();
-    // /// ```
-    // pub fn get_pacman_state(&self) -> GameEngine {
-    //     if let ReplayFrameData::PacmanGameState(data) = &self.frames[self.pacman_state_frame].data {
-    //         *data.to_owned()
-    //     } else {
-    //         panic!("Replay was corrupt - pacman_state_frame was wrong")
-    //     }
-    // }
-
-    /// Returns the most recent PacbotLocation
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use rapier2d::na::{Isometry2, Vector2};
-    /// use mdrc_pacbot_util::replay::Replay;
-    ///
-    /// let mut replay = Replay::default();
-    ///
-    /// let isometry_1 = Isometry2::new(Vector2::new(1.0, 1.0), 1.0);
-    /// replay.record_pacman_location(isometry_1.to_owned()).unwrap();
-    /// let isometry_2 = Isometry2::new(Vector2::new(2.0, 2.0), 2.0);
-    /// replay.record_pacman_location(isometry_2.to_owned()).unwrap();
-    ///
-    /// assert_eq!(isometry_2, replay.get_pacbot_location());
-    /// replay.step_back();
-    /// assert_eq!(isometry_1, replay.get_pacbot_location());
-    /// ```
-    pub fn get_pacbot_location(&self) -> Isometry2<f32> {
-        // if let ReplayFrameData::PacbotLocation(data, _) = &self.frames[self.location_frame].data {
-        //     data.to_owned()
-        // } else {
-        //     panic!("Replay was corrupt - location_frame was wrong")
-        // }
-        Isometry2::identity()
-    }
-
-    fn update_current_frames(&mut self) {
-        match &self.frames[self.current_frame].data {
-            ReplayFrameData::PacbotLocation(_, _) => self.location_frame = self.current_frame,
-            // ReplayFrameData::PacmanGameState(_) => self.pacman_state_frame = self.current_frame,
-            // _ => {}
-        };
+        self.current_frame == 0
     }
 
     /// Moves to the next frame, if it exists
     pub fn step_forwards(&mut self) {
         if !self.is_at_end() {
             self.current_frame += 1;
-            self.update_current_frames();
         }
     }
 
     /// Moves to the previous frame, if it exists
     pub fn step_back(&mut self) {
-        if self.current_frame >= 2 {
-            self.current_frame -= 1;
-            self.update_current_frames();
-        } else {
-            // play all the frames from the beginning
-            self.go_to_beginning();
-        }
+        self.current_frame = self.current_frame.saturating_sub(1);
     }
 
     /// Go back to the beginning of the recording
     pub fn go_to_beginning(&mut self) {
         self.current_frame = 0;
-        self.pacman_state_frame = 0;
-        self.location_frame = 1;
-        self.update_current_frames();
     }
 
     /// Go to the end of the recording
     pub fn go_to_end(&mut self) {
-        for frame in 0..self.frames.len() {
-            match &self.frames[frame].data {
-                ReplayFrameData::PacbotLocation(_, _) => self.location_frame = frame,
-                // ReplayFrameData::PacmanGameState(_) => self.pacman_state_frame = frame,
-                // _ => {}
-            }
-        }
-
-        self.current_frame = self.frames.len() - 1;
+        self.current_frame = self.frames.len().saturating_sub(1);
     }
 
-    /// Step forwards until a PacmanState frame is reached
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use pacbot_rs::game_engine::GameEngine;
-    /// use rapier2d::na::{Isometry2, Vector2};
-    /// use mdrc_pacbot_util::replay::Replay;
-    ///
-    /// let mut replay = Replay::default();
-    ///
-    /// replay.record_pacman_state(GameEngine::default()).unwrap();
-    /// replay.record_pacman_location(Isometry2::new(Vector2::new(1.0, 1.0), 1.0)).unwrap();
-    /// replay.record_pacman_location(Isometry2::new(Vector2::new(2.0, 2.0), 2.0)).unwrap();
-    /// replay.record_pacman_location(Isometry2::new(Vector2::new(3.0, 3.0), 3.0)).unwrap();
-    /// replay.record_pacman_state(GameEngine::default()).unwrap();
-    /// replay.record_pacman_location(Isometry2::new(Vector2::new(4.0, 4.0), 4.0)).unwrap();
-    /// replay.record_pacman_location(Isometry2::new(Vector2::new(5.0, 5.0), 5.0)).unwrap();
-    /// replay.record_pacman_location(Isometry2::new(Vector2::new(6.0, 6.0), 6.0)).unwrap();
-    /// replay.record_pacman_state(GameEngine::default()).unwrap();
-    ///
-    /// assert_eq!(replay.get_pacbot_location(), Isometry2::new(Vector2::new(6.0, 6.0), 6.0));
-    /// replay.step_backwards_until_pacman_state();
-    /// assert_eq!(replay.get_pacbot_location(), Isometry2::new(Vector2::new(4.0, 4.0), 4.0));
-    /// replay.step_backwards_until_pacman_state();
-    /// assert_eq!(replay.get_pacbot_location(), Isometry2::new(Vector2::new(1.0, 1.0), 1.0));
-    /// replay.step_forwards_until_pacman_state();
-    /// assert_eq!(replay.get_pacbot_location(), Isometry2::new(Vector2::new(3.0, 3.0), 3.0));
-    /// replay.step_forwards_until_pacman_state();
-    /// assert_eq!(replay.get_pacbot_location(), Isometry2::new(Vector2::new(6.0, 6.0), 6.0));
-    /// ```
-    pub fn step_forwards_until_pacman_state(&mut self) {
-        let previous_pacman_state_frame = self.pacman_state_frame;
-        while !self.is_at_end() {
-            self.step_forwards();
-            if previous_pacman_state_frame != self.pacman_state_frame {
-                return;
-            }
-        }
+    /// Go to the last frame recorded at or before `time`
+    pub fn seek(&mut self, time: Duration) {
+        self.current_frame = self
+            .frames
+            .partition_point(|f| f.time <= time)
+            .saturating_sub(1);
     }
 
-    /// Step backwards until a PacmanState frame is reached
-    ///
-    /// See [`Replay::step_forwards_until_pacman_state`] for example
-    pub fn step_backwards_until_pacman_state(&mut self) {
-        let previous_pacman_state_frame = self.pacman_state_frame;
-        while !self.is_at_beginning() {
-            self.step_back();
-            if previous_pacman_state_frame != self.pacman_state_frame {
-                return;
-            }
-        }
-    }
-
-    /// Add a pacman location to the end of the replay
-    ///
-    /// Returns err if the current frame is not the last frame
-    ///
-    /// # Examples
-    ///
-    /// ```
-    ///
-    /// use pacbot_rs::game_engine::GameEngine;
-    /// use mdrc_pacbot_util::replay::Replay;
-    ///
-    /// let mut replay = Replay::default();
-    ///
-    /// assert!(replay.record_pacman_state(GameEngine::default()).is_ok());
-    /// replay.step_back();
-    /// // Don't add frames to a replay that isn't at the end
-    /// assert!(replay.record_pacman_state(GameEngine::default()).is_err());
-    /// ```
-    pub fn record_pacman_location(&mut self, location: Isometry2<f32>) -> Result<(), Error> {
-        if !self.is_at_end() {
-            Err(anyhow!("Tried to record to replay that was mid-playback"))
-        } else {
-            self.frames.push(ReplayFrame {
-                timestamp: SystemTime::now(),
-                data: ReplayFrameData::PacbotLocation(location, location),
-            });
-            self.current_frame += 1;
-            self.location_frame = self.current_frame;
-            Ok(())
-        }
-    }
-    //
-    // /// Add a pacman game state to the end of the replay
-    // ///
-    // /// Returns err if the current frame is not the last frame
-    // ///
-    // /// # Examples
-    // ///
-    // /// ```
-    // ///
-    // /// use rapier2d::na::Isometry2;
-    // /// use mdrc_pacbot_util::replay::Replay;
-    // ///
-    // /// let mut replay = Replay::default();
-    // ///
-    // /// assert!(replay.record_pacman_location(Isometry2::default()).is_ok());
-    // /// replay.step_back();
-    // /// // Don't add frames to a replay that isn't at the end
-    // /// assert!(replay.record_pacman_location(Isometry2::default()).is_err());
-    // /// ```
-    // pub fn record_pacman_state(&mut self, state: &GameEngine) -> Result<(), Error> {
-    //     let state = bincode::serde::encode_to_vec(state, bincode::config::standard()).unwrap();
-    //     let state: GameEngine =
-    //         bincode::serde::decode_from_slice(&state, bincode::config::standard())
-    //             .unwrap()
-    //             .0;
-    //     if !self.is_at_end() {
-    //         Err(anyhow!("Tried to record to replay that was mid-playback"))
-    //     } else {
-    //         self.frames.push(ReplayFrame {
-    //             timestamp: SystemTime::now(),
-    //             data: ReplayFrameData::PacmanGameState(Box::new(state)),
-    //         });
-    //         self.current_frame += 1;
-    //         self.pacman_state_frame = self.current_frame;
-    //         Ok(())
-    //     }
-    // }
-
     /// Get the index of the current frame
-    ///
-    /// While this can be a good estimation of the progress through a replay, there is no guarantee
-    /// that step_forwards() or step_back(), or any other methods, will hit any specific frame
     pub fn current_frame(&self) -> usize {
         self.current_frame
     }
 
     /// Get the number of frames
-    ///
-    /// While this can be used to get a good estimation of the progress through a replay, there
-    /// is no guarantee that step_forwards() or step_back(), or any other methods, will hit
-    /// any specific frame
     pub fn frame_count(&self) -> usize {
         self.frames.len()
     }
 
+    /// How long after the start of the recording the current frame was recorded
+    pub fn current_time(&self) -> Duration {
+        self.frames
+            .get(self.current_frame)
+            .map(|f| f.time)
+            .unwrap_or_default()
+    }
+
+    /// How long after the start of the recording the last frame was recorded
+    pub fn duration(&self) -> Duration {
+        self.frames.back().map(|f| f.time).unwrap_or_default()
+    }
+
     /// Get the amount of time until the next frame
     ///
     /// If at the end, returns Duration::MAX
@@ -520,47 +233,71 @@ impl Replay {
         if self.is_at_end() {
             Duration::MAX
         } else {
-            self.frames[self.current_frame + 1]
-                .timestamp
-                .duration_since(self.frames[self.current_frame].timestamp)
-                .expect("Frames are out of order!")
+            self.frames[self.current_frame + 1].time - self.frames[self.current_frame].time
         }
     }
 
     /// Get the amount of time between the current and previous frame
     ///
-    /// If at the end, returns Duration::MAX
+    /// If at the beginning, returns Duration::MAX
     pub fn time_to_previous(&self) -> Duration {
         if self.is_at_beginning() {
             Duration::MAX
         } else {
-            self.frames[self.current_frame]
-                .timestamp
-                .duration_since(self.frames[self.current_frame - 1].timestamp)
-                .expect("Frames are out of order!")
+            self.frames[self.current_frame].time - self.frames[self.current_frame - 1].time
         }
     }
 }
 
-// #[cfg(test)]
-// mod test {
-//     use crate::replay::Replay;
-//     use pacbot_rs::game_engine::GameEngine;
-//     use rapier2d::na::Isometry2;
-//
-//     #[test]
-//     fn test_replay_starting_at_end_of_other() {
-//         let mut replay = Replay::default();
-//
-//         replay.record_pacman_location(Isometry2::default()).unwrap();
-//         replay.record_pacman_location(Isometry2::default()).unwrap();
-//         replay.record_pacman_state(&GameEngine::default()).unwrap();
-//         replay.record_pacman_location(Isometry2::default()).unwrap();
-//         replay.record_pacman_location(Isometry2::default()).unwrap();
-//
-//         let replay = Replay::starting_at(&replay);
-//
-//         replay.get_pacman_state();
-//         replay.get_pacbot_location();
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_round_trip() {
+        let mut replay = Replay::default();
+        let mut status = ServerStatus::default();
+        for i in 0..4 {
+            status.game_state.curr_score = i;
+            replay.record_status(&status, StandardGrid::Pacman, None);
+        }
+        assert!(replay.is_at_end());
+        assert_eq!(replay.frame_count(), 4);
+
+        replay.step_back();
+        replay.step_back();
+        assert_eq!(replay.status().unwrap().game_state.curr_score, 1);
+        replay.seek(Duration::MAX);
+        assert!(replay.is_at_end());
+        replay.seek(Duration::ZERO);
+        assert!(replay.is_at_beginning());
+
+        let bytes = replay.to_bytes().unwrap();
+        let mut loaded = Replay::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.frame_count(), 4);
+        loaded.go_to_end();
+        assert_eq!(loaded.status().unwrap().game_state.curr_score, 3);
+
+        // recording continues after the loaded frames
+        let custom_grid = StandardGrid::Playground.get_grid();
+        loaded.record_status(&status, StandardGrid::Pacman, Some(custom_grid));
+        assert!(loaded.current_time() >= replay.duration());
+        assert_eq!(loaded.standard_grid(), Some(StandardGrid::Pacman));
+        assert_eq!(loaded.custom_grid(), Some(custom_grid));
+    }
+
+    #[test]
+    fn test_recording_is_capped() {
+        let mut replay = Replay::default();
+        let mut status = ServerStatus::default();
+        for i in 0..MAX_RECORDED_FRAMES + 10 {
+            status.game_state.curr_score = i as _;
+            replay.record_status(&status, StandardGrid::Pacman, None);
+        }
+        assert_eq!(replay.frame_count(), MAX_RECORDED_FRAMES);
+        assert!(replay.is_at_end());
+        // the oldest frames were dropped
+        replay.go_to_beginning();
+        assert_eq!(replay.status().unwrap().game_state.curr_score, 10);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::Irqs;
 use core::cell::RefCell;
-use core_pb::driving::network::{NetworkScanInfo, RobotNetworkBehavior};
+use core_pb::driving::network::{Endpoint, NetworkScanInfo, RobotNetworkBehavior, RobotUdpSocket};
+use core_pb::messages::robot_udp::MAX_DATAGRAM_LEN;
 use cyw43::{Control, JoinOptions};
 use cyw43_pio::{PioSpi, DEFAULT_CLOCK_DIVIDER};
 use defmt::{info, unwrap, Format};
@@ -8,7 +9,8 @@ use embassy_boot_rp::{AlignedBuffer, BlockingFirmwareUpdater, FirmwareUpdaterCon
 use embassy_embedded_hal::flash::partition::BlockingPartition;
 use embassy_executor::Spawner;
 use embassy_net::tcp::{AcceptError, TcpSocket};
-use embassy_net::{Config, Stack, StackResources};
+use embassy_net::udp::{BindError, PacketMetadata, RecvError, SendError, UdpSocket};
+use embassy_net::{Config, IpAddress, IpEndpoint, Ipv4Address, Stack, StackResources};
 use embassy_rp::flash::{Blocking, Flash};
 use embassy_rp::gpio::{Level, Output};
 use embassy_rp::peripherals::{DMA_CH0, FLASH, PIN_23, PIN_24, PIN_25, PIN_29, PIO0};
@@ -36,12 +38,60 @@ pub struct Network {
 pub enum NetworkError {
     Connection(u32),
     Accept(AcceptError),
+    UdpBind(BindError),
+    UdpSend(SendError),
+    UdpRecv(RecvError),
     FirmwareUpdater,
 }
 
+/// Room for a couple of datagrams in each direction
+pub struct UdpBuffers {
+    rx_meta: [PacketMetadata; 2],
+    tx_meta: [PacketMetadata; 2],
+    rx: [u8; MAX_DATAGRAM_LEN * 2],
+    tx: [u8; MAX_DATAGRAM_LEN * 2],
+}
+
+impl Default for UdpBuffers {
+    fn default() -> Self {
+        Self {
+            rx_meta: [PacketMetadata::EMPTY; 2],
+            tx_meta: [PacketMetadata::EMPTY; 2],
+            rx: [0; MAX_DATAGRAM_LEN * 2],
+            tx: [0; MAX_DATAGRAM_LEN * 2],
+        }
+    }
+}
+
+pub struct PicoUdpSocket<'a>(UdpSocket<'a>);
+
+impl RobotUdpSocket for PicoUdpSocket<'_> {
+    type Error = NetworkError;
+
+    async fn send_to(&mut self, buf: &[u8], to: Endpoint) -> Result<(), Self::Error> {
+        let endpoint = IpEndpoint::new(IpAddress::Ipv4(Ipv4Address::from(to.0)), to.1);
+        self.0
+            .send_to(buf, endpoint)
+            .await
+            .map_err(NetworkError::UdpSend)
+    }
+
+    async fn recv_from(&mut self, buf: &mut [u8]) -> Result<(usize, Endpoint), Self::Error> {
+        let (len, meta) = self.0.recv_from(buf).await.map_err(NetworkError::UdpRecv)?;
+        let ip = match meta.endpoint.addr {
+            IpAddress::Ipv4(ip) => ip.octets(),
+            #[allow(unreachable_patterns)]
+            _ => [0; 4],
+        };
+        Ok((len, (ip, meta.endpoint.port)))
+    }
+}
+
 impl RobotNetworkBehavior for Network {
     type Error = NetworkError;
     type Socket<'a> = TcpSocket<'a>;
+    type UdpSocket<'a> = PicoUdpSocket<'a>;
+    type UdpBuffers = UdpBuffers;
 
     async fn mac_address(&mut self) -> [u8; 6] {
         self.control.address().await
@@ -133,6 +183,26 @@ impl RobotNetworkBehavior for Network {
         socket.close()
     }
 
+    async fn udp_bind<'a>(
+        &mut self,
+        port: u16,
+        buffers: &'a mut Self::UdpBuffers,
+    ) -> Result<Self::UdpSocket<'a>, Self::Error>
+    where
+        Self: 'a,
+    {
+        let mut socket = UdpSocket::new(
+            self.stack,
+            &mut buffers.rx_meta,
+            &mut buffers.rx,
+            &mut buffers.tx_meta,
+            &mut buffers.tx,
+        );
+        socket.bind(port).map_err(NetworkError::UdpBind)?;
+        info!("Listening for datagrams on port {}", port);
+        Ok(PicoUdpSocket(socket))
+    }
+
     async fn prepare_firmware_update(&mut self) {
         // let _ = self.updater.prepare_update();
     }
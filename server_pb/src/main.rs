@@ -1,16 +1,19 @@
 use crate::high_level::ReinforcementLearningManager;
 use crate::logging::RobotLoggers;
 use crate::ota::OverTheAirProgramming;
+use crate::recorder::MatchRecorder;
 use crate::sockets::Destination::{GuiClients, Simulation};
 use crate::sockets::Incoming::FromRobot;
 use crate::sockets::Outgoing::{ToGameServer, ToGui, ToSimulation};
 use crate::sockets::{Destination, Outgoing, Sockets};
+use crate::waypoints::plan_waypoints;
 use crate::Destination::Robot;
-use crate::Outgoing::ToRobot;
-use core_pb::bin_encode;
-use core_pb::constants::{GUI_LISTENER_PORT, MAX_ROBOT_PATH_LENGTH};
+use crate::Outgoing::{ToRobot, ToRobotUdp};
+use core_pb::constants::GUI_LISTENER_PORT;
 use core_pb::grid::computed_grid::ComputedGrid;
 use core_pb::grid::GRID_SIZE;
+use core_pb::messages::match_recording::MatchEvent;
+use core_pb::messages::path_stream::{PathStream, PathWindow};
 use core_pb::messages::server_status::ServerStatus;
 use core_pb::messages::settings::{
     ConnectionSettings, CvLocationSource, PacbotSettings, ShouldDoTargetPath, StrategyChoice,
@@ -22,16 +25,16 @@ use core_pb::messages::{
 use core_pb::names::{RobotName, NUM_ROBOT_NAMES};
 use core_pb::pacbot_rs::game_state::GameState;
 use core_pb::pacbot_rs::location::Direction;
-use core_pb::threaded_websocket::TextOrT;
+use core_pb::util::clock_sync::ClockSync;
 use core_pb::util::stopwatch::Stopwatch;
 use core_pb::util::utilization::UtilizationMonitor;
 use core_pb::util::WebTimeInstant;
 use env_logger::Builder;
-use log::{info, LevelFilter};
+use log::{error, info, LevelFilter};
 use nalgebra::Point2;
 use rand::prelude::IteratorRandom;
 use rand::thread_rng;
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
 use std::path::Path;
 use std::process::{Child, Command};
 use std::time::Duration;
@@ -42,8 +45,13 @@ mod high_level;
 mod logging;
 pub mod network;
 mod ota;
+mod recorder;
 mod sockets;
 // todo pub mod strategy;
+mod waypoints;
+
+/// Pings that aren't answered within this long are counted as lost
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
 
 #[allow(dead_code)]
 pub struct App {
@@ -56,8 +64,24 @@ pub struct App {
     sim_game_engine_process: Option<Child>,
 
     sockets: Sockets,
+    /// Robot timestamps are converted to time since this instant
+    started: Instant,
+    /// When a ping was last sent to each robot
     robot_ping_timers: [Option<Instant>; NUM_ROBOT_NAMES],
+    /// The sequence number and send time of each robot's unanswered pings, oldest first
+    robot_pings: [VecDeque<(u32, Instant)>; NUM_ROBOT_NAMES],
+    next_ping_seq: u32,
+    robot_clocks: [ClockSync; NUM_ROBOT_NAMES],
+    /// When configuration was last sent to each robot, so it can be resent if not acknowledged
+    robot_config_timers: [Option<Instant>; NUM_ROBOT_NAMES],
     robot_loggers: Option<RobotLoggers>,
+    /// The full target path, streamed to the pacman robot as it makes progress
+    path_stream: PathStream,
+    /// The exact location a gui asked the pacman robot to go to; only used while the target path
+    /// ends in its cell
+    target_location: Option<Point2<f32>>,
+    /// Records the current match, from unpause to reset
+    match_recorder: Option<MatchRecorder>,
 
     rl_manager: ReinforcementLearningManager,
     over_the_air_programming: OverTheAirProgramming,
@@ -88,8 +112,16 @@ impl Default for App {
             over_the_air_programming: OverTheAirProgramming::new(sockets.outgoing.clone()),
 
             sockets,
+            started: Instant::now(),
             robot_ping_timers: [None; NUM_ROBOT_NAMES],
+            robot_pings: RobotName::get_all().map(|_| VecDeque::new()),
+            next_ping_seq: 0,
+            robot_clocks: RobotName::get_all().map(|_| ClockSync::default()),
+            robot_config_timers: [None; NUM_ROBOT_NAMES],
             robot_loggers: RobotLoggers::generate().ok(),
+            path_stream: PathStream::new(rand::random()),
+            target_location: None,
+            match_recorder: None,
 
             grid: Default::default(),
         }
@@ -121,6 +153,14 @@ async fn main() {
     app.run_forever().await;
 }
 
+/// Tells the simulation to use the grid in the settings
+fn simulation_grid_message(settings: &PacbotSettings) -> ServerToSimulationMessage {
+    match settings.custom_grid {
+        Some(grid) => ServerToSimulationMessage::SetCustomGrid(grid),
+        None => ServerToSimulationMessage::SetStandardGrid(settings.standard_grid),
+    }
+}
+
 impl App {
     async fn run_forever(&mut self) {
         let mut periodic_interval = interval(Duration::from_millis(20));
@@ -145,12 +185,16 @@ impl App {
                     // which shouldn't include the amount of time spent waiting for messages
                     self.utilization_monitor.start();
 
-                    if self.settings.safe_mode {
-                        if let FromRobot(msg) = &msg.1 {
-                            let encoded = bin_encode(false, TextOrT::T(msg.clone())).unwrap();
-                            if encoded.get(9).map(|x| *x > 7).unwrap_or(false) {
-                                continue;
-                            }
+                    if let FromRobot(msg) = &msg.1 {
+                        if self.settings.safe_mode && !msg.category().allowed_in_safe_mode() {
+                            continue;
+                        }
+                    }
+                    if let (Robot(name), FromRobot(msg)) = &msg {
+                        if self.status.robots[*name as usize].connection.is_incompatible()
+                            && !msg.works_across_versions()
+                        {
+                            continue;
                         }
                     }
                     self.over_the_air_programming
@@ -201,21 +245,38 @@ impl App {
         previous_settings: &mut PacbotSettings,
         move_pacman_interval: &mut Interval,
     ) {
-        // trigger pings to any robots who don't have an active ping
+        // trigger pings to robots every so often, and give up on old ones
         for name in RobotName::get_all() {
+            let pings = &mut self.robot_pings[name as usize];
+            while pings
+                .front()
+                .is_some_and(|(_, sent)| sent.elapsed() > PING_TIMEOUT)
+            {
+                pings.pop_front();
+                self.status.robots[name as usize].ping_stats.lost += 1;
+            }
             if self.status.robots[name as usize].connection == NetworkStatus::Connected
                 && self.robot_ping_timers[name as usize]
                     .map(|x| x.elapsed().as_millis() > 500)
                     .unwrap_or(true)
             {
-                self.robot_ping_timers[name as usize] = Some(Instant::now());
-                self.send(Robot(name), ToRobot(ServerToRobotMessage::Ping))
+                self.status.robots[name as usize].ping_stats.sent += 1;
+                let seq = self.next_ping_seq;
+                self.next_ping_seq = self.next_ping_seq.wrapping_add(1);
+                let now = Instant::now();
+                self.robot_pings[name as usize].push_back((seq, now));
+                self.robot_ping_timers[name as usize] = Some(now);
+                self.send(Robot(name), ToRobot(ServerToRobotMessage::Ping(seq)))
                     .await;
             }
         }
         self.over_the_air_programming.tick(&mut self.status).await;
         if self.settings != *previous_settings {
             *previous_settings = self.settings.clone();
+            self.record_match_event(|app| MatchEvent::Settings(Box::new(app.settings.clone())));
+            if !self.settings.game_server.record_matches {
+                self.stop_match_recording();
+            }
             *move_pacman_interval =
                 interval(Duration::from_secs_f32(1.0 / self.settings.target_speed));
             self.send(
@@ -224,26 +285,58 @@ impl App {
             )
             .await; // check if new AI calculation is needed
         }
-        // send motor commands to robots
+        // send configuration and motor commands to robots
+        // the exact target location is forgotten once the target path no longer ends there
+        if self.target_location.map(|p| p.map(|x| x.round() as i8))
+            != self.status.target_path.last().copied()
+        {
+            self.target_location = None;
+        }
+        let waypoints = plan_waypoints(
+            self.status.cv_location,
+            &self.status.target_path,
+            self.target_location,
+            self.settings.driving.corner_cut,
+        );
+        self.path_stream.update(&waypoints);
         for name in RobotName::get_all() {
             let mut data = self.settings.robots[name as usize].config.clone();
+            let mut target_path = PathWindow::default();
             if name == self.settings.pacman {
                 data.grid = self.settings.standard_grid;
+                data.custom_grid = self.settings.custom_grid;
                 data.cv_location = self.status.cv_location;
-                data.target_path = self
-                    .status
-                    .target_path
-                    .clone()
-                    .into_iter()
-                    .take(MAX_ROBOT_PATH_LENGTH)
-                    .collect();
+                target_path = self.path_stream.window();
                 data.follow_target_path = self.settings.do_target_path == ShouldDoTargetPath::Yes
                     || self.settings.do_target_path == ShouldDoTargetPath::DoWhilePlayed
                         && !self.status.game_state.paused;
             }
+            // configuration is resent until the robot confirms it has been applied
+            let config = data.robot_config();
+            let hash = config.config_hash();
+            let status = &mut self.status.robots[name as usize];
+            if status.config_hash != Some(hash) {
+                status.config_hash = Some(hash);
+                self.robot_config_timers[name as usize] = None;
+            }
+            if status.connection == NetworkStatus::Connected
+                && status.applied_config_hash != Some(hash)
+                && self.robot_config_timers[name as usize]
+                    .map(|x| x.elapsed().as_millis() > 500)
+                    .unwrap_or(true)
+            {
+                self.robot_config_timers[name as usize] = Some(Instant::now());
+                self.send(
+                    Robot(name),
+                    ToRobot(ServerToRobotMessage::RobotConfig(config)),
+                )
+                .await;
+            }
             self.send(
                 Robot(name),
-                ToRobot(ServerToRobotMessage::FrequentRobotItems(data)),
+                ToRobot(ServerToRobotMessage::RobotControl(
+                    data.robot_control(target_path),
+                )),
             )
             .await;
 
@@ -260,14 +353,36 @@ impl App {
     }
 
     async fn send(&mut self, destination: Destination, outgoing: Outgoing) {
-        if self.settings.safe_mode {
-            if let ToRobot(msg) = &outgoing {
-                let encoded = bin_encode(false, TextOrT::T(msg.clone())).unwrap();
-                if encoded.get(9).map(|x| *x > 7).unwrap_or(false) {
-                    return;
-                }
+        if let ToRobot(msg) = &outgoing {
+            if self.settings.safe_mode && !msg.category().allowed_in_safe_mode() {
+                return;
             }
         }
+        // peers built with different message definitions can only be reprogrammed
+        match (destination, &outgoing) {
+            (Robot(name), ToRobot(msg))
+                if self.status.robots[name as usize]
+                    .connection
+                    .is_incompatible()
+                    && !msg.works_across_versions() =>
+            {
+                return;
+            }
+            (Simulation, ToSimulation(_))
+                if self.status.simulation_connection.is_incompatible() =>
+            {
+                return;
+            }
+            _ => {}
+        }
+        let outgoing = match (destination, outgoing) {
+            (Robot(name), ToRobot(msg))
+                if self.settings.robots[name as usize].udp && msg.is_latency_critical() =>
+            {
+                ToRobotUdp(msg)
+            }
+            (_, outgoing) => outgoing,
+        };
         self.sockets
             .outgoing
             .send((destination, outgoing))
@@ -276,6 +391,15 @@ impl App {
     }
 
     fn trigger_strategy_update(&mut self) {
+        let old_path = self.status.target_path.clone();
+        self.update_strategy_path();
+        // a rejected path from a gui no longer matters once the strategy has planned a new one
+        if self.status.target_path != old_path {
+            self.status.target_path_error = None;
+        }
+    }
+
+    fn update_strategy_path(&mut self) {
         const LOOKAHEAD_DIST: usize = 4;
         if let Some(cv_loc) = self.status.cv_location {
             match self.settings.driving.strategy {
@@ -498,7 +622,13 @@ impl App {
         }
     }
 
-    async fn update_settings(&mut self, old: &PacbotSettings, new: PacbotSettings) {
+    async fn update_settings(&mut self, old: &PacbotSettings, mut new: PacbotSettings) {
+        if let Some(grid) = new.custom_grid {
+            if let Err(e) = ComputedGrid::try_from_custom(grid) {
+                error!("Ignoring invalid custom grid: {e}");
+                new.custom_grid = None;
+            }
+        }
         self.update_connection(
             &old.simulation.connection,
             &new.simulation.connection,
@@ -520,14 +650,52 @@ impl App {
                 Robot(name),
             )
             .await;
+            // the robot stops sending it, so the last one would otherwise be shown forever
+            if !new.robots[id].config.enable_localization_debug {
+                self.status.robots[id].localization_debug = None;
+            }
         }
 
-        if new.standard_grid != old.standard_grid {
-            self.grid = new.standard_grid.compute_grid();
+        if new.reconnect_backoff != old.reconnect_backoff {
+            self.send(
+                Destination::NotApplicable,
+                Outgoing::Backoff(new.reconnect_backoff),
+            )
+            .await;
+        }
+
+        let grid_changed =
+            new.standard_grid != old.standard_grid || new.custom_grid != old.custom_grid;
+        if grid_changed {
+            self.grid = new.compute_grid();
+            self.send(Simulation, ToSimulation(simulation_grid_message(&new)))
+                .await;
+        }
+
+        if new.simulation.network != old.simulation.network {
+            self.send(
+                Simulation,
+                ToSimulation(ServerToSimulationMessage::SetNetworkImpairments(
+                    new.simulation.network,
+                )),
+            )
+            .await;
+        }
+
+        if new.simulation.ghosts != old.simulation.ghosts {
+            self.send(
+                Simulation,
+                ToSimulation(ServerToSimulationMessage::SetGhosts(
+                    new.simulation.ghosts.clone(),
+                )),
+            )
+            .await;
+        }
+        if new.simulation.record_trajectories != old.simulation.record_trajectories {
             self.send(
                 Simulation,
-                ToSimulation(ServerToSimulationMessage::SetStandardGrid(
-                    new.standard_grid,
+                ToSimulation(ServerToSimulationMessage::RecordTrajectories(
+                    new.simulation.record_trajectories,
                 )),
             )
             .await;
@@ -573,7 +741,7 @@ impl App {
             .await;
         }
 
-        if old.driving.strategy != new.driving.strategy || old.standard_grid != new.standard_grid {
+        if old.driving.strategy != new.driving.strategy || grid_changed {
             self.status.target_path.clear();
             self.settings.driving.strategy = new.driving.strategy.clone();
             self.trigger_strategy_update();
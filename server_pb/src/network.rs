@@ -3,8 +3,13 @@ use crate::sockets::Destination::*;
 use crate::sockets::Incoming::*;
 use crate::sockets::Outgoing::*;
 use crate::sockets::{Destination, Incoming, Outgoing};
-use crate::App;
+use crate::{simulation_grid_message, App};
 use core_pb::constants::GAME_SERVER_MAGIC_NUMBER;
+use core_pb::grid::computed_grid::ComputedGrid;
+use core_pb::messages::discovery::RobotBeacon;
+use core_pb::messages::match_recording::MatchEvent;
+use core_pb::messages::settings::{PacbotSettings, ShouldDoTargetPath};
+use core_pb::messages::version::ProtocolVersion;
 use core_pb::messages::{
     GuiToServerMessage, NetworkStatus, RobotToServerMessage, ServerToGuiMessage,
     ServerToSimulationMessage, SimulationToServerMessage,
@@ -12,9 +17,13 @@ use core_pb::messages::{
 use core_pb::names::RobotName;
 use core_pb::pacbot_rs::game_state::GameState;
 use log::{error, info};
+use nalgebra::Point2;
 
 impl App {
     pub async fn handle_message(&mut self, from: Destination, message: Incoming) {
+        if let FromGui(msg) = &message {
+            self.record_match_event(|_| MatchEvent::FromGui(msg.clone()));
+        }
         match (from, message) {
             (Robot(name), Bytes(data)) => {
                 if let Some(loggers) = &mut self.robot_loggers {
@@ -26,7 +35,9 @@ impl App {
                 data.len()
             ),
             (_, SleepFinished) => {
+                self.status.server_time = self.started.elapsed();
                 // send updated status to clients every so often
+                self.record_match_status();
                 self.send(
                     GuiClients,
                     ToGui(ServerToGuiMessage::Status(self.status.clone())),
@@ -35,25 +46,28 @@ impl App {
             }
             (dest, Status(status)) => match dest {
                 Simulation => {
-                    self.status.simulation_connection = status;
-                    if status == NetworkStatus::Connected {
-                        self.send(
-                            Simulation,
-                            ToSimulation(ServerToSimulationMessage::SetPacman(
-                                self.settings.pacman,
-                            )),
-                        )
-                        .await;
-                        self.send(
-                            Simulation,
-                            ToSimulation(ServerToSimulationMessage::SetStandardGrid(
-                                self.settings.standard_grid,
-                            )),
-                        )
-                        .await;
+                    // the connection stays incompatible until the simulation is restarted
+                    if !(status == NetworkStatus::Connected
+                        && self.status.simulation_connection.is_incompatible())
+                    {
+                        self.status.simulation_connection = status;
+                    }
+                }
+                Robot(name) => {
+                    let robot = &mut self.status.robots[name as usize];
+                    if status != NetworkStatus::Connected {
+                        // the robot may have restarted, so its configuration must be sent again,
+                        // and its clock may have been reset
+                        robot.applied_config_hash = None;
+                        robot.clock = None;
+                        self.robot_clocks[name as usize].clear();
+                        self.robot_pings[name as usize].clear();
+                    }
+                    // the connection stays incompatible until the robot disconnects
+                    if !(status == NetworkStatus::Connected && robot.connection.is_incompatible()) {
+                        robot.connection = status;
                     }
                 }
-                Robot(name) => self.status.robots[name as usize].connection = status,
                 GameServer => {
                     if status != NetworkStatus::Connected {
                         // assume the game server is not advanced until proven otherwise
@@ -64,13 +78,15 @@ impl App {
                 _ => {}
             },
             (_, FromGameServer(bytes)) => {
+                self.record_match_event(|_| MatchEvent::GameServerFrame(bytes.clone()));
                 if bytes == GAME_SERVER_MAGIC_NUMBER.to_vec() {
                     self.status.advanced_game_server = true;
                 } else {
                     match GameState::from_bytes(&bytes, self.status.game_state.seed) {
                         Ok(g) => {
                             if g != self.status.game_state {
-                                self.status.game_state = g.clone();
+                                let previous = std::mem::replace(&mut self.status.game_state, g);
+                                self.update_match_recording(&previous);
                                 self.trigger_cv_location_update();
                                 self.trigger_strategy_update();
                             }
@@ -80,6 +96,19 @@ impl App {
                 }
             }
             (_, FromSimulation(msg)) => match msg {
+                SimulationToServerMessage::Version(version) => {
+                    if version.is_compatible() {
+                        info!("Simulation connected with {version}");
+                        self.sync_simulation().await;
+                    } else {
+                        error!(
+                            "Simulation has {version}, but the server has {}; \
+                            rebuild the simulation",
+                            ProtocolVersion::CURRENT
+                        );
+                        self.status.simulation_connection = NetworkStatus::Incompatible(version);
+                    }
+                }
                 SimulationToServerMessage::RobotPositions(robot_positions) => {
                     for name in RobotName::get_all() {
                         self.status.robots[name as usize].sim_position =
@@ -97,8 +126,23 @@ impl App {
                 }
                 // the robot will receive motor and pid configuration via periodic actions
             }
+            (Robot(name), FromRobot(RobotToServerMessage::Version(version))) => {
+                if version.is_compatible() {
+                    info!("{name} has {version}");
+                } else {
+                    error!(
+                        "{name} has {version}, but the server has {}; \
+                        only firmware updates are possible",
+                        ProtocolVersion::CURRENT
+                    );
+                    self.status.robots[name as usize].connection =
+                        NetworkStatus::Incompatible(version);
+                }
+            }
             (Robot(name), FromRobot(RobotToServerMessage::MotorControlStatus(status))) => {
-                self.status.robots[name as usize].last_motor_status = status;
+                let robot = &mut self.status.robots[name as usize];
+                robot.motor_status_time = robot.clock.map(|clock| clock.robot_to_server(status.0));
+                robot.last_motor_status = status;
             }
             (Robot(name), FromRobot(RobotToServerMessage::Utilization(utilization))) => {
                 self.status.robots[name as usize].utilization = utilization;
@@ -109,12 +153,31 @@ impl App {
                 self.status.robots[name as usize].distance_sensors =
                     sensors.distances.map(|x| x.map_err(|s| s.to_string()));
                 self.status.robots[name as usize].estimated_location = sensors.location;
+                self.status.robots[name as usize].shadow_locations = sensors.shadow_locations;
                 self.status.robots[name as usize].battery = sensors.battery.map_err(|_| ());
+                self.status.robots[name as usize].sensors_time = self.status.robots[name as usize]
+                    .clock
+                    .map(|clock| clock.robot_to_server(sensors.timestamp));
                 self.trigger_cv_location_update();
             }
-            (Robot(name), FromRobot(RobotToServerMessage::Pong)) => {
-                if let Some(t) = self.robot_ping_timers[name as usize] {
-                    self.status.robots[name as usize].ping = Some(t.elapsed())
+            (Robot(name), FromRobot(RobotToServerMessage::Pong { seq, robot_time })) => {
+                // answers that timed out were already counted as lost
+                let pings = &mut self.robot_pings[name as usize];
+                if let Some(index) = pings.iter().position(|(s, _)| *s == seq) {
+                    let (_, t) = pings[index];
+                    // older pings that are still unanswered were overtaken by this one
+                    pings.drain(..=index);
+                    let robot = &mut self.status.robots[name as usize];
+                    robot.ping_stats.lost += index as u32;
+                    robot.ping = Some(t.elapsed());
+                    robot.ping_stats.record(t.elapsed());
+                    let clock = &mut self.robot_clocks[name as usize];
+                    clock.add_sample(
+                        t.duration_since(self.started),
+                        robot_time,
+                        self.started.elapsed(),
+                    );
+                    robot.clock = clock.estimate();
                 }
             }
             (Robot(name), FromRobot(RobotToServerMessage::Rebooting)) => {
@@ -138,10 +201,72 @@ impl App {
             (Robot(name), FromRobot(RobotToServerMessage::ExtraImuData(data))) => {
                 self.status.robots[name as usize].extra_imu_data = Some(data);
             }
+            (Robot(name), FromRobot(RobotToServerMessage::LocalizationDebug(data))) => {
+                // one sent before the setting was turned off may still arrive
+                if self.settings.robots[name as usize]
+                    .config
+                    .enable_localization_debug
+                {
+                    self.status.robots[name as usize].localization_debug = Some(data);
+                }
+            }
+            (Robot(name), FromRobot(RobotToServerMessage::ConfigApplied(hash))) => {
+                self.status.robots[name as usize].applied_config_hash = Some(hash);
+            }
+            (Robot(name), FromRobot(RobotToServerMessage::PathProgress { path_id, index })) => {
+                if name == self.settings.pacman {
+                    self.path_stream.acknowledge(path_id, index);
+                }
+            }
+            (Robot(name), FromRobot(RobotToServerMessage::FrameStats(stats))) => {
+                self.status.robots[name as usize].robot_frame_stats = stats;
+            }
+            (Robot(name), RobotFrameStats(stats)) => {
+                self.status.robots[name as usize].server_frame_stats = stats;
+            }
+            (dest, Incoming::LinkMetrics(metrics)) => match dest {
+                GameServer => self.status.game_server_link = metrics,
+                Simulation => self.status.simulation_link = metrics,
+                GuiClients => self.status.gui_link = metrics,
+                Robot(name) => self.status.robots[name as usize].link = metrics,
+                NotApplicable => error!("Unexpected link metrics from {dest:?}"),
+            },
+            (Robot(name), Beacon(beacon)) => {
+                if !beacon_is_accepted(&self.settings, &beacon) {
+                    error!(
+                        "Ignoring beacon for {name} from mac {:?}, which isn't its pinned mac",
+                        beacon.mac
+                    );
+                    return;
+                }
+                let previous = self.status.robots[name as usize].beacon.replace(beacon);
+                if !beacon.version.is_compatible()
+                    && previous.is_none_or(|p| p.version != beacon.version)
+                {
+                    error!(
+                        "{name} has {}, but the server has {}",
+                        beacon.version,
+                        ProtocolVersion::CURRENT
+                    );
+                }
+                if let Some(new_settings) = settings_after_beacon(&self.settings, &beacon) {
+                    info!("{name} announced itself at {:?}:{}", beacon.ip, beacon.port);
+                    let old_settings = self.settings.clone();
+                    self.update_settings(&old_settings, new_settings).await;
+                }
+            }
             (Robot(name), FromRobot(msg)) => info!("Message received from {name}: {msg:?}"),
             (Robot(_), _) => {}
             (_, FromRobot(_)) => {}
             (_, FromGui(msg)) => match msg {
+                GuiToServerMessage::Version(version) => {
+                    if !version.is_compatible() {
+                        error!(
+                            "A gui client has {version}, but the server has {}",
+                            ProtocolVersion::CURRENT
+                        );
+                    }
+                }
                 GuiToServerMessage::Settings(settings) => {
                     let old_settings = self.settings.clone();
                     self.update_settings(&old_settings, settings).await;
@@ -158,12 +283,42 @@ impl App {
                     self.settings.robots[robot as usize].config.target_velocity = vel;
                 }
                 GuiToServerMessage::TargetLocation(loc) => {
-                    if !self.grid.wall_at(&loc) {
-                        if let Some(cv_loc) = self.status.cv_location {
-                            if let Some(path) = self.grid.bfs_path(cv_loc, loc) {
-                                self.status.target_path = path.into_iter().skip(1).collect();
+                    if let (Some(cell), Some(cv_loc)) = (
+                        self.grid.node_nearest(loc.x, loc.y),
+                        self.status.cv_location,
+                    ) {
+                        if let Some(path) = self.grid.bfs_path(cv_loc, cell) {
+                            self.status.target_path = path.into_iter().skip(1).collect();
+                            self.status.target_path_error = None;
+                            self.target_location = Some(loc);
+                        }
+                    }
+                }
+                GuiToServerMessage::TargetPath(name, waypoints) => {
+                    let path = if name == self.settings.pacman {
+                        target_path_through(&self.grid, self.status.cv_location, &waypoints)
+                    } else {
+                        Err(format!(
+                            "{name} is not the pacman robot, so it can't follow a target path"
+                        ))
+                    };
+                    match path {
+                        Ok(path) => {
+                            self.status.target_path = path;
+                            self.target_location = None;
+                            self.status.target_path_error = None;
+                            if self.settings.do_target_path == ShouldDoTargetPath::No {
+                                info!("Target paths turned on, to follow the path sent by a gui");
+                                let old_settings = self.settings.clone();
+                                let mut new_settings = old_settings.clone();
+                                new_settings.do_target_path = ShouldDoTargetPath::Yes;
+                                self.update_settings(&old_settings, new_settings).await;
                             }
                         }
+                        Err(e) => {
+                            info!("Rejected target path for {name}: {e}");
+                            self.status.target_path_error = Some(e);
+                        }
                     }
                 }
                 GuiToServerMessage::SimulationCommand(msg) => {
@@ -194,6 +349,11 @@ impl App {
                     "Gui client #{id} connected; {} gui client(s) are connected",
                     self.status.gui_clients
                 );
+                self.send(
+                    GuiClients,
+                    ToGui(ServerToGuiMessage::Version(ProtocolVersion::CURRENT)),
+                )
+                .await;
                 self.send(
                     GuiClients,
                     ToGui(ServerToGuiMessage::Settings(self.settings.clone())),
@@ -208,6 +368,188 @@ impl App {
                 );
             }
             (dest, Incoming::Text(text)) => error!("Unexpected text from {dest:?}: {text}"),
+            (dest, RobotFrameStats(_)) => error!("Unexpected frame stats from {dest:?}"),
         }
     }
+
+    /// Bring a newly connected simulation in line with the current settings
+    async fn sync_simulation(&mut self) {
+        self.send(
+            Simulation,
+            ToSimulation(ServerToSimulationMessage::SetPacman(self.settings.pacman)),
+        )
+        .await;
+        self.send(
+            Simulation,
+            ToSimulation(simulation_grid_message(&self.settings)),
+        )
+        .await;
+        self.send(
+            Simulation,
+            ToSimulation(ServerToSimulationMessage::SetNetworkImpairments(
+                self.settings.simulation.network,
+            )),
+        )
+        .await;
+        self.send(
+            Simulation,
+            ToSimulation(ServerToSimulationMessage::SetGhosts(
+                self.settings.simulation.ghosts.clone(),
+            )),
+        )
+        .await;
+        self.send(
+            Simulation,
+            ToSimulation(ServerToSimulationMessage::RecordTrajectories(
+                self.settings.simulation.record_trajectories,
+            )),
+        )
+        .await;
+    }
+}
+
+/// The path from `start`, if there is one, through each of the waypoints, not including `start`
+fn target_path_through(
+    grid: &ComputedGrid,
+    start: Option<Point2<i8>>,
+    waypoints: &[Point2<i8>],
+) -> Result<Vec<Point2<i8>>, String> {
+    if waypoints.is_empty() {
+        return Err("The path has no waypoints".to_string());
+    }
+    if let Some(p) = waypoints.iter().find(|p| grid.wall_at(p)) {
+        return Err(format!("Waypoint ({}, {}) is inside a wall", p.x, p.y));
+    }
+    let through: Vec<_> = start.into_iter().chain(waypoints.iter().copied()).collect();
+    let path = grid
+        .bfs_path_through(&through)
+        .ok_or("Some waypoints can't be reached from the robot's location")?;
+    Ok(path.into_iter().skip(start.iter().count()).collect())
+}
+
+/// Whether a beacon should be trusted to name the robot that sent it
+///
+/// Beacons are accepted by name, so a robot with new hardware can still be found; if the robot's
+/// mac address is pinned in its settings, the beacon must also come from that mac address.
+/// Robots with a different firmware version are accepted too, since they can still be updated
+/// over the air; the mismatch is reported instead.
+fn beacon_is_accepted(settings: &PacbotSettings, beacon: &RobotBeacon) -> bool {
+    !settings.robots[beacon.name as usize].pin_mac
+        || RobotName::from_mac_address(&beacon.mac) == Some(beacon.name)
+}
+
+/// The settings with the robot's connection pointed at the address in its beacon, if that
+/// changes anything; beacons that aren't accepted change nothing
+fn settings_after_beacon(
+    settings: &PacbotSettings,
+    beacon: &RobotBeacon,
+) -> Option<PacbotSettings> {
+    if !beacon_is_accepted(settings, beacon) {
+        return None;
+    }
+    let connection = &settings.robots[beacon.name as usize].connection;
+    // a robot without an address yet can't be connected to
+    if beacon.ip == [0; 4] || (connection.ipv4, connection.port) == (beacon.ip, beacon.port) {
+        return None;
+    }
+    let mut new_settings = settings.clone();
+    new_settings.robots[beacon.name as usize].connection.ipv4 = beacon.ip;
+    new_settings.robots[beacon.name as usize].connection.port = beacon.port;
+    Some(new_settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core_pb::grid::standard_grid::StandardGrid;
+
+    fn beacon(name: RobotName, ip: [u8; 4]) -> RobotBeacon {
+        RobotBeacon {
+            name,
+            mac: name.mac_address(),
+            version: ProtocolVersion::CURRENT,
+            ip,
+            port: name.port(),
+        }
+    }
+
+    #[test]
+    fn beacon_updates_connection() {
+        let settings = PacbotSettings::default();
+        let beacon = beacon(RobotName::Stella, [192, 168, 1, 12]);
+        let new_settings = settings_after_beacon(&settings, &beacon).unwrap();
+        let connection = &new_settings.robots[RobotName::Stella as usize].connection;
+        assert_eq!((connection.ipv4, connection.port), (beacon.ip, beacon.port));
+        // nothing else changes
+        let mut expected = new_settings.clone();
+        expected.robots[RobotName::Stella as usize] =
+            settings.robots[RobotName::Stella as usize].clone();
+        assert_eq!(expected, settings);
+
+        // the same beacon again changes nothing
+        assert_eq!(settings_after_beacon(&new_settings, &beacon), None);
+    }
+
+    #[test]
+    fn beacon_without_address_is_ignored() {
+        let settings = PacbotSettings::default();
+        let beacon = beacon(RobotName::Stella, [0; 4]);
+        assert_eq!(settings_after_beacon(&settings, &beacon), None);
+    }
+
+    #[test]
+    fn beacon_from_unknown_mac_is_accepted_by_name() {
+        let settings = PacbotSettings::default();
+        let mut beacon = beacon(RobotName::Stella, [192, 168, 1, 12]);
+        beacon.mac = [0xde, 0xad, 0xbe, 0xef, 0x00, 0x01];
+        assert!(beacon_is_accepted(&settings, &beacon));
+        assert!(settings_after_beacon(&settings, &beacon).is_some());
+
+        // a different firmware version can still be updated over the air
+        beacon.version.protocol = !ProtocolVersion::CURRENT.protocol;
+        assert!(settings_after_beacon(&settings, &beacon).is_some());
+    }
+
+    #[test]
+    fn beacon_from_unpinned_mac_is_ignored() {
+        let mut settings = PacbotSettings::default();
+        settings.robots[RobotName::Stella as usize].pin_mac = true;
+        let mut beacon = beacon(RobotName::Stella, [192, 168, 1, 12]);
+        assert!(beacon_is_accepted(&settings, &beacon));
+
+        beacon.mac = [0xde, 0xad, 0xbe, 0xef, 0x00, 0x01];
+        assert!(!beacon_is_accepted(&settings, &beacon));
+        assert_eq!(settings_after_beacon(&settings, &beacon), None);
+
+        // a known mac address claiming to be a different robot
+        beacon.mac = RobotName::Stevie.mac_address();
+        assert!(!beacon_is_accepted(&settings, &beacon));
+        assert_eq!(settings_after_beacon(&settings, &beacon), None);
+    }
+
+    #[test]
+    fn target_path_starts_after_location() {
+        let grid = StandardGrid::Pacman.compute_grid();
+        let start = Point2::new(1, 1);
+        let waypoints = [Point2::new(1, 4), Point2::new(3, 1)];
+        let path = target_path_through(&grid, Some(start), &waypoints).unwrap();
+        assert_eq!(path.first(), Some(&Point2::new(1, 2)));
+        assert_eq!(path.last(), Some(&waypoints[1]));
+        assert!(path.contains(&waypoints[0]));
+
+        // without a location, the path starts at the first waypoint
+        let path = target_path_through(&grid, None, &waypoints).unwrap();
+        assert_eq!(path.first(), Some(&waypoints[0]));
+    }
+
+    #[test]
+    fn invalid_target_paths_are_rejected() {
+        let grid = StandardGrid::Pacman.compute_grid();
+        let start = Some(Point2::new(1, 1));
+        assert!(target_path_through(&grid, start, &[]).is_err());
+        assert!(target_path_through(&grid, start, &[Point2::new(0, 0)]).is_err());
+        assert!(
+            target_path_through(&grid, start, &[Point2::new(1, 4), Point2::new(0, 0)]).is_err()
+        );
+    }
 }
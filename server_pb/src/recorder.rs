@@ -0,0 +1,146 @@
+use crate::App;
+use core_pb::messages::match_recording::{
+    MatchEvent, MatchRecord, MatchRecording, MatchRecordingHeader,
+};
+use core_pb::messages::settings::PacbotSettings;
+use core_pb::pacbot_rs::game_state::GameState;
+use log::{error, info};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Match recordings are placed in this folder
+pub const MATCHES_DIR: &str = "matches";
+/// Statuses are recorded at most this often, instead of every time one is sent to guis
+pub const STATUS_INTERVAL: Duration = Duration::from_millis(200);
+/// Recordings are written to the file at least this often, so that little is lost if the
+/// server stops unexpectedly
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Writes everything that happens during one match to a file; see
+/// [`core_pb::messages::match_recording`]
+pub struct MatchRecorder {
+    started: Instant,
+    path: PathBuf,
+    file: BufWriter<File>,
+    last_status: Option<Instant>,
+    last_flush: Instant,
+}
+
+impl MatchRecorder {
+    pub fn start(settings: &PacbotSettings) -> std::io::Result<Self> {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        std::fs::create_dir_all(MATCHES_DIR)?;
+        let path = PathBuf::from(MATCHES_DIR).join(format!("{millis}.pbm"));
+        let mut file = BufWriter::new(File::create(&path)?);
+        let header = MatchRecordingHeader {
+            started_at: millis,
+            settings: settings.clone(),
+        };
+        file.write_all(&MatchRecording::encode_start(&header).map_err(std::io::Error::other)?)?;
+        Ok(Self {
+            started: Instant::now(),
+            path,
+            file,
+            last_status: None,
+            last_flush: Instant::now(),
+        })
+    }
+
+    pub fn path(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    /// Whether enough time has passed since the last status to record another
+    pub fn status_due(&self) -> bool {
+        self.last_status
+            .is_none_or(|t| t.elapsed() >= STATUS_INTERVAL)
+    }
+
+    pub fn record(&mut self, event: MatchEvent) {
+        if matches!(event, MatchEvent::Status(_)) {
+            self.last_status = Some(Instant::now());
+        }
+        let record = MatchRecord {
+            time: self.started.elapsed(),
+            event,
+        };
+        let result = MatchRecording::encode_record(&record)
+            .map_err(std::io::Error::other)
+            .and_then(|bytes| self.file.write_all(&bytes));
+        if let Err(e) = result {
+            error!("Failed to record match: {e}");
+        }
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush();
+        }
+    }
+
+    /// Write everything recorded so far to the file
+    pub fn flush(&mut self) {
+        self.last_flush = Instant::now();
+        if let Err(e) = self.file.flush() {
+            error!("Failed to save match recording: {e}");
+        }
+    }
+}
+
+impl Drop for MatchRecorder {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+impl App {
+    /// Record an event, if a match is being recorded
+    pub fn record_match_event(&mut self, event: impl FnOnce(&Self) -> MatchEvent) {
+        if self.match_recorder.is_some() {
+            let event = event(self);
+            if let Some(recorder) = &mut self.match_recorder {
+                recorder.record(event);
+            }
+        }
+    }
+
+    /// Record the server's status, if a match is being recorded and the last status was recorded
+    /// at least [`STATUS_INTERVAL`] ago
+    pub fn record_match_status(&mut self) {
+        if self.match_recorder.as_ref().is_some_and(|r| r.status_due()) {
+            self.record_match_event(|app| MatchEvent::Status(Box::new(app.status.clone())));
+        }
+    }
+
+    /// Start recording when the game is unpaused, and stop when it is reset
+    pub fn update_match_recording(&mut self, previous: &GameState) {
+        let game_state = &self.status.game_state;
+        if game_state.curr_ticks < previous.curr_ticks {
+            self.stop_match_recording();
+        }
+        if self.match_recorder.is_none()
+            && self.settings.game_server.record_matches
+            && previous.paused
+            && !self.status.game_state.paused
+        {
+            match MatchRecorder::start(&self.settings) {
+                Ok(recorder) => {
+                    info!("Recording match to {}", recorder.path());
+                    self.status.match_recording = Some(recorder.path());
+                    self.match_recorder = Some(recorder);
+                }
+                Err(e) => error!("Failed to start recording match: {e}"),
+            }
+        }
+    }
+
+    pub fn stop_match_recording(&mut self) {
+        if let Some(mut recorder) = self.match_recorder.take() {
+            recorder.flush();
+            info!("Stopped recording match to {}", recorder.path());
+        }
+        self.status.match_recording = None;
+    }
+}
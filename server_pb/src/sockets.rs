@@ -2,6 +2,7 @@
 
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::net::{IpAddr, Ipv4Addr};
 use std::time::Duration;
 
 use async_channel::{unbounded, Receiver, Sender};
@@ -13,22 +14,31 @@ use log::error;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use simple_websockets::{Event, Message, Responder};
+use tokio::net::UdpSocket;
 use tokio::select;
-use tokio::time::sleep;
+use tokio::sync::watch;
+use tokio::time::{interval, sleep, Instant};
 
-use core_pb::constants::GUI_LISTENER_PORT;
-use core_pb::messages::robot_tcp::{write_tcp, BytesOrT, StatefulTcpReader, TcpError};
+use core_pb::constants::{DISCOVERY_PORT, GUI_LISTENER_PORT};
+use core_pb::messages::discovery::{read_beacon, RobotBeacon};
+use core_pb::messages::robot_tcp::{write_tcp, BytesOrT, FrameStats, StatefulTcpReader, TcpError};
+use core_pb::messages::robot_udp::{write_udp, UdpReader, MAX_DATAGRAM_LEN};
 use core_pb::messages::{
     GameServerCommand, GuiToServerMessage, NetworkStatus, RobotToServerMessage, ServerToGuiMessage,
     ServerToRobotMessage, ServerToSimulationMessage, SimulationToServerMessage,
 };
 use core_pb::names::RobotName;
-use core_pb::threaded_websocket::{Address, TcpStreamThreadableSocket, TextOrT, ThreadedSocket};
+use core_pb::threaded_websocket::{
+    Address, Backoff, LinkMetrics, TcpStreamThreadableSocket, TextOrT, ThreadedSocket,
+};
 use core_pb::{bin_decode_single, bin_encode};
 use Destination::*;
 
 use crate::sockets::Incoming::{GuiConnected, GuiDisconnected};
 
+/// How often [`Incoming::LinkMetrics`] are reported for each connection
+const LINK_METRICS_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
 pub enum Destination {
     GuiClients,
@@ -50,6 +60,12 @@ pub enum Incoming {
     GuiDisconnected(u64),
     FromGui(GuiToServerMessage),
     FromGameServer(Vec<u8>),
+    /// Problems seen in frames received from a robot; sent when they change
+    RobotFrameStats(FrameStats),
+    /// Counters describing the connection; sent every so often
+    LinkMetrics(LinkMetrics),
+    /// A robot announced where it can be reached
+    Beacon(RobotBeacon),
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -60,8 +76,12 @@ pub enum Outgoing {
     RawBytes(Vec<u8>),
     ToSimulation(ServerToSimulationMessage),
     ToRobot(ServerToRobotMessage),
+    /// Sent over UDP; see [`core_pb::messages::robot_udp`]
+    ToRobotUdp(ServerToRobotMessage),
     ToGui(ServerToGuiMessage),
     ToGameServer(GameServerCommand),
+    /// Applies to every connection the server makes, regardless of destination
+    Backoff(Backoff),
 }
 
 // external api
@@ -88,6 +108,8 @@ async fn receive_outgoing(
     incoming_tx: Sender<(Destination, Incoming)>,
     outgoing_rx: Receiver<(Destination, Outgoing)>,
 ) -> Result<(), ()> {
+    let (backoff_tx, backoff_rx) = watch::channel(Backoff::default());
+
     // game server
     let (gs_tx, gs_rx) = unbounded();
     let _ = tokio::spawn(manage_threaded_socket(
@@ -99,6 +121,7 @@ async fn receive_outgoing(
             |_first, bytes| Ok::<_, ()>(vec![TextOrT::T(bytes.to_vec())]),
         ),
         gs_rx,
+        backoff_rx.clone(),
         incoming_tx.clone(),
         Incoming::FromGameServer,
     ));
@@ -109,6 +132,7 @@ async fn receive_outgoing(
         Simulation,
         ThreadedSocket::with_name("server[simulation]".to_string()),
         sim_rx,
+        backoff_rx.clone(),
         incoming_tx.clone(),
         Incoming::FromSimulation,
     ));
@@ -119,6 +143,8 @@ async fn receive_outgoing(
         let (robot_tx, robot_rx) = unbounded();
         let mut seq = 0;
         let mut stateful_tcp_reader = StatefulTcpReader::new();
+        let mut reported_frame_stats = stateful_tcp_reader.stats();
+        let frame_stats_tx = incoming_tx.clone();
         let _ = tokio::spawn(manage_threaded_socket(
             Robot(name),
             ThreadedSocket::new::<TcpStreamThreadableSocket, _, _, _, _>(
@@ -150,33 +176,54 @@ async fn receive_outgoing(
                             BytesOrT::Bytes(bytes) => TextOrT::Bytes(bytes.to_vec()),
                         });
                     }
+                    let frame_stats = stateful_tcp_reader.stats();
+                    if frame_stats.has_new_errors(&reported_frame_stats) {
+                        reported_frame_stats = frame_stats;
+                        let _ = frame_stats_tx
+                            .try_send((Robot(name), Incoming::RobotFrameStats(frame_stats)));
+                    }
                     Ok::<_, ()>(msgs)
                 },
             ),
             robot_rx,
-            incoming_tx,
+            backoff_rx.clone(),
+            incoming_tx.clone(),
             Incoming::FromRobot,
         ));
-        robot_tx
+        let (robot_udp_tx, robot_udp_rx) = unbounded();
+        let _ = tokio::spawn(manage_robot_udp(name, robot_udp_rx, incoming_tx));
+        (robot_tx, robot_udp_tx)
     });
 
     // gui clients
     let (gui_tx, gui_rx) = unbounded();
     let _ = tokio::spawn(manage_gui_clients(incoming_tx.clone(), gui_rx));
 
+    let _ = tokio::spawn(listen_for_beacons(incoming_tx.clone()));
+
     let _ = tokio::spawn(repeat_sleep(incoming_tx.clone(), Duration::from_millis(40)));
 
     loop {
         let (dest, msg) = outgoing_rx.recv().await.map_err(|_| ())?;
 
-        if let Outgoing::Address(addr) = msg {
+        if let Outgoing::Backoff(backoff) = msg {
+            backoff_tx.send_replace(backoff);
+        } else if let Outgoing::Address(addr) = msg {
             match dest {
                 GameServer => gs_tx.send(Left(addr)).await.map_err(|_| ())?,
                 Simulation => sim_tx.send(Left(addr)).await.map_err(|_| ())?,
-                Robot(name) => robots[name as usize]
-                    .send(Left(addr))
-                    .await
-                    .map_err(|_| ())?,
+                Robot(name) => {
+                    robots[name as usize]
+                        .0
+                        .send(Left(addr))
+                        .await
+                        .map_err(|_| ())?;
+                    robots[name as usize]
+                        .1
+                        .send(Left(addr))
+                        .await
+                        .map_err(|_| ())?;
+                }
                 _ => error!("Invalid destination {dest:?} for address {addr:?}"),
             }
         } else if let Outgoing::Text(text) = msg {
@@ -200,10 +247,17 @@ async fn receive_outgoing(
                     sim_tx.send(Right(TextOrT::T(cmd))).await.map_err(|_| ())?
                 }
                 (Robot(name), Outgoing::ToRobot(cmd)) => robots[name as usize]
+                    .0
                     .send(Right(TextOrT::T(cmd)))
                     .await
                     .map_err(|_| ())?,
+                (Robot(name), Outgoing::ToRobotUdp(cmd)) => robots[name as usize]
+                    .1
+                    .send(Right(cmd))
+                    .await
+                    .map_err(|_| ())?,
                 (Robot(name), Outgoing::RawBytes(data)) => robots[name as usize]
+                    .0
                     .send(Right(TextOrT::Bytes(data)))
                     .await
                     .map_err(|_| ())?,
@@ -241,11 +295,20 @@ async fn manage_threaded_socket<
     destination: Destination,
     mut threaded_socket: ThreadedSocket<S, R>,
     rx: Receiver<Either<Option<Address>, TextOrT<S>>>,
+    mut backoff: watch::Receiver<Backoff>,
     tx: Sender<(Destination, Incoming)>,
     r_to_inc: F,
 ) -> Result<(), ()> {
+    let mut metrics_interval = interval(LINK_METRICS_INTERVAL);
     loop {
         select! {
+            Ok(()) = backoff.changed() => {
+                threaded_socket.set_backoff(*backoff.borrow_and_update());
+            }
+            _ = metrics_interval.tick() => {
+                let metrics = threaded_socket.metrics();
+                tx.send((destination, Incoming::LinkMetrics(metrics))).await.map_err(|_| ())?;
+            }
             msg = rx.recv() => {
                 match msg.map_err(|_| ())? {
                     Left(addr) => threaded_socket.connect(addr),
@@ -270,17 +333,109 @@ async fn manage_threaded_socket<
     }
 }
 
+/// Receives [`RobotBeacon`]s broadcast by robots waiting for the server to connect
+async fn listen_for_beacons(tx: Sender<(Destination, Incoming)>) -> Result<(), ()> {
+    let socket = UdpSocket::bind(("0.0.0.0", DISCOVERY_PORT))
+        .await
+        .map_err(|e| error!("Failed to bind discovery socket: {e:?}"))?;
+    let mut buf = [0; MAX_DATAGRAM_LEN];
+
+    loop {
+        let Ok((len, _)) = socket.recv_from(&mut buf).await else {
+            continue;
+        };
+        // other broadcasts on the port are ignored
+        if let Ok(beacon) = read_beacon(&buf[..len]) {
+            tx.send((Robot(beacon.name), Incoming::Beacon(beacon)))
+                .await
+                .map_err(|_| ())?;
+        }
+    }
+}
+
+/// Exchanges datagrams with a robot, alongside its TCP connection
+///
+/// Nothing is sent until the server asks for a message to go over UDP; the robot only uses UDP
+/// once it has heard from this socket
+async fn manage_robot_udp(
+    name: RobotName,
+    rx: Receiver<Either<Option<Address>, ServerToRobotMessage>>,
+    tx: Sender<(Destination, Incoming)>,
+) -> Result<(), ()> {
+    let socket = UdpSocket::bind("0.0.0.0:0")
+        .await
+        .map_err(|e| error!("Failed to bind udp socket for {name}: {e:?}"))?;
+    let mut address = None;
+    let mut seq = 0;
+    let mut reader = UdpReader::new();
+    let mut send_buf = [0; MAX_DATAGRAM_LEN];
+    let mut recv_buf = [0; MAX_DATAGRAM_LEN];
+
+    loop {
+        select! {
+            msg = rx.recv() => {
+                match msg.map_err(|_| ())? {
+                    Left(addr) => {
+                        address = addr;
+                        reader.clear();
+                    }
+                    Right(msg) => {
+                        let Some((ip, port)) = address else {
+                            continue;
+                        };
+                        match write_udp(&mut seq, &msg, &mut send_buf) {
+                            Ok(len) => {
+                                let to = (Ipv4Addr::from(ip), port);
+                                if let Err(e) = socket.send_to(&send_buf[..len], to).await {
+                                    error!("Failed to send datagram to {name}: {e:?}");
+                                }
+                            }
+                            Err(e) => error!("Failed to encode datagram for {name}: {e:?}"),
+                        }
+                    }
+                }
+            }
+            received = socket.recv_from(&mut recv_buf) => {
+                let Ok((len, from)) = received else {
+                    continue;
+                };
+                if address.map(|(ip, _)| IpAddr::from(ip)) != Some(from.ip()) {
+                    continue;
+                }
+                // stale and corrupted datagrams are dropped
+                if let Ok((_, msg)) = reader.read(&recv_buf[..len]) {
+                    tx.send((Robot(name), Incoming::FromRobot(msg))).await.map_err(|_| ())?;
+                }
+            }
+        }
+    }
+}
+
 async fn manage_gui_clients(
     tx: Sender<(Destination, Incoming)>,
     rx: Receiver<ServerToGuiMessage>,
 ) -> Result<(), ()> {
     let event_hub = simple_websockets::launch(GUI_LISTENER_PORT).map_err(|_| ())?;
     let mut responders: HashMap<u64, Responder> = HashMap::new();
+    let mut metrics = LinkMetrics::default();
+    let mut connected_at: Option<Instant> = None;
+    let mut ever_connected = false;
+    let mut metrics_interval = interval(LINK_METRICS_INTERVAL);
 
     loop {
         select! {
+            _ = metrics_interval.tick() => {
+                let metrics = LinkMetrics {
+                    connected_for: connected_at.map(|t| t.elapsed()),
+                    ..metrics.clone()
+                };
+                tx.send((GuiClients, Incoming::LinkMetrics(metrics))).await.map_err(|_| ())?;
+            }
             outgoing = rx.recv() => {
-                let msg = Message::Binary(bin_encode(false, TextOrT::T(outgoing.map_err(|_| ())?)).unwrap());
+                let bytes = bin_encode(false, TextOrT::T(outgoing.map_err(|_| ())?)).unwrap();
+                metrics.messages_sent += responders.len() as u64;
+                metrics.bytes_sent += (bytes.len() * responders.len()) as u64;
+                let msg = Message::Binary(bytes);
                 for r in responders.values_mut() {
                     r.send(msg.clone());
                 }
@@ -288,22 +443,42 @@ async fn manage_gui_clients(
             event = event_hub.poll_async() => {
                 match event {
                     Event::Connect(id, responder) => {
+                        if responders.is_empty() {
+                            connected_at = Some(Instant::now());
+                        }
+                        if ever_connected {
+                            metrics.reconnects += 1;
+                        }
+                        ever_connected = true;
                         responders.insert(id, responder);
                         tx.send((GuiClients, GuiConnected(id))).await.map_err(|_| ())?;
                     }
                     Event::Disconnect(id) => {
                         responders.remove(&id);
+                        if responders.is_empty() {
+                            connected_at = None;
+                        }
                         tx.send((GuiClients, GuiDisconnected(id))).await.map_err(|_| ())?;
                     }
                     Event::Message(id, msg) => match msg {
-                        Message::Binary(bytes) => match bin_decode_single(&bytes) {
-                            Ok(msg) => tx.send((GuiClients, Incoming::FromGui(msg))).await.map_err(|_| ())?,
-                            Err(e) => error!(
-                                "Failed to decode bytes from gui client {id} ({} bytes): {e:?}",
-                                bytes.len()
-                            ),
-                        },
-                        Message::Text(text) => error!("Unexpected text from gui client {id}: {text}"),
+                        Message::Binary(bytes) => {
+                            metrics.messages_received += 1;
+                            metrics.bytes_received += bytes.len() as u64;
+                            match bin_decode_single(&bytes) {
+                                Ok(msg) => tx.send((GuiClients, Incoming::FromGui(msg))).await.map_err(|_| ())?,
+                                Err(e) => {
+                                    error!(
+                                        "Failed to decode bytes from gui client {id} ({} bytes): {e:?}",
+                                        bytes.len()
+                                    );
+                                    metrics.last_error = Some(format!("Failed to decode message from client {id}: {e:?}"));
+                                }
+                            }
+                        }
+                        Message::Text(text) => {
+                            error!("Unexpected text from gui client {id}: {text}");
+                            metrics.last_error = Some(format!("Unexpected text from client {id}"));
+                        }
                     },
                 }
             }
@@ -0,0 +1,86 @@
+use core_pb::messages::path_stream::Waypoint;
+use nalgebra::{Point2, Vector2};
+
+/// Turn the target path, a list of cells starting next to `start`, into the waypoints
+/// streamed to the robot
+///
+/// At each turn, the waypoint is moved `corner_cut` gu toward the inside of the turn along both
+/// directions, so the robot can cut the corner instead of driving through the center of the
+/// cell. If the path ends in the cell containing `destination`, the last waypoint is
+/// `destination` itself instead of the center of that cell.
+pub fn plan_waypoints(
+    start: Option<Point2<i8>>,
+    path: &[Point2<i8>],
+    destination: Option<Point2<f32>>,
+    corner_cut: f32,
+) -> Vec<Waypoint> {
+    let mut waypoints: Vec<Waypoint> = path.iter().map(|p| Waypoint::from(*p)).collect();
+    // the last cell has nothing after it, so it is never a turn
+    for i in 0..path.len().saturating_sub(1) {
+        let Some(prev) = i.checked_sub(1).map(|j| path[j]).or(start) else {
+            continue;
+        };
+        let back = prev - path[i];
+        let ahead = path[i + 1] - path[i];
+        if is_step(back) && is_step(ahead) && back.dot(&ahead) == 0 {
+            waypoints[i].location += (back + ahead).map(|x| x as f32) * corner_cut;
+        }
+    }
+    if let (Some(destination), Some(last)) = (destination, waypoints.last_mut()) {
+        if last.cell() == destination.map(|x| x.round() as i8) {
+            last.location = destination;
+        }
+    }
+    waypoints
+}
+
+/// Whether the vector moves to a neighboring cell
+fn is_step(v: Vector2<i8>) -> bool {
+    v.x.abs() + v.y.abs() == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locations(waypoints: &[Waypoint]) -> Vec<Point2<f32>> {
+        waypoints.iter().map(|w| w.location).collect()
+    }
+
+    #[test]
+    fn corners_are_cut() {
+        let path = [Point2::new(2, 1), Point2::new(2, 2), Point2::new(2, 3)];
+        let waypoints = plan_waypoints(Some(Point2::new(1, 1)), &path, None, 0.2);
+        assert_eq!(
+            locations(&waypoints),
+            vec![
+                Point2::new(1.8, 1.2),
+                Point2::new(2.0, 2.0),
+                Point2::new(2.0, 3.0)
+            ]
+        );
+        // cut corners stay in their cells, so the robot's progress is tracked the same way
+        assert_eq!(waypoints[0].cell(), path[0]);
+
+        // without cutting corners, or without knowing where the path starts, every waypoint is
+        // the center of its cell
+        let centers: Vec<Point2<f32>> = path.iter().map(|p| p.map(|x| x as f32)).collect();
+        let waypoints = plan_waypoints(Some(Point2::new(1, 1)), &path, None, 0.0);
+        assert_eq!(locations(&waypoints), centers);
+        let waypoints = plan_waypoints(None, &path, None, 0.2);
+        assert_eq!(locations(&waypoints), centers);
+    }
+
+    #[test]
+    fn path_ends_at_destination() {
+        let path = [Point2::new(1, 2), Point2::new(1, 3)];
+        let destination = Point2::new(1.3, 2.8);
+        let waypoints = plan_waypoints(Some(Point2::new(1, 1)), &path, Some(destination), 0.0);
+        assert_eq!(waypoints[1].location, destination);
+        assert_eq!(waypoints[1].cell(), Point2::new(1, 3));
+
+        // a destination that isn't at the end of the path is ignored
+        let waypoints = plan_waypoints(Some(Point2::new(1, 1)), &path[..1], Some(destination), 0.0);
+        assert_eq!(locations(&waypoints), vec![Point2::new(1.0, 2.0)]);
+    }
+}
@@ -0,0 +1,108 @@
+use crate::network::PacbotNetworkSimulation;
+use crate::{MyApp, RobotReference};
+use bevy::prelude::*;
+use core::f32;
+use core_pb::constants::GU_PER_M;
+use core_pb::grid::standard_grid::StandardGrid;
+use core_pb::pacbot_rs::ghost_state::GhostColor;
+use core_pb::robot_definition::RobotDefinition;
+
+const PELLET_COLOR: Color = Color::srgb(0.3, 0.3, 1.0);
+const SUPER_PELLET_COLOR: Color = Color::srgb(0.3, 0.3, 1.0);
+const PACMAN_COLOR: Color = Color::srgb(1.0, 1.0, 0.0);
+const SELECTED_ROBOT_COLOR: Color = Color::srgb(1.0, 1.0, 0.0);
+const ROBOT_HEADING_COLOR: Color = Color::srgb(1.0, 1.0, 1.0);
+const SENSOR_HIT_COLOR: Color = Color::srgb(0.0, 1.0, 0.0);
+const SENSOR_MISS_COLOR: Color = Color::srgb(1.0, 0.0, 0.0);
+
+const GHOST_RED_COLOR: Color = Color::srgb(1.0, 0.0, 0.0);
+const GHOST_PINK_COLOR: Color = Color::srgb(1.0, 0.75, 0.8);
+const GHOST_CYAN_COLOR: Color = Color::srgb(0.0, 1.0, 1.0);
+const GHOST_ORANGE_COLOR: Color = Color::srgb(1.0, 0.55, 0.0);
+const GHOST_FRIGHTENED_COLOR: Color = Color::srgb(0.2, 0.2, 1.0);
+
+/// Draw the game state and robot details on top of the physics debug shapes, so that the
+/// simulator window shows the same match as the gui
+pub fn draw_game(
+    app: Res<MyApp>,
+    network: Res<PacbotNetworkSimulation>,
+    robots: Query<(&Transform, &RobotReference)>,
+    mut gizmos: Gizmos,
+) {
+    let game_state = &network.game_state;
+
+    if app.standard_grid == StandardGrid::Pacman {
+        // pellets
+        for row in 0..32 {
+            for col in 0..32 {
+                if game_state.pellet_at((row, col)) {
+                    let super_pellet = ((row == 3) || (row == 23)) && ((col == 1) || (col == 26));
+                    let pos = Vec2::new(row as f32, col as f32);
+                    if super_pellet {
+                        gizmos.circle_2d(pos, 0.3, SUPER_PELLET_COLOR);
+                        gizmos.circle_2d(pos, 0.15, SUPER_PELLET_COLOR);
+                    } else {
+                        gizmos.circle_2d(pos, 0.1, PELLET_COLOR);
+                    }
+                }
+            }
+        }
+
+        // ghosts
+        for ghost in &game_state.ghosts {
+            let pos = Vec2::new(ghost.loc.row as f32, ghost.loc.col as f32);
+            let color = match ghost.color {
+                GhostColor::Red => GHOST_RED_COLOR,
+                GhostColor::Pink => GHOST_PINK_COLOR,
+                GhostColor::Cyan => GHOST_CYAN_COLOR,
+                GhostColor::Orange => GHOST_ORANGE_COLOR,
+            };
+            if ghost.is_frightened() {
+                gizmos.circle_2d(pos, 0.45, GHOST_FRIGHTENED_COLOR);
+                gizmos.circle_2d(pos, 0.25, color);
+            } else {
+                gizmos.circle_2d(pos, 0.45, color);
+                gizmos.circle_2d(pos, 0.35, color);
+            }
+        }
+    }
+
+    // the cell the game state believes pacman is in
+    let pacman = Vec2::new(
+        game_state.pacman_loc.row as f32,
+        game_state.pacman_loc.col as f32,
+    );
+    gizmos.rect_2d(pacman, Vec2::splat(1.0), PACMAN_COLOR);
+
+    // robots
+    for (t, robot) in &robots {
+        let name = robot.0;
+        let radius = name.robot().radius;
+        let center = t.translation.truncate();
+        // feels weird, but this does work
+        let rotation = 2.0 * t.rotation.normalize().w.acos() * t.rotation.z.signum();
+
+        if name == app.selected_robot {
+            gizmos.circle_2d(center, radius + 0.1, SELECTED_ROBOT_COLOR);
+        }
+        gizmos.line_2d(
+            center,
+            center + Vec2::from_angle(rotation) * radius,
+            ROBOT_HEADING_COLOR,
+        );
+
+        let sensor_rays = robot.1.read().unwrap().sensor_rays;
+        for (i, ray) in sensor_rays.iter().enumerate() {
+            let dir = Vec2::from_angle(rotation + (i as f32) * f32::consts::FRAC_PI_2);
+            let start = center + dir * radius;
+            match ray {
+                Some(distance) => gizmos.line_2d(start, start + dir * *distance, SENSOR_HIT_COLOR),
+                None => gizmos.line_2d(
+                    start,
+                    start + dir * RobotDefinition::new(name).sensor_distance * GU_PER_M,
+                    SENSOR_MISS_COLOR,
+                ),
+            }
+        }
+    }
+}
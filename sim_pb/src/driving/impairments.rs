@@ -0,0 +1,517 @@
+use async_channel::{unbounded, Receiver, Sender};
+use async_std::future::timeout;
+use async_std::io::{ReadExt, WriteExt};
+use async_std::net::{SocketAddr, TcpStream, UdpSocket};
+use async_std::task::{sleep, spawn};
+use core_pb::driving::network::{Endpoint, RobotUdpSocket};
+use core_pb::messages::robot_tcp::{frame_len, FRAME_HEADER_LEN};
+use core_pb::messages::robot_udp::MAX_DATAGRAM_LEN;
+use core_pb::messages::settings::NetworkImpairments;
+use embedded_io_async::{ErrorType, Read, Write};
+use rand::{thread_rng, Rng};
+use std::io;
+use std::io::ErrorKind;
+use std::net::Shutdown;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// How often the link checks for a scheduled disconnect when no data is flowing
+const OUTAGE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The network conditions a simulated robot experiences, shared with the rest of the simulation
+#[derive(Clone)]
+pub struct LinkConditions {
+    settings: Arc<RwLock<NetworkImpairments>>,
+    /// Periodic disconnects are counted from this moment
+    started: Instant,
+}
+
+impl LinkConditions {
+    pub fn new(settings: Arc<RwLock<NetworkImpairments>>) -> Self {
+        Self {
+            settings,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn settings(&self) -> NetworkImpairments {
+        *self.settings.read().unwrap()
+    }
+
+    /// If the link is currently in a scheduled disconnect, how much longer it will last
+    pub fn outage_remaining(&self) -> Option<Duration> {
+        self.outage_remaining_at(Instant::now())
+    }
+
+    /// If the link is in a scheduled disconnect at `now`, how much longer it will last
+    fn outage_remaining_at(&self, now: Instant) -> Option<Duration> {
+        let settings = self.settings();
+        if settings.disconnect_interval <= 0.0 || settings.disconnect_duration <= 0.0 {
+            return None;
+        }
+        let phase = (now - self.started).as_secs_f32() % settings.disconnect_interval;
+        let outage_start = settings.disconnect_interval - settings.disconnect_duration;
+        if phase >= outage_start {
+            Some(Duration::from_secs_f32(
+                settings.disconnect_interval - phase,
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// Decides when, and whether, each frame travelling in one direction is delivered
+struct ImpairedLink {
+    conditions: LinkConditions,
+    /// When the link will have finished transmitting the previous frame
+    next_free: Instant,
+    /// When the previous frame was delivered; TCP never reorders frames
+    last_release: Instant,
+    /// Whether jitter may cause frames to arrive in a different order than they were sent
+    reorder: bool,
+}
+
+impl ImpairedLink {
+    fn new(conditions: LinkConditions) -> Self {
+        Self {
+            conditions,
+            next_free: Instant::now(),
+            last_release: Instant::now(),
+            reorder: false,
+        }
+    }
+
+    fn unordered(conditions: LinkConditions) -> Self {
+        Self {
+            reorder: true,
+            ..Self::new(conditions)
+        }
+    }
+
+    /// The instant a frame of `len` bytes sent now should arrive, or None if it is lost
+    fn schedule(&mut self, len: usize) -> Option<Instant> {
+        self.schedule_at(len, Instant::now(), &mut thread_rng())
+    }
+
+    /// The instant a frame of `len` bytes sent at `now` should arrive, or None if it is lost
+    fn schedule_at(&mut self, len: usize, now: Instant, rng: &mut impl Rng) -> Option<Instant> {
+        let settings = self.conditions.settings();
+        if settings.frame_loss > 0.0 && rng.gen::<f32>() < settings.frame_loss {
+            return None;
+        }
+
+        let mut sent = now;
+        if settings.bandwidth_cap > 0 {
+            sent = sent.max(self.next_free)
+                + Duration::from_secs_f32(len as f32 / settings.bandwidth_cap as f32);
+            self.next_free = sent;
+        }
+        let jitter = rng.gen_range(0..=settings.jitter_ms);
+        let mut release = sent + Duration::from_millis(settings.latency_ms + jitter);
+        if !self.reorder {
+            release = release.max(self.last_release);
+        }
+        self.last_release = release;
+
+        Some(release)
+    }
+}
+
+async fn sleep_until(instant: Instant) {
+    let now = Instant::now();
+    if instant > now {
+        sleep(instant - now).await;
+    }
+}
+
+/// A [`TcpStream`] whose frames are delayed, dropped, throttled and periodically cut off
+/// according to [`NetworkImpairments`]
+///
+/// Frames are delimited using the robot TCP framing, so loss never leaves a partial frame in the stream
+pub struct ImpairedTcpStream {
+    stream: TcpStream,
+    conditions: LinkConditions,
+
+    outgoing_link: ImpairedLink,
+    outgoing: Sender<(Instant, Vec<u8>)>,
+    incoming: Receiver<(Instant, Vec<u8>)>,
+
+    /// The part of the last delivered frame that hasn't been read yet
+    pending: Vec<u8>,
+}
+
+impl ImpairedTcpStream {
+    pub fn new(stream: TcpStream, conditions: LinkConditions) -> Self {
+        let (outgoing_tx, outgoing_rx) = unbounded();
+        let (incoming_tx, incoming_rx) = unbounded();
+
+        spawn(Self::deliver_outgoing(
+            stream.clone(),
+            conditions.clone(),
+            outgoing_rx,
+        ));
+        spawn(Self::receive_incoming(
+            stream.clone(),
+            ImpairedLink::new(conditions.clone()),
+            incoming_tx,
+        ));
+
+        Self {
+            stream,
+            outgoing_link: ImpairedLink::new(conditions.clone()),
+            conditions,
+            outgoing: outgoing_tx,
+            incoming: incoming_rx,
+            pending: vec![],
+        }
+    }
+
+    pub fn shutdown(&self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+
+    /// Writes frames to the socket once their scheduled time arrives
+    async fn deliver_outgoing(
+        mut stream: TcpStream,
+        conditions: LinkConditions,
+        outgoing: Receiver<(Instant, Vec<u8>)>,
+    ) {
+        while let Ok((release, frame)) = outgoing.recv().await {
+            sleep_until(release).await;
+            if conditions.outage_remaining().is_some() || stream.write_all(&frame).await.is_err() {
+                break;
+            }
+        }
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+
+    /// Splits bytes from the socket into frames and schedules each one for delivery
+    async fn receive_incoming(
+        mut stream: TcpStream,
+        mut link: ImpairedLink,
+        incoming: Sender<(Instant, Vec<u8>)>,
+    ) {
+        let mut buf = vec![];
+        let mut read_buf = [0; 1024];
+        loop {
+            if link.conditions.outage_remaining().is_some() {
+                break;
+            }
+            match timeout(OUTAGE_POLL_INTERVAL, stream.read(&mut read_buf)).await {
+                Err(_) => continue,
+                Ok(Ok(0)) | Ok(Err(_)) => break,
+                Ok(Ok(len)) => buf.extend_from_slice(&read_buf[..len]),
+            }
+            while let Some(len) = frame_len(&buf) {
+                if len < FRAME_HEADER_LEN {
+                    // not a valid frame; let the robot's reader deal with it
+                    let _ = incoming.send((Instant::now(), buf.split_off(0))).await;
+                    break;
+                }
+                if buf.len() < len {
+                    break;
+                }
+                let rest = buf.split_off(len);
+                let frame = std::mem::replace(&mut buf, rest);
+                if let Some(release) = link.schedule(len) {
+                    if incoming.send((release, frame)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+}
+
+impl ErrorType for ImpairedTcpStream {
+    type Error = io::Error;
+}
+
+impl Read for ImpairedTcpStream {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if self.pending.is_empty() {
+            match self.incoming.recv().await {
+                Ok((release, frame)) => {
+                    sleep_until(release).await;
+                    self.pending = frame;
+                }
+                // the connection was closed
+                Err(_) => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.pending.len());
+        buf[..len].copy_from_slice(&self.pending[..len]);
+        self.pending.drain(..len);
+        Ok(len)
+    }
+}
+
+impl Write for ImpairedTcpStream {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.write_all(buf).await?;
+        Ok(buf.len())
+    }
+
+    /// The network task writes one whole frame at a time, so each call is treated as a frame
+    async fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+        if self.conditions.outage_remaining().is_some() {
+            self.shutdown();
+            return Err(io::Error::new(
+                ErrorKind::NotConnected,
+                "simulated disconnect",
+            ));
+        }
+        if let Some(release) = self.outgoing_link.schedule(buf.len()) {
+            self.outgoing
+                .send((release, buf.to_vec()))
+                .await
+                .map_err(|_| io::Error::new(ErrorKind::BrokenPipe, "socket closed"))?;
+        }
+        Ok(())
+    }
+}
+
+/// A [`UdpSocket`] whose datagrams are delayed, dropped, throttled and periodically cut off
+/// according to [`NetworkImpairments`]
+///
+/// Unlike [`ImpairedTcpStream`], jitter may reorder datagrams
+pub struct ImpairedUdpSocket {
+    socket: Arc<UdpSocket>,
+    conditions: LinkConditions,
+
+    outgoing_link: ImpairedLink,
+    incoming: Receiver<(Vec<u8>, Endpoint)>,
+}
+
+impl ImpairedUdpSocket {
+    pub fn new(socket: UdpSocket, conditions: LinkConditions) -> Self {
+        let socket = Arc::new(socket);
+        let (incoming_tx, incoming_rx) = unbounded();
+
+        spawn(Self::receive_incoming(
+            socket.clone(),
+            ImpairedLink::unordered(conditions.clone()),
+            incoming_tx,
+        ));
+
+        Self {
+            socket,
+            outgoing_link: ImpairedLink::unordered(conditions.clone()),
+            conditions,
+            incoming: incoming_rx,
+        }
+    }
+
+    /// Schedules each datagram from the socket for delivery, until this socket is dropped
+    async fn receive_incoming(
+        socket: Arc<UdpSocket>,
+        mut link: ImpairedLink,
+        incoming: Sender<(Vec<u8>, Endpoint)>,
+    ) {
+        let mut buf = [0; MAX_DATAGRAM_LEN];
+        while !incoming.is_closed() {
+            let Ok(Ok((len, SocketAddr::V4(from)))) =
+                timeout(OUTAGE_POLL_INTERVAL, socket.recv_from(&mut buf)).await
+            else {
+                continue;
+            };
+            if link.conditions.outage_remaining().is_some() {
+                continue;
+            }
+            if let Some(release) = link.schedule(len) {
+                let datagram = buf[..len].to_vec();
+                let incoming = incoming.clone();
+                spawn(async move {
+                    sleep_until(release).await;
+                    let _ = incoming
+                        .send((datagram, (from.ip().octets(), from.port())))
+                        .await;
+                });
+            }
+        }
+    }
+}
+
+impl RobotUdpSocket for ImpairedUdpSocket {
+    type Error = io::Error;
+
+    async fn send_to(&mut self, buf: &[u8], to: Endpoint) -> Result<(), Self::Error> {
+        if self.conditions.outage_remaining().is_some() {
+            return Err(io::Error::new(
+                ErrorKind::NotConnected,
+                "simulated disconnect",
+            ));
+        }
+        if let Some(release) = self.outgoing_link.schedule(buf.len()) {
+            let socket = self.socket.clone();
+            let datagram = buf.to_vec();
+            spawn(async move {
+                sleep_until(release).await;
+                let _ = socket.send_to(&datagram, SocketAddr::from(to)).await;
+            });
+        }
+        Ok(())
+    }
+
+    async fn recv_from(&mut self, buf: &mut [u8]) -> Result<(usize, Endpoint), Self::Error> {
+        let (datagram, from) = self
+            .incoming
+            .recv()
+            .await
+            .map_err(|_| io::Error::new(ErrorKind::BrokenPipe, "socket closed"))?;
+        let len = buf.len().min(datagram.len());
+        buf[..len].copy_from_slice(&datagram[..len]);
+        Ok((len, from))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn link(settings: NetworkImpairments, reorder: bool) -> (ImpairedLink, Instant) {
+        let conditions = LinkConditions::new(Arc::new(RwLock::new(settings)));
+        let start = conditions.started;
+        let mut link = ImpairedLink::new(conditions);
+        link.next_free = start;
+        link.last_release = start;
+        link.reorder = reorder;
+        (link, start)
+    }
+
+    #[test]
+    fn perfect_link_delivers_immediately() {
+        let (mut link, start) = link(NetworkImpairments::default(), false);
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            assert_eq!(link.schedule_at(100, start, &mut rng), Some(start));
+        }
+    }
+
+    #[test]
+    fn drop_rate() {
+        let (mut link, start) = link(
+            NetworkImpairments {
+                frame_loss: 0.25,
+                ..Default::default()
+            },
+            true,
+        );
+        let mut rng = StdRng::seed_from_u64(0);
+        let frames = 10_000;
+        let dropped = (0..frames)
+            .filter(|_| link.schedule_at(10, start, &mut rng).is_none())
+            .count();
+        let rate = dropped as f32 / frames as f32;
+        assert!((rate - 0.25).abs() < 0.02, "drop rate was {rate}");
+    }
+
+    #[test]
+    fn added_delay() {
+        let (mut link, start) = link(
+            NetworkImpairments {
+                latency_ms: 40,
+                jitter_ms: 10,
+                ..Default::default()
+            },
+            true,
+        );
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut saw_jitter = false;
+        for _ in 0..1000 {
+            let delay = link.schedule_at(10, start, &mut rng).unwrap() - start;
+            assert!(delay >= Duration::from_millis(40));
+            assert!(delay <= Duration::from_millis(50));
+            saw_jitter |= delay > Duration::from_millis(40);
+        }
+        assert!(saw_jitter);
+    }
+
+    #[test]
+    fn ordered_links_never_reorder() {
+        let (mut link, start) = link(
+            NetworkImpairments {
+                jitter_ms: 100,
+                ..Default::default()
+            },
+            false,
+        );
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut last = start;
+        for i in 0..1000 {
+            let release = link
+                .schedule_at(10, start + Duration::from_millis(i), &mut rng)
+                .unwrap();
+            assert!(release >= last);
+            last = release;
+        }
+    }
+
+    #[test]
+    fn bandwidth_spacing() {
+        let (mut link, start) = link(
+            NetworkImpairments {
+                bandwidth_cap: 1000,
+                ..Default::default()
+            },
+            false,
+        );
+        let mut rng = StdRng::seed_from_u64(0);
+        // 100 bytes at 1000 bytes per second takes 100ms each, even when sent together
+        for i in 1..=5 {
+            let release = link.schedule_at(100, start, &mut rng).unwrap();
+            let expected = start + Duration::from_millis(100 * i);
+            assert!((release - expected) < Duration::from_micros(10));
+        }
+        // once the link is idle, frames only wait for their own transmission
+        let later = start + Duration::from_secs(10);
+        let release = link.schedule_at(100, later, &mut rng).unwrap();
+        assert!((release - (later + Duration::from_millis(100))) < Duration::from_micros(10));
+    }
+
+    #[test]
+    fn outage_window_boundaries() {
+        let (link, start) = link(
+            NetworkImpairments {
+                disconnect_interval: 10.0,
+                disconnect_duration: 2.0,
+                ..Default::default()
+            },
+            false,
+        );
+        let at = |secs: f32| {
+            link.conditions
+                .outage_remaining_at(start + Duration::from_secs_f32(secs))
+        };
+        assert_eq!(at(0.0), None);
+        assert_eq!(at(7.9), None);
+        assert!((at(8.0).unwrap().as_secs_f32() - 2.0).abs() < 1e-3);
+        assert!((at(9.5).unwrap().as_secs_f32() - 0.5).abs() < 1e-3);
+        // the next period starts connected
+        assert_eq!(at(10.1), None);
+        assert!(at(18.5).is_some());
+    }
+
+    #[test]
+    fn no_outages_when_disabled() {
+        let (link, start) = link(
+            NetworkImpairments {
+                disconnect_interval: 10.0,
+                disconnect_duration: 0.0,
+                ..Default::default()
+            },
+            false,
+        );
+        for secs in 0..30 {
+            assert_eq!(
+                link.conditions
+                    .outage_remaining_at(start + Duration::from_secs(secs)),
+                None
+            );
+        }
+    }
+}
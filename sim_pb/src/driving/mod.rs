@@ -1,3 +1,4 @@
+use crate::driving::impairments::LinkConditions;
 use crate::driving::motors::SimMotors;
 use crate::driving::network::SimNetwork;
 use crate::driving::peripherals::{SimDisplay, SimPeripherals};
@@ -9,6 +10,7 @@ use core_pb::driving::motors::motors_task;
 use core_pb::driving::network::network_task;
 use core_pb::driving::peripherals::peripherals_task;
 use core_pb::driving::RobotBehavior;
+use core_pb::messages::settings::NetworkImpairments;
 use core_pb::messages::RobotButton;
 use core_pb::names::RobotName;
 use core_pb::util::WebTimeInstant;
@@ -17,6 +19,7 @@ use std::collections::VecDeque;
 use std::sync::{Arc, RwLock};
 use std::thread::spawn;
 
+mod impairments;
 mod motors;
 mod network;
 mod peripherals;
@@ -36,13 +39,19 @@ pub struct SimRobot {
 
     pub wasd_motor_speeds: Option<[f32; 3]>,
     pub requested_motor_speeds: [f32; 3],
+    /// The most recent distance sensor readings, kept for drawing
+    pub sensor_rays: [Option<f32>; 4],
 
     pub button_events: VecDeque<(RobotButton, bool)>,
     pub joystick: Option<(f32, f32)>,
 }
 
 impl SimRobot {
-    pub fn start(name: RobotName, firmware_swapped: bool) -> Arc<RwLock<Self>> {
+    pub fn start(
+        name: RobotName,
+        firmware_swapped: bool,
+        network_impairments: Arc<RwLock<NetworkImpairments>>,
+    ) -> Arc<RwLock<Self>> {
         let shared_data = Arc::new(SharedRobotData::new(name));
         let (thread_stopper_tx, thread_stopper_rx) = bounded(CHANNEL_BUFFER_SIZE);
 
@@ -59,13 +68,19 @@ impl SimRobot {
 
             wasd_motor_speeds: None,
             requested_motor_speeds: [0.0; 3],
+            sensor_rays: [None; 4],
 
             button_events: VecDeque::new(),
             joystick: None,
         }));
 
         let motors = SimMotors::new(name, robot.clone());
-        let network = SimNetwork::new(name, firmware_swapped, robot.clone());
+        let network = SimNetwork::new(
+            name,
+            firmware_swapped,
+            robot.clone(),
+            LinkConditions::new(network_impairments),
+        );
         let peripherals = SimPeripherals::new(robot.clone());
 
         spawn(move || {
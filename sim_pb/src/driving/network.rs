@@ -1,14 +1,10 @@
+use crate::driving::impairments::{ImpairedTcpStream, ImpairedUdpSocket, LinkConditions};
 use crate::driving::SimRobot;
-use async_std::io::{ReadExt, WriteExt};
-use async_std::net::{TcpListener, TcpStream};
+use async_std::net::{TcpListener, UdpSocket};
 use async_std::task::sleep;
 use bevy::prelude::{error, info};
 use core_pb::driving::network::{NetworkScanInfo, RobotNetworkBehavior};
 use core_pb::names::RobotName;
-use embedded_io_async::{ErrorType, Read, ReadExactError, Write};
-use std::io;
-use std::io::ErrorKind;
-use std::net::Shutdown;
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
 
@@ -16,16 +12,23 @@ pub struct SimNetwork {
     name: RobotName,
     sim_robot: Arc<RwLock<SimRobot>>,
     network_connected: bool,
+    conditions: LinkConditions,
 
     firmware_swapped: bool,
 }
 
 impl SimNetwork {
-    pub fn new(name: RobotName, firmware_swapped: bool, sim_robot: Arc<RwLock<SimRobot>>) -> Self {
+    pub fn new(
+        name: RobotName,
+        firmware_swapped: bool,
+        sim_robot: Arc<RwLock<SimRobot>>,
+        conditions: LinkConditions,
+    ) -> Self {
         Self {
             name,
             sim_robot,
             network_connected: false,
+            conditions,
             firmware_swapped,
         }
     }
@@ -34,46 +37,31 @@ impl SimNetwork {
 #[derive(Debug)]
 pub enum SimNetworkError {
     TcpAcceptFailed,
-}
-
-pub struct TcpStreamReadWrite(TcpStream);
-
-impl ErrorType for TcpStreamReadWrite {
-    type Error = io::Error;
-}
-
-impl Read for TcpStreamReadWrite {
-    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-        self.0.read(buf).await
-    }
-
-    async fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), ReadExactError<Self::Error>> {
-        self.0
-            .read_exact(buf)
-            .await
-            .map_err(|_| ReadExactError::Other(io::Error::new(ErrorKind::Other, "")))
-    }
-}
-
-impl Write for TcpStreamReadWrite {
-    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-        self.0.write(buf).await
-    }
+    UdpBindFailed,
+    SimulatedDisconnect,
 }
 
 impl RobotNetworkBehavior for SimNetwork {
     type Error = SimNetworkError;
     type Socket<'a>
-        = TcpStreamReadWrite
+        = ImpairedTcpStream
     where
         Self: 'a;
+    type UdpSocket<'a>
+        = ImpairedUdpSocket
+    where
+        Self: 'a;
+    type UdpBuffers = ();
+
+    /// Simulated robots share the server's machine, so beacons don't need to be broadcast
+    const BEACON_ADDRESS: [u8; 4] = [127, 0, 0, 1];
 
     async fn mac_address(&mut self) -> [u8; 6] {
         self.name.mac_address()
     }
 
     async fn wifi_is_connected(&self) -> Option<[u8; 4]> {
-        if self.network_connected {
+        if self.network_connected && self.conditions.outage_remaining().is_none() {
             Some([127, 0, 0, 1])
         } else {
             None
@@ -89,8 +77,11 @@ impl RobotNetworkBehavior for SimNetwork {
         _network: &str,
         _password: Option<&str>,
     ) -> Result<(), <Self as RobotNetworkBehavior>::Error> {
-        self.network_connected = true;
         sleep(Duration::from_secs(1)).await;
+        if self.conditions.outage_remaining().is_some() {
+            return Err(SimNetworkError::SimulatedDisconnect);
+        }
+        self.network_connected = true;
         Ok(())
     }
 
@@ -117,7 +108,7 @@ impl RobotNetworkBehavior for SimNetwork {
                 }
                 Ok((stream, addr)) => {
                     info!("Client connected to a {} from {addr}", self.name);
-                    return Ok(TcpStreamReadWrite(stream));
+                    return Ok(ImpairedTcpStream::new(stream, self.conditions.clone()));
                 }
             },
             Err(e) => {
@@ -128,7 +119,26 @@ impl RobotNetworkBehavior for SimNetwork {
     }
 
     async fn tcp_close<'a>(&mut self, socket: &mut Self::Socket<'a>) {
-        socket.0.shutdown(Shutdown::Both).unwrap()
+        socket.shutdown()
+    }
+
+    async fn udp_bind<'a>(
+        &mut self,
+        port: u16,
+        _buffers: &'a mut Self::UdpBuffers,
+    ) -> Result<Self::UdpSocket<'a>, Self::Error>
+    where
+        Self: 'a,
+    {
+        // give the socket of a rebooted robot time to close
+        sleep(Duration::from_millis(200)).await;
+        match UdpSocket::bind(format!("0.0.0.0:{port}")).await {
+            Ok(socket) => Ok(ImpairedUdpSocket::new(socket, self.conditions.clone())),
+            Err(e) => {
+                error!("Error binding udp socket: {e:?}");
+                Err(SimNetworkError::UdpBindFailed)
+            }
+        }
     }
 
     async fn prepare_firmware_update(&mut self) {}
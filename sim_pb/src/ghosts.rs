@@ -0,0 +1,135 @@
+use crate::network::PacbotNetworkSimulation;
+use crate::physics::{GHOST_GROUP, ROBOT_GROUP, WALL_GROUP};
+use crate::{MyApp, RobotReference};
+use bevy::prelude::*;
+use bevy_rapier2d::na::{Point2, Rotation2, Vector2};
+use bevy_rapier2d::prelude::*;
+use core_pb::names::RobotName;
+
+/// Radius of a ghost's collider, in grid units
+const GHOST_RADIUS: f32 = 0.4;
+/// How quickly a ghost body closes the distance to its game state cell, per second
+const GHOST_FOLLOW_RATE: f32 = 10.0;
+/// If a ghost body is further than this from its cell (ex. after a reset), it teleports there
+const GHOST_SNAP_DISTANCE: f32 = 2.0;
+/// The top speed of a robot chasing its ghost, in gu/s
+const GHOST_ROBOT_MAX_SPEED: f32 = 4.0;
+/// Proportional gain from distance to the next cell to robot speed
+const GHOST_ROBOT_GAIN: f32 = 6.0;
+
+/// A kinematic body that follows the ghost at the given index in the game state
+#[derive(Component)]
+pub struct GhostBody(usize);
+
+/// Marks a simulated robot that is currently driving as a ghost
+#[derive(Component)]
+pub struct GhostRobot;
+
+/// Spawn, move and despawn ghost bodies, and drive any robots that are acting as ghosts
+#[allow(clippy::type_complexity)]
+pub fn update_ghosts(
+    mut app: ResMut<MyApp>,
+    network: Res<PacbotNetworkSimulation>,
+    mut commands: Commands,
+    time: Res<Time>,
+    mut ghost_bodies: Query<(Entity, &GhostBody, &mut Transform), Without<RobotReference>>,
+    mut robots: Query<
+        (Entity, &mut Transform, &RobotReference, Has<GhostRobot>),
+        Without<GhostBody>,
+    >,
+) {
+    let ghost_locations: Vec<Vec2> = network
+        .game_state
+        .ghosts
+        .iter()
+        .map(|ghost| Vec2::new(ghost.loc.row as f32, ghost.loc.col as f32))
+        .collect();
+
+    // ghost bodies
+    if app.ghosts.bodies && ghost_bodies.is_empty() {
+        for (i, loc) in ghost_locations.iter().enumerate() {
+            commands
+                .spawn(RigidBody::KinematicPositionBased)
+                .insert(Collider::ball(GHOST_RADIUS))
+                .insert(CollisionGroups::new(GHOST_GROUP, ROBOT_GROUP))
+                .insert(Transform::from_xyz(loc.x, loc.y, 0.0))
+                .insert(GhostBody(i));
+        }
+    } else if !app.ghosts.bodies {
+        for (entity, ..) in &ghost_bodies {
+            commands.entity(entity).despawn();
+        }
+    }
+    for (_, ghost, mut t) in &mut ghost_bodies {
+        let target = ghost_locations[ghost.0];
+        let offset = target - t.translation.truncate();
+        if offset.length() > GHOST_SNAP_DISTANCE {
+            t.translation = target.extend(0.0);
+        } else {
+            let step = offset * f32::min(1.0, GHOST_FOLLOW_RATE * time.delta_secs());
+            t.translation += step.extend(0.0);
+        }
+    }
+
+    // robots driving as ghosts; each is paired with the ghost at the same position in the list
+    let ghost_robots: Vec<RobotName> = RobotName::get_all()
+        .into_iter()
+        .filter(|name| name.is_simulated() && app.ghosts.robots[*name as usize])
+        .take(ghost_locations.len())
+        .collect();
+    for name in &ghost_robots {
+        if app.robots[*name as usize].is_none() {
+            app.spawn_robot(&mut commands, *name);
+        }
+    }
+    for (entity, mut t, robot, is_ghost) in &mut robots {
+        let Some(i) = ghost_robots.iter().position(|name| *name == robot.0) else {
+            if is_ghost {
+                // give control back
+                commands
+                    .entity(entity)
+                    .remove::<GhostRobot>()
+                    .insert(CollisionGroups::new(ROBOT_GROUP, WALL_GROUP | GHOST_GROUP));
+                robot.1.write().unwrap().wasd_motor_speeds = None;
+            }
+            continue;
+        };
+        let target = ghost_locations[i];
+        let target_cell = Point2::new(target.x as i8, target.y as i8);
+        if !is_ghost {
+            commands
+                .entity(entity)
+                .insert(GhostRobot)
+                .insert(CollisionGroups::new(GHOST_GROUP, WALL_GROUP | ROBOT_GROUP));
+            if !app.grid.wall_at(&target_cell) {
+                t.translation = target.extend(0.0);
+            }
+        }
+        let pos = t.translation.truncate();
+
+        // head for the next cell on the shortest path, so walls don't get in the way
+        let next = match app.grid.node_nearest(pos.x, pos.y) {
+            Some(start) if start != target_cell && !app.grid.wall_at(&target_cell) => {
+                match app.grid.bfs_path(start, target_cell) {
+                    Some(path) if path.len() > 1 => Vec2::new(path[1].x as f32, path[1].y as f32),
+                    _ => target,
+                }
+            }
+            _ => target,
+        };
+
+        let offset = next - pos;
+        let speed = f32::min(GHOST_ROBOT_MAX_SPEED, offset.length() * GHOST_ROBOT_GAIN);
+        let velocity = offset.normalize_or_zero() * speed;
+        // the drive system expects a velocity relative to the robot's heading
+        let (_, _, yaw) = t.rotation.to_euler(EulerRot::XYZ);
+        let velocity = Rotation2::new(-yaw) * Vector2::new(velocity.x, velocity.y);
+        robot.1.write().unwrap().wasd_motor_speeds = Some(
+            robot
+                .0
+                .robot()
+                .drive_system
+                .get_motor_speed_omni(velocity, 0.0),
+        );
+    }
+}
@@ -5,17 +5,29 @@ use std::sync::{Arc, RwLock};
 
 use core_pb::grid::computed_grid::ComputedGrid;
 use core_pb::grid::standard_grid::StandardGrid;
+use core_pb::messages::scenario::{Scenario, SimulationNoise};
+use core_pb::messages::settings::{NetworkImpairments, SimulationGhostSettings};
 use core_pb::names::{RobotName, NUM_ROBOT_NAMES};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 
+use crate::drawing::draw_game;
 use crate::driving::SimRobot;
+use crate::ghosts::update_ghosts;
 use crate::network::{update_network, PacbotNetworkSimulation};
 use crate::physics::spawn_walls;
+use crate::scenario::{read_scenario, write_scenario, SAVED_SCENARIO_PATH};
+use crate::trajectory::{record_trajectories, TrajectoryRecorder};
 
 #[allow(dead_code)]
 mod delayed_value;
+mod drawing;
 mod driving;
+mod ghosts;
 mod network;
 mod physics;
+mod scenario;
+mod trajectory;
 
 #[derive(Resource)]
 pub struct MyApp {
@@ -24,6 +36,19 @@ pub struct MyApp {
 
     robots: [Option<(Entity, Arc<RwLock<SimRobot>>)>; NUM_ROBOT_NAMES],
     selected_robot: RobotName,
+
+    /// Shared with every simulated robot's network
+    network_impairments: Arc<RwLock<NetworkImpairments>>,
+    ghosts: SimulationGhostSettings,
+
+    noise: SimulationNoise,
+    /// Source of all sensor and motor noise, seeded so that scenarios are repeatable
+    rng: StdRng,
+    seed: u64,
+    /// Given on the command line, loaded at startup
+    initial_scenario: Option<Scenario>,
+    /// While recording, each robot's true pose and what it believes is written to a file
+    trajectories: Option<TrajectoryRecorder>,
 }
 
 #[derive(Clone, Component)]
@@ -35,6 +60,11 @@ pub struct Wall;
 fn main() {
     info!("Simulation starting up");
 
+    let initial_scenario = std::env::args().nth(1).map(|path| {
+        read_scenario(&path).unwrap_or_else(|e| panic!("Failed to load scenario: {e}"))
+    });
+    let seed = rand::random();
+
     App::new()
         .add_plugins(DefaultPlugins)
         .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
@@ -45,6 +75,15 @@ fn main() {
 
             robots: RobotName::get_all().map(|_| None),
             selected_robot: RobotName::Stella,
+
+            network_impairments: Arc::new(RwLock::new(NetworkImpairments::default())),
+            ghosts: SimulationGhostSettings::default(),
+
+            noise: SimulationNoise::default(),
+            rng: StdRng::seed_from_u64(seed),
+            seed,
+            initial_scenario,
+            trajectories: None,
         })
         .insert_resource(PacbotNetworkSimulation::new().expect("Failed to launch simulation. Make sure the game server is not running at the same time on the same machine."))
         .add_systems(Startup, setup_graphics)
@@ -52,6 +91,9 @@ fn main() {
         .add_systems(Update, keyboard_input)
         .add_systems(Update, update_network)
         .add_systems(Update, robot_position_to_game_state)
+        .add_systems(Update, draw_game)
+        .add_systems(Update, update_ghosts)
+        .add_systems(Update, record_trajectories)
         .run();
 }
 
@@ -61,8 +103,18 @@ fn setup_graphics(mut commands: Commands) {
     commands.spawn((Camera2d, Transform::from_xyz(15.5, 15.5, 0.0), proj));
 }
 
-fn setup_physics(app: ResMut<MyApp>, mut commands: Commands) {
-    spawn_walls(&mut commands, app.standard_grid);
+fn setup_physics(
+    mut app: ResMut<MyApp>,
+    mut network: ResMut<PacbotNetworkSimulation>,
+    mut commands: Commands,
+    walls: Query<(Entity, &Wall)>,
+) {
+    match app.initial_scenario.take() {
+        Some(scenario) => {
+            app.load_scenario(scenario, &mut network.game_state, &walls, &mut commands)
+        }
+        None => spawn_walls(&mut commands, &app.grid),
+    }
 }
 
 fn robot_position_to_game_state(
@@ -87,6 +139,7 @@ fn robot_position_to_game_state(
 
 fn keyboard_input(
     mut app: ResMut<MyApp>,
+    network: Res<PacbotNetworkSimulation>,
     mut commands: Commands,
     mut keys: ResMut<ButtonInput<KeyCode>>,
     walls: Query<(Entity, &Wall)>,
@@ -112,6 +165,21 @@ fn keyboard_input(
         app.despawn_robot(name, &mut commands);
         keys.press(KeyCode::Tab);
     }
+    if keys.just_pressed(KeyCode::KeyT) {
+        app.set_recording_trajectories(app.trajectories.is_none());
+    }
+    if keys.just_pressed(KeyCode::KeyP) {
+        match write_scenario(
+            SAVED_SCENARIO_PATH,
+            &app.current_scenario(
+                &network.game_state,
+                robots.iter().map(|(_, t, _, _, robot)| (t, robot)),
+            ),
+        ) {
+            Ok(()) => info!("Saved scenario to {SAVED_SCENARIO_PATH}"),
+            Err(e) => error!("{e}"),
+        }
+    }
     if keys.just_pressed(KeyCode::Tab) {
         app.selected_robot = RobotName::get_all()
             .into_iter()
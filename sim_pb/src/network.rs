@@ -4,6 +4,9 @@ use bevy::prelude::{error, info, Commands, Entity, Query, ResMut, Resource, Tran
 use bevy_rapier2d::dynamics::{ExternalImpulse, Velocity};
 use bevy_rapier2d::na::{Point2, Rotation2};
 use core_pb::constants::{GAME_SERVER_MAGIC_NUMBER, GAME_SERVER_PORT, SIMULATION_LISTENER_PORT};
+use core_pb::grid::computed_grid::ComputedGrid;
+use core_pb::grid::standard_grid::StandardGrid;
+use core_pb::messages::version::ProtocolVersion;
 use core_pb::messages::{GameServerCommand, ServerToSimulationMessage, SimulationToServerMessage};
 use core_pb::names::RobotName;
 use core_pb::pacbot_rs::game_state::GameState;
@@ -154,6 +157,15 @@ impl PacbotNetworkSimulation {
         while let Some(event) = self.simulation_event_hub.next_event() {
             match event {
                 Event::Connect(id, responder) => {
+                    responder.send(Message::Binary(
+                        bin_encode(
+                            false,
+                            TextOrT::T(SimulationToServerMessage::Version(
+                                ProtocolVersion::CURRENT,
+                            )),
+                        )
+                        .unwrap(),
+                    ));
                     self.simulation_clients.insert(id, responder);
                 }
                 Event::Message(_, message) => match message {
@@ -174,6 +186,17 @@ impl PacbotNetworkSimulation {
                                     app.grid = app.standard_grid.compute_grid();
                                     app.reset_grid(&walls, &mut robots, &mut commands)
                                 }
+                                ServerToSimulationMessage::SetCustomGrid(grid) => {
+                                    match ComputedGrid::try_from_custom(grid) {
+                                        Ok(grid) => {
+                                            // custom grids share the Pac-Man grid's spawn cell
+                                            app.standard_grid = StandardGrid::Pacman;
+                                            app.grid = grid;
+                                            app.reset_grid(&walls, &mut robots, &mut commands)
+                                        }
+                                        Err(e) => error!("Invalid custom grid: {e}"),
+                                    }
+                                }
                                 ServerToSimulationMessage::Teleport(name, loc) => {
                                     if !app.grid.wall_at(&loc) {
                                         if let Some((_, mut transforms, ..)) = robots
@@ -195,6 +218,24 @@ impl PacbotNetworkSimulation {
                                         sim_robot.write().unwrap().joystick = Some(values)
                                     }
                                 }
+                                ServerToSimulationMessage::SetNetworkImpairments(impairments) => {
+                                    info!("Network impairments set to {impairments:?}");
+                                    *app.network_impairments.write().unwrap() = impairments;
+                                }
+                                ServerToSimulationMessage::SetGhosts(ghosts) => {
+                                    app.ghosts = ghosts;
+                                }
+                                ServerToSimulationMessage::RecordTrajectories(record) => {
+                                    app.set_recording_trajectories(record);
+                                }
+                                ServerToSimulationMessage::LoadScenario(scenario) => {
+                                    app.load_scenario(
+                                        scenario,
+                                        &mut self.game_state,
+                                        &walls,
+                                        &mut commands,
+                                    );
+                                }
                             },
                             Err(e) => error!("Error decoding simulation message: {e:?}"),
                         }
@@ -284,6 +325,7 @@ impl PacbotNetworkSimulation {
         }
 
         // robot messages
+        let network_impairments = app.network_impairments.clone();
         for (_, robot) in app.robots.iter_mut().flatten() {
             if let Some((name, swapped)) = {
                 let mut sim_robot = robot.write().unwrap();
@@ -295,7 +337,7 @@ impl PacbotNetworkSimulation {
                     None
                 }
             } {
-                *robot = SimRobot::start(name, swapped);
+                *robot = SimRobot::start(name, swapped, network_impairments.clone());
             }
         }
 
@@ -7,15 +7,20 @@ use bevy_rapier2d::na::{Point2, Rotation2, Vector2};
 use bevy_rapier2d::prelude::*;
 use core::f32;
 use core_pb::constants::GU_PER_M;
-use core_pb::grid::standard_grid::StandardGrid;
+use core_pb::grid::computed_grid::ComputedGrid;
 use core_pb::names::RobotName;
 use core_pb::robot_definition::RobotDefinition;
 use rand::prelude::*;
 use std::sync::atomic::Ordering;
 
-pub fn spawn_walls(commands: &mut Commands, grid: StandardGrid) {
-    let grid = grid.compute_grid();
+/// Collision group for walls
+pub const WALL_GROUP: Group = Group::GROUP_1;
+/// Collision group for robots
+pub const ROBOT_GROUP: Group = Group::GROUP_2;
+/// Collision group for ghosts, including robots driving as ghosts; distance sensors ignore them
+pub const GHOST_GROUP: Group = Group::GROUP_3;
 
+pub fn spawn_walls(commands: &mut Commands, grid: &ComputedGrid) {
     // Create the walls
     for wall in grid.walls() {
         commands
@@ -23,7 +28,7 @@ pub fn spawn_walls(commands: &mut Commands, grid: StandardGrid) {
                 (wall.bottom_right.x as f32 * 1.0 - wall.top_left.x as f32 * 1.0) / 2.0,
                 (wall.bottom_right.y as f32 * 1.0 - wall.top_left.y as f32 * 1.0) / 2.0,
             ))
-            .insert(CollisionGroups::new(Group::GROUP_1, Group::GROUP_2))
+            .insert(CollisionGroups::new(WALL_GROUP, ROBOT_GROUP | GHOST_GROUP))
             .insert(Transform::from_xyz(
                 (wall.bottom_right.x as f32 * 1.0 + wall.top_left.x as f32 * 1.0) / 2.0,
                 (wall.bottom_right.y as f32 * 1.0 + wall.top_left.y as f32 * 1.0) / 2.0,
@@ -33,17 +38,37 @@ pub fn spawn_walls(commands: &mut Commands, grid: StandardGrid) {
     }
 }
 
+/// A random value in `-range..range`, or zero if noise is disabled
+fn random_noise(rng: &mut impl Rng, range: f32) -> f32 {
+    if range > 0.0 {
+        rng.gen_range(-range..range)
+    } else {
+        0.0
+    }
+}
+
 impl MyApp {
     pub fn spawn_robot(&mut self, commands: &mut Commands, name: RobotName) {
         let pos = self.standard_grid.get_default_pacbot_isometry().translation;
+        self.spawn_robot_at(commands, name, Point2::new(pos.x, pos.y), 0.0);
+    }
 
-        let sim_robot = SimRobot::start(name, false);
+    pub fn spawn_robot_at(
+        &mut self,
+        commands: &mut Commands,
+        name: RobotName,
+        pos: Point2<f32>,
+        angle: f32,
+    ) {
+        let sim_robot = SimRobot::start(name, false, self.network_impairments.clone());
 
         let new_robot = commands
             .spawn(RigidBody::Dynamic)
             .insert(Collider::ball(name.robot().radius))
-            .insert(CollisionGroups::new(Group::GROUP_2, Group::GROUP_1))
-            .insert(Transform::from_xyz(pos.x, pos.y, 0.0))
+            .insert(CollisionGroups::new(ROBOT_GROUP, WALL_GROUP | GHOST_GROUP))
+            .insert(
+                Transform::from_xyz(pos.x, pos.y, 0.0).with_rotation(Quat::from_rotation_z(angle)),
+            )
             .insert(GravityScale(0.0))
             .insert(ExternalImpulse::default())
             .insert(Velocity::default())
@@ -86,7 +111,7 @@ impl MyApp {
         rapier_context: ReadDefaultRapierContext,
     ) {
         for (_, t, v, mut imp, robot) in robots {
-            let sim_robot = robot.1.read().unwrap();
+            let mut sim_robot = robot.1.write().unwrap();
 
             // calculate current angle
             let rotation =
@@ -101,8 +126,7 @@ impl MyApp {
             // note, sim doesn't provide logs via defmt_logs
             // motor speeds updated below
 
-            let mut rng = thread_rng();
-
+            let mut sensor_rays = [None; 4];
             for (i, sig) in sim_robot.data.sig_distances.iter().enumerate() {
                 let ray_pos = Vec2::new(
                     t.translation.x
@@ -118,30 +142,31 @@ impl MyApp {
                 );
                 let max_toi: f32 = RobotDefinition::new(robot.0).sensor_distance * GU_PER_M;
                 let solid: bool = true;
-                let filter: QueryFilter = QueryFilter::default()
-                    .groups(CollisionGroups::new(Group::GROUP_2, Group::GROUP_1));
+                let filter: QueryFilter =
+                    QueryFilter::default().groups(CollisionGroups::new(ROBOT_GROUP, WALL_GROUP));
                 if let Some((_, intersection)) =
                     rapier_context.cast_ray_and_get_normal(ray_pos, ray_dir, max_toi, solid, filter)
                 {
                     let hit_point = intersection.point;
-                    let noise_range: f32 = 0.01;
-                    let dist_noise: f32 = 1.0 + rng.gen_range(-noise_range..noise_range);
+                    let dist_noise: f32 =
+                        1.0 + random_noise(&mut self.rng, self.noise.distance_sensors);
                     let distance = ray_pos.distance(hit_point) * dist_noise;
 
                     sig.signal(Ok(Some(distance)));
+                    sensor_rays[i] = Some(distance);
                 } else {
                     sig.signal(Ok(None));
                 }
             }
+            sim_robot.sensor_rays = sensor_rays;
 
-            let noise_rng: f32 = 0.08;
             let mut motor_speeds = sim_robot
                 .wasd_motor_speeds
                 .unwrap_or(sim_robot.requested_motor_speeds);
             let robot_definition = RobotDefinition::new(robot.0);
             //for each motor add noise
             for m in &mut motor_speeds {
-                let noise: f32 = rng.gen_range(-noise_rng..noise_rng).abs();
+                let noise: f32 = random_noise(&mut self.rng, self.noise.motors).abs();
                 *m += *m * noise;
             }
             for i in 0..3 {
@@ -175,7 +200,7 @@ impl MyApp {
         for wall in walls {
             commands.entity(wall.0).despawn()
         }
-        spawn_walls(commands, self.standard_grid);
+        spawn_walls(commands, &self.grid);
         for (_, mut t, mut v, _, _) in robots {
             let pos = self.standard_grid.get_default_pacbot_isometry().translation;
             t.translation = Vec3::new(pos.x, pos.y, 0.0);
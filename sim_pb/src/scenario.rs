@@ -0,0 +1,110 @@
+use crate::physics::spawn_walls;
+use crate::{MyApp, RobotReference, Wall};
+use bevy::prelude::*;
+use bevy_rapier2d::na::{Point2, Rotation2};
+use core_pb::messages::scenario::{Scenario, ScenarioRobot};
+use core_pb::names::RobotName;
+use core_pb::pacbot_rs::game_state::GameState;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::path::Path;
+
+/// Where the current setup is written when saving a scenario from the simulator window
+pub const SAVED_SCENARIO_PATH: &str = "saved_scenario.ron";
+
+/// Read a RON scenario file
+pub fn read_scenario(path: impl AsRef<Path>) -> Result<Scenario, String> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Couldn't read scenario {}: {e}", path.display()))?;
+    ron::from_str(&text).map_err(|e| format!("Couldn't parse scenario {}: {e}", path.display()))
+}
+
+/// Write a scenario as a RON file
+pub fn write_scenario(path: impl AsRef<Path>, scenario: &Scenario) -> Result<(), String> {
+    let path = path.as_ref();
+    let text = ron::ser::to_string_pretty(scenario, ron::ser::PrettyConfig::default())
+        .map_err(|e| format!("Couldn't serialize scenario: {e}"))?;
+    std::fs::write(path, text)
+        .map_err(|e| format!("Couldn't write scenario {}: {e}", path.display()))
+}
+
+impl MyApp {
+    /// Replace the grid, robots, game state and simulation settings with those from the scenario
+    pub fn load_scenario(
+        &mut self,
+        scenario: Scenario,
+        game_state: &mut GameState,
+        walls: &Query<(Entity, &Wall)>,
+        commands: &mut Commands,
+    ) {
+        info!(
+            "Loading scenario with {} robot(s) on {:?}",
+            scenario.robots.len(),
+            scenario.grid
+        );
+
+        for name in RobotName::get_all() {
+            self.despawn_robot(name, commands);
+        }
+
+        self.standard_grid = scenario.grid;
+        self.grid = scenario.grid.compute_grid();
+        for (entity, _) in walls {
+            commands.entity(entity).despawn()
+        }
+        spawn_walls(commands, &self.grid);
+
+        for robot in scenario.robots {
+            if !robot.name.is_simulated() {
+                error!("Scenario includes {}, which can't be simulated", robot.name);
+                continue;
+            }
+            let default_pos = self.standard_grid.get_default_pacbot_isometry().translation;
+            let pos = robot
+                .position
+                .unwrap_or(Point2::new(default_pos.x, default_pos.y));
+            self.spawn_robot_at(commands, robot.name, pos, robot.angle);
+        }
+        self.selected_robot = scenario.pacman;
+
+        *game_state = scenario.game_state.unwrap_or(GameState {
+            paused: true,
+            ..Default::default()
+        });
+
+        self.noise = scenario.noise;
+        *self.network_impairments.write().unwrap() = scenario.network;
+        self.ghosts = scenario.ghosts;
+        self.rng = StdRng::seed_from_u64(scenario.seed);
+        self.seed = scenario.seed;
+    }
+
+    /// Capture the current setup, so that it can be saved and loaded later
+    pub fn current_scenario<'a>(
+        &self,
+        game_state: &GameState,
+        robots: impl Iterator<Item = (&'a Transform, &'a RobotReference)>,
+    ) -> Scenario {
+        Scenario {
+            grid: self.standard_grid,
+            robots: robots
+                .map(|(t, robot)| ScenarioRobot {
+                    name: robot.0,
+                    position: Some(Point2::new(t.translation.x, t.translation.y)),
+                    // feels weird, but this does work
+                    angle: Rotation2::new(
+                        2.0 * t.rotation.normalize().w.acos() * t.rotation.z.signum(),
+                    )
+                    .angle(),
+                })
+                .collect(),
+            pacman: self.selected_robot,
+            game_state: Some(game_state.clone()),
+            noise: self.noise,
+            network: *self.network_impairments.read().unwrap(),
+            ghosts: self.ghosts.clone(),
+            seed: self.seed,
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use crate::{MyApp, RobotReference};
+use bevy::prelude::*;
+use core_pb::messages::{FrequentServerToRobot, VelocityControl};
+use core_pb::names::{RobotName, NUM_ROBOT_NAMES};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Recordings are placed in a new folder inside this one
+pub const TRAJECTORIES_DIR: &str = "trajectories";
+
+const HEADER: &str = "time,true_x,true_y,true_angle,est_x,est_y,imu_angle,\
+dist_0,dist_1,dist_2,dist_3,cmd,cmd_vel_x,cmd_vel_y,cmd_ang_vel,cmd_angle,motor_0,motor_1,motor_2";
+
+/// Writes a time-stamped CSV file per simulated robot, pairing its true pose with what the
+/// robot believes and what it was told to do, so that localization and control can be scored
+/// against ground truth
+pub struct TrajectoryRecorder {
+    started: Instant,
+    dir: PathBuf,
+    files: [Option<BufWriter<File>>; NUM_ROBOT_NAMES],
+}
+
+impl TrajectoryRecorder {
+    pub fn start() -> Self {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Self {
+            started: Instant::now(),
+            dir: PathBuf::from(TRAJECTORIES_DIR).join(secs.to_string()),
+            files: RobotName::get_all().map(|_| None),
+        }
+    }
+
+    fn write_row(&mut self, name: RobotName, row: &str) -> std::io::Result<()> {
+        let file = match &mut self.files[name as usize] {
+            Some(file) => file,
+            file @ None => {
+                std::fs::create_dir_all(&self.dir)?;
+                let path = self.dir.join(format!("{name}.csv"));
+                info!("Recording {name} trajectory to {}", path.display());
+                let mut new_file = BufWriter::new(File::create(path)?);
+                writeln!(new_file, "{HEADER}")?;
+                file.insert(new_file)
+            }
+        };
+        writeln!(file, "{row}")
+    }
+}
+
+impl Drop for TrajectoryRecorder {
+    fn drop(&mut self) {
+        for file in self.files.iter_mut().flatten() {
+            if let Err(e) = file.flush() {
+                error!("Failed to save trajectory: {e}");
+            }
+        }
+    }
+}
+
+impl MyApp {
+    pub fn set_recording_trajectories(&mut self, record: bool) {
+        if record && self.trajectories.is_none() {
+            info!("Started recording trajectories");
+            self.trajectories = Some(TrajectoryRecorder::start());
+        } else if !record && self.trajectories.is_some() {
+            info!("Stopped recording trajectories");
+            self.trajectories = None;
+        }
+    }
+}
+
+fn opt(x: Option<f32>) -> String {
+    x.map(|x| x.to_string()).unwrap_or_default()
+}
+
+/// The velocity the robot was told to drive at, as `cmd,cmd_vel_x,cmd_vel_y,cmd_ang_vel,cmd_angle`
+///
+/// Columns that don't apply to the kind of command are left empty
+fn command_columns(config: Option<&FrequentServerToRobot>) -> String {
+    let Some(config) = config else {
+        return ",,,,".to_string();
+    };
+    if config.follow_target_path {
+        return "FollowTargetPath,,,,".to_string();
+    }
+    let (cmd, lin, ang_vel, angle) = match config.target_velocity {
+        VelocityControl::None => ("None", None, None, None),
+        VelocityControl::Stop => ("Stop", None, None, None),
+        VelocityControl::LinVelAngVel(lin, ang) => ("LinVelAngVel", Some(lin), Some(ang), None),
+        VelocityControl::LinVelFixedAng(lin, ang) => ("LinVelFixedAng", Some(lin), None, Some(ang)),
+        VelocityControl::LinVelFaceForward(lin) => ("LinVelFaceForward", Some(lin), None, None),
+        VelocityControl::AssistedDriving(lin) => ("AssistedDriving", Some(lin), None, None),
+    };
+    format!(
+        "{cmd},{},{},{},{}",
+        opt(lin.map(|v| v.x)),
+        opt(lin.map(|v| v.y)),
+        opt(ang_vel),
+        opt(angle)
+    )
+}
+
+/// Append the current state of each simulated robot to its trajectory file
+pub fn record_trajectories(mut app: ResMut<MyApp>, robots: Query<(&Transform, &RobotReference)>) {
+    let Some(recorder) = &mut app.trajectories else {
+        return;
+    };
+    let time = recorder.started.elapsed().as_secs_f32();
+    for (t, robot) in &robots {
+        let name = robot.0;
+        let row = {
+            let sim_robot = robot.1.read().unwrap();
+            // feels weird, but this does work
+            let rotation = 2.0 * t.rotation.normalize().w.acos() * t.rotation.z.signum();
+            let sensors = sim_robot.data.sensors.try_get();
+            let estimate = sensors.as_ref().and_then(|s| s.location);
+            let imu_angle = sensors.as_ref().and_then(|s| s.angle.clone().ok());
+            let distances = sensors
+                .as_ref()
+                .map(|s| s.distances.clone().map(|d| d.ok().flatten()))
+                .unwrap_or_default();
+            let command = command_columns(sim_robot.data.config.try_get().as_ref());
+            let motors = sim_robot
+                .wasd_motor_speeds
+                .unwrap_or(sim_robot.requested_motor_speeds);
+            format!(
+                "{time},{},{},{rotation},{},{},{},{},{},{},{},{command},{},{},{}",
+                t.translation.x,
+                t.translation.y,
+                opt(estimate.map(|p| p.x)),
+                opt(estimate.map(|p| p.y)),
+                opt(imu_angle),
+                opt(distances[0]),
+                opt(distances[1]),
+                opt(distances[2]),
+                opt(distances[3]),
+                motors[0],
+                motors[1],
+                motors[2],
+            )
+        };
+        if let Err(e) = recorder.write_row(name, &row) {
+            error!("Failed to record {name} trajectory, stopping: {e}");
+            app.trajectories = None;
+            return;
+        }
+    }
+}